@@ -0,0 +1,162 @@
+//! Derive macros for `detalib`, enabled with its `derive` feature.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{ format_ident, quote };
+use syn::{ parse_macro_input, Data, DeriveInput, Error, Fields, LitInt, LitStr };
+
+struct Field {
+    ident: syn::Ident,
+    name: String,
+    is_key: bool,
+}
+
+fn parse_fields(input: &DeriveInput) -> Result<Vec<Field>, Error> {
+    let named = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(named) => &named.named,
+            _ => return Err(Error::new_spanned(input, "DetaRecord requires named fields")),
+        },
+        _ => return Err(Error::new_spanned(input, "DetaRecord can only be derived for structs")),
+    };
+    let mut fields = Vec::new();
+    for field in named {
+        let ident = field.ident.clone().ok_or_else(|| Error::new_spanned(field, "unnamed field"))?;
+        let mut name = ident.to_string().trim_start_matches("r#").to_string();
+        let mut is_key = false;
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("deta")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("key") {
+                    is_key = true;
+                    Ok(())
+                } else if meta.path.is_ident("rename") {
+                    name = meta.value()?.parse::<LitStr>()?.value();
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `key` or `rename = \"...\"`"))
+                }
+            })?;
+        }
+        if is_key {
+            name = String::from("key");
+        }
+        fields.push(Field { ident, name, is_key });
+    }
+    if !fields.iter().any(|f| f.name == "key") {
+        return Err(Error::new_spanned(input, "DetaRecord requires a `key` field or a field marked #[deta(key)]"));
+    }
+    Ok(fields)
+}
+
+fn parse_expires_in(input: &DeriveInput) -> Result<Option<u64>, Error> {
+    let mut expires_in = None;
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("deta")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("expires_in") {
+                expires_in = Some(meta.value()?.parse::<LitInt>()?.base10_parse::<u64>()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `expires_in = <seconds>`"))
+            }
+        })?;
+    }
+    Ok(expires_in)
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let fields = parse_fields(&input)?;
+    let expires_in = parse_expires_in(&input)?;
+    let ident = &input.ident;
+    let vis = &input.vis;
+    let fields_ident = format_ident!("{}Fields", ident);
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(&input.generics, "DetaRecord does not support generic structs"));
+    }
+    let private = quote!(::detalib::__private);
+
+    let names = fields.iter().map(|f| &f.name).collect::<Vec<_>>();
+    let idents = fields.iter().map(|f| &f.ident).collect::<Vec<_>>();
+    let key_ident = fields.iter()
+        .find(|f| f.is_key || f.name == "key")
+        .map(|f| &f.ident);
+    let expires = match expires_in {
+        Some(seconds) => quote!(::core::option::Option::Some(#seconds)),
+        None => quote!(::core::option::Option::None),
+    };
+
+    let doc = format!("The fields of `{}`, to build typed filters with.", ident);
+
+    Ok(quote! {
+        #[doc = #doc]
+        #[derive(Debug, Clone, Copy)]
+        #vis struct #fields_ident {
+            #( pub #idents: ::detalib::filter::Field, )*
+        }
+
+        impl ::detalib::DetaRecord for #ident {
+            const FIELDS: &'static [&'static str] = &[#(#names),*];
+            const EXPIRES_IN: ::core::option::Option<u64> = #expires;
+            type Fields = #fields_ident;
+
+            fn fields() -> #fields_ident {
+                #fields_ident { #( #idents: ::detalib::filter::Field::new(#names), )* }
+            }
+
+            fn key(&self) -> ::std::string::String {
+                ::std::string::ToString::to_string(&self.#key_ident)
+            }
+        }
+
+        impl #private::serde::Serialize for #ident {
+            fn serialize<S>(&self, serializer: S) -> ::core::result::Result<S::Ok, S::Error>
+                where S: #private::serde::Serializer
+            {
+                use #private::serde::ser::Error as _;
+                let mut map = #private::serde_json::Map::new();
+                #(
+                    map.insert(
+                        ::std::string::String::from(#names),
+                        #private::serde_json::to_value(&self.#idents).map_err(S::Error::custom)?,
+                    );
+                )*
+                if let ::core::option::Option::Some(seconds) = <Self as ::detalib::DetaRecord>::EXPIRES_IN {
+                    map.insert(
+                        ::std::string::String::from("__expires"),
+                        #private::serde_json::Value::from(#private::expires_at(seconds)),
+                    );
+                }
+                #private::serde::Serialize::serialize(&map, serializer)
+            }
+        }
+
+        impl<'de> #private::serde::Deserialize<'de> for #ident {
+            fn deserialize<D>(deserializer: D) -> ::core::result::Result<Self, D::Error>
+                where D: #private::serde::Deserializer<'de>
+            {
+                use #private::serde::de::Error as _;
+                let mut map = <#private::serde_json::Map<::std::string::String, #private::serde_json::Value>
+                    as #private::serde::Deserialize>::deserialize(deserializer)?;
+                ::core::result::Result::Ok(#ident {
+                    #(
+                        #idents: #private::serde_json::from_value(
+                            map.remove(#names).unwrap_or(#private::serde_json::Value::Null)
+                        ).map_err(|e| D::Error::custom(::std::format!("field `{}`: {}", #names, e)))?,
+                    )*
+                })
+            }
+        }
+    })
+}
+
+/// Derives `detalib::DetaRecord` together with `Serialize` and `Deserialize`.
+/// 
+/// * `#[deta(key)]` on a field stores it as the record `key`.
+/// * `#[deta(rename = "name")]` on a field stores it under another name.
+/// * `#[deta(expires_in = 3600)]` on the struct sets `__expires` on every write.
+/// 
+/// Also generates a `<Struct>Fields` struct with a `detalib::filter::Field` for every field.
+#[proc_macro_derive(DetaRecord, attributes(deta))]
+pub fn derive_deta_record(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input).unwrap_or_else(Error::into_compile_error).into()
+}
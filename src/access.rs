@@ -0,0 +1,245 @@
+//! Opt-in recording of query access patterns, to find where secondary indexes or new keys would help.
+
+use std::{
+    cmp::Reverse,
+    collections::{ BTreeMap, BTreeSet },
+    fmt,
+    sync::{ Arc, Mutex },
+};
+
+use serde_json::Value;
+
+/// The `(field, operator)` pairs of a query, without values.
+type Conditions = BTreeSet<(String, String)>;
+
+/// Queries averaging more pages than this are reported as candidates.
+const MAX_PAGES_PER_QUERY: f64 = 1.0;
+
+/// The operator of a query condition, e.g. `gt` for `age?gt`, and `eq` for plain equality.
+fn operator(condition: &str) -> (&str, &str) {
+    match condition.rsplit_once('?') {
+        Some((field, op)) => (field, if op == "range" { "r" } else { op }),
+        None => (condition, "eq"),
+    }
+}
+
+/// Queries of one shape on one base, as recorded by an `AccessRecorder`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AccessPattern {
+    pub base: String,
+    /// The conditions as `(field, operator)` pairs, without values. Empty for unfiltered queries.
+    pub conditions: Conditions,
+    /// Queries run, not counting the requests for their following pages.
+    pub queries: u64,
+    /// Pages fetched, including the first ones.
+    pub pages: u64,
+    /// Items returned over all pages.
+    pub items: u64,
+}
+
+impl AccessPattern {
+
+    pub fn pages_per_query(&self) -> f64 {
+        self.pages as f64 / self.queries.max(1) as f64
+    }
+}
+
+/// A change that would let queries of a pattern fetch fewer pages.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Suggestion {
+    /// Queries without conditions page through the whole base.
+    FullScan { base: String },
+    /// Equality conditions on a field could be answered by a base keyed by that field.
+    SecondaryIndex { base: String, field: String },
+    /// Range or prefix conditions on a field could become key prefix queries if keys began with it.
+    KeyPrefix { base: String, field: String },
+}
+
+impl fmt::Display for Suggestion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Suggestion::FullScan { base } => {
+                write!(f, "{}: unfiltered queries scan the whole base, consider summary records", base)
+            },
+            Suggestion::SecondaryIndex { base, field } => {
+                write!(f, "{}: consider a secondary index base keyed by `{}`", base, field)
+            },
+            Suggestion::KeyPrefix { base, field } => {
+                write!(f, "{}: consider keys starting with `{}` to query it by key prefix", base, field)
+            },
+        }
+    }
+}
+
+/// A summary of the recorded queries, returned by `AccessRecorder::report`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AccessReport {
+    /// Every recorded pattern, the most pages fetched first.
+    pub patterns: Vec<AccessPattern>,
+    /// Suggestions for patterns averaging more than one page per query, the most pages fetched first.
+    pub suggestions: Vec<(Suggestion, u64)>,
+}
+
+impl fmt::Display for AccessReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for pattern in &self.patterns {
+            let conditions = pattern.conditions.iter()
+                .map(|(field, op)| format!("{}?{}", field, op))
+                .collect::<Vec<_>>();
+            let conditions = match conditions.is_empty() {
+                true => String::from("(all)"),
+                false => conditions.join(", "),
+            };
+            writeln!(
+                f, "{} [{}]: {} queries, {} pages, {} items",
+                pattern.base, conditions, pattern.queries, pattern.pages, pattern.items
+            )?;
+        }
+        for (suggestion, pages) in &self.suggestions {
+            writeln!(f, "- {} ({} pages)", suggestion, pages)?;
+        }
+        Ok(())
+    }
+}
+
+/// Collects the shape of every query a client runs and how many pages it fetches.
+///
+/// Only fields and operators are kept, never the values queried for. Clones share the
+/// recorded patterns, so keep one to call `report` on.
+/// ```rust,no_run
+/// use detalib::{ Deta, access::AccessRecorder };
+/// use serde_json::json;
+///
+/// let recorder = AccessRecorder::new();
+/// let deta = Deta::builder().access_recorder(recorder.clone()).build();
+/// deta.base("orders").query().equals("customer", json!("c1")).walk().unwrap();
+/// println!("{}", recorder.report());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AccessRecorder {
+    patterns: Arc<Mutex<BTreeMap<(String, Conditions), AccessPattern>>>,
+}
+
+impl AccessRecorder {
+
+    pub fn new() -> AccessRecorder {
+        AccessRecorder::default()
+    }
+
+    /// Records one page of a query, given its request body and the number of items returned.
+    pub (crate) fn record(&self, base: &str, payload: &Value, items: usize) {
+        let conditions = payload["query"].as_array().into_iter().flatten()
+            .filter_map(Value::as_object)
+            .flat_map(|group| group.keys())
+            .map(|condition| {
+                let (field, op) = operator(condition);
+                (field.to_string(), op.to_string())
+            })
+            .collect::<Conditions>();
+        let mut patterns = self.patterns.lock().unwrap_or_else(|e| e.into_inner());
+        let key = (base.to_string(), conditions.clone());
+        let pattern = patterns.entry(key).or_insert_with(|| AccessPattern {
+            base: base.to_string(),
+            conditions,
+            ..AccessPattern::default()
+        });
+        if payload["last"].is_null() {
+            pattern.queries += 1;
+        }
+        pattern.pages += 1;
+        pattern.items += items as u64;
+    }
+
+    /// Forgets everything recorded so far.
+    pub fn clear(&self) {
+        self.patterns.lock().unwrap_or_else(|e| e.into_inner()).clear();
+    }
+
+    /// Summarizes the recorded patterns and suggests secondary indexes or key changes.
+    pub fn report(&self) -> AccessReport {
+        let mut patterns = self.patterns.lock().unwrap_or_else(|e| e.into_inner())
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+        patterns.sort_by_key(|pattern| Reverse(pattern.pages));
+        let mut suggestions = BTreeMap::<String, (Suggestion, u64)>::new();
+        let mut suggest = |suggestion: Suggestion, pages: u64| {
+            suggestions.entry(suggestion.to_string()).or_insert((suggestion, 0)).1 += pages;
+        };
+        let scanning = patterns.iter().filter(|pattern| pattern.pages_per_query() > MAX_PAGES_PER_QUERY);
+        for pattern in scanning {
+            let base = pattern.base.clone();
+            if pattern.conditions.is_empty() {
+                suggest(Suggestion::FullScan { base: base.clone() }, pattern.pages);
+            }
+            for (field, op) in pattern.conditions.iter().filter(|(field, _)| field != "key") {
+                let field = field.clone();
+                match op.as_str() {
+                    "eq" => suggest(Suggestion::SecondaryIndex { base: base.clone(), field }, pattern.pages),
+                    "gt" | "gte" | "lt" | "lte" | "r" | "pfx" => {
+                        suggest(Suggestion::KeyPrefix { base: base.clone(), field }, pattern.pages)
+                    },
+                    _ => {},
+                }
+            }
+        }
+        let mut suggestions = suggestions.into_values().collect::<Vec<_>>();
+        suggestions.sort_by_key(|(_, pages)| Reverse(*pages));
+        AccessReport { patterns, suggestions }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    #[cfg(feature = "mock")]
+    use crate::Deta;
+
+    #[test]
+    fn patterns_and_suggestions() {
+        let recorder = AccessRecorder::new();
+        let by_customer = json!({ "query": [{ "customer": "c1", "total?gt": 10 }] });
+        recorder.record("orders", &by_customer, 1000);
+        let next_page = json!({ "query": [{ "customer": "c2", "total?gt": 5 }], "last": "k" });
+        recorder.record("orders", &next_page, 20);
+        recorder.record("orders", &json!({ "query": [{ "key?pfx": "2024" }] }), 3);
+        recorder.record("users", &json!({ "query": [] }), 1000);
+        recorder.record("users", &json!({ "query": [], "last": "u9" }), 1000);
+        let report = recorder.report();
+        assert_eq!(report.patterns.len(), 3);
+        let top = &report.patterns[0];
+        assert_eq!((top.queries, top.pages, top.items), (1, 2, 1020));
+        let suggestions = report.suggestions.iter().map(|(s, _)| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(suggestions, [
+            "orders: consider a secondary index base keyed by `customer`",
+            "orders: consider keys starting with `total` to query it by key prefix",
+            "users: unfiltered queries scan the whole base, consider summary records",
+        ]);
+        recorder.clear();
+        assert!(recorder.report().patterns.is_empty());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn access_patterns_are_recorded() {
+        let recorder = AccessRecorder::new();
+        let deta = Deta::builder()
+            .project_key("mock_key")
+            .mock(Arc::default())
+            .access_recorder(recorder.clone())
+            .build();
+        let orders = deta.base("orders");
+        let records = (0..30)
+            .map(|i| json!({ "key": format!("o{:02}", i), "customer": i % 2 }))
+            .collect::<Vec<_>>();
+        orders.put_many(&records).unwrap();
+        orders.query().equals("customer", json!(1)).limit(5).walk().unwrap();
+        let report = recorder.report();
+        let top = &report.patterns[0];
+        assert_eq!((top.queries, top.pages, top.items), (1, 3, 15));
+        let index = Suggestion::SecondaryIndex { base: "orders".into(), field: "customer".into() };
+        assert_eq!(report.suggestions[0].0, index);
+    }
+}
@@ -0,0 +1,157 @@
+//! Retention of timestamped backup files on a drive.
+
+use std::collections::{ BTreeMap, HashSet };
+
+use chrono::{ Datelike, NaiveDate, NaiveDateTime };
+
+use crate::{ drive::Drive, errors::DetaError };
+
+/// Timestamp formats recognized in backup file names, tried in order.
+const DATE_TIMES: [&str; 5] = [
+    "%Y-%m-%dT%H:%M:%S",
+    "%Y-%m-%dT%H-%M-%S",
+    "%Y-%m-%d_%H-%M-%S",
+    "%Y%m%dT%H%M%S",
+    "%Y%m%d%H%M%S",
+];
+const DATES: [&str; 2] = ["%Y-%m-%d", "%Y%m%d"];
+
+/// The first timestamp found in `name`.
+fn timestamp(name: &str) -> Option<NaiveDateTime> {
+    let starts = name.char_indices()
+        .filter(|&(i, c)| c.is_ascii_digit() && !name[..i].ends_with(|c: char| c.is_ascii_digit()));
+    for (i, _) in starts {
+        let rest = &name[i..];
+        let date_time = DATE_TIMES.iter()
+            .find_map(|format| Some(NaiveDateTime::parse_and_remainder(rest, format).ok()?.0))
+            .or_else(|| DATES.iter().find_map(|format| {
+                NaiveDate::parse_and_remainder(rest, format).ok()?.0.and_hms_opt(0, 0, 0)
+            }));
+        if date_time.is_some() {
+            return date_time;
+        }
+    }
+    None
+}
+
+/// The backups to keep: the newest of each of the `keep_daily` most recent days with a backup,
+/// and the newest of each of the `keep_weekly` most recent ISO weeks with a backup.
+fn retained(timestamps: &[NaiveDateTime], keep_daily: usize, keep_weekly: usize) -> HashSet<NaiveDateTime> {
+    let mut newest_first = timestamps.to_vec();
+    newest_first.sort_by(|a, b| b.cmp(a));
+    let (mut days, mut weeks, mut kept) = (HashSet::new(), HashSet::new(), HashSet::new());
+    for at in newest_first {
+        if days.len() < keep_daily && days.insert(at.date()) {
+            kept.insert(at);
+        }
+        let week = at.iso_week();
+        if weeks.len() < keep_weekly && weeks.insert((week.year(), week.week())) {
+            kept.insert(at);
+        }
+    }
+    kept
+}
+
+/// The outcome of `Backups::rotate`, as file names.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Rotation {
+    pub kept: Vec<String>,
+    pub deleted: Vec<String>,
+    /// Files without a timestamp in their name, left untouched.
+    pub ignored: Vec<String>,
+}
+
+/// Retention policies for backups stored on a drive.
+pub struct Backups;
+
+impl Backups {
+
+    /// Deletes the backups under `prefix` that fall outside the retention policy.
+    ///
+    /// A backup is every file whose name holds the same timestamp, so the parts and manifest of
+    /// an export made with `Base::export_sharded` under e.g. `users/2024-01-31T02-00-00/` are
+    /// kept or deleted together. Timestamps are read from the first match in the name of
+    /// `2024-01-31T02:00:00`, `2024-01-31T02-00-00`, `2024-01-31_02-00-00`, `20240131T020000`,
+    /// `20240131020000`, `2024-01-31` or `20240131`.
+    ///
+    /// The newest backup of each of the `keep_daily` most recent days and of each of the
+    /// `keep_weekly` most recent ISO weeks is kept, the rest is deleted. Nothing is deleted
+    /// if the files cannot be listed.
+    /// ```rust,no_run
+    /// use detalib::{ Deta, backups::Backups };
+    ///
+    /// let deta = Deta::new();
+    /// let backups = deta.drive("backups");
+    /// let prefix = format!("users/{}/", chrono::Utc::now().format("%Y-%m-%dT%H-%M-%S"));
+    /// deta.base("users").export_sharded(&backups, &prefix, 5 << 20).unwrap();
+    /// let rotation = Backups::rotate(&backups, "users/", 7, 4).unwrap();
+    /// println!("deleted {} files", rotation.deleted.len());
+    /// ```
+    pub fn rotate(
+        drive: &Drive, prefix: &str, keep_daily: usize, keep_weekly: usize
+    ) -> Result<Rotation, DetaError> {
+        let names = drive.walk(Some(prefix))?;
+        let mut rotation = Rotation::default();
+        let mut backups = BTreeMap::<NaiveDateTime, Vec<String>>::new();
+        for name in names {
+            match timestamp(&name[prefix.len().min(name.len())..]) {
+                Some(at) => backups.entry(at).or_default().push(name),
+                None => rotation.ignored.push(name),
+            }
+        }
+        let kept = retained(&backups.keys().copied().collect::<Vec<_>>(), keep_daily, keep_weekly);
+        for (at, files) in backups {
+            match kept.contains(&at) {
+                true => rotation.kept.extend(files),
+                false => rotation.deleted.extend(files),
+            }
+        }
+        for chunk in rotation.deleted.chunks(1000) {
+            drive.delete(chunk.iter().map(String::as_str).collect())?;
+        }
+        Ok(rotation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "mock")]
+    use crate::mock::MockDeta;
+
+    fn at(s: &str) -> NaiveDateTime {
+        timestamp(s).unwrap()
+    }
+
+    #[test]
+    fn timestamps_in_names() {
+        assert_eq!(at("db-2024-01-31T10:11:12Z.jsonl"), at("20240131T101112.gz"));
+        assert_eq!(at("v2/2024-01-31/part-0001.jsonl.gz"), at("20240131.jsonl"));
+        assert_eq!(at("2024-01-31_10-11-12.tar"), at("2024-01-31T10-11-12"));
+        assert_eq!(timestamp("backup-v2.jsonl"), None);
+    }
+
+    #[test]
+    fn newest_per_day_and_week() {
+        let timestamps = ["2024-01-31T12-00-00", "2024-01-31T00-00-00", "2024-01-30", "2024-01-29",
+            "2024-01-24", "2024-01-17", "2024-01-10"].map(at);
+        let kept = retained(&timestamps, 2, 3);
+        let mut kept = kept.into_iter().collect::<Vec<_>>();
+        kept.sort();
+        assert_eq!(kept, ["2024-01-17", "2024-01-24", "2024-01-30", "2024-01-31T12-00-00"].map(at));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn backups_are_rotated() {
+        let drive = MockDeta::new().drive("backups");
+        let names = ["db/2024-01-31T02-00-00/manifest.json", "db/2024-01-31T02-00-00/part-0001.jsonl.gz",
+            "db/2024-01-30T02-00-00.jsonl", "db/2024-01-24T02-00-00.jsonl", "db/2024-01-23T02-00-00.jsonl",
+            "db/notes.txt", "other/2020-01-01.jsonl"];
+        names.iter().for_each(|name| { drive.put(name, b"{}", None).unwrap(); });
+        let rotation = Backups::rotate(&drive, "db/", 1, 2).unwrap();
+        assert_eq!(rotation.deleted, ["db/2024-01-23T02-00-00.jsonl", "db/2024-01-30T02-00-00.jsonl"]);
+        assert_eq!((rotation.kept.len(), rotation.ignored.clone()), (3, vec![String::from("db/notes.txt")]));
+        assert_eq!(drive.walk(None).unwrap().len(), 5);
+    }
+}
@@ -1,16 +1,270 @@
-use crate::{ errors::DetaError, query::Query, updater::Updater };
+use std::sync::Arc;
+#[cfg(feature = "blocking")]
+use std::{ collections::HashMap, time::Duration };
 
-use serde::{ Serialize, de::DeserializeOwned };
-use serde_json::{ Value, Map, json };
+use crate::{
+    dates::DateFormat,
+    errors::DetaError,
+    keys::KeyStrategy,
+    query::Query,
+    updater::Updater,
+};
+#[cfg(feature = "blocking")]
+use crate::{
+    batch::Batch,
+    cache::CachedBase,
+    checksum,
+    errors::ErrorDetails,
+    expiring,
+    guard::{ self, Guards },
+    keys::Key,
+    kv::KvCache,
+    mailbox::Mailbox,
+    merge::{ deep_merge, ArrayStrategy },
+    normalize::Normalizer,
+    parse,
+    response,
+    tail::Tail,
+};
+
+use serde::{ Deserialize, Deserializer, Serialize, de::DeserializeOwned };
+use serde_json::{ Value, Map };
+#[cfg(feature = "blocking")]
+use serde_json::json;
+
+/// Proof that the caller really means to delete every record of a base.
+/// 
+/// The token must name the base it is used on, so a clear can not hit the wrong base by accident.
+#[cfg(feature = "blocking")]
+pub struct ClearConfirmation {
+    base: String,
+}
+
+#[cfg(feature = "blocking")]
+impl ClearConfirmation {
+
+    /// Confirm clearing the base with the given name.
+    pub fn new(base: &str) -> ClearConfirmation {
+        ClearConfirmation { base: base.to_string() }
+    }
+}
+
+/// Options for writing records.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PutOptions {
+    /// Seconds from now after which the records expire.
+    pub expires_in: Option<u64>,
+    /// Unix timestamp (seconds) at which the records expire. Takes precedence over `expires_in`.
+    pub expires_at: Option<i64>,
+    /// Stamp the records with a `__checksum`, verified by `Base::get_verified`.
+    /// 
+    /// Records must have a key, or the base must generate one with `Base::with_generated_keys`.
+    pub checksum: bool,
+}
+
+/// Keys of the records written by `Base::put_many`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PutResult {
+    pub processed: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+/// How often each JSON type occurred for a field, reported by `Base::field_types`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FieldTypes {
+    /// Records inspected.
+    pub sampled: usize,
+    pub string: usize,
+    pub number: usize,
+    pub bool: usize,
+    pub array: usize,
+    pub object: usize,
+    /// Records where the field is `null`.
+    pub null: usize,
+    /// Records without the field.
+    pub missing: usize,
+}
+
+impl FieldTypes {
+
+    #[cfg(feature = "blocking")]
+    fn record(&mut self, value: Option<&Value>) {
+        self.sampled += 1;
+        match value {
+            None => self.missing += 1,
+            Some(Value::Null) => self.null += 1,
+            Some(Value::Bool(_)) => self.bool += 1,
+            Some(Value::Number(_)) => self.number += 1,
+            Some(Value::String(_)) => self.string += 1,
+            Some(Value::Array(_)) => self.array += 1,
+            Some(Value::Object(_)) => self.object += 1,
+        }
+    }
+
+    /// Whether every present, non-null value has the same type.
+    pub fn is_consistent(&self) -> bool {
+        [self.string, self.number, self.bool, self.array, self.object].iter().filter(|&&n| n > 0).count() <= 1
+    }
+}
+
+impl PutResult {
+
+    #[cfg(feature = "blocking")]
+    pub (crate) fn extend(&mut self, other: PutResult) {
+        self.processed.extend(other.processed);
+        self.failed.extend(other.failed);
+    }
+}
+
+fn items<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Value>, D::Error> {
+    #[derive(Deserialize)]
+    struct Items {
+        #[serde(default)]
+        items: Vec<Value>,
+    }
+    Option::<Items>::deserialize(deserializer).map(|items| items.map(|i| i.items).unwrap_or_default())
+}
+
+/// The response to `Base::put`.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct PutResponse {
+    /// The records that were stored.
+    #[serde(default, deserialize_with = "items")]
+    pub processed: Vec<Value>,
+    /// The records that were rejected.
+    #[serde(default, deserialize_with = "items")]
+    pub failed: Vec<Value>,
+}
+
+/// The response to `Base::insert`, the stored record.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct InsertResponse {
+    pub key: String,
+    /// Every field of the record except the key.
+    #[serde(flatten)]
+    pub fields: Map<String, Value>,
+}
+
+/// The path taken by `Base::upsert`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Upsert {
+    /// No record had the key, so it was inserted.
+    Inserted(InsertResponse),
+    /// A record with the key existed and was replaced.
+    Replaced(PutResponse),
+}
+
+/// The response to `Updater::commit`, echoing the applied operations.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct UpdateResponse {
+    pub key: String,
+    #[serde(default)]
+    pub set: Map<String, Value>,
+    #[serde(default)]
+    pub increment: Map<String, Value>,
+    #[serde(default)]
+    pub append: Map<String, Value>,
+    #[serde(default)]
+    pub prepend: Map<String, Value>,
+    #[serde(default)]
+    pub delete: Vec<String>,
+}
+
+/// The response to `Base::delete`. Deta does not tell whether the record existed.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct DeleteResponse {
+    pub key: String,
+}
+
+pub (crate) fn typed<T: DeserializeOwned>(value: Value) -> Result<T, DetaError> {
+    serde_json::from_value(value).map_err(DetaError::from)
+}
 
 /// Represents a Deta Base.
 #[derive(Clone)]
 pub struct Base {
-    pub name: String,
+    pub(crate) name: Arc<str>,
     pub(crate) service: crate::Deta,
+    pub(crate) dates: DateFormat,
+    pub(crate) keys: Option<KeyStrategy>,
 }
 
 
+impl Base {
+
+    /// The name of the base, which replaces the public `name` field of 0.1.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sets how dates are stored in this base, used by date filters like `Query::between_dates`.
+    pub fn with_date_format(mut self, format: DateFormat) -> Base {
+        self.dates = format;
+        self
+    }
+
+    /// How dates are stored in this base, `DateFormat::EpochSeconds` unless set otherwise.
+    pub fn date_format(&self) -> DateFormat {
+        self.dates
+    }
+
+    /// Generates keys client-side for records put or inserted without one,
+    /// instead of leaving them to Deta's random keys.
+    /// ```rust,no_run
+    /// use detalib::{ Deta, keys::KeyStrategy };
+    /// use serde_json::json;
+    ///
+    /// let events = Deta::new().base("events").with_generated_keys(KeyStrategy::Ulid);
+    /// let event = events.insert(json!({ "kind": "signup" })).unwrap();
+    /// println!("{}", event.key);
+    /// ```
+    pub fn with_generated_keys(mut self, strategy: KeyStrategy) -> Base {
+        self.keys = Some(strategy);
+        self
+    }
+
+    /// Serializes a record for a write, filling in a missing key if keys are generated.
+    pub (crate) fn record<T: Serialize>(&self, record: T) -> Result<Value, DetaError> {
+        let mut record = serde_json::to_value(record)?;
+        if let Some(keys) = &self.keys {
+            keys.fill(&mut record);
+        }
+        Ok(record)
+    }
+
+    pub (crate) fn url(&self, path: &str) -> String {
+        format!("https://database.deta.sh/v1/{}/{}{}", self.service.inner.project_id, self.name, path)
+    }
+
+    /// Update a record by key in the base.
+    pub fn update(&self, key: &str) -> Updater {
+        Updater::new(self.clone(), key)
+    }
+
+    /// Create a set-only updater from a partial serializable record.
+    /// 
+    /// Every top-level field of `partial` becomes a `set` operation, so `None` fields
+    /// are set to `null` unless skipped with `#[serde(skip_serializing_if = "Option::is_none")]`.
+    pub fn patch<T: Serialize>(&self, key: &str, partial: T) -> Result<Updater, DetaError> {
+        let value = serde_json::to_value(partial)?;
+        let fields = match value {
+            Value::Object(map) => map,
+            _ => return Err(
+                DetaError::PayloadError {
+                    msg: "patch requires a value that serializes to an object".to_string()
+                }
+            ),
+        };
+        Ok(fields.into_iter().fold(self.update(key), |u, (field, value)| u.set(&field, value)))
+    }
+
+    /// Create a new query for this base.
+    pub fn query(&self) -> Query {
+        Query::new(self.clone())
+    }
+}
+
+#[cfg(feature = "blocking")]
 impl Base {
 
     pub (crate) fn request(
@@ -19,18 +273,10 @@ impl Base {
         path: &str,
         body: Option<Value>
     ) -> Result<Value, DetaError> {
-        let req = ureq::request(method, &format!(
-            "https://database.deta.sh/v1/{}/{}{}", self.service.project_id, self.name, path))
-            .set("X-API-Key", &self.service.project_key);
-        let resp = match body {
-            Some(body) => req.send_json(body),
-            None => req.call()
-        };
-        
-        resp.map_err(DetaError::from)
-            .and_then(
-                |res| serde_json::from_reader(res.into_reader()).map_err(DetaError::from)
-            )
+        let body = body.map(|body| serde_json::to_vec(&body)).transpose()?;
+        let content_type = body.as_ref().map(|_| "application/json");
+        self.service.send(method, &self.url(path), body.as_deref(), content_type)
+            .and_then(response::parse)
     }
 
     /// fetch a record by key from the base. 
@@ -38,6 +284,27 @@ impl Base {
         self.request("GET", &format!("/items/{}", key), None)
     }
 
+    /// Fetch a record by key, returning `None` if there is no record with that key.
+    pub fn get_opt(&self, key: &str) -> Result<Option<Value>, DetaError> {
+        match self.get(key) {
+            Ok(record) => Ok(Some(record)),
+            Err(DetaError::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Same as `get_opt`, deserializing the record to a struct.
+    pub fn get_opt_as<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, DetaError> {
+        self.get_opt(key)?.map(typed).transpose()
+    }
+
+    /// Fetch a record by key and verify its `__checksum`, see `PutOptions::checksum`.
+    pub fn get_verified(&self, key: &str) -> Result<Value, DetaError> {
+        let record = self.get(key)?;
+        checksum::verify(&record)?;
+        Ok(record)
+    }
+
     /// Fetch a record by key from the base and deserialize it to a struct.
     pub fn get_as<T: DeserializeOwned>(&self, key: &str) -> Result<T, DetaError> {
         self.get(key).and_then(|v| serde_json::from_value::<T>(v).map_err(DetaError::from))
@@ -48,7 +315,13 @@ impl Base {
     /// Maximum 25 records can be put at a time.
     /// 
     /// Overwrites existing records with the same key.
-    pub fn put<T: Serialize>(&self, records: Vec<T>) -> Result<Value, DetaError> {
+    /// Records rejected by Deta are reported in `PutResponse::failed` instead of failing the call.
+    pub fn put<T: Serialize>(&self, records: Vec<T>) -> Result<PutResponse, DetaError> {
+        self.put_raw(records).and_then(typed)
+    }
+
+    /// Same as `put`, returning the raw response body.
+    pub fn put_raw<T: Serialize>(&self, records: Vec<T>) -> Result<Value, DetaError> {
         if records.len() > 25 {
             return Err(
                 DetaError::PayloadError {
@@ -56,31 +329,536 @@ impl Base {
                 }
             );
         }
+        let records = records.into_iter().map(|record| self.record(record)).collect::<Result<Vec<_>, _>>()?;
         let mut payload = Map::new();
-        payload.insert(String::from("items"), json!(&records));
-        self.request("PUT", "/items", Some(json!(payload)))
+        payload.insert(String::from("items"), Value::Array(records));
+        match self.request("PUT", "/items", Some(json!(payload))) {
+            Err(DetaError::PartialFailure { body }) => Ok(body),
+            result => result,
+        }
+    }
+
+    /// Put any number of serializable records into the base, 25 per request.
+    /// 
+    /// Records rejected by Deta are reported in `PutResult::failed` instead of failing the call.
+    /// If a request fails after earlier ones stored records, the call fails with
+    /// `DetaError::PartialPut`, whose result lists the stored keys and, as failed, the keys
+    /// of the failed request and of the records after it. Records without a key are not listed.
+    pub fn put_many<T: Serialize>(&self, records: &[T]) -> Result<PutResult, DetaError> {
+        let records = records.iter().map(|record| self.record(record)).collect::<Result<Vec<_>, _>>()?;
+        let mut result = PutResult::default();
+        let keys = |items: &[Value]| items.iter()
+            .filter_map(|item| item["key"].as_str().map(String::from))
+            .collect::<Vec<String>>();
+        for (index, chunk) in records.chunks(25).enumerate() {
+            let response = self.put_raw(chunk.to_vec()).and_then(|body| parse::put_response(&body));
+            let (processed, failed) = match response {
+                Ok(response) => response,
+                Err(e) if index == 0 => return Err(e),
+                Err(e) => {
+                    result.failed.extend(keys(&records[index * 25..]));
+                    return Err(DetaError::PartialPut { result, source: Box::new(e) });
+                },
+            };
+            result.processed.extend(keys(&processed));
+            result.failed.extend(keys(&failed));
+        }
+        Ok(result)
+    }
+
+    /// Put multiple serializable records into the base with the given options.
+    /// 
+    /// Maximum 25 records can be put at a time.
+    pub fn put_with_options<T: Serialize>(
+        &self, records: Vec<T>, options: PutOptions
+    ) -> Result<PutResponse, DetaError> {
+        let expires_at = expiring::expires_at(options.expires_in, options.expires_at);
+        let records = records.iter()
+            .map(|record| {
+                let mut value = self.record(record)?;
+                expiring::stamp(&mut value, expires_at);
+                if options.checksum {
+                    // the key is part of the checksum, so it cannot be left to Deta
+                    if value["key"].as_str().is_none_or(str::is_empty) {
+                        return Err(DetaError::PayloadError {
+                            msg: "checksummed records need a key, see `Base::with_generated_keys`".to_string()
+                        });
+                    }
+                    checksum::stamp(&mut value);
+                }
+                Ok(value)
+            })
+            .collect::<Result<Vec<Value>, DetaError>>()?;
+        self.put(records)
+    }
+
+    /// Put a record under a key derived from some of its fields, see `Key::from_fields`.
+    /// 
+    /// Putting the same entity twice overwrites the first record instead of creating a duplicate.
+    pub fn put_by_natural_key<T: Serialize>(
+        &self, record: T, fields: &[&str]
+    ) -> Result<PutResponse, DetaError> {
+        let mut value = serde_json::to_value(record)?;
+        let Value::Object(map) = &mut value else {
+            return Err(DetaError::PayloadError { msg: "record must serialize to an object".to_string() });
+        };
+        let natural = fields.iter()
+            .map(|field| match map.get(*field) {
+                Some(Value::String(s)) => Ok(s.clone()),
+                Some(Value::Null) | None => Err(
+                    DetaError::PayloadError { msg: format!("natural key field `{}` is missing", field) }
+                ),
+                Some(other) => Ok(other.to_string()),
+            })
+            .collect::<Result<Vec<String>, DetaError>>()?;
+        let natural = natural.iter().map(String::as_str).collect::<Vec<&str>>();
+        map.insert(String::from("key"), Value::from(Key::from_fields(&natural)));
+        self.put(vec![value])
     }
 
     /// Insert a serializable record into the base.
-    pub fn insert<T: Serialize>(&self, record: T) -> Result<Value, DetaError> {
+    pub fn insert<T: Serialize>(&self, record: T) -> Result<InsertResponse, DetaError> {
+        self.insert_raw(record).and_then(typed)
+    }
+
+    /// Same as `insert`, returning the raw response body.
+    pub fn insert_raw<T: Serialize>(&self, record: T) -> Result<Value, DetaError> {
         let mut payload = Map::new();
-        payload.insert(String::from("item"), json!(&record));
+        payload.insert(String::from("item"), self.record(record)?);
         self.request("POST", "/items", Some(json!(payload)))
     }
 
+    /// Insert a record, replacing the existing one if its key is already taken.
+    /// 
+    /// Unlike `put`, the result tells whether the record was new.
+    pub fn upsert<T: Serialize>(&self, record: T) -> Result<Upsert, DetaError> {
+        let record = serde_json::to_value(record)?;
+        match self.insert(&record) {
+            Ok(inserted) => Ok(Upsert::Inserted(inserted)),
+            Err(DetaError::Conflict { .. }) => self.put(vec![record]).map(Upsert::Replaced),
+            Err(e) => Err(e),
+        }
+    }
+
     /// Delete a record by key from the base.
-    pub fn delete(&self, key: &str) -> Result<Value, DetaError> {
+    pub fn delete(&self, key: &str) -> Result<DeleteResponse, DetaError> {
+        self.delete_raw(key).and_then(typed)
+    }
+
+    /// Same as `delete`, returning the raw response body.
+    pub fn delete_raw(&self, key: &str) -> Result<Value, DetaError> {
         self.request("DELETE", &format!("/items/{}", key), None)
     }
 
-    /// Update a record by key in the base.
-    pub fn update(&self, key: &str) -> Updater {
-        Updater::new(self.clone(), key)
+    /// Fetch multiple records by key, one request per key.
+    /// 
+    /// Returns the result for every key, missing records are `Err(DetaError::NotFound)`.
+    pub fn get_many(&self, keys: &[&str]) -> HashMap<String, Result<Value, DetaError>> {
+        keys.iter().map(|key| (key.to_string(), self.get(key))).collect()
     }
 
-    /// Create a new query for this base.
-    pub fn query(&self) -> Query {
-        Query::new(self.clone())
+    /// Delete multiple records by key, one request per key.
+    /// 
+    /// Returns the result for every key.
+    pub fn delete_many(&self, keys: &[&str]) -> HashMap<String, Result<DeleteResponse, DetaError>> {
+        keys.iter().map(|key| (key.to_string(), self.delete(key))).collect()
+    }
+
+    pub (crate) fn delete_concurrently(&self, keys: &[String], concurrency: usize) -> Result<(), DetaError> {
+        let size = keys.len().div_ceil(concurrency.max(1)).max(1);
+        std::thread::scope(|scope| {
+            let handles = keys.chunks(size)
+                .map(|chunk| scope.spawn(move || {
+                    chunk.iter().try_for_each(|key| self.delete(key).map(|_| ()))
+                }))
+                .collect::<Vec<_>>();
+            handles.into_iter().try_for_each(|handle| {
+                handle.join().unwrap_or(Err(DetaError::TransportError))
+            })
+        })
+    }
+
+    /// Delete every record in the base, using at most `concurrency` parallel requests.
+    /// 
+    /// Returns the number of deleted records.
+    /// Fails without deleting anything if a page of records cannot be listed.
+    pub fn clear(&self, confirm: ClearConfirmation, concurrency: usize) -> Result<usize, DetaError> {
+        if confirm.base != *self.name {
+            return Err(DetaError::PayloadError {
+                msg: format!("clear confirmation is for `{}`, not `{}`", confirm.base, self.name)
+            });
+        }
+        let keys = self.query().walk()?
+            .iter()
+            .filter_map(|item| item["key"].as_str().map(String::from))
+            .collect::<Vec<String>>();
+        self.delete_concurrently(&keys, concurrency)?;
+        Ok(keys.len())
+    }
+
+    /// Read-modify-write a record, retrying on concurrent modification.
+    /// 
+    /// The record is fetched and passed to `f`, which must return an object. The result is put
+    /// back with its `__revision` field incremented, but only if the stored revision is still
+    /// the one `f` was given. Otherwise the whole cycle is retried up to `max_retries` times
+    /// before failing with `DetaError::Conflict`.
+    /// 
+    /// Deta has no conditional writes, so the revision is compared against a read just before
+    /// the put and a write landing in between is not detected. Only writes made through `modify`
+    /// change the revision.
+    pub fn modify<F: FnMut(Value) -> Value>(
+        &self, key: &str, max_retries: u32, mut f: F
+    ) -> Result<Value, DetaError> {
+        let mut conflict = None;
+        for _ in 0..=max_retries {
+            let current = self.get(key)?;
+            let revision = guard::revision(&current);
+            let Value::Object(mut next) = f(current) else {
+                return Err(DetaError::PayloadError { msg: "modified record must be an object".to_string() });
+            };
+            next.insert(String::from("key"), Value::from(key));
+            next.insert(String::from(guard::REVISION), Value::from(revision + 1));
+            let next = Value::Object(next);
+            let guards = Guards { revision: Some(revision), ..Guards::default() };
+            match guards.commit(self, key, |_| self.put(vec![&next])) {
+                Ok(_) => return Ok(next),
+                Err(e @ DetaError::Conflict { .. }) => conflict = Some(e),
+                Err(e) => return Err(e),
+            }
+        }
+        Err(conflict.unwrap_or(DetaError::Conflict { details: ErrorDetails::default() }))
+    }
+
+    /// Deep-merge a partial record over the stored one and put the result.
+    /// 
+    /// Unlike `put`, fields missing from `partial` are preserved. A missing record is created.
+    pub fn merge_put<T: Serialize>(&self, key: &str, partial: T) -> Result<Value, DetaError> {
+        self.merge_put_guarded(key, partial, Guards::default())
+    }
+
+    /// Like `merge_put`, but only if `field` of the stored record equals `expected`,
+    /// e.g. a revision number. Fails with `DetaError::PreconditionFailed` otherwise.
+    /// 
+    /// Nested fields can be addressed with dots, e.g. `meta.revision`.
+    /// Like `Updater::only_if`, the guard is best-effort.
+    pub fn merge_put_if<T: Serialize>(
+        &self, key: &str, partial: T, field: &str, expected: Value
+    ) -> Result<Value, DetaError> {
+        let guards = Guards { fields: vec![(field.to_string(), expected)], ..Guards::default() };
+        self.merge_put_guarded(key, partial, guards)
+    }
+
+    fn merge_put_guarded<T: Serialize>(
+        &self, key: &str, partial: T, guards: Guards
+    ) -> Result<Value, DetaError> {
+        let partial = serde_json::to_value(partial)?;
+        if !partial.is_object() {
+            return Err(
+                DetaError::PayloadError { msg: "partial record must serialize to an object".to_string() }
+            );
+        }
+        guards.commit(self, key, |mut record| {
+            deep_merge(&mut record, partial, &ArrayStrategy::Replace);
+            record["key"] = Value::from(key);
+            self.put(vec![&record])?;
+            Ok(record)
+        })
+    }
+
+    /// Prepare a batch fix-up of the records matching `query`, see `Normalizer`.
+    pub fn normalize<F>(&self, query: Query, fix: F) -> Normalizer
+        where F: FnMut(&Value) -> Option<Value> + 'static
+    {
+        Normalizer::new(self.clone(), query, fix)
+    }
+
+    /// Count the JSON types found for a field in up to `sample` records.
+    /// 
+    /// Nested fields are addressed with dots, e.g. `profile.age`.
+    /// Mixed types are a common reason for `get_as` to fail on some records only.
+    pub fn field_types(&self, field: &str, sample: usize) -> Result<FieldTypes, DetaError> {
+        let mut types = FieldTypes::default();
+        for item in self.query().iter().take(sample) {
+            let item = item?;
+            types.record(field.split('.').try_fold(&item, |value, part| value.get(part)));
+        }
+        Ok(types)
+    }
+
+    /// Cache records read with `get` for `ttl`, see `CachedBase`.
+    pub fn cached(&self, ttl: Duration) -> CachedBase {
+        CachedBase::new(self.clone(), ttl)
+    }
+
+    /// Use this base as a key-value cache with Redis-like commands, see `KvCache`.
+    pub fn kv(&self) -> KvCache {
+        KvCache::new(self.clone())
+    }
+
+    /// Start a group of writes that is rolled back if one of them fails, see `Batch`.
+    pub fn batch(&self) -> Batch {
+        Batch::new(self.clone())
+    }
+
+    /// Use this base to store per-actor message queues, see `Mailbox`.
+    pub fn mailbox(&self) -> Mailbox {
+        Mailbox::new(self.clone())
+    }
+
+    /// Follow records inserted after the newest one, polling at the given interval.
+    /// 
+    /// Keys must sort in insertion order for new records to be detected.
+    pub fn tail(&self, poll_interval: Duration) -> Tail {
+        Tail::new(self.clone(), poll_interval)
     }
     
 }
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::Deta;
+    #[cfg(feature = "blocking")]
+    use std::sync::{ Arc, atomic::{ AtomicU64, Ordering } };
+
+    #[cfg(feature = "blocking")]
+    use crate::{ errors::DetaError, transport::{ Request, Transport } };
+    #[cfg(feature = "mock")]
+    use crate::{ base::{ PutOptions, Upsert }, keys::KeyStrategy, mock::MockDeta };
+
+    /// Accepts the first record of every put and rejects the rest with a `207`.
+    #[cfg(feature = "blocking")]
+    struct Partial;
+
+    #[cfg(feature = "blocking")]
+    impl Transport for Partial {
+        fn send(&self, request: &Request) -> Result<ureq::Response, ureq::Error> {
+            let payload = serde_json::from_slice::<serde_json::Value>(request.body.unwrap_or_default())
+                .unwrap_or_default();
+            let items = payload["items"].as_array().cloned().unwrap_or_default();
+            let (processed, failed) = items.split_at(items.len().min(1));
+            let body = json!({ "processed": { "items": processed }, "failed": { "items": failed } });
+            ureq::Response::new(207, "Multi-Status", &body.to_string())
+        }
+    }
+
+    #[derive(serde::Serialize)]
+    struct Profile {
+        name: &'static str,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        age: Option<u8>,
+    }
+
+    /// Moves the revision of the record on every read, as if another writer kept changing it.
+    #[cfg(feature = "blocking")]
+    #[derive(Default)]
+    struct Racing {
+        reads: AtomicU64,
+        writes: AtomicU64,
+    }
+
+    #[cfg(feature = "blocking")]
+    impl Transport for Arc<Racing> {
+        fn send(&self, request: &Request) -> Result<ureq::Response, ureq::Error> {
+            if request.method != "GET" {
+                self.writes.fetch_add(1, Ordering::SeqCst);
+                return ureq::Response::new(200, "OK", "{}");
+            }
+            let revision = self.reads.fetch_add(1, Ordering::SeqCst);
+            ureq::Response::new(200, "OK", &json!({ "key": "k", "__revision": revision }).to_string())
+        }
+    }
+
+    /// Lists one page of records and fails on the next, counting deletes.
+    #[cfg(feature = "blocking")]
+    #[derive(Default)]
+    struct FailingSecondPage {
+        deletes: AtomicU64,
+    }
+
+    #[cfg(feature = "blocking")]
+    impl Transport for Arc<FailingSecondPage> {
+        fn send(&self, request: &Request) -> Result<ureq::Response, ureq::Error> {
+            if request.method == "DELETE" {
+                self.deletes.fetch_add(1, Ordering::SeqCst);
+                return ureq::Response::new(200, "OK", "{}");
+            }
+            let payload = serde_json::from_slice::<serde_json::Value>(request.body.unwrap_or_default());
+            match payload {
+                Ok(payload) if payload["last"].is_null() => {
+                    ureq::Response::new(200, "OK", r#"{"items": [{"key": "a"}], "paging": {"last": "a"}}"#)
+                },
+                _ => Err(ureq::Error::Status(500, ureq::Response::new(500, "Server Error", "")?)),
+            }
+        }
+    }
+
+    /// Stores the first put and fails every later one.
+    #[cfg(feature = "blocking")]
+    #[derive(Default)]
+    struct FailingSecondPut {
+        puts: AtomicU64,
+    }
+
+    #[cfg(feature = "blocking")]
+    impl Transport for Arc<FailingSecondPut> {
+        fn send(&self, request: &Request) -> Result<ureq::Response, ureq::Error> {
+            if self.puts.fetch_add(1, Ordering::SeqCst) > 0 {
+                return Err(ureq::Error::Status(500, ureq::Response::new(500, "Server Error", "")?));
+            }
+            let payload = serde_json::from_slice::<serde_json::Value>(request.body.unwrap_or_default())
+                .unwrap_or_default();
+            let body = json!({ "processed": { "items": payload["items"] } });
+            ureq::Response::new(200, "OK", &body.to_string())
+        }
+    }
+
+    #[test]
+    fn patch_sets_every_field() {
+        let base = Deta::from("id_secret").base("hello");
+        let updater = base.patch("k", Profile { name: "John", age: None }).unwrap();
+        assert_eq!(serde_json::to_value(&updater).unwrap(), json!({ "set": { "name": "John" } }));
+        assert!(base.patch("k", json!([1, 2])).is_err());
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn modify_fails_when_the_revision_moves() {
+        let racing = Arc::new(Racing::default());
+        let deta = Deta::builder().project_key("id_secret").transport(racing.clone()).build();
+        let modified = deta.base("hello").modify("k", 2, |record| record);
+        assert!(matches!(modified, Err(DetaError::Conflict { .. })));
+        assert_eq!((racing.reads.load(Ordering::SeqCst), racing.writes.load(Ordering::SeqCst)), (6, 0));
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn clear_deletes_nothing_when_a_page_fails() {
+        let pages = Arc::new(FailingSecondPage::default());
+        let deta = Deta::builder().project_key("id_secret").transport(pages.clone()).build();
+        let cleared = deta.base("hello").clear(super::ClearConfirmation::new("hello"), 4);
+        assert!(matches!(cleared, Err(DetaError::HTTPError { status: 500, .. })));
+        assert_eq!(pages.deletes.load(Ordering::SeqCst), 0);
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn multi_status_puts_report_failed_records() {
+        let deta = Deta::builder().project_key("id_secret").transport(Partial).build();
+        let base = deta.base("hello");
+        let response = base.put(vec![json!({ "key": "a" }), json!({ "key": "b" })]).unwrap();
+        assert_eq!(response.processed, vec![json!({ "key": "a" })]);
+        assert_eq!(response.failed, vec![json!({ "key": "b" })]);
+
+        let records = (0..30).map(|i| json!({ "key": i.to_string() })).collect::<Vec<_>>();
+        let result = base.put_many(&records).unwrap();
+        assert_eq!(result.processed, vec!["0", "25"]);
+        assert_eq!(result.failed.len(), 28);
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn put_many_reports_stored_records_when_a_chunk_fails() {
+        let puts = Arc::new(FailingSecondPut::default());
+        let deta = Deta::builder().project_key("id_secret").transport(puts.clone()).build();
+        let records = (0..60).map(|i| json!({ "key": format!("{:02}", i) })).collect::<Vec<_>>();
+        let Err(DetaError::PartialPut { result, source }) = deta.base("hello").put_many(&records) else {
+            panic!("expected a partial put");
+        };
+        assert!(matches!(*source, DetaError::HTTPError { status: 500, .. }));
+        assert_eq!((result.processed.len(), result.processed[24].as_str()), (25, "24"));
+        assert_eq!((result.failed.len(), result.failed[0].as_str()), (35, "25"));
+        assert_eq!(puts.puts.load(Ordering::SeqCst), 2);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn generated_keys_fill_missing_keys() {
+        let strategy = KeyStrategy::Timestamped(Arc::from("e_"));
+        let events = MockDeta::new().base("events").with_generated_keys(strategy);
+        let inserted = events.insert(json!({ "kind": "signup" })).unwrap();
+        assert!(inserted.key.starts_with("e_"));
+        events.put(vec![json!({ "kind": "login" }), json!({ "key": "fixed" })]).unwrap();
+        let keys = events.query().walk().unwrap().into_iter().map(|e| e["key"].clone()).collect::<Vec<_>>();
+        assert_eq!(keys.len(), 3);
+        assert!(keys.contains(&json!("fixed")));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn checksummed_puts_verify_with_generated_keys() {
+        let options = PutOptions { checksum: true, ..PutOptions::default() };
+        let keyless = MockDeta::new().base("events");
+        let missing = keyless.put_with_options(vec![json!({ "kind": "signup" })], options);
+        assert!(matches!(missing, Err(DetaError::PayloadError { .. })));
+        let events = keyless.with_generated_keys(KeyStrategy::Ulid);
+        let put = events.put_with_options(vec![json!({ "kind": "signup" })], options).unwrap();
+        let key = put.processed[0]["key"].as_str().unwrap();
+        assert_eq!(events.get_verified(key).unwrap()["kind"], "signup");
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn put_many_chunks() {
+        let base = MockDeta::new().base("numbers");
+        let records = (0..60).map(|i| json!({ "key": format!("{:02}", i) })).collect::<Vec<_>>();
+        let result = base.put_many(&records).unwrap();
+        assert_eq!(result.processed.len(), 60);
+        assert!(result.failed.is_empty());
+        assert_eq!(base.query().walk().unwrap().len(), 60);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn merge_put_keeps_fields() {
+        let base = MockDeta::new().base("users");
+        base.merge_put("u", json!({ "name": "John", "meta": { "rev": 1 } })).unwrap();
+        base.merge_put_if("u", json!({ "age": 20, "meta": { "rev": 2 } }), "meta.rev", json!(1)).unwrap();
+        let stale = base.merge_put_if("u", json!({ "age": 30 }), "meta.rev", json!(1));
+        assert!(matches!(stale, Err(DetaError::PreconditionFailed { .. })));
+        let merged = json!({ "key": "u", "name": "John", "age": 20, "meta": { "rev": 2 } });
+        assert_eq!(base.get("u").unwrap(), merged);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn field_types_counts_each_type() {
+        let base = MockDeta::new().base("users");
+        let users = vec![
+            json!({ "key": "a", "profile": { "age": 20 } }),
+            json!({ "key": "b", "profile": { "age": "20" } }),
+            json!({ "key": "c", "profile": { "age": null } }),
+            json!({ "key": "d" }),
+        ];
+        base.put(users).unwrap();
+        let types = base.field_types("profile.age", 10).unwrap();
+        assert_eq!((types.sampled, types.number, types.string, types.null, types.missing), (4, 1, 1, 1, 1));
+        assert!(!types.is_consistent());
+        assert_eq!(base.field_types("profile.age", 1).unwrap().sampled, 1);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn modify_bumps_revision() {
+        let base = MockDeta::new().base("counters");
+        base.put(vec![json!({ "key": "c", "n": 1 })]).unwrap();
+        let increment = |mut record: serde_json::Value| {
+            record["n"] = json!(record["n"].as_i64().unwrap() + 1);
+            record
+        };
+        assert_eq!(base.modify("c", 0, increment).unwrap()["__revision"], 1);
+        base.modify("c", 0, increment).unwrap();
+        assert_eq!(base.get("c").unwrap(), json!({ "key": "c", "n": 3, "__revision": 2 }));
+        assert!(matches!(base.modify("c", 0, |_| json!([1])), Err(DetaError::PayloadError { .. })));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn upsert_reports_path() {
+        let base = MockDeta::new().base("users");
+        assert!(matches!(base.upsert(json!({ "key": "u", "v": 1 })).unwrap(), Upsert::Inserted(_)));
+        assert!(matches!(base.upsert(json!({ "key": "u", "v": 2 })).unwrap(), Upsert::Replaced(_)));
+        assert_eq!(base.get("u").unwrap()["v"], 2);
+    }
+}
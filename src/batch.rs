@@ -0,0 +1,164 @@
+//! Best-effort atomic groups of writes, undone when one of them fails.
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{ base::Base, errors::DetaError, updater::Updater };
+
+enum Op {
+    Put(Value),
+    Delete(String),
+    Update(String, Updater),
+}
+
+/// The operation of a `Batch` that failed.
+#[derive(Debug)]
+pub struct BatchFailure {
+    /// Position of the operation in the batch.
+    pub index: usize,
+    pub key: Option<String>,
+    pub error: DetaError,
+}
+
+/// What happened when a `Batch` was executed.
+#[derive(Debug, Default)]
+pub struct BatchReport {
+    /// Operations that succeeded before the failure, or all of them.
+    pub applied: usize,
+    pub failure: Option<BatchFailure>,
+    /// Keys restored to their value from before the batch.
+    pub rolled_back: Vec<String>,
+    /// Keys that could not be restored and may be left with a value written by the batch.
+    pub rollback_failed: Vec<String>,
+}
+
+impl BatchReport {
+
+    /// Whether every operation succeeded.
+    pub fn is_committed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+/// A sequence of puts, updates and deletes on one base, applied in order.
+///
+/// Deta has no transactions, so a batch snapshots each record before changing it and,
+/// when an operation fails, restores the snapshots of the operations that succeeded, newest
+/// first. Writes by other clients in the meantime may be overwritten by the rollback,
+/// and a crash mid-batch leaves it partially applied.
+/// ```rust,no_run
+/// use detalib::Deta;
+/// use serde_json::json;
+///
+/// let accounts = Deta::new().base("accounts");
+/// let report = accounts.batch()
+///     .update("alice", |update| update.increment("balance", json!(-10)))
+///     .update("bob", |update| update.increment("balance", json!(10)))
+///     .put(json!({ "key": "transfer_1", "amount": 10 }))
+///     .execute();
+/// if let Some(failure) = report.failure {
+///     println!("operation {} failed: {}", failure.index, failure.error);
+/// }
+/// ```
+pub struct Batch {
+    base: Base,
+    ops: Vec<Result<Op, DetaError>>,
+}
+
+impl Batch {
+
+    pub (crate) fn new(base: Base) -> Batch {
+        Batch { base, ops: Vec::new() }
+    }
+
+    /// Adds a put of a record, which must have a key so it can be rolled back.
+    pub fn put<T: Serialize>(mut self, record: T) -> Self {
+        let record = self.base.record(record).and_then(|record| match record["key"].is_string() {
+            true => Ok(record),
+            false => Err(DetaError::PayloadError { msg: String::from("batched records need a key") }),
+        });
+        self.ops.push(record.map(Op::Put));
+        self
+    }
+
+    pub fn delete(mut self, key: &str) -> Self {
+        self.ops.push(Ok(Op::Delete(key.to_string())));
+        self
+    }
+
+    /// Adds an update of the record with `key`, built by `build`.
+    pub fn update<F: FnOnce(Updater) -> Updater>(mut self, key: &str, build: F) -> Self {
+        self.ops.push(Ok(Op::Update(key.to_string(), build(self.base.update(key)))));
+        self
+    }
+
+    /// Applies the operations in order, rolling back the applied ones if one fails.
+    pub fn execute(self) -> BatchReport {
+        let mut report = BatchReport::default();
+        let mut snapshots = Vec::new();
+        for (index, op) in self.ops.into_iter().enumerate() {
+            let key = op.as_ref().ok().map(|op| match op {
+                Op::Put(record) => record["key"].as_str().unwrap_or_default().to_string(),
+                Op::Delete(key) | Op::Update(key, _) => key.clone(),
+            });
+            let applied = op.and_then(|op| {
+                let key = key.as_deref().unwrap_or_default();
+                let snapshot = self.base.get_opt(key)?;
+                match op {
+                    Op::Put(record) => self.base.put(vec![record]).map(drop),
+                    Op::Delete(key) => self.base.delete(&key).map(drop),
+                    Op::Update(_, updater) => updater.commit().map(drop),
+                }?;
+                snapshots.push((key.to_string(), snapshot));
+                Ok(())
+            });
+            match applied {
+                Ok(()) => report.applied += 1,
+                Err(error) => {
+                    report.failure = Some(BatchFailure { index, key, error });
+                    break;
+                },
+            }
+        }
+        if report.failure.is_some() {
+            for (key, snapshot) in snapshots.into_iter().rev() {
+                let restored = match snapshot {
+                    Some(record) => self.base.put(vec![record]).map(drop),
+                    None => self.base.delete(&key).map(drop),
+                };
+                match restored {
+                    Ok(()) => report.rolled_back.push(key),
+                    Err(_) => report.rollback_failed.push(key),
+                }
+            }
+        }
+        report
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use serde_json::json;
+
+    use crate::mock::MockDeta;
+
+    #[test]
+    fn batch_rolls_back_on_failure() {
+        let accounts = MockDeta::new().base("accounts");
+        accounts.put(vec![json!({ "key": "alice", "balance": 100 })]).unwrap();
+        let report = accounts.batch()
+            .update("alice", |update| update.increment("balance", json!(-10)))
+            .put(json!({ "key": "log", "amount": 10 }))
+            .update("bob", |update| update.increment("balance", json!(10)))
+            .execute();
+        assert!(!report.is_committed());
+        assert_eq!(report.applied, 2);
+        assert_eq!(report.failure.as_ref().map(|failure| failure.index), Some(2));
+        assert_eq!(report.rolled_back, vec!["log", "alice"]);
+        assert_eq!(accounts.get("alice").unwrap()["balance"], 100);
+        assert!(accounts.get_opt("log").unwrap().is_none());
+        let report = accounts.batch().delete("alice").put(json!({ "key": "bob", "balance": 0 })).execute();
+        assert!(report.is_committed() && report.rolled_back.is_empty());
+        assert!(accounts.batch().put(json!({ "no": "key" })).execute().failure.is_some());
+    }
+}
@@ -0,0 +1,170 @@
+//! Bloom filters of known keys, to skip lookups of keys that certainly do not exist.
+
+use std::io::Read;
+
+use sha2::{ Digest, Sha256 };
+
+use crate::{ base::Base, drive::Drive, errors::DetaError };
+
+const MAGIC: &[u8; 4] = b"DBF1";
+
+/// A probabilistic set of keys, kept in memory and saved to a drive.
+///
+/// `probably_contains` never misses a key that was inserted, and wrongly reports a key
+/// as present at about the configured false positive rate. Deletions cannot be removed
+/// from the filter, so rebuild it from time to time for bases with many deletes.
+/// ```rust,no_run
+/// use detalib::{ Deta, bloom::BloomFilter };
+///
+/// let deta = Deta::new();
+/// let (events, state) = (deta.base("events"), deta.drive("state"));
+/// let mut seen = BloomFilter::load(&state, "events.bloom")
+///     .or_else(|_| BloomFilter::from_base(&events, 0.01))
+///     .unwrap();
+/// if !seen.contains(&events, "evt_42").unwrap() {
+///     events.put(vec![serde_json::json!({ "key": "evt_42" })]).unwrap();
+///     seen.insert("evt_42");
+/// }
+/// seen.save(&state, "events.bloom").unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter {
+    bits: Vec<u8>,
+    hashes: u32,
+    items: u64,
+}
+
+impl BloomFilter {
+
+    /// An empty filter sized for `expected_items` keys at the given false positive rate.
+    pub fn new(expected_items: usize, false_positive_rate: f64) -> BloomFilter {
+        let n = expected_items.max(1) as f64;
+        let p = false_positive_rate.clamp(1e-9, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-n * p.ln() / (ln2 * ln2)).ceil().max(8.0) as usize;
+        let hashes = ((bits as f64 / n) * ln2).round().clamp(1.0, 32.0) as u32;
+        BloomFilter { bits: vec![0; bits.div_ceil(8)], hashes, items: 0 }
+    }
+
+    /// A filter of every key in `base`, sized for its current number of records.
+    pub fn from_base(base: &Base, false_positive_rate: f64) -> Result<BloomFilter, DetaError> {
+        let mut keys = Vec::new();
+        for item in base.query().select(&[]).iter() {
+            if let Some(key) = item?["key"].as_str() {
+                keys.push(key.to_string());
+            }
+        }
+        let mut filter = BloomFilter::new(keys.len(), false_positive_rate);
+        keys.iter().for_each(|key| filter.insert(key));
+        Ok(filter)
+    }
+
+    fn positions(&self, key: &str) -> impl Iterator<Item = usize> {
+        let digest = Sha256::digest(key.as_bytes());
+        let half = |range: std::ops::Range<usize>| {
+            u64::from_le_bytes(digest[range].try_into().unwrap_or_default())
+        };
+        let (h1, h2) = (half(0..8), half(8..16) | 1);
+        let bits = self.bits.len() as u64 * 8;
+        (0..u64::from(self.hashes)).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % bits) as usize)
+    }
+
+    pub fn insert(&mut self, key: &str) {
+        for position in self.positions(key).collect::<Vec<_>>() {
+            self.bits[position / 8] |= 1 << (position % 8);
+        }
+        self.items += 1;
+    }
+
+    /// `false` if `key` was never inserted, `true` if it probably was.
+    pub fn probably_contains(&self, key: &str) -> bool {
+        self.positions(key).all(|position| self.bits[position / 8] & (1 << (position % 8)) != 0)
+    }
+
+    /// Whether `base` has a record with `key`, asking Deta only if the filter cannot rule it out.
+    pub fn contains(&self, base: &Base, key: &str) -> Result<bool, DetaError> {
+        match self.probably_contains(key) {
+            true => Ok(base.get_opt(key)?.is_some()),
+            false => Ok(false),
+        }
+    }
+
+    /// Number of inserts, counting repeated keys every time.
+    pub fn len(&self) -> u64 {
+        self.items
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items == 0
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(16 + self.bits.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&self.hashes.to_le_bytes());
+        bytes.extend_from_slice(&self.items.to_le_bytes());
+        bytes.extend_from_slice(&self.bits);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<BloomFilter, DetaError> {
+        let invalid = || DetaError::PayloadError { msg: String::from("not a saved bloom filter") };
+        if bytes.len() <= 16 || &bytes[..4] != MAGIC {
+            return Err(invalid());
+        }
+        let hashes = u32::from_le_bytes(bytes[4..8].try_into().map_err(|_| invalid())?);
+        let items = u64::from_le_bytes(bytes[8..16].try_into().map_err(|_| invalid())?);
+        if hashes == 0 {
+            return Err(invalid());
+        }
+        Ok(BloomFilter { bits: bytes[16..].to_vec(), hashes, items })
+    }
+
+    /// Saves the filter as a file on `drive`.
+    pub fn save(&self, drive: &Drive, name: &str) -> Result<(), DetaError> {
+        drive.put(name, &self.to_bytes(), Some("application/octet-stream"))?;
+        Ok(())
+    }
+
+    /// Loads a filter saved with `save`.
+    pub fn load(drive: &Drive, name: &str) -> Result<BloomFilter, DetaError> {
+        let mut bytes = Vec::new();
+        drive.get(name)?.into_reader().read_to_end(&mut bytes)?;
+        BloomFilter::from_bytes(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "mock")]
+    use serde_json::json;
+    #[cfg(feature = "mock")]
+    use crate::mock::MockDeta;
+
+    #[test]
+    fn no_false_negatives() {
+        let mut filter = BloomFilter::new(1000, 0.01);
+        (0..1000).for_each(|i| filter.insert(&format!("key{}", i)));
+        assert!((0..1000).all(|i| filter.probably_contains(&format!("key{}", i))));
+        let false_positives = (0..1000).filter(|i| filter.probably_contains(&format!("other{}", i))).count();
+        assert!(false_positives < 30);
+        assert_eq!(BloomFilter::from_bytes(&filter.to_bytes()).unwrap(), filter);
+        assert!(BloomFilter::from_bytes(b"garbage").is_err());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn bloom_filter_from_base_roundtrip() {
+        let deta = MockDeta::new();
+        let (events, state) = (deta.base("events"), deta.drive("state"));
+        let records = (0..50).map(|i| json!({ "key": format!("e{}", i), "n": i })).collect::<Vec<_>>();
+        events.put_many(&records).unwrap();
+        let filter = BloomFilter::from_base(&events, 0.01).unwrap();
+        assert_eq!(filter.len(), 50);
+        filter.save(&state, "events.bloom").unwrap();
+        let filter = BloomFilter::load(&state, "events.bloom").unwrap();
+        assert!(filter.contains(&events, "e7").unwrap());
+        assert!((0..50).all(|i| filter.probably_contains(&format!("e{}", i))));
+    }
+}
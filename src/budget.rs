@@ -0,0 +1,119 @@
+use std::{ sync::Mutex, time::Duration };
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+use crate::errors::DetaError;
+
+/// A soft usage budget for a Deta client.
+/// 
+/// Once the budget is used up, requests fail with `DetaError::BudgetExceeded` instead of
+/// being sent, protecting the project limits from runaway loops. Without a window the
+/// budget applies to the lifetime of the client.
+/// ```rust
+/// use std::time::Duration;
+/// use detalib::{ Deta, budget::Budget };
+/// 
+/// let deta = Deta::builder()
+///     .budget(Budget::requests(10_000).max_bytes(100 * 1024 * 1024).per(Duration::from_secs(3600)))
+///     .build();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Budget {
+    max_requests: Option<u64>,
+    max_bytes: Option<u64>,
+    window: Option<Duration>,
+}
+
+impl Budget {
+
+    /// A budget allowing at most `max` requests.
+    pub fn requests(max: u64) -> Budget {
+        Budget::default().max_requests(max)
+    }
+
+    /// A budget allowing at most `max` uploaded bytes.
+    pub fn bytes(max: u64) -> Budget {
+        Budget::default().max_bytes(max)
+    }
+
+    /// Sets the maximum number of requests.
+    pub fn max_requests(mut self, max: u64) -> Self {
+        self.max_requests = Some(max);
+        self
+    }
+
+    /// Sets the maximum number of uploaded bytes.
+    pub fn max_bytes(mut self, max: u64) -> Self {
+        self.max_bytes = Some(max);
+        self
+    }
+
+    /// Resets the budget every `window` instead of applying it to the client lifetime.
+    pub fn per(mut self, window: Duration) -> Self {
+        self.window = Some(window);
+        self
+    }
+}
+
+struct Usage {
+    since: Instant,
+    requests: u64,
+    bytes: u64,
+}
+
+pub (crate) struct BudgetGuard {
+    budget: Budget,
+    usage: Mutex<Usage>,
+}
+
+impl BudgetGuard {
+
+    pub (crate) fn new(budget: Budget) -> BudgetGuard {
+        BudgetGuard { budget, usage: Mutex::new(Usage { since: Instant::now(), requests: 0, bytes: 0 }) }
+    }
+
+    /// Accounts for one request uploading `bytes`, failing if that exceeds the budget.
+    pub (crate) fn charge(&self, bytes: usize) -> Result<(), DetaError> {
+        let mut usage = self.usage.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(window) = self.budget.window {
+            if usage.since.elapsed() >= window {
+                *usage = Usage { since: Instant::now(), requests: 0, bytes: 0 };
+            }
+        }
+        let requests = usage.requests + 1;
+        let bytes = usage.bytes + bytes as u64;
+        if self.budget.max_requests.is_some_and(|max| requests > max) {
+            return Err(DetaError::BudgetExceeded { msg: format!("more than {} requests", requests - 1) });
+        }
+        if self.budget.max_bytes.is_some_and(|max| bytes > max) {
+            return Err(DetaError::BudgetExceeded { msg: format!("more than {} bytes", usage.bytes) });
+        }
+        usage.requests = requests;
+        usage.bytes = bytes;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_over_budget() {
+        let guard = BudgetGuard::new(Budget::requests(2).max_bytes(10));
+        assert!(guard.charge(4).is_ok());
+        assert!(matches!(guard.charge(7), Err(DetaError::BudgetExceeded { .. })));
+        assert!(guard.charge(6).is_ok());
+        assert!(matches!(guard.charge(0), Err(DetaError::BudgetExceeded { .. })));
+    }
+
+    #[test]
+    fn window_resets_usage() {
+        let guard = BudgetGuard::new(Budget::requests(1).per(Duration::ZERO));
+        assert!(guard.charge(0).is_ok());
+        assert!(guard.charge(0).is_ok());
+    }
+}
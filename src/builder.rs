@@ -0,0 +1,234 @@
+use std::{ sync::Arc, time::Duration };
+
+use crate::{
+    Deta,
+    Inner,
+    access::AccessRecorder,
+    budget::{ Budget, BudgetGuard },
+    queue::{ WriteLimiter, WriteQueue },
+    ratelimit::{ RateLimit, RateLimiter },
+    retry::RetryPolicy,
+    validate,
+};
+#[cfg(feature = "blocking")]
+use crate::{
+    transport::{ Transport, UreqTransport },
+    webhook::{ Webhook, WebhookSender },
+};
+
+/// The default `User-Agent` sent with every request.
+pub const USER_AGENT: &str = concat!("detalib-rs/", env!("CARGO_PKG_VERSION"));
+
+/// The default time allowed to establish a connection.
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// The default time allowed between reads of a response.
+pub const READ_TIMEOUT: Duration = Duration::from_secs(60);
+
+pub (crate) type CorrelationId = Arc<dyn Fn() -> String + Send + Sync>;
+
+/// Builder for a configured Deta instance.
+/// ```rust
+/// use detalib::Deta;
+/// 
+/// use std::time::Duration;
+/// 
+/// let deta = Deta::builder()
+///     .project_key("project_key")
+///     .user_agent("my-app/1.0")
+///     .read_timeout(Duration::from_secs(5))
+///     .build();
+/// ```
+#[derive(Clone)]
+pub struct DetaBuilder {
+    project_key: Option<String>,
+    user_agent: String,
+    connect_timeout: Duration,
+    read_timeout: Duration,
+    correlation_id: Option<CorrelationId>,
+    budget: Option<Budget>,
+    retry: Option<RetryPolicy>,
+    write_queue: Option<WriteQueue>,
+    rate_limit: Option<RateLimit>,
+    #[cfg(feature = "blocking")]
+    webhook: Option<Webhook>,
+    access_recorder: Option<AccessRecorder>,
+    #[cfg(feature = "blocking")]
+    transport: Option<Arc<dyn Transport>>,
+    #[cfg(any(feature = "tokio", feature = "wasm"))]
+    async_transport: Option<Arc<dyn crate::transport::AsyncTransport>>,
+    #[cfg(feature = "mock")]
+    mock: Option<Arc<crate::mock::MockStore>>,
+}
+
+impl Default for DetaBuilder {
+    fn default() -> Self {
+        DetaBuilder {
+            project_key: None,
+            user_agent: USER_AGENT.to_string(),
+            connect_timeout: CONNECT_TIMEOUT,
+            read_timeout: READ_TIMEOUT,
+            correlation_id: None,
+            budget: None,
+            retry: None,
+            write_queue: None,
+            rate_limit: None,
+            #[cfg(feature = "blocking")]
+            webhook: None,
+            access_recorder: None,
+            #[cfg(feature = "blocking")]
+            transport: None,
+            #[cfg(any(feature = "tokio", feature = "wasm"))]
+            async_transport: None,
+            #[cfg(feature = "mock")]
+            mock: None,
+        }
+    }
+}
+
+impl DetaBuilder {
+
+    /// Sets the project key. Defaults to the `DETA_PROJECT_KEY` environment variable.
+    pub fn project_key(mut self, project_key: &str) -> Self {
+        self.project_key = Some(project_key.to_string());
+        self
+    }
+
+    /// Overrides the `User-Agent` header sent with every request.
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.user_agent = user_agent.to_string();
+        self
+    }
+
+    /// Sets the time allowed to establish a connection. Defaults to `CONNECT_TIMEOUT`.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = timeout;
+        self
+    }
+
+    /// Sets the time allowed between reads of a response before it fails
+    /// with a transport error. Defaults to `READ_TIMEOUT`.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Sets a generator for the `X-Correlation-Id` header, called once per request.
+    pub fn correlation_id<F: Fn() -> String + Send + Sync + 'static>(mut self, generator: F) -> Self {
+        self.correlation_id = Some(Arc::new(generator));
+        self
+    }
+
+    /// Limits the requests or uploaded bytes this client may use, see `Budget`.
+    pub fn budget(mut self, budget: Budget) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// Retries transient failures of Base and Drive requests, see `RetryPolicy`.
+    pub fn retry(mut self, policy: RetryPolicy) -> Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    /// Limits concurrent writes and sheds or queues the excess, see `WriteQueue`.
+    pub fn write_queue(mut self, queue: WriteQueue) -> Self {
+        self.write_queue = Some(queue);
+        self
+    }
+
+    /// Throttles requests on the client to stay within Deta's rate limits, see `RateLimit`.
+    pub fn rate_limit(mut self, limit: RateLimit) -> Self {
+        self.rate_limit = Some(limit);
+        self
+    }
+
+    /// Posts an event to the webhook after every successful write, see `Webhook`.
+    #[cfg(feature = "blocking")]
+    pub fn webhook(mut self, webhook: Webhook) -> Self {
+        self.webhook = Some(webhook);
+        self
+    }
+
+    /// Records the shape of every query and the pages it fetches, see `AccessRecorder`.
+    pub fn access_recorder(mut self, recorder: AccessRecorder) -> Self {
+        self.access_recorder = Some(recorder);
+        self
+    }
+
+    /// Sends blocking requests through a custom transport instead of the default `ureq` agent.
+    ///
+    /// The user agent and timeouts are not applied to a custom transport. Webhooks are
+    /// still delivered with the default agent.
+    #[cfg(feature = "blocking")]
+    pub fn transport<T: Transport + 'static>(mut self, transport: T) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Sends async requests through a custom transport instead of the default `reqwest` client.
+    ///
+    /// The user agent and timeouts are not applied to a custom transport.
+    #[cfg(any(feature = "tokio", feature = "wasm"))]
+    pub fn async_transport<T: crate::transport::AsyncTransport + 'static>(mut self, transport: T) -> Self {
+        self.async_transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Serves every request from the given in-memory store instead of the network.
+    #[cfg(feature = "mock")]
+    pub (crate) fn mock(mut self, store: Arc<crate::mock::MockStore>) -> Self {
+        self.mock = Some(store);
+        self
+    }
+
+    /// Builds the Deta instance.
+    /// 
+    /// Panics if the project key is missing or invalid.
+    pub fn build(self) -> Deta {
+        let project_key = self.project_key.unwrap_or_else(|| {
+            std::env::var("DETA_PROJECT_KEY")
+                .expect("Environment variable `DETA_PROJECT_KEY` is not set.")
+        });
+        let project_id = validate(&project_key)
+            .expect("Invalid project key, must be in the format `projectId_secret`.")
+            .to_string();
+        #[cfg(feature = "blocking")]
+        let agent = ureq::AgentBuilder::new()
+            .user_agent(&self.user_agent)
+            .timeout_connect(self.connect_timeout)
+            .timeout_read(self.read_timeout)
+            .build();
+        Deta {
+            inner: Arc::new(Inner {
+                project_id,
+                project_key,
+                #[cfg(feature = "blocking")]
+                webhook: self.webhook.map(|webhook| WebhookSender::new(webhook, agent.clone())),
+                #[cfg(feature = "blocking")]
+                transport: self.transport.unwrap_or_else(|| Arc::new(UreqTransport::new(agent))),
+                #[cfg(any(feature = "tokio", feature = "wasm"))]
+                http: self.async_transport.unwrap_or_else(|| {
+                    // on wasm32 requests go through `fetch`, which sets its own user agent and timeouts
+                    #[cfg(target_arch = "wasm32")]
+                    let client = reqwest::Client::new();
+                    #[cfg(not(target_arch = "wasm32"))]
+                    let client = reqwest::Client::builder()
+                        .user_agent(&self.user_agent)
+                        .connect_timeout(self.connect_timeout)
+                        .read_timeout(self.read_timeout)
+                        .build()
+                        .unwrap_or_default();
+                    Arc::new(crate::transport::ReqwestTransport::new(client))
+                }),
+                recorder: self.access_recorder,
+                correlation_id: self.correlation_id,
+                budget: self.budget.map(BudgetGuard::new),
+                retry: self.retry,
+                writes: self.write_queue.map(WriteLimiter::new),
+                limiter: self.rate_limit.map(RateLimiter::new),
+                #[cfg(feature = "mock")]
+                mock: self.mock,
+            }),
+        }
+    }
+}
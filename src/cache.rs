@@ -0,0 +1,328 @@
+use std::{
+    cmp::Ordering,
+    collections::{ BTreeMap, HashMap },
+    sync::{ atomic::{ AtomicU64, Ordering as AtomicOrdering }, Arc, Mutex },
+    time::{ Duration, Instant },
+};
+
+use serde::{ Serialize, de::DeserializeOwned };
+use serde_json::Value;
+
+use crate::{
+    base::{ typed, Base, DeleteResponse, PutResponse, UpdateResponse },
+    errors::DetaError,
+    query::Query,
+    sort::compare_values,
+    updater::Updater,
+};
+
+type ChangeHook = Box<dyn Fn(&[Value]) + Send + Sync>;
+
+/// A query whose results are cached and refreshed incrementally.
+/// 
+/// Records must carry a monotonically increasing timestamp field (`updated_at` by default).
+/// After the first full walk, only records at or after the newest timestamp seen are fetched,
+/// whichever alternative of the query they match, and merged into the cached results by key.
+/// Change hooks only get the records that differ from the cached ones.
+/// Deletions are not detected incrementally, call `invalidate` to force a full walk.
+pub struct CachedQuery {
+    query: Query,
+    field: String,
+    items: BTreeMap<String, Value>,
+    newest: Option<Value>,
+    hooks: Vec<ChangeHook>,
+}
+
+impl CachedQuery {
+
+    pub (crate) fn new(query: Query) -> CachedQuery {
+        CachedQuery {
+            query,
+            field: String::from("updated_at"),
+            items: BTreeMap::new(),
+            newest: None,
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Sets the timestamp field used to detect changed records.
+    pub fn updated_field(mut self, field: &str) -> Self {
+        self.field = field.to_string();
+        self.newest = None;
+        self
+    }
+
+    /// Registers a hook called with the changed records after every refresh that found changes.
+    pub fn on_change<F: Fn(&[Value]) + Send + Sync + 'static>(mut self, hook: F) -> Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    /// Drops the cached results so the next run walks the whole query again.
+    pub fn invalidate(&mut self) {
+        self.items.clear();
+        self.newest = None;
+    }
+
+    /// Returns the cached results without contacting the base.
+    pub fn cached(&self) -> Vec<Value> {
+        self.items.values().cloned().collect()
+    }
+
+    /// Refreshes the cache and returns all cached results.
+    pub fn run(&mut self) -> Result<Vec<Value>, DetaError> {
+        let fetched = match &self.newest {
+            Some(newest) => {
+                let condition = format!("{}?gte", self.field);
+                self.query.clone().in_every_group(&condition, newest.clone()).walk()?
+            },
+            None => self.query.walk()?,
+        };
+        let mut changed = Vec::new();
+        for item in fetched {
+            if let Some(stamp) = item.get(&self.field) {
+                let newer = match &self.newest {
+                    Some(newest) => compare_values(stamp, newest) == Ordering::Greater,
+                    None => !stamp.is_null(),
+                };
+                if newer {
+                    self.newest = Some(stamp.clone());
+                }
+            }
+            let Some(key) = item["key"].as_str() else {
+                continue;
+            };
+            if self.items.get(key) != Some(&item) {
+                self.items.insert(key.to_string(), item.clone());
+                changed.push(item);
+            }
+        }
+        if !changed.is_empty() {
+            self.hooks.iter().for_each(|hook| hook(&changed));
+        }
+        Ok(self.cached())
+    }
+}
+
+/// Where a `CachedBase` keeps its records, e.g. in memory or in a shared cache server.
+///
+/// Stores are responsible for expiring records after their time to live.
+pub trait CacheStore: Send + Sync {
+    fn get(&self, key: &str) -> Option<Value>;
+    fn insert(&self, key: &str, record: Value, ttl: Duration);
+    fn remove(&self, key: &str);
+    fn clear(&self);
+}
+
+/// The default `CacheStore`, a map in process memory.
+#[derive(Default)]
+pub struct MemoryStore {
+    records: Mutex<HashMap<String, (Value, Instant)>>,
+}
+
+impl MemoryStore {
+
+    fn records(&self) -> std::sync::MutexGuard<'_, HashMap<String, (Value, Instant)>> {
+        self.records.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl CacheStore for MemoryStore {
+
+    fn get(&self, key: &str) -> Option<Value> {
+        let mut records = self.records();
+        match records.get(key) {
+            Some((record, expires)) if *expires > Instant::now() => Some(record.clone()),
+            Some(_) => {
+                records.remove(key);
+                None
+            },
+            None => None,
+        }
+    }
+
+    fn insert(&self, key: &str, record: Value, ttl: Duration) {
+        self.records().insert(key.to_string(), (record, Instant::now() + ttl));
+    }
+
+    fn remove(&self, key: &str) {
+        self.records().remove(key);
+    }
+
+    fn clear(&self) {
+        self.records().clear();
+    }
+}
+
+/// Hit and miss counts of a `CachedBase`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl CacheStats {
+
+    /// The share of reads served from the cache, between 0 and 1.
+    pub fn hit_rate(&self) -> f64 {
+        match self.hits + self.misses {
+            0 => 0.0,
+            reads => self.hits as f64 / reads as f64,
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// A base whose `get` results are cached for a time to live.
+///
+/// Writes made through the cached base invalidate the records they touch. Writes made
+/// elsewhere, e.g. by another process, are seen once the cached record expires.
+/// Clones share the cache and the stats.
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use detalib::Deta;
+///
+/// let users = Deta::new().base("users").cached(Duration::from_secs(60));
+/// let user = users.get("u1").unwrap();
+/// users.update("u1", |update| update.increment("logins", 1.into())).unwrap();
+/// println!("hit rate: {}", users.stats().hit_rate());
+/// ```
+#[derive(Clone)]
+pub struct CachedBase {
+    base: Base,
+    ttl: Duration,
+    store: Arc<dyn CacheStore>,
+    counters: Arc<Counters>,
+}
+
+impl CachedBase {
+
+    pub (crate) fn new(base: Base, ttl: Duration) -> CachedBase {
+        CachedBase {
+            base,
+            ttl,
+            store: Arc::new(MemoryStore::default()),
+            counters: Arc::default(),
+        }
+    }
+
+    /// Keeps records in `store` instead of process memory.
+    pub fn with_store<S: CacheStore + 'static>(mut self, store: S) -> Self {
+        self.store = Arc::new(store);
+        self
+    }
+
+    /// Returns the underlying uncached base.
+    pub fn base(&self) -> &Base {
+        &self.base
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.counters.hits.load(AtomicOrdering::Relaxed),
+            misses: self.counters.misses.load(AtomicOrdering::Relaxed),
+        }
+    }
+
+    /// Fetch a record by key, from the cache if it holds a fresh copy.
+    pub fn get(&self, key: &str) -> Result<Value, DetaError> {
+        if let Some(record) = self.store.get(key) {
+            self.counters.hits.fetch_add(1, AtomicOrdering::Relaxed);
+            return Ok(record);
+        }
+        self.counters.misses.fetch_add(1, AtomicOrdering::Relaxed);
+        let record = self.base.get(key)?;
+        self.store.insert(key, record.clone(), self.ttl);
+        Ok(record)
+    }
+
+    /// Same as `get`, deserializing the record to a struct.
+    pub fn get_as<T: DeserializeOwned>(&self, key: &str) -> Result<T, DetaError> {
+        self.get(key).and_then(typed)
+    }
+
+    /// Put records and drop them from the cache.
+    pub fn put<T: Serialize>(&self, records: Vec<T>) -> Result<PutResponse, DetaError> {
+        let records = records.iter().map(serde_json::to_value).collect::<Result<Vec<_>, _>>()?;
+        let result = self.base.put(records.iter().collect());
+        records.iter().filter_map(|record| record["key"].as_str()).for_each(|key| self.store.remove(key));
+        result
+    }
+
+    /// Update a record with the updater built by `build` and drop it from the cache.
+    pub fn update<F: FnOnce(Updater) -> Updater>(
+        &self, key: &str, build: F
+    ) -> Result<UpdateResponse, DetaError> {
+        let result = build(self.base.update(key)).commit();
+        self.store.remove(key);
+        result
+    }
+
+    /// Delete a record and drop it from the cache.
+    pub fn delete(&self, key: &str) -> Result<DeleteResponse, DetaError> {
+        let result = self.base.delete(key);
+        self.store.remove(key);
+        result
+    }
+
+    /// Drop every cached record.
+    pub fn invalidate(&self) {
+        self.store.clear();
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::{ sync::Arc, time::Duration };
+
+    use serde_json::json;
+
+    use super::CacheStats;
+    use crate::{ errors::DetaError, mock::MockDeta };
+
+    #[test]
+    fn cached_query_refreshes_every_group() {
+        let base = MockDeta::new().base("posts");
+        let post = |key: &str, tag: &str, at: i64| json!({ "key": key, "tag": tag, "updated_at": at });
+        base.put(vec![post("a", "x", 1), post("b", "y", 1), post("c", "z", 1)]).unwrap();
+        let changes = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = changes.clone();
+        let mut cached = base.query()
+            .equals("tag", json!("x"))
+            .or(|q| q.equals("tag", json!("y")))
+            .cached()
+            .on_change(move |items| seen.lock().unwrap().push(items.len()));
+        assert_eq!(cached.run().unwrap().len(), 2);
+        base.put(vec![post("a", "x", 2), post("c", "z", 2)]).unwrap();
+        assert_eq!(cached.run().unwrap(), vec![post("a", "x", 2), post("b", "y", 1)]);
+        base.put(vec![post("b", "y", 2)]).unwrap();
+        assert_eq!(cached.run().unwrap(), vec![post("a", "x", 2), post("b", "y", 2)]);
+        cached.run().unwrap();
+        assert_eq!(*changes.lock().unwrap(), vec![2, 1, 1]);
+    }
+
+    #[test]
+    fn cached_base_invalidates_on_write() {
+        let deta = MockDeta::new();
+        let users = deta.base("users").cached(Duration::from_secs(60));
+        users.put(vec![json!({ "key": "u", "logins": 1 })]).unwrap();
+        assert_eq!(users.get("u").unwrap()["logins"], 1);
+        deta.base("users").put(vec![json!({ "key": "u", "logins": 5 })]).unwrap();
+        assert_eq!(users.get("u").unwrap()["logins"], 1);
+        users.update("u", |update| update.increment("logins", json!(1))).unwrap();
+        assert_eq!(users.get("u").unwrap()["logins"], 6);
+        users.delete("u").unwrap();
+        assert!(matches!(users.get("u"), Err(DetaError::NotFound { .. })));
+        assert_eq!(users.stats(), CacheStats { hits: 1, misses: 3 });
+        let expired = deta.base("users").cached(Duration::ZERO);
+        expired.put(vec![json!({ "key": "v" })]).unwrap();
+        expired.get("v").unwrap();
+        expired.get("v").unwrap();
+        assert_eq!(expired.stats().hits, 0);
+    }
+}
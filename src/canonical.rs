@@ -0,0 +1,74 @@
+//! Canonical JSON, for hashing and signing records.
+
+use serde_json::{ Number, Value };
+
+/// Serializes a value to a canonical JSON string, so equal values always produce equal strings.
+/// 
+/// Object keys are sorted, whitespace is omitted and numbers are normalized:
+/// floats without a fractional part are written as integers and `-0` as `0`.
+/// ```rust
+/// use detalib::canonical::canonicalize;
+/// use serde_json::json;
+/// 
+/// assert_eq!(canonicalize(&json!({ "b": 1.0, "a": [2.5, -0.0] })), r#"{"a":[2.5,0],"b":1}"#);
+/// ```
+pub fn canonicalize(value: &Value) -> String {
+    let mut out = String::new();
+    write(&mut out, value);
+    out
+}
+
+fn write(out: &mut String, value: &Value) {
+    match value {
+        Value::Object(map) => {
+            let mut fields = map.iter().collect::<Vec<_>>();
+            fields.sort_by(|a, b| a.0.cmp(b.0));
+            out.push('{');
+            for (i, (field, value)) in fields.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                out.push_str(&Value::from(field.as_str()).to_string());
+                out.push(':');
+                write(out, value);
+            }
+            out.push('}');
+        },
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                write(out, item);
+            }
+            out.push(']');
+        },
+        Value::Number(number) => out.push_str(&normalize(number)),
+        value => out.push_str(&value.to_string()),
+    }
+}
+
+fn normalize(number: &Number) -> String {
+    match number.as_f64() {
+        Some(float) if number.is_f64() && float.fract() == 0.0 && float.abs() < 9.007_199_254_740_992e15 => {
+            format!("{}", float as i64)
+        },
+        _ => number.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn equal_values_equal_strings() {
+        let a = json!({ "x": { "z": 1, "y": 2.0 }, "w": "\"q\"" });
+        let b = json!({ "w": "\"q\"", "x": { "y": 2, "z": 1.0 } });
+        assert_eq!(canonicalize(&a), canonicalize(&b));
+        assert_eq!(canonicalize(&json!(1e300)), "1e+300");
+    }
+}
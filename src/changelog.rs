@@ -0,0 +1,263 @@
+//! Structured changelogs between two JSON Lines exports, without loading them in memory.
+
+use std::{
+    fs::{ self, File },
+    io::{ BufRead, BufReader, BufWriter, Lines, Write },
+    path::PathBuf,
+    sync::atomic::{ AtomicU64, Ordering },
+};
+
+use serde_json::Value;
+
+use crate::{ diff::{ diff_records, RecordDiff }, errors::DetaError };
+
+/// Records sorted in memory before a run is spilled to a temporary file.
+const RUN_RECORDS: usize = 50_000;
+
+static RUNS: AtomicU64 = AtomicU64::new(0);
+
+/// A record with its key.
+type Keyed = (String, Value);
+
+/// A difference between two exports, see `compare_exports`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExportChange {
+    /// A record only present in the new export.
+    Added(Value),
+    /// A record only present in the old export.
+    Removed(Value),
+    Changed { key: String, old: Value, new: Value, diff: RecordDiff },
+}
+
+impl ExportChange {
+
+    /// The key of the record that changed.
+    pub fn key(&self) -> &str {
+        match self {
+            ExportChange::Added(record) | ExportChange::Removed(record) => {
+                record["key"].as_str().unwrap_or_default()
+            },
+            ExportChange::Changed { key, .. } => key,
+        }
+    }
+}
+
+fn keyed(number: usize, line: &str) -> Result<Option<Keyed>, DetaError> {
+    if line.trim().is_empty() {
+        return Ok(None);
+    }
+    let record = serde_json::from_str::<Value>(line)?;
+    match record["key"].as_str() {
+        Some(key) => Ok(Some((key.to_string(), record))),
+        None => Err(DetaError::PayloadError {
+            msg: format!("line {} is not a record with a key", number + 1)
+        }),
+    }
+}
+
+/// A sorted part of an export, in memory or spilled to a temporary file.
+enum Run {
+    Memory(std::vec::IntoIter<Keyed>),
+    File { path: PathBuf, lines: Lines<BufReader<File>>, number: usize },
+}
+
+impl Run {
+
+    fn spill(records: &[Keyed]) -> Result<Run, DetaError> {
+        let id = RUNS.fetch_add(1, Ordering::Relaxed);
+        let name = format!("detalib-run-{}-{}.jsonl", std::process::id(), id);
+        let path = std::env::temp_dir().join(name);
+        let mut writer = BufWriter::new(File::create(&path)?);
+        for (_, record) in records {
+            serde_json::to_writer(&mut writer, record)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        let lines = BufReader::new(File::open(&path)?).lines();
+        Ok(Run::File { path, lines, number: 0 })
+    }
+
+    fn next(&mut self) -> Result<Option<Keyed>, DetaError> {
+        match self {
+            Run::Memory(records) => Ok(records.next()),
+            Run::File { lines, number, .. } => {
+                *number += 1;
+                match lines.next() {
+                    Some(line) => keyed(*number - 1, &line?),
+                    None => Ok(None),
+                }
+            },
+        }
+    }
+}
+
+impl Drop for Run {
+    fn drop(&mut self) {
+        if let Run::File { path, .. } = self {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// The records of an export in key order, merged from sorted runs.
+struct Sorted {
+    runs: Vec<Run>,
+    heads: Vec<Option<Keyed>>,
+}
+
+impl Sorted {
+
+    fn new<R: BufRead>(reader: R, run_records: usize) -> Result<Sorted, DetaError> {
+        let (mut runs, mut records) = (Vec::new(), Vec::new());
+        for (number, line) in reader.lines().enumerate() {
+            if let Some(record) = keyed(number, &line?)? {
+                records.push(record);
+            }
+            if records.len() >= run_records {
+                records.sort_by(|a, b| a.0.cmp(&b.0));
+                runs.push(Run::spill(&records)?);
+                records.clear();
+            }
+        }
+        records.sort_by(|a, b| a.0.cmp(&b.0));
+        runs.push(Run::Memory(records.into_iter()));
+        let heads = runs.iter_mut().map(Run::next).collect::<Result<Vec<_>, _>>()?;
+        Ok(Sorted { runs, heads })
+    }
+
+    /// The run holding the smallest key, the earliest run on ties.
+    fn smallest(&self) -> Option<usize> {
+        let heads = self.heads.iter().enumerate().filter_map(|(i, head)| Some((i, &head.as_ref()?.0)));
+        heads.min_by(|a, b| a.1.cmp(b.1)).map(|(i, _)| i)
+    }
+
+    fn take(&mut self, run: usize) -> Result<Option<Keyed>, DetaError> {
+        let next = self.runs[run].next()?;
+        Ok(std::mem::replace(&mut self.heads[run], next))
+    }
+
+    /// The next record, keeping the last one written when a key appears several times.
+    fn next(&mut self) -> Result<Option<Keyed>, DetaError> {
+        let Some(run) = self.smallest() else {
+            return Ok(None);
+        };
+        let mut record = self.take(run)?;
+        while let Some(run) = self.smallest() {
+            if self.heads[run].as_ref().map(|head| &head.0) != record.as_ref().map(|record| &record.0) {
+                break;
+            }
+            record = self.take(run)?;
+        }
+        Ok(record)
+    }
+}
+
+/// The changes between two exports, yielded in key order. Returned by `compare_exports`.
+pub struct ExportChanges {
+    old: Sorted,
+    new: Sorted,
+    heads: (Option<Keyed>, Option<Keyed>),
+    failed: bool,
+}
+
+impl ExportChanges {
+
+    fn step(&mut self) -> Result<Option<ExportChange>, DetaError> {
+        use std::cmp::Ordering::*;
+        loop {
+            let order = match (&self.heads.0, &self.heads.1) {
+                (None, None) => return Ok(None),
+                (Some(_), None) => Less,
+                (None, Some(_)) => Greater,
+                (Some(old), Some(new)) => old.0.cmp(&new.0),
+            };
+            let old = match order {
+                Greater => None,
+                _ => std::mem::replace(&mut self.heads.0, self.old.next()?),
+            };
+            let new = match order {
+                Less => None,
+                _ => std::mem::replace(&mut self.heads.1, self.new.next()?),
+            };
+            match (old, new) {
+                (Some((_, old)), None) => return Ok(Some(ExportChange::Removed(old))),
+                (None, Some((_, new))) => return Ok(Some(ExportChange::Added(new))),
+                (Some((key, old)), Some((_, new))) => {
+                    let diff = diff_records(&old, &new);
+                    if !diff.is_empty() {
+                        return Ok(Some(ExportChange::Changed { key, old, new, diff }));
+                    }
+                },
+                (None, None) => {},
+            }
+        }
+    }
+}
+
+impl Iterator for ExportChanges {
+    type Item = Result<ExportChange, DetaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        let change = self.step().transpose();
+        self.failed = matches!(change, Some(Err(_)));
+        change
+    }
+}
+
+fn compare<A: BufRead, B: BufRead>(
+    old: A, new: B, run_records: usize
+) -> Result<ExportChanges, DetaError> {
+    let (mut old, mut new) = (Sorted::new(old, run_records)?, Sorted::new(new, run_records)?);
+    let heads = (old.next()?, new.next()?);
+    Ok(ExportChanges { old, new, heads, failed: false })
+}
+
+/// Compares two JSON Lines exports, e.g. written by `Base::export_jsonl`, by record key.
+///
+/// Both exports are sorted by key in runs spilled to temporary files, so memory use stays
+/// bounded however large they are. The runs are read back lazily as the changes are iterated.
+/// When a key appears several times in one export, its last record is used.
+/// Blank lines are skipped, and a line that is not a record with a key fails the comparison.
+/// ```rust,no_run
+/// use std::{ fs::File, io::BufReader };
+/// use detalib::changelog::{ compare_exports, ExportChange };
+///
+/// let old = BufReader::new(File::open("users-monday.jsonl").unwrap());
+/// let new = BufReader::new(File::open("users-tuesday.jsonl").unwrap());
+/// for change in compare_exports(old, new).unwrap() {
+///     match change.unwrap() {
+///         ExportChange::Added(record) => println!("+ {}", record),
+///         ExportChange::Removed(record) => println!("- {}", record),
+///         ExportChange::Changed { key, diff, .. } => println!("~ {}\n{}", key, diff),
+///     }
+/// }
+/// ```
+pub fn compare_exports<A: BufRead, B: BufRead>(old: A, new: B) -> Result<ExportChanges, DetaError> {
+    compare(old, new, RUN_RECORDS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spilled_runs_are_merged() {
+        let lines = |records: &[(&str, i64)]| records.iter()
+            .map(|(key, n)| format!("{{\"key\":\"{}\",\"n\":{}}}\n", key, n))
+            .collect::<String>();
+        let old = lines(&[("c", 1), ("a", 1), ("b", 1), ("e", 1)]) + "\n";
+        let new = lines(&[("d", 1), ("b", 1), ("a", 2), ("a", 3)]);
+        let changes = compare(old.as_bytes(), new.as_bytes(), 2).unwrap();
+        let changes = changes.collect::<Result<Vec<_>, _>>().unwrap();
+        let summary = changes.iter().map(|change| match change {
+            ExportChange::Added(_) => format!("+{}", change.key()),
+            ExportChange::Removed(_) => format!("-{}", change.key()),
+            ExportChange::Changed { diff, .. } => format!("~{} {}", change.key(), diff),
+        }).collect::<Vec<_>>();
+        assert_eq!(summary, ["~a ~ n: 1 -> 3", "-c", "+d", "-e"]);
+        assert!(compare("{\"n\":1}".as_bytes(), "".as_bytes(), 2).is_err());
+    }
+}
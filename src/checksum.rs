@@ -0,0 +1,60 @@
+//! Record checksums for detecting modifications made outside the application.
+//! 
+//! A checksum is the SHA-256 of the canonicalized record without its `__checksum` field.
+//! Records changed through `Updater` lose their valid checksum and have to be put again.
+
+use serde_json::Value;
+use sha2::{ Digest, Sha256 };
+
+use crate::{ canonical::canonicalize, errors::DetaError, hex };
+
+const FIELD: &str = "__checksum";
+
+/// The hex-encoded SHA-256 of some bytes, as used for record checksums and `Drive::get_if_changed`.
+pub fn digest(bytes: &[u8]) -> String {
+    hex::encode(&Sha256::digest(bytes))
+}
+
+fn checksum(record: &Value) -> String {
+    let mut record = record.clone();
+    if let Value::Object(map) = &mut record {
+        map.remove(FIELD);
+    }
+    digest(canonicalize(&record).as_bytes())
+}
+
+/// Sets `__checksum` on a serialized record if it is an object.
+pub fn stamp(record: &mut Value) {
+    let sum = checksum(record);
+    if let Value::Object(map) = record {
+        map.insert(String::from(FIELD), Value::from(sum));
+    }
+}
+
+/// Checks the `__checksum` of a record, failing with `DetaError::IntegrityError`
+/// if it is missing or does not match the record.
+pub fn verify(record: &Value) -> Result<(), DetaError> {
+    match record[FIELD].as_str() {
+        Some(sum) if sum == checksum(record) => Ok(()),
+        _ => Err(DetaError::IntegrityError { key: record["key"].as_str().map(String::from) }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn detects_modification() {
+        let mut record = json!({ "key": "k", "b": 1, "a": [true, null] });
+        stamp(&mut record);
+        assert!(verify(&record).is_ok());
+        let reordered = json!({ "a": [true, null], "key": "k", "b": 1, "__checksum": record["__checksum"] });
+        assert!(verify(&reordered).is_ok());
+        record["b"] = json!(2);
+        assert!(matches!(verify(&record), Err(DetaError::IntegrityError { key: Some(_) })));
+        assert!(verify(&json!({ "key": "k" })).is_err());
+    }
+}
@@ -0,0 +1,237 @@
+//! Coalescing of bursts of updates to the same record into fewer requests.
+
+use std::{
+    collections::BTreeMap,
+    sync::{ Arc, Condvar, Mutex, MutexGuard },
+    thread,
+    time::{ Duration, Instant },
+};
+
+use crate::{ errors::DetaError, updater::Updater };
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Counts of a `Coalescer`, to see how many requests it saved.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CoalescerStats {
+    /// Updaters submitted.
+    pub updates: u64,
+    /// Updates committed, one request each (plus the reads of guarded ones).
+    pub requests: u64,
+}
+
+/// The updates waiting for one record, in order. Only the last one takes in new updates.
+struct Pending {
+    since: Instant,
+    updaters: Vec<Updater>,
+}
+
+#[derive(Default)]
+struct State {
+    pending: BTreeMap<String, Pending>,
+    failures: Vec<(String, DetaError)>,
+    stats: CoalescerStats,
+    stopped: bool,
+}
+
+struct Shared {
+    window: Mutex<Duration>,
+    state: Mutex<State>,
+    wake: Condvar,
+    /// Held while committing, so updates to a record are never committed out of order.
+    sending: Mutex<()>,
+}
+
+impl Shared {
+
+    /// Commits the pending updates of the records `due` selects, returning how many commits
+    /// were made and the ones that failed.
+    fn send(&self, due: impl Fn(&Pending) -> bool) -> (usize, Vec<(String, DetaError)>) {
+        let _sending = lock(&self.sending);
+        let batches = {
+            let mut state = lock(&self.state);
+            let keys = state.pending.iter()
+                .filter(|(_, pending)| due(pending))
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>();
+            keys.into_iter().filter_map(|key| state.pending.remove(&key)).collect::<Vec<_>>()
+        };
+        let (mut sent, mut failures) = (0, Vec::new());
+        for updater in batches.into_iter().flat_map(|pending| pending.updaters) {
+            sent += 1;
+            if let Err(e) = updater.commit_raw() {
+                failures.push((updater.target(), e));
+            }
+            lock(&self.state).stats.requests += 1;
+        }
+        (sent, failures)
+    }
+}
+
+/// Merges updates to the same record made within a short window into a single commit.
+///
+/// Chatty clients, like a UI saving a document on every keystroke, can submit every change
+/// and have the ones to the same record within `window` sent as one `PATCH`. Updates are
+/// combined in order: later `set`s and `delete`s win, `increment`s add up and `append`s
+/// and `prepend`s concatenate. Updates that cannot be combined with the ones before them,
+/// and updates with guards, are committed separately, in the order they were submitted.
+///
+/// Updates are committed by a background thread once their window has passed, so errors
+/// are collected for `take_failures`. Dropping the coalescer commits what is pending.
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use detalib::{ Deta, coalesce::Coalescer };
+/// use serde_json::json;
+///
+/// let docs = Deta::new().base("docs");
+/// let coalescer = Coalescer::new().window(Duration::from_millis(200));
+/// for text in ["H", "He", "Hey"] {
+///     coalescer.submit(docs.update("d1").set("text", json!(text)).increment("edits", json!(1)));
+/// }
+/// coalescer.flush().unwrap();
+/// assert_eq!(coalescer.stats().requests, 1);
+/// ```
+pub struct Coalescer {
+    shared: Arc<Shared>,
+}
+
+impl Default for Coalescer {
+    fn default() -> Self {
+        Coalescer::new()
+    }
+}
+
+impl Coalescer {
+
+    pub fn new() -> Coalescer {
+        let shared = Arc::new(Shared {
+            window: Mutex::new(Duration::from_millis(50)),
+            state: Mutex::new(State::default()),
+            wake: Condvar::new(),
+            sending: Mutex::new(()),
+        });
+        let sender = shared.clone();
+        thread::spawn(move || {
+            let mut state = lock(&sender.state);
+            while !state.stopped {
+                let window = *lock(&sender.window);
+                let now = Instant::now();
+                match state.pending.values().map(|pending| pending.since + window).min() {
+                    Some(due) if due <= now => {
+                        drop(state);
+                        let (_, failures) = sender.send(|pending| pending.since + window <= now);
+                        state = lock(&sender.state);
+                        state.failures.extend(failures);
+                    },
+                    Some(due) => {
+                        let woken = sender.wake.wait_timeout(state, due - now);
+                        state = woken.unwrap_or_else(|e| e.into_inner()).0;
+                    },
+                    None => state = sender.wake.wait(state).unwrap_or_else(|e| e.into_inner()),
+                }
+            }
+        });
+        Coalescer { shared }
+    }
+
+    /// Sets how long updates wait for more updates to the same record. Defaults to 50 milliseconds.
+    pub fn window(self, window: Duration) -> Self {
+        *lock(&self.shared.window) = window;
+        self
+    }
+
+    /// Queues an update, merging it into the pending update of the same record if possible.
+    pub fn submit(&self, updater: Updater) {
+        let mut state = lock(&self.shared.state);
+        state.stats.updates += 1;
+        let pending = state.pending.entry(updater.target()).or_insert_with(|| Pending {
+            since: Instant::now(),
+            updaters: Vec::new(),
+        });
+        let updater = match pending.updaters.last_mut() {
+            Some(last) => last.absorb(updater),
+            None => Some(updater),
+        };
+        pending.updaters.extend(updater);
+        self.shared.wake.notify_all();
+    }
+
+    /// The number of commits waiting for their window to pass.
+    pub fn pending(&self) -> usize {
+        lock(&self.shared.state).pending.values().map(|pending| pending.updaters.len()).sum()
+    }
+
+    /// Commits every pending update now, returning how many commits were made.
+    ///
+    /// Fails with the first error after trying every commit. Errors of further commits are
+    /// kept for `take_failures`.
+    pub fn flush(&self) -> Result<usize, DetaError> {
+        let (sent, mut failures) = self.shared.send(|_| true);
+        if failures.is_empty() {
+            return Ok(sent);
+        }
+        let (_, first) = failures.remove(0);
+        lock(&self.shared.state).failures.extend(failures);
+        Err(first)
+    }
+
+    /// Takes the errors of the commits made in the background, with the URL of their record.
+    pub fn take_failures(&self) -> Vec<(String, DetaError)> {
+        std::mem::take(&mut lock(&self.shared.state).failures)
+    }
+
+    pub fn stats(&self) -> CoalescerStats {
+        lock(&self.shared.state).stats
+    }
+}
+
+impl Drop for Coalescer {
+    fn drop(&mut self) {
+        lock(&self.shared.state).stopped = true;
+        self.shared.wake.notify_all();
+        let _ = self.flush();
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::Coalescer;
+    use crate::mock::MockDeta;
+
+    #[test]
+    fn coalescer_merges_updates() {
+        let docs = MockDeta::new().base("docs");
+        docs.put(vec![
+            json!({ "key": "d1", "text": "", "edits": 0, "tags": [] }),
+            json!({ "key": "d2" }),
+        ]).unwrap();
+        let coalescer = Coalescer::new().window(Duration::from_secs(60));
+        for text in ["H", "He", "Hey"] {
+            coalescer.submit(docs.update("d1").set("text", json!(text)).increment("edits", json!(1)));
+        }
+        coalescer.submit(docs.update("d1").append("tags", json!("greeting")));
+        coalescer.submit(docs.update("d1").set("tags", json!(["draft"])));
+        coalescer.submit(docs.update("d2").set("text", json!("new")));
+        assert_eq!(coalescer.pending(), 2);
+        assert_eq!(docs.get("d1").unwrap()["edits"], 0);
+        assert_eq!(coalescer.flush().unwrap(), 2);
+        let d1 = docs.get("d1").unwrap();
+        assert_eq!((&d1["text"], &d1["edits"], &d1["tags"]), (&json!("Hey"), &json!(3), &json!(["draft"])));
+        assert_eq!(coalescer.stats().updates, 6);
+        assert!(coalescer.take_failures().is_empty());
+
+        let coalescer = Coalescer::new().window(Duration::from_millis(20));
+        coalescer.submit(docs.update("d1").increment("edits", json!(1)));
+        coalescer.submit(docs.update("d1").append("edits", json!(1)));
+        assert_eq!(coalescer.pending(), 2);
+        std::thread::sleep(Duration::from_millis(500));
+        assert_eq!((coalescer.pending(), coalescer.stats().requests), (0, 2));
+        assert_eq!(docs.get("d1").unwrap()["edits"], json!([1]));
+    }
+}
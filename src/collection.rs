@@ -0,0 +1,126 @@
+use std::marker::PhantomData;
+
+use serde::{ Serialize, de::DeserializeOwned };
+
+use crate::{
+    base::{ Base, DeleteResponse, InsertResponse, PutResponse },
+    dates::{ self, DateFormat },
+    errors::DetaError,
+    filter::Filter,
+    query::Query,
+    DetaRecord,
+};
+
+/// A typed view over a Deta Base where every record is of type `T`.
+/// 
+/// Queries issued through a collection are paginated automatically.
+/// `Timestamped` fields are stored in the date format of the base.
+pub struct Collection<T> {
+    base: Base,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Clone for Collection<T> {
+    fn clone(&self) -> Self {
+        Collection { base: self.base.clone(), _marker: PhantomData }
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> Collection<T> {
+
+    pub (crate) fn new(base: Base) -> Collection<T> {
+        Collection { base, _marker: PhantomData }
+    }
+
+    /// Sets the format `Timestamped` fields are stored in, see `Base::with_date_format`.
+    pub fn with_date_format(self, format: DateFormat) -> Collection<T> {
+        Collection::new(self.base.with_date_format(format))
+    }
+
+    fn scoped<R>(&self, f: impl FnOnce() -> R) -> R {
+        dates::scoped(self.base.date_format(), f)
+    }
+
+    /// Returns the underlying untyped base.
+    pub fn base(&self) -> &Base {
+        &self.base
+    }
+
+    /// Fetch a record by key.
+    pub fn get(&self, key: &str) -> Result<T, DetaError> {
+        self.scoped(|| self.base.get_as::<T>(key))
+    }
+
+    /// Put multiple records, overwriting existing records with the same key.
+    pub fn put(&self, records: &[T]) -> Result<PutResponse, DetaError> {
+        self.scoped(|| self.base.put(records.iter().collect()))
+    }
+
+    /// Insert a record, failing if the key already exists.
+    pub fn insert(&self, record: &T) -> Result<InsertResponse, DetaError> {
+        self.scoped(|| self.base.insert(record))
+    }
+
+    /// Delete a record by key.
+    pub fn delete(&self, key: &str) -> Result<DeleteResponse, DetaError> {
+        self.base.delete(key)
+    }
+
+    /// Fetch every record in the collection.
+    pub fn all(&self) -> Result<Vec<T>, DetaError> {
+        self.find(self.base.query())
+    }
+
+    /// Run a prepared query and deserialize all matching records.
+    pub fn find(&self, query: Query) -> Result<Vec<T>, DetaError> {
+        self.scoped(|| query.walk_as::<T>())
+    }
+
+    /// Build a query with the given closure and deserialize all matching records.
+    /// 
+    /// ```rust,no_run
+    /// use detalib::Deta;
+    /// use serde_json::json;
+    ///
+    /// #[derive(serde::Serialize, serde::Deserialize)]
+    /// struct User { key: String, age: u8 }
+    ///
+    /// let users = Deta::new().collection::<User>("users");
+    /// let adults = users.find_with(|q| q.greater_than("age", json!(18)));
+    /// ```
+    pub fn find_with<F: FnOnce(Query) -> Query>(&self, build: F) -> Result<Vec<T>, DetaError> {
+        self.find(build(self.base.query()))
+    }
+
+    /// Fetch the first record matching the query built by the closure, if any.
+    pub fn find_one_with<F: FnOnce(Query) -> Query>(&self, build: F) -> Result<Option<T>, DetaError> {
+        match build(self.base.query()).first()? {
+            Some(item) => Ok(Some(self.scoped(|| serde_json::from_value::<T>(item))?)),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<T: DetaRecord> Collection<T> {
+
+    /// Fetch every record matching a filter built from the fields of `T`.
+    /// 
+    /// ```rust,ignore
+    /// use detalib::{ Deta, DetaRecord };
+    ///
+    /// #[derive(DetaRecord)]
+    /// struct User { key: String, name: String, age: u8 }
+    ///
+    /// let users = Deta::new().collection::<User>("users");
+    /// let adults = users.find_where(|f| f.age.gt(18).and(f.name.prefix("J")));
+    /// ```
+    pub fn find_where<F: FnOnce(T::Fields) -> Filter>(&self, filter: F) -> Result<Vec<T>, DetaError> {
+        self.find(filter(T::fields()).apply(self.base.query())?)
+    }
+
+    /// Fetch the first record matching a filter built from the fields of `T`, if any.
+    pub fn find_one_where<F: FnOnce(T::Fields) -> Filter>(&self, filter: F) -> Result<Option<T>, DetaError> {
+        let query = filter(T::fields()).apply(self.base.query())?;
+        self.find_one_with(|_| query)
+    }
+}
@@ -0,0 +1,95 @@
+use std::io::Write;
+
+use crate::{ base::Base, errors::DetaError };
+
+fn arrow_err<E: std::fmt::Display>(e: E) -> DetaError {
+    DetaError::PayloadError { msg: e.to_string() }
+}
+
+impl Base {
+
+    /// Export every record in the base as a Parquet file with the given Arrow schema.
+    /// 
+    /// Records are converted page by page, so only one page is held in memory at a time.
+    /// Fields missing from the schema are ignored and missing values become nulls.
+    pub fn export_parquet<W: Write + Send>(
+        &self, writer: W, schema: arrow_schema::SchemaRef
+    ) -> Result<(), DetaError> {
+        let mut decoder = arrow_json::ReaderBuilder::new(schema.clone())
+            .build_decoder()
+            .map_err(arrow_err)?;
+        let mut parquet = parquet::arrow::ArrowWriter::try_new(writer, schema, None)
+            .map_err(arrow_err)?;
+        let mut query = self.query();
+        loop {
+            let resp = query.run_raw()?;
+            if let Some(items) = resp["items"].as_array() {
+                decoder.serialize(items).map_err(arrow_err)?;
+            }
+            if let Some(batch) = decoder.flush().map_err(arrow_err)? {
+                parquet.write(&batch).map_err(arrow_err)?;
+            }
+            match resp["paging"]["last"].as_str() {
+                Some(last) if !last.is_empty() => query = query.last(last),
+                _ => break,
+            }
+        }
+        parquet.close().map_err(arrow_err)?;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::{ fs::File, sync::Arc };
+
+    use arrow_schema::{ DataType, Field, Fields, Schema };
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use serde_json::{ json, Value };
+
+    use crate::{ errors::DetaError, mock::MockDeta };
+
+    fn schema(score: DataType) -> arrow_schema::SchemaRef {
+        let profile = Fields::from(vec![Field::new("city", DataType::Utf8, true)]);
+        Arc::new(Schema::new(vec![
+            Field::new("key", DataType::Utf8, false),
+            Field::new("score", score, true),
+            Field::new("profile", DataType::Struct(profile), true),
+        ]))
+    }
+
+    #[test]
+    fn exports_nested_objects_and_mixed_numbers() {
+        let base = MockDeta::new().base("users");
+        base.put(vec![
+            json!({ "key": "a", "score": 1, "profile": { "city": "Oslo" }, "ignored": true }),
+            json!({ "key": "b", "score": 2.5 }),
+        ]).unwrap();
+        let path = std::env::temp_dir().join(format!("detalib-parquet-{}", std::process::id()));
+        base.export_parquet(File::create(&path).unwrap(), schema(DataType::Float64)).unwrap();
+
+        let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(&path).unwrap())
+            .unwrap()
+            .build()
+            .unwrap();
+        let mut writer = arrow_json::ArrayWriter::new(Vec::new());
+        for batch in reader {
+            writer.write(&batch.unwrap()).unwrap();
+        }
+        writer.finish().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let rows = serde_json::from_slice::<Value>(&writer.into_inner()).unwrap();
+        assert_eq!(rows, json!([
+            { "key": "a", "score": 1.0, "profile": { "city": "Oslo" } },
+            { "key": "b", "score": 2.5 },
+        ]));
+    }
+
+    #[test]
+    fn mixed_type_columns_fail() {
+        let base = MockDeta::new().base("users");
+        base.put(vec![json!({ "key": "a", "score": 1 }), json!({ "key": "b", "score": "high" })]).unwrap();
+        let exported = base.export_parquet(Vec::new(), schema(DataType::Int64));
+        assert!(matches!(exported, Err(DetaError::PayloadError { .. })));
+    }
+}
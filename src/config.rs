@@ -0,0 +1,155 @@
+//! Configuration loaded from a Drive file and reloaded when the file changes.
+
+use std::{
+    io::Read,
+    sync::{ Arc, Condvar, Mutex, RwLock },
+    thread,
+    time::Duration,
+};
+
+use serde::de::DeserializeOwned;
+use sha2::{ Digest, Sha256 };
+
+use crate::{ drive::Drive, errors::DetaError };
+
+type ChangeHook<T> = Box<dyn Fn(&T) + Send + Sync>;
+
+struct Shared<T> {
+    drive: Drive,
+    name: String,
+    current: RwLock<Arc<T>>,
+    digest: Mutex<[u8; 32]>,
+    hooks: Mutex<Vec<ChangeHook<T>>>,
+    stopped: Mutex<bool>,
+    stop: Condvar,
+}
+
+impl<T: DeserializeOwned> Shared<T> {
+
+    fn fetch(drive: &Drive, name: &str) -> Result<(T, [u8; 32]), DetaError> {
+        let mut content = Vec::new();
+        drive.get(name)?.into_reader().read_to_end(&mut content)?;
+        let config = serde_json::from_slice(&content)?;
+        Ok((config, Sha256::digest(&content).into()))
+    }
+
+    fn reload(&self) -> Result<bool, DetaError> {
+        let (config, digest) = Self::fetch(&self.drive, &self.name)?;
+        {
+            let mut known = self.digest.lock().unwrap_or_else(|e| e.into_inner());
+            if *known == digest {
+                return Ok(false);
+            }
+            *known = digest;
+        }
+        let config = Arc::new(config);
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = config.clone();
+        for hook in self.hooks.lock().unwrap_or_else(|e| e.into_inner()).iter() {
+            hook(&config);
+        }
+        Ok(true)
+    }
+}
+
+/// A JSON config file on a Drive, polled in the background and swapped in when it changes.
+///
+/// Readers always see a complete config: `current` hands out the latest parsed version,
+/// and a file that fails to download or parse leaves the previous version in place.
+/// Polling stops when the watcher is dropped.
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use detalib::{ Deta, config::ConfigWatcher };
+///
+/// #[derive(serde::Deserialize)]
+/// struct Config { maintenance: bool }
+///
+/// let drive = Deta::new().drive("settings");
+/// let config = ConfigWatcher::<Config>::new(drive, "config.json", Duration::from_secs(30)).unwrap();
+/// config.on_change(|config| println!("maintenance: {}", config.maintenance));
+/// if config.current().maintenance {
+///     println!("down for maintenance");
+/// }
+/// ```
+pub struct ConfigWatcher<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T: DeserializeOwned + Send + Sync + 'static> ConfigWatcher<T> {
+
+    /// Loads the file and starts polling it every `interval`.
+    ///
+    /// Fails if the first load fails, so an application never starts without a config.
+    pub fn new(drive: Drive, name: &str, interval: Duration) -> Result<ConfigWatcher<T>, DetaError> {
+        let (config, digest) = Shared::<T>::fetch(&drive, name)?;
+        let shared = Arc::new(Shared {
+            drive,
+            name: name.to_string(),
+            current: RwLock::new(Arc::new(config)),
+            digest: Mutex::new(digest),
+            hooks: Mutex::new(Vec::new()),
+            stopped: Mutex::new(false),
+            stop: Condvar::new(),
+        });
+        let poller = shared.clone();
+        thread::spawn(move || {
+            let mut stopped = poller.stopped.lock().unwrap_or_else(|e| e.into_inner());
+            loop {
+                stopped = poller.stop.wait_timeout(stopped, interval).unwrap_or_else(|e| e.into_inner()).0;
+                if *stopped {
+                    return;
+                }
+                drop(stopped);
+                let _ = poller.reload();
+                stopped = poller.stopped.lock().unwrap_or_else(|e| e.into_inner());
+            }
+        });
+        Ok(ConfigWatcher { shared })
+    }
+
+    /// The latest successfully loaded config.
+    pub fn current(&self) -> Arc<T> {
+        self.shared.current.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Registers a hook called with the new config whenever the file changes.
+    pub fn on_change<F: Fn(&T) + Send + Sync + 'static>(&self, hook: F) {
+        self.shared.hooks.lock().unwrap_or_else(|e| e.into_inner()).push(Box::new(hook));
+    }
+
+    /// Checks the file now instead of waiting for the next poll. Returns whether it changed.
+    pub fn reload(&self) -> Result<bool, DetaError> {
+        self.shared.reload()
+    }
+}
+
+impl<T> Drop for ConfigWatcher<T> {
+    fn drop(&mut self) {
+        *self.shared.stopped.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        self.shared.stop.notify_all();
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::time::Duration;
+
+    use super::ConfigWatcher;
+    use crate::mock::MockDeta;
+
+    #[test]
+    fn config_watcher_picks_up_changes() {
+        let drive = MockDeta::new().drive("settings");
+        drive.put("config.json", br#"{ "level": 1 }"#, None).unwrap();
+        let interval = Duration::from_millis(5);
+        let watcher = ConfigWatcher::<serde_json::Value>::new(drive.clone(), "config.json", interval).unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        watcher.on_change(move |config| tx.send(config["level"].clone()).unwrap());
+        assert_eq!(watcher.current()["level"], 1);
+        drive.put("config.json", b"not json", None).unwrap();
+        assert!(watcher.reload().is_err());
+        drive.put("config.json", br#"{ "level": 2 }"#, None).unwrap();
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)).unwrap(), 2);
+        assert_eq!(watcher.current()["level"], 2);
+        assert!(!watcher.reload().unwrap());
+    }
+}
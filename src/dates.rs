@@ -0,0 +1,147 @@
+//! How dates are stored in a base, used by date filters such as `Query::between_dates`
+//! and by `Timestamped` fields of a `Collection`.
+
+use std::cell::Cell;
+
+use chrono::{ DateTime, SecondsFormat, TimeZone, Utc };
+use serde::{ de, Deserialize, Deserializer, Serialize, Serializer };
+use serde_json::Value;
+
+/// The representation of dates stored in a base, set with `Base::with_date_format`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DateFormat {
+    /// Unix timestamp in seconds, the format Deta uses for `__expires`.
+    #[default]
+    EpochSeconds,
+    /// Unix timestamp in milliseconds.
+    EpochMillis,
+    /// RFC 3339 string in UTC with millisecond precision, e.g. `2024-01-31T12:00:00.000Z`.
+    ///
+    /// The fixed width keeps lexicographic order equal to chronological order.
+    Rfc3339,
+}
+
+impl DateFormat {
+
+    /// Converts a date to its stored representation.
+    pub fn encode<Tz: TimeZone>(&self, date: &DateTime<Tz>) -> Value {
+        match self {
+            DateFormat::EpochSeconds => Value::from(date.timestamp()),
+            DateFormat::EpochMillis => Value::from(date.timestamp_millis()),
+            DateFormat::Rfc3339 => {
+                Value::from(date.with_timezone(&Utc).to_rfc3339_opts(SecondsFormat::Millis, true))
+            },
+        }
+    }
+
+    /// Reads a stored date, accepting RFC 3339 strings and integer timestamps in any format.
+    ///
+    /// Integers are read in this format's unit; in an RFC 3339 base they were written elsewhere,
+    /// so they are read as milliseconds when too large to be seconds.
+    pub fn decode(&self, value: &Value) -> Option<DateTime<Utc>> {
+        match value {
+            Value::String(s) => DateTime::parse_from_rfc3339(s).ok().map(|date| date.to_utc()),
+            Value::Number(n) => {
+                let n = n.as_i64()?;
+                let millis = match self {
+                    DateFormat::EpochSeconds => false,
+                    DateFormat::EpochMillis => true,
+                    DateFormat::Rfc3339 => n.abs() > 100_000_000_000,
+                };
+                match millis {
+                    true => DateTime::from_timestamp_millis(n),
+                    false => DateTime::from_timestamp(n, 0),
+                }
+            },
+            _ => None,
+        }
+    }
+}
+
+thread_local! {
+    static CURRENT: Cell<DateFormat> = const { Cell::new(DateFormat::EpochSeconds) };
+}
+
+/// Runs `f` with `format` as the format of `Timestamped` values serialized or deserialized on this thread.
+#[cfg(feature = "blocking")]
+pub (crate) fn scoped<R>(format: DateFormat, f: impl FnOnce() -> R) -> R {
+    struct Restore(DateFormat);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            CURRENT.with(|current| current.set(self.0));
+        }
+    }
+    let _restore = Restore(CURRENT.with(|current| current.replace(format)));
+    f()
+}
+
+/// A date stored in the format configured on the base, see `Base::with_date_format`.
+/// 
+/// Records of a `Collection` are converted with the format of its base, so every date field
+/// is stored the same way and range queries compare like with like. Elsewhere the default
+/// `DateFormat::EpochSeconds` applies. When reading, RFC 3339 strings and numbers are both
+/// accepted, so records written in another format still load.
+/// ```rust
+/// use chrono::{ Local, Utc };
+/// use detalib::dates::Timestamped;
+/// 
+/// #[derive(serde::Serialize, serde::Deserialize)]
+/// struct Event {
+///     key: String,
+///     created: Timestamped,
+///     local: Timestamped<Local>,
+/// }
+/// 
+/// let event = Event { key: "e".into(), created: Timestamped(Utc::now()), local: Timestamped(Local::now()) };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamped<Tz: TimeZone = Utc>(pub DateTime<Tz>) where Tz::Offset: Copy;
+
+impl<Tz: TimeZone> Serialize for Timestamped<Tz> where Tz::Offset: Copy {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        CURRENT.with(Cell::get).encode(&self.0).serialize(serializer)
+    }
+}
+
+impl<'de, Tz: TimeZone> Deserialize<'de> for Timestamped<Tz>
+    where Tz::Offset: Copy, DateTime<Tz>: From<DateTime<Utc>>
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        let date = match CURRENT.with(Cell::get).decode(&value) {
+            Some(date) => date,
+            None => return Err(de::Error::custom(format!("expected a date, found {}", value))),
+        };
+        Ok(Timestamped(date.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::FixedOffset;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn encodes_each_format() {
+        let offset = FixedOffset::east_opt(3600).unwrap();
+        let date = offset.with_ymd_and_hms(2024, 1, 31, 13, 0, 0).unwrap();
+        assert_eq!(DateFormat::EpochSeconds.encode(&date), json!(1706702400));
+        assert_eq!(DateFormat::EpochMillis.encode(&date), json!(1706702400000_i64));
+        assert_eq!(DateFormat::Rfc3339.encode(&date), json!("2024-01-31T12:00:00.000Z"));
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn timestamped_follows_scope() {
+        let date = Timestamped(Utc.with_ymd_and_hms(2024, 1, 31, 12, 0, 0).unwrap());
+        let millis = scoped(DateFormat::EpochMillis, || serde_json::to_value(date).unwrap());
+        assert_eq!(millis, json!(1706702400000_i64));
+        assert_eq!(serde_json::to_value(date).unwrap(), json!(1706702400));
+        let read = |value| serde_json::from_value::<Timestamped<FixedOffset>>(value).unwrap().0.to_utc();
+        assert_eq!(scoped(DateFormat::EpochMillis, || read(millis)), date.0);
+        assert_eq!(scoped(DateFormat::Rfc3339, || read(json!(1706702400))), date.0);
+        assert_eq!(read(json!("2024-01-31T13:00:00+01:00")), date.0);
+    }
+}
@@ -0,0 +1,102 @@
+use std::{ collections::BTreeSet, fmt };
+
+use serde_json::Value;
+
+/// The field level differences between two records.
+/// 
+/// Nested objects are compared field by field and reported with dotted paths, e.g. `profile.age`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RecordDiff {
+    /// Fields only present in the second record.
+    pub added: Vec<(String, Value)>,
+    /// Fields only present in the first record.
+    pub removed: Vec<(String, Value)>,
+    /// Fields present in both records with different values, as `(path, old, new)`.
+    pub changed: Vec<(String, Value, Value)>,
+}
+
+impl RecordDiff {
+
+    /// Returns true if the records are identical.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+impl fmt::Display for RecordDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no changes");
+        }
+        let mut lines = Vec::new();
+        for (path, value) in self.added.iter() {
+            lines.push(format!("+ {}: {}", path, value));
+        }
+        for (path, value) in self.removed.iter() {
+            lines.push(format!("- {}: {}", path, value));
+        }
+        for (path, old, new) in self.changed.iter() {
+            lines.push(format!("~ {}: {} -> {}", path, old, new));
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+fn join(prefix: &str, field: &str) -> String {
+    if prefix.is_empty() { field.to_string() } else { format!("{}.{}", prefix, field) }
+}
+
+fn walk(prefix: &str, a: &Value, b: &Value, diff: &mut RecordDiff) {
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            let fields = a.keys().chain(b.keys()).collect::<BTreeSet<&String>>();
+            for field in fields {
+                let path = join(prefix, field);
+                match (a.get(field), b.get(field)) {
+                    (Some(old), Some(new)) => walk(&path, old, new, diff),
+                    (Some(old), None) => diff.removed.push((path, old.clone())),
+                    (None, Some(new)) => diff.added.push((path, new.clone())),
+                    (None, None) => {},
+                }
+            }
+        },
+        (a, b) if a != b => {
+            let path = if prefix.is_empty() { String::from("(root)") } else { prefix.to_string() };
+            diff.changed.push((path, a.clone(), b.clone()));
+        },
+        _ => {},
+    }
+}
+
+/// Compares two records and returns the added, removed and changed fields.
+/// ```rust
+/// use detalib::diff::diff_records;
+/// use serde_json::json;
+/// 
+/// let diff = diff_records(&json!({ "name": "John", "age": 20 }), &json!({ "name": "John", "age": 21 }));
+/// assert_eq!(diff.to_string(), "~ age: 20 -> 21");
+/// ```
+pub fn diff_records(a: &Value, b: &Value) -> RecordDiff {
+    let mut diff = RecordDiff::default();
+    walk("", a, b, &mut diff);
+    diff
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn nested_fields() {
+        let a = json!({ "key": "1", "tags": ["a"], "profile": { "age": 20, "city": "Paris" } });
+        let b = json!({ "key": "1", "tags": ["a", "b"], "profile": { "age": 20, "zip": "75" } });
+        let diff = diff_records(&a, &b);
+        assert_eq!(diff.added, vec![(String::from("profile.zip"), json!("75"))]);
+        assert_eq!(diff.removed, vec![(String::from("profile.city"), json!("Paris"))]);
+        assert_eq!(diff.changed, vec![(String::from("tags"), json!(["a"]), json!(["a", "b"]))]);
+        assert!(diff_records(&a, &a).is_empty());
+        assert_eq!(diff_records(&a, &a).to_string(), "no changes");
+    }
+}
@@ -1,12 +1,51 @@
-use crate::{errors::DetaError, query::Paging };
+use crate::{ errors::DetaError, query::Paging };
+#[cfg(feature = "blocking")]
+use crate::{ checksum, response };
 
+use std::{ fmt, sync::Arc, time::Duration };
+#[cfg(feature = "blocking")]
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fs::File,
+    io::{ BufRead, BufReader, Read, Write },
+    path::Path,
+    sync::{ Mutex, MutexGuard },
+    time::Instant,
+};
+
+use chrono::{ DateTime, Utc };
+#[cfg(feature = "blocking")]
+use flate2::{ read::MultiGzDecoder, write::GzEncoder, Compression };
+#[cfg(feature = "blocking")]
 use ureq::Response;
 use serde::{ Serialize, Deserialize };
+#[cfg(feature = "blocking")]
 use serde::de::DeserializeOwned;
+#[cfg(feature = "blocking")]
 use serde_json::{ json, Value };
 
+#[cfg(feature = "serve")]
+pub use crate::serve::serve;
+
 
-const MAX_CHUNK_SIZE: usize = 10 * 1024 * 1024;
+pub (crate) const MAX_CHUNK_SIZE: usize = 10 * 1024 * 1024;
+#[cfg(feature = "blocking")]
+const JSONL_COMPACT_THRESHOLD: usize = 32;
+pub (crate) const PENDING_UPLOADS_PREFIX: &str = ".detalib/uploads/";
+
+#[cfg(feature = "blocking")]
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(feature = "blocking")]
+fn compressed_name(name: &str) -> Cow<'_, str> {
+    match name.ends_with(".gz") {
+        true => Cow::Borrowed(name),
+        false => Cow::Owned(format!("{}.gz", name)),
+    }
+}
 
 #[derive(Deserialize, Serialize)]
 pub struct FileList {
@@ -14,27 +53,329 @@ pub struct FileList {
     pub(crate) names: Vec<String>
 }
 
+impl FileList {
+
+    /// Drops the markers of upload sessions, unless `prefix` points inside them.
+    pub (crate) fn without_markers(mut self, prefix: Option<&str>) -> FileList {
+        if !prefix.is_some_and(|prefix| prefix.starts_with(PENDING_UPLOADS_PREFIX)) {
+            self.names.retain(|name| !name.starts_with(PENDING_UPLOADS_PREFIX));
+        }
+        self
+    }
+}
+
 #[derive(Deserialize, Serialize)]
-struct Metadata {
+pub (crate) struct Metadata {
     name: String,
-    upload_id: String,
+    pub(crate) upload_id: String,
     project_id: String,
     drive_name: String
 }
 
+/// A chunked upload session that was started but not completed or aborted.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PendingUpload {
+    pub upload_id: String,
+    pub name: String,
+    /// Unix timestamp (seconds) of when the upload was started.
+    pub started_at: i64,
+}
+
+/// Metadata of a drive file, returned by `Drive::head`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileMetadata {
+    pub name: String,
+    /// Size of the content in bytes, if the server reported it.
+    pub size: Option<u64>,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+#[cfg(feature = "blocking")]
+impl FileMetadata {
+
+    fn from_response(name: &str, resp: &Response) -> FileMetadata {
+        FileMetadata {
+            name: name.to_string(),
+            size: resp.header("Content-Length").and_then(|size| size.parse().ok()),
+            content_type: resp.header("Content-Type").map(String::from),
+            etag: resp.header("ETag").map(String::from),
+            last_modified: resp.header("Last-Modified")
+                .and_then(|date| DateTime::parse_from_rfc2822(date).ok())
+                .map(|date| date.with_timezone(&Utc)),
+        }
+    }
+
+    /// Whether both describe the same version of a file, as far as the server tells.
+    fn same_version(&self, other: &FileMetadata) -> bool {
+        match (&self.etag, &other.etag, &self.last_modified, &other.last_modified) {
+            (Some(a), Some(b), _, _) => a == b,
+            (_, _, Some(a), Some(b)) => a == b && self.size == other.size,
+            _ => false,
+        }
+    }
+}
+
+#[cfg(feature = "blocking")]
+pub (crate) struct CachedFile {
+    content: Arc<[u8]>,
+    meta: FileMetadata,
+    fetched: Instant,
+}
+
+#[cfg(feature = "blocking")]
+pub (crate) type FileCache = Arc<Mutex<HashMap<String, CachedFile>>>;
+
+/// The part files of a JSON Lines file that are not merged into it yet.
+#[cfg(feature = "blocking")]
+#[derive(Default)]
+pub (crate) struct JsonlParts {
+    next: u64,
+    pending: Vec<String>,
+}
+
+#[cfg(feature = "blocking")]
+pub (crate) type JsonlSegments = Arc<Mutex<HashMap<String, JsonlParts>>>;
+
+/// The resumable state of a multi-part upload session.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct UploadState {
+    pub name: String,
+    pub upload_id: String,
+    pub content_type: Option<String>,
+    /// Numbers of the parts uploaded so far.
+    pub parts: Vec<u32>,
+}
+
+/// How files over 10 MB are uploaded in parts by `Drive::put` and `Drive::put_file`,
+/// set with `Drive::with_upload_options`.
+/// ```rust,no_run
+/// use detalib::{ Deta, drive::UploadOptions };
+///
+/// let videos = Deta::new().drive("videos")
+///     .with_upload_options(UploadOptions::new().parallelism(4).part_retries(3));
+/// videos.put_file("talk.mp4", "talk.mp4", Some("video/mp4")).unwrap();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UploadOptions {
+    pub (crate) parallelism: usize,
+    part_retries: u32,
+}
+
+impl Default for UploadOptions {
+    fn default() -> Self {
+        UploadOptions::new()
+    }
+}
+
+impl UploadOptions {
+
+    /// Uploads one part at a time, retrying each part twice.
+    pub fn new() -> UploadOptions {
+        UploadOptions { parallelism: 1, part_retries: 2 }
+    }
+
+    /// Sets how many parts are uploaded at once, each holding a 10 MB chunk in memory.
+    /// Parts are sent from a thread pool, or as tasks of the tokio runtime for async drives.
+    pub fn parallelism(mut self, parallelism: usize) -> Self {
+        self.parallelism = parallelism.max(1);
+        self
+    }
+
+    /// Sets how many times a part is sent again after a server or transport error,
+    /// before the whole upload is aborted.
+    pub fn part_retries(mut self, retries: u32) -> Self {
+        self.part_retries = retries;
+        self
+    }
+
+    /// The delay before sending a part again after the given attempt (starting at 0).
+    pub (crate) fn retry_delay(attempt: u32) -> Duration {
+        Duration::from_millis(500).saturating_mul(2u32.saturating_pow(attempt))
+    }
+
+    /// Whether a part that failed with this error on the given attempt should be sent again.
+    pub (crate) fn should_retry(&self, attempt: u32, e: &DetaError) -> bool {
+        attempt < self.part_retries && match e {
+            DetaError::TransportError => true,
+            DetaError::HTTPError { status, .. } => *status == 408 || *status == 429 || *status >= 500,
+            _ => false,
+        }
+    }
+}
+
+/// A multi-part upload session, created with `Drive::start_upload`.
+#[cfg(feature = "blocking")]
+pub struct Upload {
+    drive: Drive,
+    state: UploadState,
+}
+
+#[cfg(feature = "blocking")]
+impl Upload {
 
+    /// The current state, to persist for resuming after a crash.
+    pub fn state(&self) -> &UploadState {
+        &self.state
+    }
+
+    /// The number of the next part after the ones uploaded so far.
+    pub fn next_part(&self) -> u32 {
+        self.state.parts.iter().max().map_or(1, |part| part + 1)
+    }
+
+    /// Upload a part. Parts are numbered from 1 and assembled in order.
+    /// 
+    /// Sending a part again replaces it. Failed parts are retried as set by `UploadOptions`.
+    pub fn send_part(&mut self, part: u32, content: &[u8]) -> Result<(), DetaError> {
+        self.post_part(part, content)?;
+        if !self.state.parts.contains(&part) {
+            self.state.parts.push(part);
+        }
+        Ok(())
+    }
+
+    fn post_part(&self, part: u32, content: &[u8]) -> Result<(), DetaError> {
+        let path = format!(
+            "/uploads/{}/parts?name={}&part={}",
+            self.state.upload_id, urlencoding::encode(&self.state.name), part
+        );
+        let mut attempt = 0;
+        loop {
+            match self.drive.request("POST", &path, None, Some(content), self.state.content_type.as_deref()) {
+                Ok(_) => return Ok(()),
+                Err(e) if self.drive.upload.should_retry(attempt, &e) => {
+                    std::thread::sleep(UploadOptions::retry_delay(attempt));
+                    attempt += 1;
+                },
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Assemble the uploaded parts into the file and end the session.
+    pub fn complete(self) -> Result<Response, DetaError> {
+        let path = format!(
+            "/uploads/{}?name={}", self.state.upload_id, urlencoding::encode(&self.state.name)
+        );
+        let resp = self.drive.request("PATCH", &path, None, None, None)?;
+        _ = self.drive.delete(vec![&format!("{}{}", PENDING_UPLOADS_PREFIX, self.state.upload_id)]);
+        Ok(resp)
+    }
+
+    /// Discard the uploaded parts and end the session.
+    pub fn abort(self) -> Result<(), DetaError> {
+        self.drive.abort_upload(&self.state.upload_id, &self.state.name)
+    }
+}
+
+#[cfg(feature = "blocking")]
 fn de<T: DeserializeOwned>(r: Result<Response, DetaError>) -> Result<T, DetaError> {
-    r.map_err(DetaError::from).and_then(|r| {
-        r.into_json::<T>().map_err(DetaError::from)
-    })
+    r.and_then(response::parse)
+}
+
+/// The HMAC-SHA256 signing a proxy link to a file, keyed with the project key.
+pub (crate) fn proxy_signature(drive: &Drive, name: &str, expires: i64) -> String {
+    let payload = format!("{}\n{}\n{}", drive.name, name, expires);
+    crate::webhook::sign(drive.service.inner.project_key.as_bytes(), payload.as_bytes())
+}
+
+/// A signed link to a drive file that stops working after a while, made with `Drive::proxy_url`.
+///
+/// Links are signed with the project key and point to a `drive::serve` server (feature `serve`),
+/// which checks them and streams the file, so files can be shared without making the drive
+/// public. Format the link with `to_string`.
+#[derive(Clone)]
+pub struct ProxyUrl {
+    drive: Drive,
+    name: String,
+    base_url: String,
+    expires_in: Duration,
+}
+
+impl ProxyUrl {
+
+    /// Sets the address the server is reachable at, e.g. `https://files.example.com`.
+    /// Without it the link is a path to append to that address.
+    pub fn base_url(mut self, base_url: &str) -> Self {
+        self.base_url = base_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Sets how long the link works, counted from when it is formatted. Defaults to one hour.
+    pub fn expires_in(mut self, expires_in: Duration) -> Self {
+        self.expires_in = expires_in;
+        self
+    }
+}
+
+impl fmt::Display for ProxyUrl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let expires_in = i64::try_from(self.expires_in.as_secs()).unwrap_or(i64::MAX);
+        let expires = Utc::now().timestamp().saturating_add(expires_in);
+        write!(
+            f, "{}/{}/{}?expires={}&signature={}",
+            self.base_url,
+            urlencoding::encode(&self.drive.name),
+            urlencoding::encode(&self.name),
+            expires,
+            proxy_signature(&self.drive, &self.name, expires)
+        )
+    }
 }
 
 /// Represents a Deta Drive.
+#[derive(Clone)]
 pub struct Drive {
-    pub name: String,
+    pub(crate) name: Arc<str>,
     pub(crate) service: crate::Deta,
+    #[cfg(feature = "blocking")]
+    pub(crate) cache: FileCache,
+    #[cfg(feature = "blocking")]
+    pub(crate) segments: JsonlSegments,
+    pub(crate) upload: UploadOptions,
 }
 
+impl Drive {
+
+    /// The name of the drive, which replaces the public `name` field of 0.1.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Sets how files over 10 MB are uploaded in parts, see `UploadOptions`.
+    pub fn with_upload_options(mut self, options: UploadOptions) -> Drive {
+        self.upload = options;
+        self
+    }
+
+    pub (crate) fn url(&self, path: &str) -> String {
+        format!("https://drive.deta.sh/v1/{}/{}{}", self.service.inner.project_id, self.name, path)
+    }
+
+    /// Returns a signed link to a file that expires after an hour, see `ProxyUrl`.
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    /// use detalib::Deta;
+    ///
+    /// let uploads = Deta::new().drive("uploads");
+    /// let link = uploads.proxy_url("avatars/u1.png")
+    ///     .base_url("https://files.example.com")
+    ///     .expires_in(Duration::from_secs(600))
+    ///     .to_string();
+    /// ```
+    pub fn proxy_url(&self, name: &str) -> ProxyUrl {
+        ProxyUrl {
+            drive: self.clone(),
+            name: name.to_string(),
+            base_url: String::new(),
+            expires_in: Duration::from_secs(3600),
+        }
+    }
+}
+
+#[cfg(feature = "blocking")]
 impl Drive {
 
     fn request(
@@ -45,28 +386,22 @@ impl Drive {
         body: Option<&[u8]>,
         content_type: Option<&str>
     ) -> Result<Response, DetaError> {
-        let mut req = ureq::request(method, &format!(
-            "https://drive.deta.sh/v1/{}/{}{}", self.service.project_id, self.name, path))
-            .set("X-API-Key", &self.service.project_key);
         match (json, body) {
             (Some(_), Some(_)) => Err(
                 DetaError::PayloadError { msg: String::from("body and json are mutually exclusive.") }
             ),
             (Some(o), None) => {
-                req = req.set("Content-Type", "application/json");
-                req.send_json(o).map_err(DetaError::from)
-            },
-            (None, Some(b)) => {
-                if content_type.is_some() {
-                    req = req.set("Content-Type", content_type.unwrap());
-                }
-                req.send_bytes(b).map_err(DetaError::from)
+                let o = serde_json::to_vec(&o)?;
+                self.service.send(method, &self.url(path), Some(&o), Some("application/json"))
             },
-            (None, None) => req.call().map_err(DetaError::from),
+            (None, Some(b)) => self.service.send(method, &self.url(path), Some(b), content_type),
+            (None, None) => self.service.send(method, &self.url(path), None, None),
         }
     }
 
     /// List files in drive.
+    /// 
+    /// Upload session markers under `.detalib/uploads/` are left out unless `prefix` points inside them.
     pub fn list(
         &self,
         prefix: Option<&str>,
@@ -85,51 +420,181 @@ impl Drive {
         if let Some(last) = last {
             path.push_str(&format!("&last={}", last));
         }
-        de::<FileList>(self.request("GET", &path, None, None, None))
+        de::<FileList>(self.request("GET", &path, None, None, None)).map(|list| list.without_markers(prefix))
     }
 
     /// Walk through all files in drive and returns a list of file names.
-    pub fn walk(&self, prefix: Option<&str>) -> Vec<String> {
-        let mut files: Vec<String> = vec![];
-        let mut res = self.list(prefix, None, None);
-        if res.is_err() {
-            return files;
-        }
-        let mut list = res.unwrap();
-        files.append(&mut list.names);
-        if list.paging.is_none() {
-            return files;
-        }
-        let mut last = list.paging.unwrap().last;
-        while !last.is_empty() {
-            res = self.list(prefix, Some(1000), Some(&last));
-            if res.is_err() {
-                return files;
-            }
-            list = res.unwrap();
-            files.append(&mut list.names);
-            last = list.paging.unwrap().last
+    ///
+    /// Fails if any page cannot be listed. Use `iter_files` to process huge drives page by page.
+    pub fn walk(&self, prefix: Option<&str>) -> Result<Vec<String>, DetaError> {
+        let mut files = Vec::new();
+        for page in self.iter_files(prefix) {
+            files.extend(page?);
         }
-        files
+        Ok(files)
+    }
+
+    /// Returns a lazy iterator over the pages of file names, listing each page as it is consumed.
+    /// ```rust,no_run
+    /// use detalib::Deta;
+    ///
+    /// let drive = Deta::new().drive("photos");
+    /// for page in drive.iter_files(Some("2024/")) {
+    ///     for name in page.unwrap() {
+    ///         println!("{}", name);
+    ///     }
+    /// }
+    /// ```
+    pub fn iter_files(&self, prefix: Option<&str>) -> crate::iter::FilePages {
+        crate::iter::FilePages::new(self.clone(), prefix)
     }
 
     /// Get a file from drive.
     pub fn get(&self, name: &str) -> Result<Response, DetaError> {
-        let path = format!("/files/download?name={}", name);
-        let url = format!(
-            "https://drive.deta.sh/v1/{}/{}{}", self.service.project_id, self.name, path);
-        ureq::get(&url)
-            .set("X-API-Key", &self.service.project_key)
-            .call()
-            .map_err(DetaError::from)
+        let path = format!("/files/download?name={}", urlencoding::encode(name));
+        self.service.send("GET", &self.url(&path), None, None)
+    }
+
+    /// Get the bytes of a file from `start` up to, but not including, `end`, or to the end of the
+    /// file if `end` is `None`.
+    ///
+    /// Only the requested bytes are downloaded, so large files can be read from any offset.
+    /// Fewer bytes are returned if the file ends before `end`, and none if it ends before `start`.
+    /// ```rust,no_run
+    /// use detalib::Deta;
+    ///
+    /// let drive = Deta::new().drive("videos");
+    /// let header = drive.get_range("intro.mp4", 0, Some(1024)).unwrap();
+    /// let rest = drive.get_range("intro.mp4", 1024, None).unwrap();
+    /// ```
+    pub fn get_range(&self, name: &str, start: u64, end: Option<u64>) -> Result<Vec<u8>, DetaError> {
+        let range = match end {
+            Some(end) if end <= start => return Ok(Vec::new()),
+            Some(end) => format!("bytes={}-{}", start, end - 1),
+            None => format!("bytes={}-", start),
+        };
+        let url = self.url(&format!("/files/download?name={}", urlencoding::encode(name)));
+        let resp = match self.service.send_with("GET", &url, None, None, &[("Range", range)]) {
+            Ok(resp) => resp,
+            Err(DetaError::HTTPError { status: 416, .. }) => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        // a server ignoring the range sends the whole file
+        let skip = if resp.status() == 206 { 0 } else { start };
+        let mut reader = resp.into_reader();
+        std::io::copy(&mut reader.by_ref().take(skip), &mut std::io::sink())?;
+        let mut content = Vec::new();
+        reader.take(end.map_or(u64::MAX, |end| end - start)).read_to_end(&mut content)?;
+        Ok(content)
+    }
+
+    /// Opens an existing file for `Read`, `Write` and `Seek`, see `DriveFile`.
+    pub fn open_file(&self, name: &str) -> Result<crate::file::DriveFile, DetaError> {
+        crate::file::DriveFile::open(self.clone(), name)
+    }
+
+    /// Creates an empty file, replacing any existing one when flushed, see `DriveFile`.
+    pub fn create_file(&self, name: &str) -> crate::file::DriveFile {
+        crate::file::DriveFile::create(self.clone(), name)
+    }
+
+    /// Get the metadata of a file without downloading its content.
+    pub fn head(&self, name: &str) -> Result<FileMetadata, DetaError> {
+        let path = format!("/files/download?name={}", urlencoding::encode(name));
+        let resp = self.service.send("HEAD", &self.url(&path), None, None)?;
+        Ok(FileMetadata::from_response(name, &resp))
+    }
+
+    /// Get a file from an in-memory cache shared by clones of this drive, downloading it if needed.
+    /// 
+    /// Once an entry is older than `ttl`, a `head` request checks whether the file changed
+    /// and it is only downloaded again if so, or if the server gives no version information.
+    /// Writes and deletes through this drive evict the cached file.
+    pub fn get_cached(&self, name: &str, ttl: Duration) -> Result<Arc<[u8]>, DetaError> {
+        let cached = self.cache().get(name)
+            .map(|file| (file.content.clone(), file.meta.clone(), file.fetched));
+        if let Some((content, meta, fetched)) = cached {
+            if fetched.elapsed() < ttl {
+                return Ok(content);
+            }
+            if self.head(name)?.same_version(&meta) {
+                if let Some(file) = self.cache().get_mut(name) {
+                    file.fetched = Instant::now();
+                }
+                return Ok(content);
+            }
+        }
+        let resp = self.get(name)?;
+        let meta = FileMetadata::from_response(name, &resp);
+        let mut content = Vec::new();
+        resp.into_reader().read_to_end(&mut content)?;
+        let content = Arc::<[u8]>::from(content);
+        let file = CachedFile { content: content.clone(), meta, fetched: Instant::now() };
+        self.cache().insert(name.to_string(), file);
+        Ok(content)
+    }
+
+    /// Download a file unless it matches `known_hash`, returning `None` if it is unchanged.
+    /// 
+    /// `known_hash` is either the `etag` from `head` or the `checksum::digest` of a local copy.
+    /// An ETag match skips the download. Deta keeps no content hashes, so a digest can only be
+    /// compared after downloading, which still spares the caller from rewriting an unchanged copy.
+    pub fn get_if_changed(&self, name: &str, known_hash: &str) -> Result<Option<Vec<u8>>, DetaError> {
+        if self.head(name)?.etag.as_deref() == Some(known_hash) {
+            return Ok(None);
+        }
+        let mut content = Vec::new();
+        self.get(name)?.into_reader().read_to_end(&mut content)?;
+        match checksum::digest(&content) == known_hash {
+            true => Ok(None),
+            false => Ok(Some(content)),
+        }
+    }
+
+    fn cache(&self) -> MutexGuard<'_, HashMap<String, CachedFile>> {
+        lock(&self.cache)
+    }
+
+    fn evict(&self, name: &str) {
+        self.cache().remove(name);
+    }
+
+    /// Stream a file from drive line by line.
+    /// 
+    /// Files ending with `.gz` are decompressed on the fly.
+    pub fn lines(
+        &self, name: &str
+    ) -> Result<impl Iterator<Item = Result<String, DetaError>>, DetaError> {
+        let mut reader: Box<dyn Read + Send + Sync> = self.get(name)?.into_reader();
+        if name.ends_with(".gz") {
+            reader = Box::new(MultiGzDecoder::new(reader));
+        }
+        Ok(BufReader::new(reader).lines().map(|line| line.map_err(DetaError::from)))
+    }
+
+    /// Gzip content and put it as `save_as` with a `.gz` extension, added if missing.
+    ///
+    /// Read it back with `get_decompressed` or `lines` under the name without the extension.
+    pub fn put_compressed(&self, save_as: &str, content: &[u8]) -> Result<Response, DetaError> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(content)?;
+        self.put(&compressed_name(save_as), &encoder.finish()?, Some("application/gzip"))
+    }
+
+    /// Download a file stored with `put_compressed` and decompress it.
+    pub fn get_decompressed(&self, name: &str) -> Result<Vec<u8>, DetaError> {
+        let mut content = Vec::new();
+        MultiGzDecoder::new(self.get(&compressed_name(name))?.into_reader()).read_to_end(&mut content)?;
+        Ok(content)
     }
 
     /// Put a new file to drive.
     pub fn put(
         &self, save_as: &str, content: &[u8], content_type: Option<&str>
     ) -> Result<Response, DetaError> {
-        let encoded = &urlencoding::encode(save_as).into_owned();
+        self.evict(save_as);
         if content.len() <= MAX_CHUNK_SIZE {
+            let encoded = urlencoding::encode(save_as).into_owned();
             return self.request(
                 "POST",
                 &format!("/files?name={}", encoded),
@@ -138,27 +603,471 @@ impl Drive {
                 content_type
             );
         }
-        let res = de::<Metadata>(
+        let chunks = content.chunks(MAX_CHUNK_SIZE).map(|chunk| Ok(Cow::Borrowed(chunk)));
+        self.upload(save_as, content_type, chunks)
+    }
+
+    /// Put a file from disk to drive, streaming it in 10 MB chunks
+    /// instead of loading it into memory at once.
+    pub fn put_file<P: AsRef<Path>>(
+        &self, save_as: &str, path: P, content_type: Option<&str>
+    ) -> Result<Response, DetaError> {
+        let mut file = File::open(path)?;
+        if file.metadata()?.len() <= MAX_CHUNK_SIZE as u64 {
+            let mut content = Vec::new();
+            file.read_to_end(&mut content)?;
+            return self.put(save_as, &content, content_type);
+        }
+        let chunks = std::iter::from_fn(move || {
+            let mut chunk = Vec::with_capacity(MAX_CHUNK_SIZE);
+            match (&mut file).take(MAX_CHUNK_SIZE as u64).read_to_end(&mut chunk) {
+                Ok(0) => None,
+                Ok(_) => Some(Ok(Cow::Owned(chunk))),
+                Err(e) => Some(Err(DetaError::from(e))),
+            }
+        });
+        self.upload(save_as, content_type, chunks)
+    }
+
+    /// Start a multi-part upload session, tracked by a marker file until it is completed or aborted.
+    /// 
+    /// Keep `Upload::state` somewhere durable to resume the upload after a crash with `resume_upload`.
+    pub fn start_upload(&self, name: &str, content_type: Option<&str>) -> Result<Upload, DetaError> {
+        let encoded = urlencoding::encode(name).into_owned();
+        let meta = de::<Metadata>(
             self.request(
-                "POST", &format!("/uploads?name={}", encoded), None, None, None));
-        if res.is_err() {
-            return Err(res.err().unwrap());
-        }
-        let meta = res.unwrap();
-        for (i, chunk) in content.chunks(MAX_CHUNK_SIZE).enumerate() {
-            let path = &format!("/uploads/{}/parts?name={}&part={}", meta.upload_id, encoded, i+1);
-            let resp = self.request(
-                "POST", path, None, Some(chunk), content_type);
-            if resp.is_err() {
-                _ = self.request("DELETE", path, None, None, None);
-                return Err(resp.err().unwrap());
+                "POST", &format!("/uploads?name={}", encoded), None, None, None))?;
+        let marker = PendingUpload {
+            upload_id: meta.upload_id.clone(),
+            name: name.to_string(),
+            started_at: chrono::Utc::now().timestamp(),
+        };
+        let marker_name = format!("{}{}", PENDING_UPLOADS_PREFIX, meta.upload_id);
+        if let Err(e) = self.put(&marker_name, &serde_json::to_vec(&marker)?, Some("application/json")) {
+            _ = self.abort_upload(&meta.upload_id, name);
+            return Err(e);
+        }
+        Ok(self.resume_upload(UploadState {
+            name: name.to_string(),
+            upload_id: meta.upload_id,
+            content_type: content_type.map(String::from),
+            parts: vec![],
+        }))
+    }
+
+    /// Continue an upload session from a saved state.
+    pub fn resume_upload(&self, state: UploadState) -> Upload {
+        Upload { drive: self.clone(), state }
+    }
+
+    /// Uploads the chunks through a multi-part upload session, aborting it on failure.
+    ///
+    /// Up to `UploadOptions::parallelism` threads take the next chunk and send it as a part.
+    fn upload<'a, I>(
+        &self, save_as: &str, content_type: Option<&str>, chunks: I
+    ) -> Result<Response, DetaError>
+        where I: Iterator<Item = Result<Cow<'a, [u8]>, DetaError>> + Send
+    {
+        self.evict(save_as);
+        let mut upload = self.start_upload(save_as, content_type)?;
+        let chunks = Mutex::new(chunks.enumerate());
+        let (sent, failure) = (Mutex::new(Vec::new()), Mutex::new(None));
+        std::thread::scope(|scope| {
+            for _ in 0..self.upload.parallelism {
+                scope.spawn(|| while lock(&failure).is_none() {
+                    let Some((i, chunk)) = lock(&chunks).next() else {
+                        return;
+                    };
+                    let part = i as u32 + 1;
+                    match chunk.and_then(|chunk| upload.post_part(part, &chunk)) {
+                        Ok(()) => lock(&sent).push(part),
+                        Err(e) => _ = lock(&failure).get_or_insert(e),
+                    }
+                });
             }
+        });
+        if let Some(e) = failure.into_inner().unwrap_or_else(|e| e.into_inner()) {
+            _ = upload.abort();
+            return Err(e);
+        }
+        let mut parts = sent.into_inner().unwrap_or_else(|e| e.into_inner());
+        parts.sort_unstable();
+        upload.state.parts = parts;
+        upload.complete()
+    }
+
+    /// List chunked uploads that were started but never completed or aborted,
+    /// e.g. because the uploading process crashed.
+    /// 
+    /// Sessions are tracked with small marker files under `.detalib/uploads/`.
+    pub fn list_pending_uploads(&self) -> Result<Vec<PendingUpload>, DetaError> {
+        let mut uploads = Vec::new();
+        for marker in self.walk(Some(PENDING_UPLOADS_PREFIX))? {
+            uploads.push(de::<PendingUpload>(self.get(&marker))?);
+        }
+        Ok(uploads)
+    }
+
+    /// Abort a chunked upload session, discarding its uploaded parts.
+    pub fn abort_upload(&self, upload_id: &str, name: &str) -> Result<(), DetaError> {
+        let encoded = urlencoding::encode(name).into_owned();
+        match self.request(
+            "DELETE", &format!("/uploads/{}?name={}", upload_id, encoded), None, None, None) {
+            Ok(_) | Err(DetaError::NotFound { .. }) => {},
+            Err(e) => return Err(e),
+        }
+        self.delete(vec![&format!("{}{}", PENDING_UPLOADS_PREFIX, upload_id)])?;
+        Ok(())
+    }
+
+    /// Append serializable records to a JSON Lines file in drive.
+    /// 
+    /// Drive objects are immutable, so every append is stored as a numbered part file under
+    /// `<name>.parts/` and the parts are merged into `name` once enough of them pile up.
+    /// Call `compact_jsonl` before reading `name` to include the latest appends.
+    /// 
+    /// The parts are tracked by this `Drive` and its clones, listing `<name>.parts/` only on
+    /// the first append or compaction of `name`. A file must have a single writer: appends
+    /// from another process are missed until the next listing and concurrent compactions
+    /// merge the same parts twice.
+    pub fn append_jsonl<T: Serialize>(&self, name: &str, records: &[T]) -> Result<(), DetaError> {
+        let mut content = Vec::new();
+        for record in records {
+            serde_json::to_writer(&mut content, record)?;
+            content.push(b'\n');
+        }
+        let mut segments = lock(&self.segments);
+        let parts = self.jsonl_parts(&mut segments, name)?;
+        let part = format!("{}.parts/{:020}", name, parts.next);
+        self.put(&part, &content, Some("application/x-ndjson"))?;
+        parts.next += 1;
+        parts.pending.push(part);
+        if parts.pending.len() >= JSONL_COMPACT_THRESHOLD {
+            self.merge_jsonl(name, parts)?;
+        }
+        Ok(())
+    }
+
+    /// Merge all pending part files of a JSON Lines file into the file itself.
+    /// 
+    /// Compactions through the same `Drive` are serialized, see `append_jsonl`.
+    pub fn compact_jsonl(&self, name: &str) -> Result<(), DetaError> {
+        let mut segments = lock(&self.segments);
+        let parts = self.jsonl_parts(&mut segments, name)?;
+        self.merge_jsonl(name, parts)
+    }
+
+    /// The tracked parts of `name`, seeded from a listing the first time it is used.
+    fn jsonl_parts<'a>(
+        &self, segments: &'a mut HashMap<String, JsonlParts>, name: &str
+    ) -> Result<&'a mut JsonlParts, DetaError> {
+        if !segments.contains_key(name) {
+            let mut pending = self.walk(Some(&format!("{}.parts/", name)))?;
+            pending.sort();
+            let next = pending.iter()
+                .filter_map(|part| part.rsplit('/').next()?.parse::<u64>().ok())
+                .max()
+                .map_or(0, |last| last + 1);
+            segments.insert(name.to_string(), JsonlParts { next, pending });
+        }
+        Ok(segments.get_mut(name).expect("parts were just tracked"))
+    }
+
+    fn merge_jsonl(&self, name: &str, parts: &mut JsonlParts) -> Result<(), DetaError> {
+        if parts.pending.is_empty() {
+            return Ok(());
+        }
+        let mut content = Vec::new();
+        match self.get(name) {
+            Ok(resp) => { resp.into_reader().read_to_end(&mut content)?; },
+            Err(DetaError::NotFound { .. }) => {},
+            Err(e) => return Err(e),
         }
-        self.request("PATCH", &format!("/uploads?name={}", encoded), None, None, None)
+        for part in parts.pending.iter() {
+            self.get(part)?.into_reader().read_to_end(&mut content)?;
+        }
+        self.put(name, &content, Some("application/x-ndjson"))?;
+        for chunk in parts.pending.chunks(1000) {
+            self.delete(chunk.iter().map(String::as_str).collect())?;
+        }
+        parts.pending.clear();
+        Ok(())
     }
 
     /// Delete multiple files from drive.
     pub fn delete(&self, names: Vec<&str>) -> Result<Response, DetaError> {
+        for name in names.iter() {
+            self.evict(name);
+        }
         self.request("DELETE", "/files", Some(json!({ "names": names })), None, None)
     }
 }
+
+#[cfg(all(test, feature = "blocking"))]
+mod tests {
+    use std::{ collections::HashMap, sync::{ Arc, Mutex } };
+
+    use super::*;
+    use crate::{ Deta, transport::{ Request, Transport } };
+    #[cfg(feature = "mock")]
+    use crate::mock::MockDeta;
+
+    const CONTENT: &str = "0123456789";
+
+    /// Serves `CONTENT` for every download, honoring the `Range` header like Deta does.
+    struct Ranges;
+
+    impl Transport for Ranges {
+        fn send(&self, request: &Request) -> Result<ureq::Response, ureq::Error> {
+            let range = request.header("range").and_then(|range| range.strip_prefix("bytes="));
+            let Some(range) = range else {
+                return ureq::Response::new(200, "OK", CONTENT);
+            };
+            let (first, last) = range.split_once('-').unwrap();
+            let first = first.parse::<usize>().unwrap();
+            let end = CONTENT.len() - 1;
+            let last = last.parse::<usize>().map_or(end, |last| last.min(end));
+            match first < CONTENT.len() {
+                true => ureq::Response::new(206, "Partial Content", &CONTENT[first..=last]),
+                false => {
+                    let resp = ureq::Response::new(416, "Range Not Satisfiable", "")?;
+                    Err(ureq::Error::Status(416, resp))
+                },
+            }
+        }
+    }
+
+    /// Accepts multi-part uploads, failing the first attempt at every part.
+    #[derive(Default)]
+    struct Flaky {
+        attempts: Mutex<HashMap<String, u32>>,
+    }
+
+    impl Transport for Arc<Flaky> {
+        fn send(&self, request: &Request) -> Result<ureq::Response, ureq::Error> {
+            if request.url.contains("/uploads?") {
+                let meta = r#"{"name": "big", "upload_id": "u1", "project_id": "id", "drive_name": "d"}"#;
+                return ureq::Response::new(200, "OK", meta);
+            }
+            if !request.url.contains("/parts?") {
+                return ureq::Response::new(200, "OK", "{}");
+            }
+            let mut attempts = self.attempts.lock().unwrap();
+            let attempt = attempts.entry(request.url.to_string()).or_default();
+            *attempt += 1;
+            match attempt {
+                1 => Err(ureq::Error::Status(503, ureq::Response::new(503, "Service Unavailable", "")?)),
+                _ => ureq::Response::new(200, "OK", "{}"),
+            }
+        }
+    }
+
+    #[test]
+    fn parts_are_retried() {
+        let flaky = Arc::new(Flaky::default());
+        let drive = Deta::builder().project_key("id_secret").transport(flaky.clone()).build().drive("d")
+            .with_upload_options(UploadOptions::new().parallelism(3).part_retries(1));
+        drive.put("big", &vec![0; 2 * MAX_CHUNK_SIZE + 1], None).unwrap();
+        let attempts = flaky.attempts.lock().unwrap();
+        assert_eq!(attempts.len(), 3);
+        assert!(attempts.values().all(|attempts| *attempts == 2));
+        let options = UploadOptions::new().part_retries(1);
+        assert!(options.should_retry(0, &DetaError::TransportError));
+        assert!(!options.should_retry(1, &DetaError::TransportError));
+        assert!(!options.should_retry(0, &DetaError::PayloadTooLarge { details: Default::default() }));
+    }
+
+    /// Starts upload sessions but fails to store files, remembering every request.
+    #[derive(Default)]
+    struct Unwritable {
+        requests: Mutex<Vec<String>>,
+    }
+
+    impl Transport for Arc<Unwritable> {
+        fn send(&self, request: &Request) -> Result<ureq::Response, ureq::Error> {
+            let path = request.url.split_once("/d/").map_or(request.url, |(_, path)| path);
+            self.requests.lock().unwrap().push(format!("{} {}", request.method, path));
+            match path.starts_with("uploads?") {
+                true => {
+                    let meta = r#"{"name": "big", "upload_id": "u1", "project_id": "id", "drive_name": "d"}"#;
+                    ureq::Response::new(200, "OK", meta)
+                },
+                false => Err(ureq::Error::Status(500, ureq::Response::new(500, "Server Error", "")?)),
+            }
+        }
+    }
+
+    #[test]
+    fn uploads_abort_without_a_marker() {
+        let unwritable = Arc::new(Unwritable::default());
+        let drive = Deta::builder().project_key("id_secret").transport(unwritable.clone()).build().drive("d");
+        assert!(drive.start_upload("big", None).is_err());
+        let requests = unwritable.requests.lock().unwrap();
+        assert_eq!(requests[..3], [
+            "POST uploads?name=big",
+            "POST files?name=.detalib%2Fuploads%2Fu1",
+            "DELETE uploads/u1?name=big",
+        ]);
+    }
+
+    #[test]
+    fn ranged_downloads() {
+        let drive = Deta::builder().project_key("id_secret").transport(Ranges).build().drive("d");
+        let range = |start, end| String::from_utf8(drive.get_range("f", start, end).unwrap()).unwrap();
+        assert_eq!(range(2, Some(5)), "234");
+        assert_eq!(range(7, None), "789");
+        assert_eq!(range(8, Some(50)), "89");
+        assert_eq!(range(5, Some(5)), "");
+        assert_eq!(range(10, None), "");
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn compressed_roundtrip() {
+        let drive = MockDeta::new().drive("logs");
+        let log = "GET / 200\n".repeat(100);
+        drive.put_compressed("access.log", log.as_bytes()).unwrap();
+        assert_eq!(drive.walk(None).unwrap(), vec!["access.log.gz"]);
+        assert!(drive.head("access.log.gz").unwrap().size < Some(100));
+        assert_eq!(drive.get_decompressed("access.log").unwrap(), log.as_bytes());
+        assert_eq!(drive.get_decompressed("access.log.gz").unwrap(), log.as_bytes());
+        assert_eq!(drive.lines("access.log.gz").unwrap().count(), 100);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn head_reports_size() {
+        let drive = MockDeta::new().drive("files");
+        drive.put("a.txt", b"hello", None).unwrap();
+        let meta = drive.head("a.txt").unwrap();
+        assert_eq!((meta.name.as_str(), meta.size), ("a.txt", Some(5)));
+        assert!(matches!(drive.head("b.txt"), Err(DetaError::NotFound { .. })));
+        drive.put("notes & todo #1.txt", b"hi", None).unwrap();
+        assert_eq!(drive.head("notes & todo #1.txt").unwrap().size, Some(2));
+        assert!(matches!(drive.head("notes "), Err(DetaError::NotFound { .. })));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn get_cached_refetches_after_ttl() {
+        let deta = MockDeta::new();
+        let drive = deta.drive("assets");
+        drive.put("a.html", b"<p>1</p>", None).unwrap();
+        assert_eq!(&*drive.get_cached("a.html", Duration::from_secs(60)).unwrap(), b"<p>1</p>");
+        deta.drive("assets").put("a.html", b"<p>2</p>", None).unwrap();
+        assert_eq!(&*drive.get_cached("a.html", Duration::from_secs(60)).unwrap(), b"<p>1</p>");
+        assert_eq!(&*drive.get_cached("a.html", Duration::ZERO).unwrap(), b"<p>2</p>");
+        drive.delete(vec!["a.html"]).unwrap();
+        let missing = drive.get_cached("a.html", Duration::from_secs(60));
+        assert!(matches!(missing, Err(DetaError::NotFound { .. })));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn get_if_changed_compares_digest() {
+        let drive = MockDeta::new().drive("assets");
+        drive.put("logo.svg", b"<svg/>", None).unwrap();
+        let known = crate::checksum::digest(b"<svg/>");
+        assert_eq!(drive.get_if_changed("logo.svg", &known).unwrap(), None);
+        drive.put("logo.svg", b"<svg></svg>", None).unwrap();
+        assert_eq!(drive.get_if_changed("logo.svg", &known).unwrap().unwrap(), b"<svg></svg>");
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn resumed_upload() {
+        let drive = MockDeta::new().drive("files");
+        let mut upload = drive.start_upload("video.mp4", None).unwrap();
+        upload.send_part(1, b"hello ").unwrap();
+        let state = serde_json::to_string(upload.state()).unwrap();
+        let mut upload = drive.resume_upload(serde_json::from_str(&state).unwrap());
+        assert_eq!(upload.next_part(), 2);
+        upload.send_part(2, b"world").unwrap();
+        upload.complete().unwrap();
+        let mut stored = String::new();
+        drive.get("video.mp4").unwrap().into_reader().read_to_string(&mut stored).unwrap();
+        assert_eq!(stored, "hello world");
+        assert!(drive.list_pending_uploads().unwrap().is_empty());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn upload_markers_are_not_listed() {
+        let drive = MockDeta::new().drive("files");
+        drive.put("a.txt", b"a", None).unwrap();
+        let upload = drive.start_upload("video.mp4", None).unwrap();
+        assert_eq!(drive.walk(None).unwrap(), vec!["a.txt"]);
+        assert_eq!(drive.list(None, None, None).unwrap().names, vec!["a.txt"]);
+        assert_eq!(drive.iter_files(None).flat_map(Result::unwrap).collect::<Vec<_>>(), vec!["a.txt"]);
+        assert_eq!(drive.list_pending_uploads().unwrap()[0].upload_id, upload.state().upload_id);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn ranges_of_full_downloads() {
+        let drive = MockDeta::new().drive("files");
+        drive.put("a.txt", b"hello world", None).unwrap();
+        assert_eq!(drive.get_range("a.txt", 6, Some(9)).unwrap(), b"wor");
+        assert_eq!(drive.get_range("a.txt", 6, None).unwrap(), b"world");
+        assert_eq!(drive.get_range("a.txt", 20, None).unwrap(), b"");
+        assert!(matches!(drive.get_range("b.txt", 0, None), Err(DetaError::NotFound { .. })));
+        drive.put("my notes&more.txt", b"hello world", None).unwrap();
+        assert_eq!(drive.get_range("my notes&more.txt", 0, Some(5)).unwrap(), b"hello");
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn chunked_file_upload() {
+        let drive = MockDeta::new().drive("files");
+        let path = std::env::temp_dir().join(format!("detalib-upload-{}", std::process::id()));
+        let content = (0..11 * 1024 * 1024).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        std::fs::write(&path, &content).unwrap();
+        let uploaded = drive.put_file("big.bin", &path, None);
+        std::fs::remove_file(&path).unwrap();
+        uploaded.unwrap();
+        let mut stored = vec![];
+        drive.get("big.bin").unwrap().into_reader().read_to_end(&mut stored).unwrap();
+        assert!(stored == content);
+        assert!(drive.list_pending_uploads().unwrap().is_empty());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn parallel_chunked_upload() {
+        let drive = MockDeta::new().drive("files")
+            .with_upload_options(UploadOptions::new().parallelism(3));
+        let content = (0..35 * 1024 * 1024).map(|i| (i % 253) as u8).collect::<Vec<u8>>();
+        drive.put("big.bin", &content, None).unwrap();
+        let mut stored = vec![];
+        drive.get("big.bin").unwrap().into_reader().read_to_end(&mut stored).unwrap();
+        assert!(stored == content);
+        assert!(drive.list_pending_uploads().unwrap().is_empty());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn jsonl_appends_are_tracked_and_compacted() {
+        let deta = MockDeta::new();
+        let drive = deta.drive("logs");
+        let lines = |drive: &crate::Drive| {
+            drive.lines("log.jsonl").unwrap().map(Result::unwrap).collect::<Vec<_>>()
+        };
+        drive.append_jsonl("log.jsonl", &[1, 2]).unwrap();
+        drive.append_jsonl("log.jsonl", &[3]).unwrap();
+        // Parts written by another process after the first listing are not picked up.
+        drive.put("log.jsonl.parts/00000000000000000100", b"9\n", None).unwrap();
+        drive.compact_jsonl("log.jsonl").unwrap();
+        assert_eq!(lines(&drive), ["1", "2", "3"]);
+        assert_eq!(drive.walk(Some("log.jsonl.parts/")).unwrap().len(), 1);
+
+        drive.append_jsonl("log.jsonl", &[4]).unwrap();
+        let restarted = deta.drive("logs");
+        restarted.compact_jsonl("log.jsonl").unwrap();
+        assert_eq!(lines(&restarted), ["1", "2", "3", "4", "9"]);
+        assert!(restarted.walk(Some("log.jsonl.parts/")).unwrap().is_empty());
+
+        (0..32).for_each(|i| drive.append_jsonl("many.jsonl", &[i]).unwrap());
+        assert_eq!(drive.lines("many.jsonl").unwrap().count(), 32);
+        assert!(drive.walk(Some("many.jsonl.parts/")).unwrap().is_empty());
+    }
+}
@@ -1,42 +1,153 @@
+use std::{ fmt, sync::Arc };
+
+use serde::Deserialize;
+use serde_json::Value;
 use thiserror::Error;
 
+/// The error body returned by Deta alongside a failed request.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct ErrorDetails {
+    /// Messages describing why the request failed, e.g. validation errors.
+    #[serde(default)]
+    pub errors: Vec<String>,
+    /// The key of the record the error is about, if any.
+    #[serde(default)]
+    pub key: Option<String>,
+}
+
+impl ErrorDetails {
+
+    /// Parses an error body, falling back to empty details when it is not the expected JSON.
+    pub (crate) fn parse(body: &[u8]) -> ErrorDetails {
+        serde_json::from_slice(body).unwrap_or_default()
+    }
+}
+
+impl fmt::Display for ErrorDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if !self.errors.is_empty() {
+            write!(f, ": {}", self.errors.join("; "))?;
+        }
+        if let Some(key) = &self.key {
+            write!(f, " (key `{}`)", key)?;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum DetaError {
-    #[error("400 bad request")]
-    BadRequest,
-    #[error("401 unauthorized")]
-    Unauthorized,
-    #[error("404 not found")]
-    NotFound,
-    #[error("409 conflict")]
-    Conflict,
-    #[error("413 payload too large")]
-    PayloadTooLarge,
-    #[error("HTTP error: {status} {msg}")]
-    HTTPError { status: u16, msg: String },
+    #[error("400 bad request{details}")]
+    BadRequest { details: ErrorDetails },
+    #[error("401 unauthorized{details}")]
+    Unauthorized { details: ErrorDetails },
+    #[error("404 not found{details}")]
+    NotFound { details: ErrorDetails },
+    #[error("409 conflict{details}")]
+    Conflict { details: ErrorDetails },
+    #[error("413 payload too large{details}")]
+    PayloadTooLarge { details: ErrorDetails },
+    #[error("HTTP error: {status} {msg}{details}")]
+    HTTPError { status: u16, msg: String, details: ErrorDetails },
     #[error("transport error")]
     TransportError,
+    #[error("207 multi-status: request partially failed")]
+    PartialFailure { body: Value },
+    /// `Base::put_many` stopped at a failed request. `result` tells which records were stored.
+    #[error("put stopped after {} stored records: {source}", result.processed.len())]
+    PartialPut { result: crate::base::PutResult, source: Box<DetaError> },
+    #[error("precondition failed on field `{field}`")]
+    PreconditionFailed { field: String },
+    #[error("budget exceeded: {msg}")]
+    BudgetExceeded { msg: String },
+    #[error("record {key:?} failed checksum verification")]
+    IntegrityError { key: Option<String> },
+    #[error("overloaded: {msg}")]
+    Overloaded { msg: String },
+    #[error("write spilled to journal {path:?}")]
+    Spilled { path: std::path::PathBuf },
+    #[error("forbidden: {msg}")]
+    Forbidden { msg: String },
     #[error("Custom error: {msg}")]
     PayloadError { msg: String },
+    #[error("failed to deserialize item {index} (key {key:?}): {source}")]
+    ItemDeserialize { index: usize, key: Option<String>, #[source] source: serde_json::Error },
+    /// One failure handed to several callers, e.g. every caller of `Loader::load` waiting for a key.
+    #[error(transparent)]
+    Shared(Arc<DetaError>),
     #[error("IO error")]
     IOError(#[from] std::io::Error),
     #[error("JSON error")]
     JSONError(#[from] serde_json::Error),
 }
 
+impl DetaError {
+
+    pub (crate) fn from_status(status: u16, msg: &str, body: &[u8]) -> Self {
+        let details = ErrorDetails::parse(body);
+        match status {
+            400 => DetaError::BadRequest { details },
+            401 => DetaError::Unauthorized { details },
+            404 => DetaError::NotFound { details },
+            409 => DetaError::Conflict { details },
+            413 => DetaError::PayloadTooLarge { details },
+            status => DetaError::HTTPError { status, msg: msg.to_string(), details },
+        }
+    }
+
+    /// The error body returned by Deta, if this is an HTTP error.
+    pub fn details(&self) -> Option<&ErrorDetails> {
+        match self {
+            DetaError::BadRequest { details }
+            | DetaError::Unauthorized { details }
+            | DetaError::NotFound { details }
+            | DetaError::Conflict { details }
+            | DetaError::PayloadTooLarge { details }
+            | DetaError::HTTPError { details, .. } => Some(details),
+            DetaError::Shared(e) => e.details(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(feature = "blocking")]
 impl From<ureq::Error> for DetaError {
     fn from(ureq_err: ureq::Error) -> Self {
         match ureq_err {
-            ureq::Error::Status(400, _) => DetaError::BadRequest,
-            ureq::Error::Status(401, _) => DetaError::Unauthorized,
-            ureq::Error::Status(404, _) => DetaError::NotFound,
-            ureq::Error::Status(409, _) => DetaError::Conflict,
-            ureq::Error::Status(413, _) => DetaError::PayloadTooLarge,
-            ureq::Error::Status(status, res) => DetaError::HTTPError {
-                status,
-                msg: res.status_text().to_string(),
+            ureq::Error::Status(status, res) => {
+                let msg = res.status_text().to_string();
+                let mut body = Vec::new();
+                _ = std::io::Read::read_to_end(&mut res.into_reader(), &mut body);
+                DetaError::from_status(status, &msg, &body)
             },
             ureq::Error::Transport(_) => DetaError::TransportError,
         }
     }
 }
+
+#[cfg(any(feature = "tokio", feature = "wasm"))]
+impl From<reqwest::Error> for DetaError {
+    fn from(reqwest_err: reqwest::Error) -> Self {
+        match reqwest_err.status() {
+            Some(status) => DetaError::from_status(
+                status.as_u16(), status.canonical_reason().unwrap_or_default(), &[]),
+            None => DetaError::TransportError,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_errors_carry_details() {
+        let err = DetaError::from_status(400, "Bad Request", br#"{"errors": ["Bad item"]}"#);
+        assert_eq!(err.details().unwrap().errors, vec!["Bad item"]);
+        assert_eq!(err.to_string(), "400 bad request: Bad item");
+        let err = DetaError::from_status(404, "Not Found", br#"{"key": "k1"}"#);
+        assert_eq!(err.to_string(), "404 not found (key `k1`)");
+        let err = DetaError::from_status(502, "Bad Gateway", b"<html>");
+        assert_eq!(err.details(), Some(&ErrorDetails::default()));
+    }
+}
@@ -0,0 +1,68 @@
+use serde::{ Deserialize, Deserializer, Serialize, Serializer, de::DeserializeOwned, de::Error as _, ser::Error as _ };
+use serde_json::Value;
+
+/// Computes the `__expires` timestamp from a relative and an absolute expiry.
+/// 
+/// The absolute time wins when both are given.
+#[cfg(feature = "blocking")]
+pub (crate) fn expires_at(expires_in: Option<u64>, expires_at: Option<i64>) -> Option<i64> {
+    expires_at.or_else(|| expires_in.map(crate::__private::expires_at))
+}
+
+/// Sets `__expires` on a serialized record if it is an object.
+pub (crate) fn stamp(value: &mut Value, expires_at: Option<i64>) {
+    if let (Value::Object(map), Some(at)) = (value, expires_at) {
+        map.insert(String::from("__expires"), Value::from(at));
+    }
+}
+
+/// A record that carries its expiry time, stored as the `__expires` field.
+/// ```rust
+/// use detalib::expiring::Expiring;
+/// use serde_json::json;
+/// 
+/// let session = Expiring::in_seconds(json!({ "key": "s1", "user": "john" }), 3600);
+/// assert!(serde_json::to_value(&session).unwrap()["__expires"].is_i64());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expiring<T> {
+    pub record: T,
+    /// Unix timestamp (seconds) at which the record expires.
+    pub expires_at: i64,
+}
+
+impl<T> Expiring<T> {
+
+    /// Wrap a record expiring at the given unix timestamp.
+    pub fn at(record: T, expires_at: i64) -> Expiring<T> {
+        Expiring { record, expires_at }
+    }
+
+    /// Wrap a record expiring the given number of seconds from now.
+    pub fn in_seconds(record: T, seconds: u64) -> Expiring<T> {
+        Expiring { record, expires_at: crate::__private::expires_at(seconds) }
+    }
+}
+
+impl<T: Serialize> Serialize for Expiring<T> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: Serializer {
+        let mut value = serde_json::to_value(&self.record).map_err(S::Error::custom)?;
+        if !value.is_object() {
+            return Err(S::Error::custom("expiring records must serialize to an object"));
+        }
+        stamp(&mut value, Some(self.expires_at));
+        value.serialize(serializer)
+    }
+}
+
+impl<'de, T: DeserializeOwned> Deserialize<'de> for Expiring<T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error> where D: Deserializer<'de> {
+        let mut value = Value::deserialize(deserializer)?;
+        let expires_at = value.as_object_mut()
+            .and_then(|map| map.remove("__expires"))
+            .and_then(|at| at.as_i64())
+            .ok_or_else(|| D::Error::custom("missing `__expires` field"))?;
+        let record = serde_json::from_value::<T>(value).map_err(D::Error::custom)?;
+        Ok(Expiring { record, expires_at })
+    }
+}
@@ -0,0 +1,205 @@
+//! `std::io` access to Drive files, for code written against `Read`, `Write` and `Seek`.
+
+use std::io::{ self, Read, Seek, SeekFrom, Write };
+
+use crate::{ drive::Drive, errors::DetaError };
+
+/// Bytes pulled from the download at a time.
+const READ_CHUNK: usize = 64 * 1024;
+
+fn io_error(e: DetaError) -> io::Error {
+    match e {
+        DetaError::IOError(e) => e,
+        DetaError::NotFound { .. } => io::Error::new(io::ErrorKind::NotFound, e),
+        e => io::Error::other(e),
+    }
+}
+
+/// A Drive file opened for reading and writing through `Read`, `Write` and `Seek`.
+///
+/// The file is downloaded as it is read and the bytes read so far are kept in memory, making
+/// seeks backwards free. Seeking from the end downloads the rest of the file, use
+/// `Drive::get_range` to read parts of large files instead. Writes change the buffered copy
+/// and are uploaded as a whole by `flush`, in chunks for files over 10 MB. Dropping a file
+/// with unsaved writes flushes it and ignores errors, call `flush` to see them.
+/// ```rust,no_run
+/// use std::io::{ BufRead, BufReader, Write };
+/// use detalib::Deta;
+///
+/// let drive = Deta::new().drive("reports");
+/// let mut file = drive.create_file("totals.csv").content_type("text/csv");
+/// writeln!(file, "month,total").unwrap();
+/// writeln!(file, "2024-01,42").unwrap();
+/// file.flush().unwrap();
+///
+/// for line in BufReader::new(drive.open_file("totals.csv").unwrap()).lines() {
+///     println!("{}", line.unwrap());
+/// }
+/// ```
+pub struct DriveFile {
+    drive: Drive,
+    name: String,
+    content_type: Option<String>,
+    download: Option<Box<dyn Read + Send + Sync>>,
+    data: Vec<u8>,
+    position: u64,
+    dirty: bool,
+}
+
+impl DriveFile {
+
+    pub (crate) fn open(drive: Drive, name: &str) -> Result<DriveFile, DetaError> {
+        let download = drive.get(name)?.into_reader();
+        Ok(DriveFile {
+            drive,
+            name: name.to_string(),
+            content_type: None,
+            download: Some(download),
+            data: Vec::new(),
+            position: 0,
+            dirty: false,
+        })
+    }
+
+    pub (crate) fn create(drive: Drive, name: &str) -> DriveFile {
+        DriveFile {
+            drive,
+            name: name.to_string(),
+            content_type: None,
+            download: None,
+            data: Vec::new(),
+            position: 0,
+            dirty: true,
+        }
+    }
+
+    /// Sets the content type the file is uploaded with.
+    pub fn content_type(mut self, content_type: &str) -> Self {
+        self.content_type = Some(content_type.to_string());
+        self
+    }
+
+    /// The name of the file in the drive.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Downloads until at least `len` bytes are buffered or the download ends.
+    fn fill(&mut self, len: u64) -> io::Result<()> {
+        while (self.data.len() as u64) < len {
+            let Some(download) = self.download.as_mut() else {
+                return Ok(());
+            };
+            let start = self.data.len();
+            self.data.resize(start + READ_CHUNK, 0);
+            let read = download.read(&mut self.data[start..]);
+            self.data.truncate(start + read.as_ref().map_or(0, |read| *read));
+            match read {
+                Ok(0) => self.download = None,
+                Ok(_) => {},
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    fn fill_all(&mut self) -> io::Result<()> {
+        if let Some(mut download) = self.download.take() {
+            download.read_to_end(&mut self.data)?;
+        }
+        Ok(())
+    }
+}
+
+impl Read for DriveFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.fill(self.position.saturating_add(buf.len() as u64))?;
+        let start = (self.position as usize).min(self.data.len());
+        let read = buf.len().min(self.data.len() - start);
+        buf[..read].copy_from_slice(&self.data[start..start + read]);
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Write for DriveFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let end = self.position.saturating_add(buf.len() as u64);
+        self.fill(end)?;
+        let (start, end) = (self.position as usize, end as usize);
+        if self.data.len() < end {
+            self.data.resize(end, 0);
+        }
+        self.data[start..end].copy_from_slice(buf);
+        self.position = end as u64;
+        self.dirty = true;
+        Ok(buf.len())
+    }
+
+    /// Uploads the file if it was written to since it was opened or last flushed.
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        self.fill_all()?;
+        self.drive.put(&self.name, &self.data, self.content_type.as_deref()).map_err(io_error)?;
+        self.dirty = false;
+        Ok(())
+    }
+}
+
+impl Seek for DriveFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(offset) => self.position.checked_add_signed(offset),
+            SeekFrom::End(offset) => {
+                self.fill_all()?;
+                (self.data.len() as u64).checked_add_signed(offset)
+            },
+        };
+        match position {
+            Some(position) => {
+                self.position = position;
+                Ok(position)
+            },
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput, "seek to a negative position")),
+        }
+    }
+}
+
+impl Drop for DriveFile {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::io::{ Read, Seek, SeekFrom, Write };
+
+    use crate::mock::MockDeta;
+
+    #[test]
+    fn drive_files_read_write_seek() {
+        let drive = MockDeta::new().drive("files");
+        let mut file = drive.create_file("notes.txt").content_type("text/plain");
+        file.write_all(b"hello world").unwrap();
+        file.seek(SeekFrom::Start(6)).unwrap();
+        file.write_all(b"drive").unwrap();
+        drop(file);
+        let mut file = drive.open_file("notes.txt").unwrap();
+        assert_eq!(file.seek(SeekFrom::End(-5)).unwrap(), 6);
+        let mut word = String::new();
+        file.read_to_string(&mut word).unwrap();
+        assert_eq!(word, "drive");
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.write_all(b"HELLO").unwrap();
+        file.flush().unwrap();
+        let mut content = String::new();
+        drive.open_file("notes.txt").unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!(content, "HELLO drive");
+        assert!(drive.open_file("missing.txt").is_err());
+    }
+}
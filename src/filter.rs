@@ -0,0 +1,229 @@
+//! Typed conditions on the fields of a `DetaRecord`, see `Collection::find_where`.
+//!
+//! `#[derive(DetaRecord)]` generates a struct with a `Field` for every field of the record,
+//! named as it is stored, so a misspelled field is a compile error instead of an empty result.
+
+use serde_json::{ Map, Value };
+
+use crate::{ errors::DetaError, query::Query };
+
+/// A stored field of a record type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    name: &'static str,
+}
+
+impl Field {
+
+    /// A field stored under the given name.
+    pub const fn new(name: &'static str) -> Field {
+        Field { name }
+    }
+
+    /// The name the field is stored under.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn condition(self, operator: &str, value: Value) -> Filter {
+        let name = match operator {
+            "" => self.name.to_string(),
+            operator => format!("{}?{}", self.name, operator),
+        };
+        let mut group = Map::new();
+        group.insert(name, value);
+        Filter { groups: vec![group], conflicts: Vec::new() }
+    }
+
+    /// Checks equality of the field with the given value.
+    pub fn eq(self, value: impl Into<Value>) -> Filter {
+        self.condition("", value.into())
+    }
+
+    /// Checks inequality of the field with the given value.
+    pub fn ne(self, value: impl Into<Value>) -> Filter {
+        self.condition("ne", value.into())
+    }
+
+    /// Checks if the field is greater than the given value.
+    pub fn gt(self, value: impl Into<Value>) -> Filter {
+        self.condition("gt", value.into())
+    }
+
+    /// Checks if the field is greater than or equal to the given value.
+    pub fn gte(self, value: impl Into<Value>) -> Filter {
+        self.condition("gte", value.into())
+    }
+
+    /// Checks if the field is less than the given value.
+    pub fn lt(self, value: impl Into<Value>) -> Filter {
+        self.condition("lt", value.into())
+    }
+
+    /// Checks if the field is less than or equal to the given value.
+    pub fn lte(self, value: impl Into<Value>) -> Filter {
+        self.condition("lte", value.into())
+    }
+
+    /// Checks if the field is between `start` and `end` (both inclusive).
+    pub fn range(self, start: impl Into<Value>, end: impl Into<Value>) -> Filter {
+        self.condition("r", Value::Array(vec![start.into(), end.into()]))
+    }
+
+    /// Checks if the string field starts with the given prefix.
+    pub fn prefix(self, prefix: &str) -> Filter {
+        self.condition("pfx", Value::from(prefix))
+    }
+
+    /// Checks if the field contains the given value.
+    pub fn contains(self, value: impl Into<Value>) -> Filter {
+        self.condition("contains", value.into())
+    }
+
+    /// Checks if the field does not contain the given value.
+    pub fn not_contains(self, value: impl Into<Value>) -> Filter {
+        self.condition("not_contains", value.into())
+    }
+}
+
+/// Conditions on the fields of a record, combined with `and` and `or`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    /// Records match if they satisfy every condition of any group.
+    groups: Vec<Map<String, Value>>,
+    /// Conditions set twice with different values that one group can not hold, reported by `apply`.
+    conflicts: Vec<String>,
+}
+
+/// Whether `new` is a stricter bound than `old`, or `None` if the condition is not a
+/// `gt`, `gte`, `lt` or `lte` bound on two numbers or two strings.
+fn stricter(condition: &str, old: &Value, new: &Value) -> Option<bool> {
+    let ordering = match (old, new) {
+        (Value::Number(old), Value::Number(new)) => old.as_f64()?.partial_cmp(&new.as_f64()?)?,
+        (Value::String(old), Value::String(new)) => old.cmp(new),
+        _ => return None,
+    };
+    match condition.rsplit_once('?').map(|(_, operator)| operator) {
+        Some("gt" | "gte") => Some(ordering.is_lt()),
+        Some("lt" | "lte") => Some(ordering.is_gt()),
+        _ => None,
+    }
+}
+
+impl Filter {
+
+    /// Matches records satisfying both filters.
+    ///
+    /// Of two `gt`, `gte`, `lt` or `lte` bounds on the same field the stricter one is kept.
+    /// Other conditions on the same field and operator with different values, such as
+    /// `age.ne(1).and(age.ne(2))`, can not be sent to Deta and make `apply` fail.
+    pub fn and(mut self, other: Filter) -> Filter {
+        let mut conflicts = std::mem::take(&mut self.conflicts);
+        conflicts.extend(other.conflicts);
+        let mut groups = Vec::new();
+        for group in &self.groups {
+            for then in &other.groups {
+                let mut group = group.clone();
+                for (condition, value) in then {
+                    let keep_old = match group.get(condition) {
+                        None => false,
+                        Some(old) if old == value => true,
+                        Some(old) => match stricter(condition, old, value) {
+                            Some(stricter) => !stricter,
+                            None => {
+                                conflicts.push(condition.clone());
+                                true
+                            },
+                        },
+                    };
+                    if !keep_old {
+                        group.insert(condition.clone(), value.clone());
+                    }
+                }
+                groups.push(group);
+            }
+        }
+        Filter { groups, conflicts }
+    }
+
+    /// Matches records satisfying either filter.
+    pub fn or(mut self, other: Filter) -> Filter {
+        self.groups.extend(other.groups);
+        self.conflicts.extend(other.conflicts);
+        self
+    }
+
+    /// Adds the conditions to a query, so records have to match both the query and the filter.
+    ///
+    /// Fails with `DetaError::PayloadError` if the conditions conflict, see `and`.
+    pub fn apply(self, query: Query) -> Result<Query, DetaError> {
+        let groups = query.groups();
+        let filter = match groups.is_empty() {
+            true => self,
+            false => Filter { groups, conflicts: Vec::new() }.and(self),
+        };
+        if !filter.conflicts.is_empty() {
+            let mut conflicts = filter.conflicts;
+            conflicts.sort();
+            conflicts.dedup();
+            return Err(DetaError::PayloadError {
+                msg: format!("conflicting conditions on `{}`", conflicts.join("`, `")),
+            });
+        }
+        Ok(query.with_groups(filter.groups))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::Deta;
+
+    #[test]
+    fn groups_conditions() {
+        let (age, name) = (Field::new("age"), Field::new("name"));
+        let filter = age.gt(18).and(name.prefix("Jo")).or(age.range(1, 5).and(name.ne("Bob")));
+        let query = filter.apply(Deta::from("id_secret").base("users").query()).unwrap();
+        assert_eq!(serde_json::to_value(&query).unwrap()["query"], json!([
+            { "age?gt": 18, "name?pfx": "Jo" },
+            { "age?r": [1, 5], "name?ne": "Bob" },
+        ]));
+        let either = age.eq(1).or(age.eq(2)).and(name.contains("a"));
+        assert_eq!(either.groups, vec![
+            json!({ "age": 1, "name?contains": "a" }).as_object().unwrap().clone(),
+            json!({ "age": 2, "name?contains": "a" }).as_object().unwrap().clone(),
+        ]);
+    }
+
+    #[test]
+    fn applies_to_every_group_of_a_query() {
+        let (age, name) = (Field::new("age"), Field::new("name"));
+        let query = Deta::from("id_secret").base("users").query()
+            .equals("country", json!("JP"))
+            .or(|q| q.equals("country", json!("NO")));
+        let query = age.gt(18).or(name.eq("Jo")).apply(query).unwrap();
+        assert_eq!(serde_json::to_value(&query).unwrap()["query"], json!([
+            { "country": "NO", "age?gt": 18 },
+            { "country": "NO", "name": "Jo" },
+            { "country": "JP", "age?gt": 18 },
+            { "country": "JP", "name": "Jo" },
+        ]));
+    }
+
+    #[test]
+    fn keeps_the_stricter_bound() {
+        let (age, name) = (Field::new("age"), Field::new("name"));
+        let filter = age.gt(18).and(age.gt(10)).and(age.lte(65)).and(age.lte(70).and(name.prefix("J")));
+        assert_eq!(filter.groups, vec![
+            json!({ "age?gt": 18, "age?lte": 65, "name?pfx": "J" }).as_object().unwrap().clone(),
+        ]);
+        let query = Deta::from("id_secret").base("users").query().greater_than("age", json!(30));
+        let query = age.gt(18).apply(query).unwrap();
+        assert_eq!(serde_json::to_value(&query).unwrap()["query"], json!([{ "age?gt": 30 }]));
+        let conflicting = age.ne(1).and(age.ne(2)).apply(Deta::from("id_secret").base("users").query());
+        assert!(matches!(conflicting, Err(DetaError::PayloadError { .. })));
+        assert!(age.eq(1).and(age.eq(1)).apply(Deta::from("id_secret").base("users").query()).is_ok());
+    }
+}
@@ -0,0 +1,159 @@
+//! Best-effort preconditions on stored records, shared by every guarded write.
+//!
+//! Deta has no conditional writes. A guarded write reads the record once, checks its guards
+//! against it and then writes, so a change made by another writer between that read and the
+//! write is not detected. Guards narrow the race window but cannot close it.
+
+use std::time::Duration;
+
+use serde_json::{ Map, Value };
+
+use crate::{ base::Base, errors::{ DetaError, ErrorDetails } };
+
+/// The field counting the writes made through `Base::modify`.
+pub (crate) const REVISION: &str = "__revision";
+
+/// The revision of a record, 0 if it was never written by `Base::modify`.
+pub (crate) fn revision(record: &Value) -> u64 {
+    record[REVISION].as_u64().unwrap_or(0)
+}
+
+/// Field values the stored record must hold for a write to happen.
+#[derive(Debug, Clone)]
+pub (crate) struct Guards {
+    pub (crate) fields: Vec<(String, Value)>,
+    /// The revision the record must still be at, failing with `DetaError::Conflict` otherwise.
+    pub (crate) revision: Option<u64>,
+    /// How many times the record is read again when a guard does not hold.
+    pub (crate) retries: u32,
+    pub (crate) backoff: Duration,
+}
+
+impl Default for Guards {
+    fn default() -> Self {
+        Guards { fields: Vec::new(), revision: None, retries: 0, backoff: Duration::from_millis(50) }
+    }
+}
+
+/// A missing record is read as an empty one, so guards on it fail instead of the read.
+fn stored(record: Result<Value, DetaError>) -> Result<Value, DetaError> {
+    match record {
+        Err(DetaError::NotFound { .. }) => Ok(Value::Object(Map::new())),
+        record => record,
+    }
+}
+
+impl Guards {
+
+    pub (crate) fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Fails with `DetaError::PreconditionFailed` for the first field that does not hold.
+    ///
+    /// Nested fields are addressed with dots, e.g. `profile.state`.
+    pub (crate) fn check(&self, record: &Value) -> Result<(), DetaError> {
+        if self.revision.is_some_and(|expected| revision(record) != expected) {
+            return Err(DetaError::Conflict { details: ErrorDetails {
+                errors: vec![String::from("record changed since it was read")],
+                key: record["key"].as_str().map(String::from),
+            } });
+        }
+        for (field, expected) in self.fields.iter() {
+            let pointer = format!("/{}", field.replace('.', "/"));
+            if record.pointer(&pointer) != Some(expected) {
+                return Err(DetaError::PreconditionFailed { field: field.clone() });
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads the record once, checks the guards against it and hands it to `write`.
+    #[cfg(feature = "blocking")]
+    pub (crate) fn commit<T, F>(&self, base: &Base, key: &str, write: F) -> Result<T, DetaError>
+        where F: FnOnce(Value) -> Result<T, DetaError>
+    {
+        for _ in 0..self.retries {
+            let record = stored(base.get(key))?;
+            if self.check(&record).is_ok() {
+                return write(record);
+            }
+            std::thread::sleep(self.backoff);
+        }
+        let record = stored(base.get(key))?;
+        self.check(&record)?;
+        write(record)
+    }
+
+    /// Same as `commit`, reading the record asynchronously.
+    #[cfg(any(feature = "tokio", feature = "wasm"))]
+    pub (crate) async fn commit_async<T, F, Fut>(
+        &self, base: &Base, key: &str, write: F
+    ) -> Result<T, DetaError>
+        where F: FnOnce(Value) -> Fut, Fut: std::future::Future<Output = Result<T, DetaError>>
+    {
+        let path = format!("/items/{}", key);
+        for _ in 0..self.retries {
+            let record = stored(base.request_async(reqwest::Method::GET, &path, None).await)?;
+            if self.check(&record).is_ok() {
+                return write(record).await;
+            }
+            crate::nonblocking::sleep(self.backoff).await;
+        }
+        let record = stored(base.request_async(reqwest::Method::GET, &path, None).await)?;
+        self.check(&record)?;
+        write(record).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[cfg(feature = "blocking")]
+    use std::sync::{ Arc, Mutex };
+
+    use serde_json::json;
+
+    use super::*;
+    #[cfg(feature = "blocking")]
+    use crate::{ Deta, transport::{ Request, Transport } };
+
+    /// Serves a record with `state` set to `done`, remembering the method of every request.
+    #[cfg(feature = "blocking")]
+    #[derive(Default)]
+    struct Recorder {
+        methods: Mutex<Vec<String>>,
+    }
+
+    #[cfg(feature = "blocking")]
+    impl Transport for Arc<Recorder> {
+        fn send(&self, request: &Request) -> Result<ureq::Response, ureq::Error> {
+            self.methods.lock().unwrap().push(request.method.to_string());
+            ureq::Response::new(200, "OK", r#"{"key": "k", "state": "done"}"#)
+        }
+    }
+
+    #[test]
+    fn checks_nested_fields() {
+        let mut guards = Guards::default();
+        guards.fields.push((String::from("profile.state"), json!("active")));
+        assert!(guards.check(&json!({ "profile": { "state": "active" } })).is_ok());
+        let Err(DetaError::PreconditionFailed { field }) = guards.check(&json!({ "state": "active" })) else {
+            panic!("expected a failed precondition");
+        };
+        assert_eq!(field, "profile.state");
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn guarded_commits_read_once() {
+        let recorder = Arc::new(Recorder::default());
+        let deta = Deta::builder().project_key("id_secret").transport(recorder.clone()).build();
+        let update = |state| deta.base("jobs").update("k").only_if("state", json!(state)).set("n", json!(1));
+        update("done").commit_raw().unwrap();
+        assert_eq!(*recorder.methods.lock().unwrap(), vec!["GET", "PATCH"]);
+
+        recorder.methods.lock().unwrap().clear();
+        assert!(matches!(update("new").retries(1).commit_raw(), Err(DetaError::PreconditionFailed { .. })));
+        assert_eq!(*recorder.methods.lock().unwrap(), vec!["GET", "GET"]);
+    }
+}
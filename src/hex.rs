@@ -0,0 +1,30 @@
+//! Lowercase hex encoding shared by checksums, keys, signatures and secrets.
+
+/// Encodes bytes as lowercase hex.
+pub (crate) fn encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decodes a hex string, `None` if it has an odd length or a non-hex character.
+#[cfg(any(test, feature = "secrets"))]
+pub (crate) fn decode(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    s.as_bytes().chunks(2)
+        .map(|pair| Some(((pair[0] as char).to_digit(16)? * 16 + (pair[1] as char).to_digit(16)?) as u8))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        assert_eq!(encode(&[0, 15, 255]), "000fff");
+        assert_eq!(decode("000fFF"), Some(vec![0, 15, 255]));
+        assert_eq!(decode("abc"), None);
+        assert_eq!(decode("zz"), None);
+    }
+}
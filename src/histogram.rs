@@ -0,0 +1,92 @@
+//! Bucket counts of a numeric field, computed by `Query::histogram`.
+
+#[cfg(feature = "blocking")]
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+#[cfg(feature = "blocking")]
+use serde_json::Value;
+
+/// A range of values `[start, end)` and how many results fall into it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Bucket {
+    pub start: f64,
+    pub end: f64,
+    pub count: usize,
+}
+
+/// Counts of a numeric field in buckets of equal width.
+///
+/// Buckets cover the smallest to the largest value without gaps, so empty buckets are included.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Histogram {
+    pub buckets: Vec<Bucket>,
+    /// Results where the field is missing or not a number.
+    pub skipped: usize,
+}
+
+impl Histogram {
+
+    /// The number of values counted in the buckets.
+    pub fn total(&self) -> usize {
+        self.buckets.iter().map(|bucket| bucket.count).sum()
+    }
+}
+
+/// Accumulates values into buckets while results are streamed.
+#[cfg(feature = "blocking")]
+pub (crate) struct Buckets {
+    width: f64,
+    counts: BTreeMap<i64, usize>,
+    skipped: usize,
+}
+
+#[cfg(feature = "blocking")]
+impl Buckets {
+
+    pub (crate) fn new(width: f64) -> Buckets {
+        Buckets { width, counts: BTreeMap::new(), skipped: 0 }
+    }
+
+    pub (crate) fn add(&mut self, value: Option<&Value>) {
+        match value.and_then(Value::as_f64) {
+            Some(value) => *self.counts.entry((value / self.width).floor() as i64).or_default() += 1,
+            None => self.skipped += 1,
+        }
+    }
+
+    pub (crate) fn finish(self) -> Histogram {
+        let range = match (self.counts.first_key_value(), self.counts.last_key_value()) {
+            (Some((&first, _)), Some((&last, _))) => first..=last,
+            _ => return Histogram { buckets: vec![], skipped: self.skipped },
+        };
+        let buckets = range
+            .map(|index| Bucket {
+                start: index as f64 * self.width,
+                end: (index + 1) as f64 * self.width,
+                count: self.counts.get(&index).copied().unwrap_or_default(),
+            })
+            .collect();
+        Histogram { buckets, skipped: self.skipped }
+    }
+}
+
+#[cfg(all(test, feature = "blocking"))]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn fills_gaps_between_buckets() {
+        let mut buckets = Buckets::new(10.0);
+        for value in [json!(1), json!(9.5), json!(35), json!(-2), json!("x")] {
+            buckets.add(Some(&value));
+        }
+        buckets.add(None);
+        let histogram = buckets.finish();
+        let counts = histogram.buckets.iter().map(|b| (b.start, b.count)).collect::<Vec<_>>();
+        assert_eq!(counts, vec![(-10.0, 1), (0.0, 2), (10.0, 0), (20.0, 0), (30.0, 1)]);
+        assert_eq!((histogram.total(), histogram.skipped), (4, 2));
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::VecDeque;
+#[cfg(feature = "blocking")]
+use std::{ sync::mpsc::{ self, Receiver }, thread };
+
+use serde_json::Value;
+
+#[cfg(feature = "blocking")]
+use crate::{ drive::Drive, query::QueryPage };
+use crate::{ errors::DetaError, query::Query };
+
+/// A lazy iterator over the results of a query, fetching pages on demand or ahead with `prefetch`.
+/// 
+/// Iteration stops after the first error.
+#[cfg(feature = "blocking")]
+pub struct QueryIter {
+    query: Query,
+    buffer: VecDeque<Value>,
+    cursor: Option<String>,
+    done: bool,
+    prefetch: usize,
+    pages: Option<Receiver<Result<QueryPage, DetaError>>>,
+}
+
+#[cfg(feature = "blocking")]
+impl QueryIter {
+
+    pub (crate) fn new(query: Query) -> QueryIter {
+        QueryIter { query, buffer: VecDeque::new(), cursor: None, done: false, prefetch: 0, pages: None }
+    }
+
+    /// Fetches up to `pages` pages ahead on a background thread, so items of the next page
+    /// are ready when the current one is used up. Defaults to 0, fetching pages on demand.
+    ///
+    /// The thread stops when the query is exhausted, fails or the iterator is dropped.
+    /// ```rust,no_run
+    /// use detalib::Deta;
+    ///
+    /// let base = Deta::new().base("events");
+    /// for event in base.query().iter().prefetch(2) {
+    ///     println!("{}", event.unwrap()["key"]);
+    /// }
+    /// ```
+    pub fn prefetch(mut self, pages: usize) -> Self {
+        self.prefetch = pages;
+        self
+    }
+
+    fn page(&self) -> Query {
+        match &self.cursor {
+            Some(cursor) => self.query.clone().last(cursor),
+            None => self.query.clone(),
+        }
+    }
+
+    /// Starts fetching the pages after the cursor, waiting while `prefetch` pages are unread.
+    fn prefetcher(&self) -> Receiver<Result<QueryPage, DetaError>> {
+        let (sender, pages) = mpsc::sync_channel(self.prefetch - 1);
+        let mut query = self.page();
+        thread::spawn(move || loop {
+            let page = query.run();
+            let next = page.as_ref().ok().and_then(|page| page.last.clone());
+            if sender.send(page).is_err() {
+                return;
+            }
+            match next {
+                Some(cursor) => query = query.last(&cursor),
+                None => return,
+            }
+        });
+        pages
+    }
+
+    fn next_page(&mut self) -> Result<QueryPage, DetaError> {
+        if self.prefetch == 0 {
+            return self.page().run();
+        }
+        if self.pages.is_none() {
+            self.pages = Some(self.prefetcher());
+        }
+        match self.pages.as_ref().map(Receiver::recv) {
+            Some(Ok(page)) => page,
+            // the thread only ends early if fetching a page panicked
+            _ => Err(DetaError::TransportError),
+        }
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl Iterator for QueryIter {
+    type Item = Result<Value, DetaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.buffer.pop_front() {
+                return Some(Ok(item));
+            }
+            if self.done {
+                return None;
+            }
+            match self.next_page() {
+                Ok(page) => {
+                    self.done = page.last.is_none();
+                    self.cursor = page.last;
+                    self.buffer.extend(page.items);
+                },
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                },
+            }
+        }
+    }
+}
+
+/// A lazy iterator over the pages of file names in a drive, returned by `Drive::iter_files`.
+///
+/// Iteration stops after the first error.
+#[cfg(feature = "blocking")]
+pub struct FilePages {
+    drive: Drive,
+    prefix: Option<String>,
+    cursor: Option<String>,
+    done: bool,
+}
+
+#[cfg(feature = "blocking")]
+impl FilePages {
+
+    pub (crate) fn new(drive: Drive, prefix: Option<&str>) -> FilePages {
+        FilePages { drive, prefix: prefix.map(String::from), cursor: None, done: false }
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl Iterator for FilePages {
+    type Item = Result<Vec<String>, DetaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        match self.drive.list(self.prefix.as_deref(), None, self.cursor.as_deref()) {
+            Ok(list) => {
+                self.cursor = list.paging.map(|paging| paging.last).filter(|last| !last.is_empty());
+                self.done = self.cursor.is_none();
+                Some(Ok(list.names))
+            },
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+/// The async counterpart of `QueryIter`, fetching pages on demand.
+#[cfg(any(feature = "tokio", feature = "wasm"))]
+pub struct AsyncQueryIter {
+    query: Query,
+    buffer: VecDeque<Value>,
+    cursor: Option<String>,
+    done: bool,
+}
+
+#[cfg(any(feature = "tokio", feature = "wasm"))]
+impl AsyncQueryIter {
+
+    pub (crate) fn new(query: Query) -> AsyncQueryIter {
+        AsyncQueryIter { query, buffer: VecDeque::new(), cursor: None, done: false }
+    }
+
+    /// Returns the next item, fetching the next page if needed.
+    pub async fn next(&mut self) -> Option<Result<Value, DetaError>> {
+        loop {
+            if let Some(item) = self.buffer.pop_front() {
+                return Some(Ok(item));
+            }
+            if self.done {
+                return None;
+            }
+            let page = match &self.cursor {
+                Some(cursor) => self.query.clone().last(cursor),
+                None => self.query.clone(),
+            };
+            match page.run_async().await {
+                Ok(page) => {
+                    self.done = page.last.is_none();
+                    self.cursor = page.last;
+                    self.buffer.extend(page.items);
+                },
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                },
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use serde_json::json;
+
+    use crate::mock::MockDeta;
+
+    #[test]
+    fn drive_files_are_listed_by_page() {
+        let drive = MockDeta::new().drive("files");
+        (0..1001).for_each(|i| { drive.put(&format!("f{:04}", i), b"", None).unwrap(); });
+        let pages = drive.iter_files(None).map(|page| page.unwrap().len()).collect::<Vec<_>>();
+        assert_eq!(pages, [1000, 1]);
+        assert_eq!(drive.walk(None).unwrap().len(), 1001);
+    }
+
+    #[test]
+    fn prefetching_iterator() {
+        let base = MockDeta::new().base("events");
+        let records = (0..23).map(|i| json!({ "key": format!("e{:02}", i), "n": i })).collect::<Vec<_>>();
+        base.put_many(&records).unwrap();
+        let query = base.query().limit(5);
+        let on_demand = query.iter().collect::<Result<Vec<_>, _>>().unwrap();
+        let prefetched = query.iter().prefetch(2).collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(prefetched.len(), 23);
+        assert_eq!(prefetched, on_demand);
+        let mut partial = query.iter().prefetch(1);
+        assert_eq!(partial.nth(7).unwrap().unwrap()["n"], 7);
+        drop(partial);
+        let missing = MockDeta::new().base("missing").query().iter().prefetch(3).count();
+        assert_eq!(missing, 0);
+    }
+}
@@ -0,0 +1,295 @@
+//! JSON Lines import and export of bases, to a writer or as sharded files on a drive.
+
+use std::io::{ BufRead, BufReader, Read, Write };
+
+use flate2::{ read::MultiGzDecoder, write::GzEncoder, Compression };
+use serde::{ Deserialize, Serialize };
+use serde_json::Value;
+
+use crate::{ base::{ typed, Base, PutResult }, checksum::digest, drive::Drive, errors::DetaError };
+
+/// A part file written by `Base::export_sharded`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportPart {
+    /// Name of the part file on the drive.
+    pub name: String,
+    pub records: usize,
+}
+
+/// The `manifest.json` describing a sharded export, written after all parts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportManifest {
+    /// Name of the exported base.
+    pub base: String,
+    /// Unix timestamp (seconds) of when the export finished.
+    pub created_at: i64,
+    pub records: usize,
+    pub parts: Vec<ExportPart>,
+}
+
+/// Progress of `Base::import_from_drive`, stored as a record of the checkpoint base.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImportCheckpoint {
+    /// Name of the base imported into.
+    pub base: String,
+    pub drive: String,
+    pub file: String,
+    /// Lines of the file imported so far, blank ones included.
+    pub lines: u64,
+    /// Records put so far, over all runs.
+    pub records: u64,
+    /// Keys Deta reported as failed.
+    pub failed: Vec<String>,
+    pub complete: bool,
+}
+
+fn manifest_name(prefix: &str) -> String {
+    format!("{}manifest.json", prefix)
+}
+
+fn write_part(
+    drive: &Drive, prefix: &str, lines: &[u8], records: usize, manifest: &mut ExportManifest
+) -> Result<(), DetaError> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(lines)?;
+    let name = format!("{}part-{:04}.jsonl.gz", prefix, manifest.parts.len() + 1);
+    drive.put(&name, &encoder.finish()?, Some("application/gzip"))?;
+    manifest.records += records;
+    manifest.parts.push(ExportPart { name, records });
+    Ok(())
+}
+
+impl Base {
+
+    /// Write every record in the base to `writer` as JSON Lines, one record per line.
+    ///
+    /// Records are written page by page, so only one page is held in memory at a time.
+    /// Returns the number of records written.
+    pub fn export_jsonl<W: Write>(&self, mut writer: W) -> Result<usize, DetaError> {
+        let mut count = 0;
+        for item in self.query().iter() {
+            serde_json::to_writer(&mut writer, &item?)?;
+            writer.write_all(b"\n")?;
+            count += 1;
+        }
+        writer.flush()?;
+        Ok(count)
+    }
+
+    /// Put every record read from JSON Lines into the base, `chunk_size` records at a time (at most 25).
+    ///
+    /// Blank lines are skipped. Records with a key replace existing ones, so an import can be re-run.
+    /// A line that is not a JSON object fails the import, after the chunks before it were put.
+    pub fn import_jsonl<R: BufRead>(&self, reader: R, chunk_size: usize) -> Result<PutResult, DetaError> {
+        let chunk_size = chunk_size.clamp(1, 25);
+        let mut result = PutResult::default();
+        let mut chunk = Vec::with_capacity(chunk_size);
+        for (number, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<Value>(&line)? {
+                record @ Value::Object(_) => chunk.push(record),
+                _ => return Err(DetaError::PayloadError {
+                    msg: format!("line {} is not a JSON object", number + 1)
+                }),
+            }
+            if chunk.len() == chunk_size {
+                result.extend(self.put_many(&chunk)?);
+                chunk.clear();
+            }
+        }
+        if !chunk.is_empty() {
+            result.extend(self.put_many(&chunk)?);
+        }
+        Ok(result)
+    }
+
+    /// Export every record to gzipped JSON Lines part files on a drive, followed by a manifest.
+    ///
+    /// Parts are named `{prefix}part-0001.jsonl.gz` and so on, each holding at most
+    /// `max_part_size` bytes of uncompressed JSON Lines (or a single larger record).
+    /// The manifest `{prefix}manifest.json` is written last, so its presence marks a complete export.
+    /// Only one part is held in memory at a time.
+    /// ```rust,no_run
+    /// use detalib::Deta;
+    ///
+    /// let deta = Deta::new();
+    /// let backups = deta.drive("backups");
+    /// let manifest = deta.base("users").export_sharded(&backups, "users/", 5 << 20).unwrap();
+    /// println!("{} records in {} parts", manifest.records, manifest.parts.len());
+    /// ```
+    pub fn export_sharded(
+        &self, drive: &Drive, prefix: &str, max_part_size: usize
+    ) -> Result<ExportManifest, DetaError> {
+        let mut manifest = ExportManifest {
+            base: self.name().to_string(),
+            created_at: 0,
+            records: 0,
+            parts: Vec::new(),
+        };
+        let (mut lines, mut records) = (Vec::new(), 0);
+        for item in self.query().iter() {
+            let mut line = serde_json::to_vec(&item?)?;
+            line.push(b'\n');
+            if records > 0 && lines.len() + line.len() > max_part_size {
+                write_part(drive, prefix, &lines, records, &mut manifest)?;
+                (lines, records) = (Vec::new(), 0);
+            }
+            lines.extend_from_slice(&line);
+            records += 1;
+        }
+        if records > 0 {
+            write_part(drive, prefix, &lines, records, &mut manifest)?;
+        }
+        manifest.created_at = chrono::Utc::now().timestamp();
+        drive.put(&manifest_name(prefix), &serde_json::to_vec(&manifest)?, Some("application/json"))?;
+        Ok(manifest)
+    }
+
+    /// Put every record of a JSON Lines file on a drive into the base, resuming where a
+    /// previous run stopped.
+    ///
+    /// The file is streamed and put 25 records at a time, and after each batch the progress is
+    /// saved as an `ImportCheckpoint` in `checkpoint_base`. Running the import again, e.g. in a
+    /// new invocation after a Micro timed out, skips the lines already imported, and once the
+    /// import is complete does nothing. At most one batch is put twice, which only duplicates
+    /// records without a key. Files ending in `.gz` are decompressed.
+    /// ```rust,no_run
+    /// use detalib::Deta;
+    ///
+    /// let deta = Deta::new();
+    /// let checkpoint = deta.base("users")
+    ///     .import_from_drive(&deta.drive("backups"), "users.jsonl.gz", &deta.base("imports"))
+    ///     .unwrap();
+    /// println!("{} records imported", checkpoint.records);
+    /// ```
+    pub fn import_from_drive(
+        &self, drive: &Drive, name: &str, checkpoint_base: &Base
+    ) -> Result<ImportCheckpoint, DetaError> {
+        let key = digest(format!("{}\n{}\n{}", self.name(), drive.name(), name).as_bytes());
+        let mut checkpoint = match checkpoint_base.get_opt(&key)? {
+            Some(record) => typed::<ImportCheckpoint>(record)?,
+            None => ImportCheckpoint {
+                base: self.name().to_string(),
+                drive: drive.name().to_string(),
+                file: name.to_string(),
+                ..ImportCheckpoint::default()
+            },
+        };
+        if checkpoint.complete {
+            return Ok(checkpoint);
+        }
+        let save = |checkpoint: &ImportCheckpoint| -> Result<(), DetaError> {
+            let mut record = serde_json::to_value(checkpoint)?;
+            record["key"] = Value::from(key.as_str());
+            checkpoint_base.put(vec![record]).map(drop)
+        };
+        let mut reader: Box<dyn Read> = Box::new(drive.get(name)?.into_reader());
+        if name.ends_with(".gz") {
+            reader = Box::new(MultiGzDecoder::new(reader));
+        }
+        let mut chunk = Vec::with_capacity(25);
+        let lines = BufReader::new(reader).lines().enumerate().skip(checkpoint.lines as usize);
+        for (number, line) in lines {
+            let line = line?;
+            if !line.trim().is_empty() {
+                match serde_json::from_str::<Value>(&line)? {
+                    record @ Value::Object(_) => chunk.push(record),
+                    _ => return Err(DetaError::PayloadError {
+                        msg: format!("line {} is not a JSON object", number + 1)
+                    }),
+                }
+            }
+            if chunk.len() == 25 {
+                let result = self.put_many(&chunk)?;
+                chunk.clear();
+                checkpoint.lines = number as u64 + 1;
+                checkpoint.records += result.processed.len() as u64;
+                checkpoint.failed.extend(result.failed);
+                save(&checkpoint)?;
+            }
+        }
+        if !chunk.is_empty() {
+            let result = self.put_many(&chunk)?;
+            checkpoint.records += result.processed.len() as u64;
+            checkpoint.failed.extend(result.failed);
+        }
+        checkpoint.complete = true;
+        save(&checkpoint)?;
+        Ok(checkpoint)
+    }
+
+    /// Put every record of a sharded export made with `export_sharded` into the base.
+    ///
+    /// Fails if the manifest under `prefix` is missing, e.g. because the export did not finish.
+    pub fn import_sharded(&self, drive: &Drive, prefix: &str) -> Result<PutResult, DetaError> {
+        let manifest = drive.get(&manifest_name(prefix))?.into_reader();
+        let manifest: ExportManifest = serde_json::from_reader(manifest)?;
+        let mut result = PutResult::default();
+        for part in manifest.parts.iter() {
+            let reader = BufReader::new(MultiGzDecoder::new(drive.get(&part.name)?.into_reader()));
+            result.extend(self.import_jsonl(reader, 25)?);
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use serde_json::json;
+
+    use crate::mock::MockDeta;
+
+    #[test]
+    fn jsonl_roundtrip() {
+        let deta = MockDeta::new();
+        let source = deta.base("source");
+        let records = (0..30).map(|i| json!({ "key": format!("{:02}", i), "n": i })).collect::<Vec<_>>();
+        source.put_many(&records).unwrap();
+        let mut exported = vec![];
+        assert_eq!(source.export_jsonl(&mut exported).unwrap(), 30);
+        exported.extend_from_slice(b"\n");
+        let target = deta.base("target");
+        let result = target.import_jsonl(exported.as_slice(), 7).unwrap();
+        assert_eq!((result.processed.len(), result.failed.len()), (30, 0));
+        assert_eq!(target.query().walk().unwrap(), source.query().walk().unwrap());
+        assert!(target.import_jsonl(&b"{}\n[1]\n"[..], 10).is_err());
+    }
+
+    #[test]
+    fn sharded_roundtrip() {
+        let deta = MockDeta::new();
+        let (source, backups) = (deta.base("source"), deta.drive("backups"));
+        let records = (0..40).map(|i| json!({ "key": format!("{:02}", i), "n": i })).collect::<Vec<_>>();
+        source.put_many(&records).unwrap();
+        let manifest = source.export_sharded(&backups, "source/", 200).unwrap();
+        assert_eq!(manifest.records, 40);
+        assert!(manifest.parts.len() > 1 && manifest.parts.iter().all(|part| part.records <= 10));
+        assert_eq!(manifest.parts[0].name, "source/part-0001.jsonl.gz");
+        let target = deta.base("target");
+        assert_eq!(target.import_sharded(&backups, "source/").unwrap().processed.len(), 40);
+        assert_eq!(target.query().walk().unwrap(), source.query().walk().unwrap());
+        assert!(target.import_sharded(&backups, "missing/").is_err());
+    }
+
+    #[test]
+    fn drive_import_resumes_from_checkpoint() {
+        let deta = MockDeta::new();
+        let (users, backups, imports) = (deta.base("users"), deta.drive("backups"), deta.base("imports"));
+        let lines = |bad: &str| (0..66)
+            .map(|i| if i == 60 { bad.to_string() } else { json!({ "key": format!("u{}", i) }).to_string() })
+            .collect::<Vec<_>>()
+            .join("\n");
+        backups.put("users.jsonl", lines("[1]").as_bytes(), None).unwrap();
+        assert!(users.import_from_drive(&backups, "users.jsonl", &imports).is_err());
+        let saved = imports.query().walk().unwrap();
+        assert_eq!((saved[0]["lines"].as_u64(), saved[0]["complete"].as_bool()), (Some(50), Some(false)));
+        backups.put("users.jsonl", lines("").as_bytes(), None).unwrap();
+        let checkpoint = users.import_from_drive(&backups, "users.jsonl", &imports).unwrap();
+        assert!(checkpoint.complete);
+        assert_eq!((checkpoint.records, users.query().count().unwrap()), (65, 65));
+        assert_eq!(users.import_from_drive(&backups, "users.jsonl", &imports).unwrap(), checkpoint);
+    }
+}
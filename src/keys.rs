@@ -0,0 +1,155 @@
+use std::sync::{ Arc, Mutex };
+
+use serde_json::Value;
+
+/// Helpers to generate record keys.
+pub struct Key;
+
+const FNV_OFFSET: u128 = 0x6c62272e07bb014262b821756295c58d;
+const FNV_PRIME: u128 = 0x0000000001000000000000000000013b;
+const CROCKFORD: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const ULID_RANDOM_BITS: u32 = 80;
+
+/// Timestamp and random part of the last ULID, to keep ULIDs of the same millisecond increasing.
+static LAST_ULID: Mutex<(u64, u128)> = Mutex::new((0, 0));
+
+fn random<const N: usize>() -> [u8; N] {
+    let mut bytes = [0; N];
+    getrandom::getrandom(&mut bytes).expect("the operating system random number generator failed");
+    bytes
+}
+
+fn now_millis() -> u64 {
+    chrono::Utc::now().timestamp_millis().max(0) as u64
+}
+
+/// How `Base::with_generated_keys` fills in missing keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyStrategy {
+    /// `Key::ulid`
+    Ulid,
+    /// `Key::uuid`
+    Uuid,
+    /// `Key::timestamped` with the given prefix, shared by clones of the base.
+    Timestamped(Arc<str>),
+}
+
+impl KeyStrategy {
+
+    pub fn generate(&self) -> String {
+        match self {
+            KeyStrategy::Ulid => Key::ulid(),
+            KeyStrategy::Uuid => Key::uuid(),
+            KeyStrategy::Timestamped(prefix) => Key::timestamped(prefix),
+        }
+    }
+
+    /// Sets a generated key on a serialized record without a key.
+    pub (crate) fn fill(&self, record: &mut Value) {
+        if let Value::Object(map) = record {
+            let missing = match map.get("key") {
+                None | Some(Value::Null) => true,
+                Some(Value::String(key)) => key.is_empty(),
+                Some(_) => false,
+            };
+            if missing {
+                map.insert(String::from("key"), Value::from(self.generate()));
+            }
+        }
+    }
+}
+
+impl Key {
+
+    /// A stable key derived from natural identifiers such as an email or an external id.
+    /// 
+    /// The same fields always hash to the same 32 character hex key, across processes
+    /// and library versions, so ingesting an entity twice overwrites instead of duplicating it.
+    /// ```rust
+    /// use detalib::keys::Key;
+    /// 
+    /// assert_eq!(Key::from_fields(&["john@example.com"]), Key::from_fields(&["john@example.com"]));
+    /// assert_ne!(Key::from_fields(&["a", "bc"]), Key::from_fields(&["ab", "c"]));
+    /// ```
+    pub fn from_fields(fields: &[&str]) -> String {
+        let mut hash = FNV_OFFSET;
+        for field in fields {
+            // length prefix keeps ["a", "bc"] and ["ab", "c"] apart
+            let bytes = (field.len() as u64).to_le_bytes().into_iter().chain(field.bytes());
+            for byte in bytes {
+                hash ^= byte as u128;
+                hash = hash.wrapping_mul(FNV_PRIME);
+            }
+        }
+        format!("{:032x}", hash)
+    }
+
+    /// A 26 character ULID, e.g. `01HNB6ZQ4MZ7V5XTC0G3W8Y2KD`.
+    ///
+    /// ULIDs sort by creation time. Those created in the same millisecond by this process
+    /// still sort in creation order, so they work with `Base::tail`.
+    pub fn ulid() -> String {
+        let millis = now_millis();
+        let mask = (1u128 << ULID_RANDOM_BITS) - 1;
+        let random = {
+            let mut last = LAST_ULID.lock().unwrap_or_else(|e| e.into_inner());
+            let random = match last.0 == millis {
+                true => last.1.wrapping_add(1) & mask,
+                false => u128::from_be_bytes(random::<16>()) & mask,
+            };
+            *last = (millis, random);
+            random
+        };
+        let value = (u128::from(millis) << ULID_RANDOM_BITS) | random;
+        (0..26).map(|i| CROCKFORD[(value >> (125 - 5 * i)) as usize & 31] as char).collect()
+    }
+
+    /// A random (version 4) UUID, e.g. `f47ac10b-58cc-4372-a567-0e02b2c3d479`.
+    pub fn uuid() -> String {
+        let mut bytes = random::<16>();
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+        let hex = crate::hex::encode(&bytes);
+        format!("{}-{}-{}-{}-{}", &hex[..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+    }
+
+    /// A key made of `prefix`, the current time in milliseconds and a random suffix,
+    /// e.g. `order_1706702400000` followed by 8 hex characters.
+    ///
+    /// Keys with the same prefix sort by creation time, to the millisecond.
+    pub fn timestamped(prefix: &str) -> String {
+        format!("{}{:013}{:08x}", prefix, now_millis(), u32::from_be_bytes(random::<4>()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stable_across_runs() {
+        assert_eq!(Key::from_fields(&[]), "6c62272e07bb014262b821756295c58d");
+        assert_eq!(Key::from_fields(&["x"]).len(), 32);
+        assert_eq!(Key::from_fields(&["x"]), Key::from_fields(&["x"]));
+    }
+
+    #[test]
+    fn generated_keys_sort_by_time() {
+        let ulids = (0..100).map(|_| Key::ulid()).collect::<Vec<_>>();
+        assert!(ulids.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(ulids.iter().all(|ulid| ulid.len() == 26));
+        let uuid = Key::uuid();
+        assert_eq!((uuid.len(), &uuid[14..15]), (36, "4"));
+        assert_ne!(uuid, Key::uuid());
+        let key = Key::timestamped("order_");
+        assert!(key.starts_with("order_") && key.len() == 6 + 13 + 8);
+    }
+
+    #[test]
+    fn fills_only_missing_keys() {
+        let mut records = [serde_json::json!({ "a": 1 }), serde_json::json!({ "key": "k" })];
+        records.iter_mut().for_each(|record| KeyStrategy::Uuid.fill(record));
+        assert_eq!(records[0]["key"].as_str().map(str::len), Some(36));
+        assert_eq!(records[1]["key"], "k");
+    }
+}
@@ -0,0 +1,159 @@
+//! A key-value cache with Redis-like commands, stored in a base.
+
+use std::time::Duration;
+
+use serde::{ Serialize, de::DeserializeOwned };
+use serde_json::{ json, Value };
+
+use crate::{ base::{ typed, Base }, errors::DetaError };
+
+fn found<T>(result: Result<T, DetaError>) -> Result<bool, DetaError> {
+    match result {
+        Ok(_) => Ok(true),
+        Err(DetaError::NotFound { .. }) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// The remaining lifetime of an entry, returned by `KvCache::ttl`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ttl {
+    /// There is no entry with that key.
+    Missing,
+    /// The entry never expires.
+    Persistent,
+    Expires(Duration),
+}
+
+/// Redis-style commands over a base, to ease moving code written against Redis onto Deta.
+///
+/// Every entry is a record `{ "key": key, "value": value }`, with `__expires` set when it
+/// has a time to live, so expired entries are removed by Deta. Expiry has a resolution of
+/// one second.
+/// ```rust,no_run
+/// use detalib::Deta;
+/// use serde_json::json;
+///
+/// let cache = Deta::new().base("cache").kv();
+/// cache.set_ex("session:1", json!({ "user": "john" }), 3600).unwrap();
+/// let visits = cache.incr("visits", 1).unwrap();
+/// ```
+#[derive(Clone)]
+pub struct KvCache {
+    base: Base,
+}
+
+impl KvCache {
+
+    pub (crate) fn new(base: Base) -> KvCache {
+        KvCache { base }
+    }
+
+    /// Returns the underlying base.
+    pub fn base(&self) -> &Base {
+        &self.base
+    }
+
+    /// Stores a value that never expires, replacing any existing entry.
+    pub fn set<T: Serialize>(&self, key: &str, value: T) -> Result<(), DetaError> {
+        self.base.put(vec![json!({ "key": key, "value": value })])?;
+        Ok(())
+    }
+
+    /// Stores a value that expires after `seconds`, replacing any existing entry.
+    pub fn set_ex<T: Serialize>(&self, key: &str, value: T, seconds: u64) -> Result<(), DetaError> {
+        let expires = crate::__private::expires_at(seconds);
+        self.base.put(vec![json!({ "key": key, "value": value, "__expires": expires })])?;
+        Ok(())
+    }
+
+    /// The value stored under `key`, or `None` if there is none or it expired.
+    pub fn get(&self, key: &str) -> Result<Option<Value>, DetaError> {
+        Ok(self.base.get_opt(key)?.map(|mut record| record["value"].take()))
+    }
+
+    /// Same as `get`, deserializing the value.
+    pub fn get_as<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, DetaError> {
+        self.get(key)?.map(typed).transpose()
+    }
+
+    /// How long the entry under `key` has left to live.
+    pub fn ttl(&self, key: &str) -> Result<Ttl, DetaError> {
+        let Some(record) = self.base.get_opt(key)? else {
+            return Ok(Ttl::Missing);
+        };
+        Ok(match record["__expires"].as_i64() {
+            Some(at) => {
+                let left = at.saturating_sub(chrono::Utc::now().timestamp()).max(0);
+                Ttl::Expires(Duration::from_secs(left as u64))
+            },
+            None => Ttl::Persistent,
+        })
+    }
+
+    /// Adds `by` to the number under `key`, starting from 0 if there is none, and returns the result.
+    ///
+    /// The increment itself is atomic. The result is read afterwards, so with concurrent
+    /// increments it may already include later ones. The time to live is kept.
+    pub fn incr(&self, key: &str, by: i64) -> Result<i64, DetaError> {
+        loop {
+            match self.base.update(key).increment("value", json!(by)).commit() {
+                Ok(_) => break,
+                Err(DetaError::NotFound { .. }) => {
+                    match self.base.insert(json!({ "key": key, "value": by })) {
+                        Ok(_) => return Ok(by),
+                        // another client created it first, increment theirs
+                        Err(DetaError::Conflict { .. }) => continue,
+                        Err(e) => return Err(e),
+                    }
+                },
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(self.get(key)?.and_then(|value| value.as_i64()).unwrap_or(by))
+    }
+
+    /// Makes the entry under `key` expire after `seconds`. Returns `false` if there is none.
+    pub fn expire(&self, key: &str, seconds: u64) -> Result<bool, DetaError> {
+        let expires = crate::__private::expires_at(seconds);
+        found(self.base.update(key).set("__expires", json!(expires)).commit())
+    }
+
+    /// Removes the time to live of the entry under `key`. Returns `false` if there is none.
+    pub fn persist(&self, key: &str) -> Result<bool, DetaError> {
+        found(self.base.update(key).delete("__expires").commit())
+    }
+
+    /// Removes the entry under `key`, if any.
+    pub fn del(&self, key: &str) -> Result<(), DetaError> {
+        self.base.delete(key)?;
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use serde_json::json;
+
+    use super::Ttl;
+    use crate::mock::MockDeta;
+
+    #[test]
+    fn kv_cache_commands() {
+        let cache = MockDeta::new().base("cache").kv();
+        assert_eq!(cache.incr("visits", 2).unwrap(), 2);
+        assert_eq!(cache.incr("visits", 3).unwrap(), 5);
+        assert_eq!(cache.ttl("visits").unwrap(), Ttl::Persistent);
+        assert!(cache.expire("visits", 60).unwrap());
+        assert!(matches!(cache.ttl("visits").unwrap(), Ttl::Expires(left) if left.as_secs() > 50));
+        assert!(cache.persist("visits").unwrap());
+        assert_eq!(cache.ttl("visits").unwrap(), Ttl::Persistent);
+        cache.set_ex("session", json!({ "user": "john" }), 0).unwrap();
+        assert_eq!(cache.get("session").unwrap(), None);
+        cache.set("name", "john").unwrap();
+        assert_eq!(cache.get_as::<String>("name").unwrap().as_deref(), Some("john"));
+        cache.del("name").unwrap();
+        assert_eq!(cache.ttl("name").unwrap(), Ttl::Missing);
+        assert!(!cache.expire("name", 60).unwrap());
+    }
+}
@@ -1,15 +1,125 @@
 //! # deta.rs
 //! This is the unofficial Rust SDK for Deta Base and Drive.
+#![cfg_attr(not(test), deny(clippy::unwrap_used))]
 
+#[cfg(not(any(feature = "blocking", feature = "tokio", feature = "wasm")))]
+compile_error!("detalib needs the `blocking`, `tokio` or `wasm` feature to send requests");
+
+#[cfg(all(target_arch = "wasm32", feature = "blocking"))]
+compile_error!("the `blocking` feature needs threads; build for wasm32 with the `wasm` feature only");
+
+
+use std::sync::{ Arc, OnceLock };
 
 use base::Base;
+use builder::DetaBuilder;
+#[cfg(feature = "blocking")]
+use collection::Collection;
 use drive::Drive;
 
-mod base;
-mod drive;
+pub mod base;
+pub mod drive;
+#[cfg(feature = "blocking")]
+pub mod file;
+mod sql;
+mod response;
+#[cfg(feature = "arrow")]
+mod columnar;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(feature = "secrets")]
+pub mod secrets;
+#[cfg(any(feature = "tokio", feature = "wasm"))]
+pub mod nonblocking;
+#[cfg(feature = "testkit")]
+pub mod testkit;
+#[cfg(feature = "mock")]
+pub mod mock;
+#[cfg(feature = "serve")]
+mod serve;
 pub mod query;
+pub mod filter;
+pub mod access;
 pub mod errors;
 pub mod updater;
+mod guard;
+mod hex;
+#[cfg(feature = "blocking")]
+pub mod tail;
+#[cfg(feature = "blocking")]
+pub mod collection;
+#[cfg(feature = "blocking")]
+pub mod cache;
+pub mod builder;
+pub mod parse;
+#[cfg(feature = "blocking")]
+pub mod scoped;
+#[cfg(any(feature = "blocking", feature = "tokio", feature = "wasm"))]
+pub mod iter;
+pub mod diff;
+pub mod changelog;
+#[cfg(feature = "blocking")]
+pub mod backups;
+#[cfg(feature = "blocking")]
+pub mod versioned;
+pub mod budget;
+pub mod expiring;
+pub mod retry;
+pub mod queue;
+pub mod ratelimit;
+pub mod keys;
+#[cfg(feature = "blocking")]
+pub mod kv;
+#[cfg(feature = "blocking")]
+pub mod mailbox;
+pub mod merge;
+pub mod checksum;
+pub mod canonical;
+pub mod registry;
+#[cfg(feature = "blocking")]
+pub mod normalize;
+pub mod sort;
+pub mod histogram;
+pub mod dates;
+#[cfg(feature = "blocking")]
+pub mod migrate;
+#[cfg(feature = "blocking")]
+pub mod config;
+#[cfg(feature = "blocking")]
+pub mod jsonl;
+#[cfg(feature = "blocking")]
+pub mod replicate;
+pub mod webhook;
+#[cfg(feature = "blocking")]
+pub mod bloom;
+#[cfg(feature = "blocking")]
+pub mod batch;
+#[cfg(feature = "blocking")]
+pub mod coalesce;
+#[cfg(feature = "blocking")]
+pub mod loader;
+#[cfg(feature = "blocking")]
+pub mod tracker;
+pub mod transport;
+mod record;
+mod telemetry;
+
+pub use record::DetaRecord;
+#[cfg(feature = "derive")]
+pub use detalib_derive::DetaRecord;
+
+#[cfg(all(test, feature = "derive"))]
+extern crate self as detalib;
+
+#[doc(hidden)]
+pub mod __private {
+    pub use serde;
+    pub use serde_json;
+
+    pub fn expires_at(seconds: u64) -> i64 {
+        chrono::Utc::now().timestamp().saturating_add(seconds as i64)
+    }
+}
 
 fn validate(key: &str) -> Option<&str> {
     let splits = key.split('_').collect::<Vec<&str>>();
@@ -20,10 +130,29 @@ fn validate(key: &str) -> Option<&str> {
     }
 }
 
-#[derive(Clone)]
-pub struct Deta {
+struct Inner {
     project_id: String,
     project_key: String,
+    #[cfg(feature = "blocking")]
+    transport: Arc<dyn transport::Transport>,
+    #[cfg(any(feature = "tokio", feature = "wasm"))]
+    http: Arc<dyn transport::AsyncTransport>,
+    correlation_id: Option<builder::CorrelationId>,
+    budget: Option<budget::BudgetGuard>,
+    retry: Option<retry::RetryPolicy>,
+    writes: Option<queue::WriteLimiter>,
+    limiter: Option<ratelimit::RateLimiter>,
+    #[cfg(feature = "blocking")]
+    webhook: Option<webhook::WebhookSender>,
+    recorder: Option<access::AccessRecorder>,
+    #[cfg(feature = "mock")]
+    mock: Option<Arc<mock::MockStore>>,
+}
+
+/// A Deta client. Cloning is cheap as the client state and connection pool are shared.
+#[derive(Clone)]
+pub struct Deta {
+    inner: Arc<Inner>,
 }
 
 impl Deta {
@@ -36,14 +165,7 @@ impl Deta {
     /// let base = deta.base("hello");
     /// ```
     pub fn from(project_key: &str) -> Deta {
-        let v = validate(project_key);
-        if v.is_none() {
-            panic!("Invalid project key, must be in the format `projectId_secret`.");
-        }
-        Deta{
-            project_id: v.unwrap().to_string(),
-            project_key: project_key.to_string(),
-        }
+        Deta::builder().project_key(project_key).build()
     }
 
     /// Create a new Deta instance from the `DETA_PROJECT_KEY` environment variable
@@ -53,16 +175,135 @@ impl Deta {
     /// let deta = Deta::new();
     /// let base = deta.base("world");
     /// ```
+    #[allow(clippy::new_without_default)]
     pub fn new() -> Deta {
-        let env_var = std::env::var("DETA_PROJECT_KEY")
-            .expect("Environment variable `DETA_PROJECT_KEY` is not set.");
-        let v = validate(&env_var);
-        if v.is_none() {
-            panic!("Invalid project key, must be in the format `projectId_secret`.");
+        Deta::builder().build()
+    }
+
+    /// Create a builder to configure a new Deta instance
+    /// ```rust
+    /// use detalib::Deta;
+    /// 
+    /// let deta = Deta::builder().user_agent("my-app/1.0").build();
+    /// ```
+    pub fn builder() -> DetaBuilder {
+        DetaBuilder::default()
+    }
+
+    /// Get the process wide Deta instance, created from the `DETA_PROJECT_KEY`
+    /// environment variable on first use
+    /// ```rust
+    /// use detalib::Deta;
+    /// 
+    /// let base = Deta::global().base("hello");
+    /// let drive = Deta::global().drive("world");
+    /// ```
+    pub fn global() -> Deta {
+        static GLOBAL: OnceLock<Deta> = OnceLock::new();
+        GLOBAL.get_or_init(Deta::new).clone()
+    }
+
+    fn charge(&self, bytes: usize) -> Result<(), errors::DetaError> {
+        match &self.inner.budget {
+            Some(budget) => budget.charge(bytes),
+            None => Ok(()),
+        }
+    }
+
+    /// Charges the budget and returns the headers sent with every request.
+    pub (crate) fn headers(
+        &self, bytes: usize, content_type: Option<&str>
+    ) -> Result<Vec<(&'static str, String)>, errors::DetaError> {
+        self.charge(bytes)?;
+        let mut headers = vec![("X-API-Key", self.inner.project_key.clone())];
+        if let Some(generator) = &self.inner.correlation_id {
+            headers.push(("X-Correlation-Id", generator()));
+        }
+        if let Some(content_type) = content_type {
+            headers.push(("Content-Type", content_type.to_string()));
         }
-        Deta {
-            project_id: v.unwrap().to_string(),
-            project_key: env_var,
+        Ok(headers)
+    }
+
+    /// Sends a request, holding a write slot for writes when a write queue is configured.
+    #[cfg(feature = "blocking")]
+    pub (crate) fn send(
+        &self, method: &str, url: &str, body: Option<&[u8]>, content_type: Option<&str>
+    ) -> Result<ureq::Response, errors::DetaError> {
+        let _permit = match &self.inner.writes {
+            Some(writes) if queue::is_write(method, url) => Some(writes.acquire(|| queue::JournalEntry {
+                method: method.to_string(),
+                url: url.to_string(),
+                body: body.map(<[u8]>::to_vec),
+                content_type: content_type.map(String::from),
+            })?),
+            _ => None,
+        };
+        let resp = self.send_now(method, url, body, content_type)?;
+        if let Some(webhook) = &self.inner.webhook {
+            webhook.emit(method, url, body);
+        }
+        Ok(resp)
+    }
+
+    /// Sends a request, retrying transient failures according to the retry policy.
+    #[cfg(feature = "blocking")]
+    pub (crate) fn send_now(
+        &self, method: &str, url: &str, body: Option<&[u8]>, content_type: Option<&str>
+    ) -> Result<ureq::Response, errors::DetaError> {
+        self.send_with(method, url, body, content_type, &[])
+    }
+
+    /// Same as `send_now`, adding `extra` to the headers. The mock backend ignores them.
+    #[cfg(feature = "blocking")]
+    pub (crate) fn send_with(
+        &self,
+        method: &str,
+        url: &str,
+        body: Option<&[u8]>,
+        content_type: Option<&str>,
+        extra: &[(&'static str, String)],
+    ) -> Result<ureq::Response, errors::DetaError> {
+        #[cfg(feature = "mock")]
+        if let Some(store) = &self.inner.mock {
+            self.charge(body.map_or(0, <[u8]>::len))?;
+            return store.respond(method, url, body);
+        }
+        let span = telemetry::RequestSpan::start(method, url, &self.inner.project_key);
+        let mut attempt = 0;
+        loop {
+            if let Some(limiter) = &self.inner.limiter {
+                std::thread::sleep(limiter.reserve());
+            }
+            let mut headers = self.headers(body.map_or(0, <[u8]>::len), content_type)?;
+            headers.extend_from_slice(extra);
+            let resp = self.inner.transport.send(&transport::Request { method, url, headers, body });
+            let status = match &resp {
+                Ok(resp) => Some(resp.status()),
+                Err(ureq::Error::Status(status, _)) => Some(*status),
+                Err(ureq::Error::Transport(_)) => None,
+            };
+            if let Some(limiter) = &self.inner.limiter {
+                match (&resp, status) {
+                    (_, Some(429)) => limiter.throttled(),
+                    (Ok(_), _) => limiter.succeeded(),
+                    _ => {},
+                }
+            }
+            if resp.is_ok() {
+                span.finish(status, attempt);
+                return resp.map_err(errors::DetaError::from);
+            }
+            match &self.inner.retry {
+                Some(policy) if policy.should_retry(attempt, status) => {
+                    std::thread::sleep(policy.delay(attempt));
+                    attempt += 1;
+                },
+                _ => {
+                    span.finish(status, attempt);
+                    return resp.map_err(errors::DetaError::from);
+                },
+            }
         }
     }
 
@@ -75,11 +316,54 @@ impl Deta {
     /// ```
     pub fn base(&self, name: &str) -> Base {
         Base {
-            name: name.to_string(),
+            name: Arc::from(name),
             service: self.clone(),
+            dates: dates::DateFormat::default(),
+            keys: None,
         }
     }
 
+    /// Create a typed collection over a Deta Base
+    /// ```rust
+    /// use detalib::Deta;
+    /// 
+    /// #[derive(serde::Serialize, serde::Deserialize)]
+    /// struct User { key: String, name: String }
+    /// 
+    /// let deta = Deta::new();
+    /// let users = deta.collection::<User>("users");
+    /// ```
+    #[cfg(feature = "blocking")]
+    pub fn collection<T>(&self, name: &str) -> Collection<T>
+        where T: serde::Serialize + serde::de::DeserializeOwned
+    {
+        Collection::new(self.base(name))
+    }
+
+    /// Create a new async Deta Base instance
+    /// ```rust
+    /// use detalib::Deta;
+    /// 
+    /// let deta = Deta::new();
+    /// let base = deta.base_async("hello");
+    /// ```
+    #[cfg(any(feature = "tokio", feature = "wasm"))]
+    pub fn base_async(&self, name: &str) -> nonblocking::AsyncBase {
+        nonblocking::AsyncBase::new(self.base(name))
+    }
+
+    /// Create a new async Deta Drive instance
+    /// ```rust
+    /// use detalib::Deta;
+    /// 
+    /// let deta = Deta::new();
+    /// let drive = deta.drive_async("world");
+    /// ```
+    #[cfg(any(feature = "tokio", feature = "wasm"))]
+    pub fn drive_async(&self, name: &str) -> nonblocking::AsyncDrive {
+        nonblocking::AsyncDrive::new(self.drive(name))
+    }
+
     /// Create a new Deta Drive instance
     /// ```rust
     /// use detalib::Deta;
@@ -89,14 +373,19 @@ impl Deta {
     /// ```
     pub fn drive(&self, name: &str) -> Drive {
         Drive {
-            name: name.to_string(),
+            name: Arc::from(name),
             service: self.clone(),
+            #[cfg(feature = "blocking")]
+            cache: drive::FileCache::default(),
+            #[cfg(feature = "blocking")]
+            segments: drive::JsonlSegments::default(),
+            upload: drive::UploadOptions::default(),
         }
     }
 }
 
 
-#[cfg(test)]
+#[cfg(all(test, feature = "blocking"))]
 mod run_tests {
     use serde_json::json;
 
@@ -119,7 +408,7 @@ mod run_tests {
             age: 20,
             address: String::from("123 Broadway")
         };
-        assert!(db.put(vec![user]).unwrap().to_string().contains("db8213bc"));
+        assert_eq!(db.put(vec![user]).unwrap().processed[0]["key"], "db8213bc");
         assert_eq!(db.get_as::<User>("db8213bc").unwrap().name, user.name);
         assert!(db.insert(user).is_err_and(|e| e.to_string().contains("409")));
         assert!(!db.query()
@@ -131,7 +420,7 @@ mod run_tests {
         assert!(db.update("db8213bc")
             .set("name", json!("John"))
             .increment("age", json!(24))
-            .commit().unwrap().to_string().contains("db8213bc")
+            .commit().unwrap().key == "db8213bc"
         );
         assert!(db.delete("db8213bc").is_ok());
     }
@@ -141,7 +430,7 @@ mod run_tests {
         let db = Deta::new().drive("world");
         assert!(db.put("test.txt", b"Hello, World!", None).is_ok());
         assert!(!db.list(None, None, None).unwrap().names.is_empty());
-        assert!(!db.walk(None).is_empty());
+        assert!(!db.walk(None).unwrap().is_empty());
         assert!(db.get("test.txt").is_ok());
         assert!(db.delete(vec!["test.txt"]).is_ok());
     }
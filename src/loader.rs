@@ -0,0 +1,261 @@
+//! Batched, de-duplicated and cached `get`s, in the style of GraphQL's DataLoader.
+
+use std::{
+    collections::HashMap,
+    sync::{ Arc, Condvar, Mutex, MutexGuard },
+    thread,
+    time::Duration,
+};
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::{ base::Base, errors::DetaError };
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+type Loaded<T> = Result<Option<T>, Arc<DetaError>>;
+
+enum Entry<T> {
+    /// Requested in the current tick or being fetched.
+    Pending,
+    Loaded(Loaded<T>),
+}
+
+#[derive(Clone, Copy)]
+struct Config {
+    tick: Duration,
+    concurrency: usize,
+}
+
+struct State<T> {
+    entries: HashMap<String, Entry<T>>,
+    /// Keys of the current tick, not fetched yet.
+    queue: Vec<String>,
+    /// Whether a caller is waiting for the current tick to end to fetch the queue.
+    collecting: bool,
+}
+
+struct Shared<T> {
+    base: Base,
+    config: Mutex<Config>,
+    state: Mutex<State<T>>,
+    loaded: Condvar,
+}
+
+/// Loads records of a base by key, batching and de-duplicating the loads made within a tick
+/// and caching every result.
+///
+/// The first load of a tick waits `tick` for loads from other threads, then fetches every
+/// distinct key requested in the meantime with up to `concurrency` parallel requests, and
+/// each caller gets the result for its key. Keys loaded before are answered from the cache,
+/// including missing records and failures, so a loader is meant to live as long as a request
+/// to your server, with clones shared by the code handling it. Failures are returned as
+/// `DetaError::Shared`, as several callers may get the same one.
+/// ```rust,no_run
+/// use detalib::{ Deta, loader::Loader };
+/// use serde::Deserialize;
+///
+/// #[derive(Clone, Deserialize)]
+/// struct User { key: String, name: String }
+///
+/// let users = Loader::<User>::new(Deta::new().base("users"));
+/// let authors = ["u1", "u2", "u1"].map(|key| {
+///     let users = users.clone();
+///     std::thread::spawn(move || users.load(key))
+/// });
+/// for author in authors {
+///     if let Some(user) = author.join().unwrap().unwrap() {
+///         println!("{}", user.name);
+///     }
+/// }
+/// ```
+pub struct Loader<T = Value> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Clone for Loader<T> {
+    fn clone(&self) -> Self {
+        Loader { shared: self.shared.clone() }
+    }
+}
+
+impl<T: DeserializeOwned + Clone + Send> Loader<T> {
+
+    pub fn new(base: Base) -> Loader<T> {
+        Loader {
+            shared: Arc::new(Shared {
+                base,
+                config: Mutex::new(Config { tick: Duration::from_millis(5), concurrency: 8 }),
+                state: Mutex::new(State {
+                    entries: HashMap::new(),
+                    queue: Vec::new(),
+                    collecting: false,
+                }),
+                loaded: Condvar::new(),
+            }),
+        }
+    }
+
+    /// Sets how long the first load of a batch waits for more loads. Defaults to 5 milliseconds.
+    pub fn tick(self, tick: Duration) -> Self {
+        lock(&self.shared.config).tick = tick;
+        self
+    }
+
+    /// Sets how many records of a batch are fetched at once. Defaults to 8.
+    pub fn concurrency(self, concurrency: usize) -> Self {
+        lock(&self.shared.config).concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Loads a record, `None` if it does not exist.
+    pub fn load(&self, key: &str) -> Result<Option<T>, DetaError> {
+        self.resolve(&[key]).remove(0)
+    }
+
+    /// Loads several records in one batch, returning their results in the order of `keys`.
+    pub fn load_many(&self, keys: &[&str]) -> Vec<Result<Option<T>, DetaError>> {
+        self.resolve(keys)
+    }
+
+    /// Caches a record, e.g. one just written or fetched by a query, unless its key was loaded.
+    pub fn prime(&self, key: &str, record: T) {
+        let mut state = lock(&self.shared.state);
+        state.entries.entry(key.to_string()).or_insert(Entry::Loaded(Ok(Some(record))));
+    }
+
+    /// Forgets the cached result of a key, so the next load fetches it again.
+    pub fn clear(&self, key: &str) {
+        let mut state = lock(&self.shared.state);
+        if let Some(Entry::Loaded(_)) = state.entries.get(key) {
+            state.entries.remove(key);
+        }
+    }
+
+    fn resolve(&self, keys: &[&str]) -> Vec<Result<Option<T>, DetaError>> {
+        let mut state = lock(&self.shared.state);
+        loop {
+            for key in keys {
+                if !state.entries.contains_key(*key) {
+                    state.entries.insert(key.to_string(), Entry::Pending);
+                    state.queue.push(key.to_string());
+                }
+            }
+            if !state.queue.is_empty() && !state.collecting {
+                state.collecting = true;
+                drop(state);
+                self.fetch();
+                state = lock(&self.shared.state);
+                continue;
+            }
+            while keys.iter().any(|key| matches!(state.entries.get(*key), Some(Entry::Pending))) {
+                state = self.shared.loaded.wait(state).unwrap_or_else(|e| e.into_inner());
+            }
+            let loaded = keys.iter()
+                .map(|key| match state.entries.get(*key) {
+                    Some(Entry::Loaded(loaded)) => Some(loaded.clone().map_err(DetaError::Shared)),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>();
+            // a key cleared by another caller right after it was loaded is loaded again
+            if let Some(loaded) = loaded {
+                return loaded;
+            }
+        }
+    }
+
+    /// Waits for the tick to end, then fetches the queued keys and wakes their callers.
+    fn fetch(&self) {
+        let Config { tick, concurrency } = *lock(&self.shared.config);
+        thread::sleep(tick);
+        let keys = {
+            let mut state = lock(&self.shared.state);
+            state.collecting = false;
+            std::mem::take(&mut state.queue)
+        };
+        let size = keys.len().div_ceil(concurrency).max(1);
+        let base = &self.shared.base;
+        let mut fetched = thread::scope(|scope| {
+            let handles = keys.chunks(size)
+                .map(|chunk| scope.spawn(move || {
+                    chunk.iter()
+                        .map(|key| (key.clone(), base.get_opt_as::<T>(key)))
+                        .collect::<Vec<_>>()
+                }))
+                .collect::<Vec<_>>();
+            handles.into_iter()
+                .flat_map(|handle| handle.join().unwrap_or_default())
+                .collect::<HashMap<_, _>>()
+        });
+        let mut state = lock(&self.shared.state);
+        for key in keys {
+            let loaded = fetched.remove(&key).unwrap_or(Err(DetaError::TransportError));
+            state.entries.insert(key, Entry::Loaded(loaded.map_err(Arc::new)));
+        }
+        self.shared.loaded.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::{ Deta, transport::{ Request, Transport } };
+
+    /// Serves records named by their key, counting the requests for every key.
+    #[derive(Default)]
+    struct Records {
+        gets: Mutex<HashMap<String, u32>>,
+    }
+
+    impl Transport for Arc<Records> {
+        fn send(&self, request: &Request) -> Result<ureq::Response, ureq::Error> {
+            let key = request.url.rsplit('/').next().unwrap_or_default().to_string();
+            *self.gets.lock().unwrap().entry(key.clone()).or_default() += 1;
+            match key.as_str() {
+                "missing" => Err(ureq::Error::Status(404, ureq::Response::new(404, "Not Found", "{}")?)),
+                "broken" => Err(ureq::Error::Status(500, ureq::Response::new(500, "Server Error", "")?)),
+                key => {
+                    let record = json!({ "key": key, "name": key.to_uppercase() });
+                    ureq::Response::new(200, "OK", &record.to_string())
+                },
+            }
+        }
+    }
+
+    #[test]
+    fn batches_and_caches_loads() {
+        let records = Arc::new(Records::default());
+        let deta = Deta::builder().project_key("id_secret").transport(records.clone()).build();
+        let base = deta.base("users");
+        let users = Loader::<Value>::new(base).tick(Duration::from_millis(50));
+        let loads = ["a", "b", "a", "missing", "b", "a"].map(|key| {
+            let users = users.clone();
+            thread::spawn(move || users.load(key).unwrap().map(|user| user["name"].clone()))
+        });
+        let expected = ["A", "B", "A", "", "B", "A"]
+            .map(|name| Some(json!(name)).filter(|_| !name.is_empty()));
+        assert_eq!(loads.map(|load| load.join().unwrap()), expected);
+        assert_eq!(records.gets.lock().unwrap().values().sum::<u32>(), 3);
+
+        let loaded = users.load_many(&["a", "broken", "c", "broken"]);
+        assert_eq!(loaded[2].as_ref().unwrap().as_ref().unwrap()["name"], "C");
+        let Err(DetaError::Shared(e)) = &loaded[1] else {
+            panic!("expected a shared error");
+        };
+        assert!(matches!(**e, DetaError::HTTPError { status: 500, .. }));
+        assert!(loaded[3].is_err());
+        users.prime("d", json!({ "key": "d", "name": "primed" }));
+        assert_eq!(users.load("d").unwrap().unwrap()["name"], "primed");
+        users.clear("a");
+        users.load("a").unwrap();
+        let gets = records.gets.lock().unwrap();
+        assert_eq!((gets["a"], gets["broken"], gets.get("d")), (2, 1, None));
+    }
+}
@@ -0,0 +1,94 @@
+//! Durable point-to-point messages between services, stored in a base.
+
+use serde::{ Serialize, de::DeserializeOwned };
+use serde_json::{ json, Value };
+
+use crate::{ base::{ typed, Base }, errors::DetaError, keys::Key };
+
+/// Per-actor message queues stored as records of a base.
+///
+/// Every message is a record keyed with a ULID, so an actor receives its messages
+/// in the order they were sent. Messages are delivered at least once: `drain` deletes
+/// them after reading, and a message whose delete failed is delivered again by the next
+/// `drain`. Each actor should have a single consumer.
+/// ```rust,no_run
+/// use detalib::Deta;
+/// use serde_json::json;
+///
+/// let mailbox = Deta::new().base("mailbox").mailbox();
+/// mailbox.send("billing", json!({ "invoice": 42 })).unwrap();
+/// for message in mailbox.drain("billing").unwrap() {
+///     println!("{}", message);
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Mailbox {
+    base: Base,
+}
+
+impl Mailbox {
+
+    pub (crate) fn new(base: Base) -> Mailbox {
+        Mailbox { base }
+    }
+
+    /// Returns the underlying base.
+    pub fn base(&self) -> &Base {
+        &self.base
+    }
+
+    /// Appends a message to the queue of `actor_id`. Returns the key of the message.
+    pub fn send<T: Serialize>(&self, actor_id: &str, message: T) -> Result<String, DetaError> {
+        let key = Key::ulid();
+        let sent_at = chrono::Utc::now().timestamp_millis();
+        self.base.insert(json!({ "key": key, "actor": actor_id, "message": message, "sent_at": sent_at }))?;
+        Ok(key)
+    }
+
+    /// The number of messages waiting for `actor_id`.
+    pub fn len(&self, actor_id: &str) -> Result<usize, DetaError> {
+        self.base.query().equals("actor", json!(actor_id)).count()
+    }
+
+    /// Removes and returns every message waiting for `actor_id`, oldest first.
+    pub fn drain(&self, actor_id: &str) -> Result<Vec<Value>, DetaError> {
+        let records = self.base.query().equals("actor", json!(actor_id)).walk()?;
+        let mut messages = Vec::with_capacity(records.len());
+        for mut record in records {
+            let key = record["key"].as_str().unwrap_or_default().to_string();
+            match self.base.delete(&key) {
+                Ok(_) | Err(DetaError::NotFound { .. }) => messages.push(record["message"].take()),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Same as `drain`, deserializing the messages.
+    ///
+    /// Messages are deleted before they are deserialized, so a malformed message is lost.
+    pub fn drain_as<T: DeserializeOwned>(&self, actor_id: &str) -> Result<Vec<T>, DetaError> {
+        self.drain(actor_id)?.into_iter().map(typed).collect()
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use serde_json::json;
+
+    use crate::mock::MockDeta;
+
+    #[test]
+    fn mailbox_delivers_in_order() {
+        let mailbox = MockDeta::new().base("mailbox").mailbox();
+        for n in 0..30 {
+            mailbox.send(if n % 3 == 0 { "billing" } else { "email" }, json!({ "n": n })).unwrap();
+        }
+        assert_eq!(mailbox.len("billing").unwrap(), 10);
+        let billing = mailbox.drain("billing").unwrap();
+        let numbers = billing.iter().map(|message| message["n"].as_i64().unwrap()).collect::<Vec<_>>();
+        assert_eq!(numbers, (0..30).step_by(3).collect::<Vec<_>>());
+        assert!(mailbox.drain("billing").unwrap().is_empty());
+        assert_eq!(mailbox.drain_as::<serde_json::Value>("email").unwrap().len(), 20);
+    }
+}
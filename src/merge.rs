@@ -0,0 +1,85 @@
+//! Deep merging of JSON records.
+
+use serde_json::Value;
+
+/// How arrays present on both sides of a merge are combined.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ArrayStrategy {
+    /// The array from the patch replaces the existing one.
+    #[default]
+    Replace,
+    /// The array from the patch is appended to the existing one.
+    Concat,
+    /// Elements are matched by the value of the given field. Matching elements are merged,
+    /// the rest are appended unless an equal element already exists.
+    UnionByKey(String),
+}
+
+/// Merges `patch` into `target`, recursing into objects present on both sides.
+/// 
+/// Arrays are combined according to `arrays`. Any other value in `patch`,
+/// including `null`, replaces the one in `target`.
+/// ```rust
+/// use detalib::merge::{ deep_merge, ArrayStrategy };
+/// use serde_json::json;
+/// 
+/// let mut record = json!({ "profile": { "name": "John" }, "roles": [{ "id": 1, "on": false }] });
+/// let patch = json!({ "profile": { "age": 20 }, "roles": [{ "id": 1, "on": true }, { "id": 2 }] });
+/// deep_merge(&mut record, patch, &ArrayStrategy::UnionByKey("id".to_string()));
+/// assert_eq!(record, json!({
+///     "profile": { "name": "John", "age": 20 },
+///     "roles": [{ "id": 1, "on": true }, { "id": 2 }]
+/// }));
+/// ```
+pub fn deep_merge(target: &mut Value, patch: Value, arrays: &ArrayStrategy) {
+    match (target, patch) {
+        (Value::Object(target), Value::Object(patch)) => {
+            for (field, value) in patch {
+                match target.get_mut(&field) {
+                    Some(existing) => deep_merge(existing, value, arrays),
+                    None => { target.insert(field, value); },
+                }
+            }
+        },
+        (Value::Array(target), Value::Array(patch)) => match arrays {
+            ArrayStrategy::Replace => *target = patch,
+            ArrayStrategy::Concat => target.extend(patch),
+            ArrayStrategy::UnionByKey(field) => {
+                for item in patch {
+                    let existing = item.get(field)
+                        .and_then(|id| target.iter().position(|t| t.get(field) == Some(id)));
+                    match existing {
+                        Some(i) => deep_merge(&mut target[i], item, arrays),
+                        None if !target.contains(&item) => target.push(item),
+                        None => {},
+                    }
+                }
+            },
+        },
+        (target, patch) => *target = patch,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn merges_nested_objects() {
+        let mut record = json!({ "key": "k", "profile": { "name": "John", "age": 20 }, "tags": [1] });
+        deep_merge(&mut record, json!({ "profile": { "age": 21 }, "tags": [2] }), &ArrayStrategy::Replace);
+        assert_eq!(record, json!({ "key": "k", "profile": { "name": "John", "age": 21 }, "tags": [2] }));
+    }
+
+    #[test]
+    fn array_strategies() {
+        let mut tags = json!([1, 2]);
+        deep_merge(&mut tags, json!([2, 3]), &ArrayStrategy::Concat);
+        assert_eq!(tags, json!([1, 2, 2, 3]));
+        let mut tags = json!([1, 2]);
+        deep_merge(&mut tags, json!([2, 3]), &ArrayStrategy::UnionByKey("id".to_string()));
+        assert_eq!(tags, json!([1, 2, 3]));
+    }
+}
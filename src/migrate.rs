@@ -0,0 +1,192 @@
+//! Copying records between bases, e.g. to rename a base or move it to another project.
+
+use serde_json::Value;
+
+use crate::{ base::Base, errors::DetaError };
+
+/// Progress and outcome of `copy_base`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CopyReport {
+    /// Records read from the source base.
+    pub scanned: usize,
+    /// Records selected for copying. In a dry run nothing is written.
+    pub selected: usize,
+    /// Records written to the destination base.
+    pub copied: usize,
+    /// Keys of the records the destination base rejected.
+    pub failed: Vec<String>,
+}
+
+type KeyFilter = Box<dyn FnMut(&str) -> bool>;
+type Transform = Box<dyn FnMut(Value) -> Option<Value>>;
+type Progress = Box<dyn FnMut(&CopyReport)>;
+
+/// Which records `copy_base` copies and how.
+/// ```rust,no_run
+/// use detalib::{ Deta, migrate::{ copy_base, CopyOptions } };
+///
+/// let (old, new) = (Deta::new(), Deta::from("other_project_key"));
+/// let options = CopyOptions::new()
+///     .key_prefix("user_")
+///     .transform(|mut user| {
+///         user["migrated"] = true.into();
+///         Some(user)
+///     })
+///     .on_progress(|report| println!("{} copied", report.copied));
+/// let report = copy_base(&old.base("users"), &new.base("users"), options).unwrap();
+/// ```
+pub struct CopyOptions {
+    key_prefix: Option<String>,
+    filter: Option<KeyFilter>,
+    transform: Option<Transform>,
+    dry_run: bool,
+    batch_size: usize,
+    progress: Option<Progress>,
+}
+
+impl Default for CopyOptions {
+    fn default() -> Self {
+        CopyOptions::new()
+    }
+}
+
+impl CopyOptions {
+
+    /// Copies every record unchanged.
+    pub fn new() -> CopyOptions {
+        CopyOptions {
+            key_prefix: None,
+            filter: None,
+            transform: None,
+            dry_run: false,
+            batch_size: 25,
+            progress: None,
+        }
+    }
+
+    /// Only copies records whose key starts with `prefix`, filtered by Deta.
+    pub fn key_prefix(mut self, prefix: &str) -> Self {
+        self.key_prefix = Some(prefix.to_string());
+        self
+    }
+
+    /// Only copies records whose key passes `f`.
+    pub fn filter_keys<F: FnMut(&str) -> bool + 'static>(mut self, f: F) -> Self {
+        self.filter = Some(Box::new(f));
+        self
+    }
+
+    /// Rewrites each record before it is written, returning `None` to skip it.
+    ///
+    /// The transform may change the key, e.g. to add a namespace.
+    pub fn transform<F: FnMut(Value) -> Option<Value> + 'static>(mut self, f: F) -> Self {
+        self.transform = Some(Box::new(f));
+        self
+    }
+
+    /// Only report what would be copied, without writing to the destination.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Sets how many records are written per request, at most 25.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.clamp(1, 25);
+        self
+    }
+
+    /// Calls `f` with the report so far after every written batch and at the end.
+    pub fn on_progress<F: FnMut(&CopyReport) + 'static>(mut self, f: F) -> Self {
+        self.progress = Some(Box::new(f));
+        self
+    }
+
+    fn flush(
+        &mut self, dst: &Base, batch: &mut Vec<Value>, report: &mut CopyReport
+    ) -> Result<(), DetaError> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        if !self.dry_run {
+            let result = dst.put_many(batch)?;
+            report.copied += result.processed.len();
+            report.failed.extend(result.failed);
+        }
+        batch.clear();
+        if let Some(progress) = &mut self.progress {
+            progress(report);
+        }
+        Ok(())
+    }
+}
+
+/// Copy the records of `src` into `dst`, replacing records with the same key.
+///
+/// The bases may belong to different projects. Records are streamed, so only one page
+/// and one batch are held in memory at a time. The source base is never modified.
+pub fn copy_base(src: &Base, dst: &Base, mut options: CopyOptions) -> Result<CopyReport, DetaError> {
+    let mut query = src.query();
+    if let Some(prefix) = &options.key_prefix {
+        query = query.key_prefix(prefix);
+    }
+    let mut report = CopyReport::default();
+    let mut batch = Vec::new();
+    for item in query.iter() {
+        let item = item?;
+        report.scanned += 1;
+        if let Some(filter) = &mut options.filter {
+            if !filter(item["key"].as_str().unwrap_or_default()) {
+                continue;
+            }
+        }
+        let item = match &mut options.transform {
+            Some(transform) => match transform(item) {
+                Some(item) => item,
+                None => continue,
+            },
+            None => item,
+        };
+        report.selected += 1;
+        batch.push(item);
+        if batch.len() >= options.batch_size {
+            options.flush(dst, &mut batch, &mut report)?;
+        }
+    }
+    options.flush(dst, &mut batch, &mut report)?;
+    if let Some(progress) = &mut options.progress {
+        progress(&report);
+    }
+    Ok(report)
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use serde_json::json;
+
+    use super::{ copy_base, CopyOptions };
+    use crate::mock::MockDeta;
+
+    #[test]
+    fn copy_base_filters_and_transforms() {
+        let deta = MockDeta::new();
+        let (src, dst) = (deta.base("old"), deta.base("new"));
+        let users = (0..30).map(|i| json!({ "key": format!("u{:02}", i), "n": i })).collect::<Vec<_>>();
+        src.put_many(&users).unwrap();
+        src.put(vec![json!({ "key": "other" })]).unwrap();
+        let options = || CopyOptions::new()
+            .key_prefix("u")
+            .filter_keys(|key| key != "u00")
+            .transform(|mut user| {
+                user["n"] = json!(user["n"].as_i64()? * 2);
+                Some(user)
+            });
+        let report = copy_base(&src, &dst, options().dry_run(true)).unwrap();
+        assert_eq!((report.scanned, report.selected, report.copied), (30, 29, 0));
+        assert!(dst.query().walk().unwrap().is_empty());
+        let report = copy_base(&src, &dst, options().batch_size(10)).unwrap();
+        assert_eq!(report.copied, 29);
+        assert_eq!(dst.get("u29").unwrap()["n"], 58);
+        assert!(dst.get_opt("other").unwrap().is_none());
+    }
+}
@@ -0,0 +1,506 @@
+//! An in-memory Deta backend for tests, enabled with the `mock` feature.
+//!
+//! `MockDeta` answers the requests of `Base`, `Drive`, `Query` and `Updater` from memory
+//! instead of the network, so code written against `Deta` can be unit-tested without a project key.
+
+use std::{
+    collections::{ BTreeMap, HashMap },
+    ops::Deref,
+    sync::{ Arc, Mutex },
+};
+
+use serde_json::{ json, Map, Value };
+
+use crate::{ Deta, errors::DetaError };
+
+/// A Deta client backed by an in-memory store.
+///
+/// Dereferences to `Deta`, so bases and drives are created as usual.
+/// ```rust
+/// use detalib::mock::MockDeta;
+/// use serde_json::json;
+///
+/// let deta = MockDeta::new();
+/// let base = deta.base("users");
+/// base.put(vec![json!({ "key": "u1", "name": "John" })]).unwrap();
+/// assert_eq!(base.get("u1").unwrap()["name"], "John");
+/// ```
+#[derive(Clone)]
+pub struct MockDeta {
+    deta: Deta,
+}
+
+impl MockDeta {
+
+    /// Create a client with an empty store.
+    pub fn new() -> MockDeta {
+        MockDeta { deta: Deta::builder().project_key("mock_key").mock(Arc::default()).build() }
+    }
+
+    /// The underlying client, for code that takes a `Deta`.
+    pub fn deta(&self) -> Deta {
+        self.deta.clone()
+    }
+}
+
+impl Default for MockDeta {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for MockDeta {
+    type Target = Deta;
+
+    fn deref(&self) -> &Deta {
+        &self.deta
+    }
+}
+
+struct Upload {
+    drive: String,
+    name: String,
+    parts: BTreeMap<u64, Vec<u8>>,
+}
+
+#[derive(Default)]
+struct State {
+    bases: HashMap<String, BTreeMap<String, Value>>,
+    drives: HashMap<String, BTreeMap<String, Vec<u8>>>,
+    uploads: HashMap<String, Upload>,
+    counter: u64,
+}
+
+type Reply = (u16, Vec<u8>);
+
+fn reply(status: u16, body: Value) -> Reply {
+    (status, body.to_string().into_bytes())
+}
+
+fn json_body(body: Option<&[u8]>) -> Value {
+    body.and_then(|body| serde_json::from_slice::<Value>(body).ok()).unwrap_or_default()
+}
+
+fn failure(status: u16, msg: &str) -> Reply {
+    reply(status, json!({ "errors": [msg] }))
+}
+
+/// In-memory emulation of the Deta Base and Drive HTTP APIs.
+#[derive(Default)]
+pub (crate) struct MockStore {
+    state: Mutex<State>,
+}
+
+impl MockStore {
+
+    fn handle(&self, method: &str, url: &str, body: Option<&[u8]>) -> Reply {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let (url, query) = url.split_once('?').unwrap_or((url, ""));
+        let params = query.split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(k, v)| (k, urlencoding::decode(v).map(|v| v.into_owned()).unwrap_or_default()))
+            .collect::<HashMap<&str, String>>();
+        let segments = url.split('/').skip(5).collect::<Vec<&str>>();
+        let (name, path) = match segments.split_first() {
+            Some((name, path)) => (name.to_string(), path),
+            None => return failure(400, "missing name"),
+        };
+        let method = match method.to_ascii_uppercase() {
+            head if head == "HEAD" => String::from("GET"),
+            method => method,
+        };
+        if url.starts_with("https://database.deta.sh/") {
+            state.base(&name, &method, path, body)
+        } else if url.starts_with("https://drive.deta.sh/") {
+            state.drive(&name, &method, path, &params, body)
+        } else {
+            failure(400, "unsupported mock request")
+        }
+    }
+
+    pub (crate) fn respond(
+        &self, method: &str, url: &str, body: Option<&[u8]>
+    ) -> Result<ureq::Response, DetaError> {
+        let (status, mut body) = self.handle(method, url, body);
+        if status >= 400 {
+            return Err(DetaError::from_status(status, "mock error", &body));
+        }
+        let length = body.len();
+        if method.eq_ignore_ascii_case("HEAD") {
+            body.clear();
+        }
+        let resp = http02::Response::builder().status(status).header("Content-Length", length).body(body)
+            .map_err(|_| DetaError::TransportError)?;
+        Ok(resp.into())
+    }
+
+    #[cfg(any(feature = "tokio", feature = "wasm"))]
+    pub (crate) fn respond_async(
+        &self, method: &str, url: &str, body: Option<&[u8]>
+    ) -> Result<reqwest::Response, DetaError> {
+        let (status, body) = self.handle(method, url, body);
+        if status >= 400 {
+            return Err(DetaError::from_status(status, "mock error", &body));
+        }
+        let resp = http::Response::builder().status(status).body(body)
+            .map_err(|_| DetaError::TransportError)?;
+        Ok(resp.into())
+    }
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+fn expired(item: &Value) -> bool {
+    item["__expires"].as_i64().is_some_and(|at| at <= now())
+}
+
+fn field<'a>(item: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(item, |value, part| value.get(part))
+}
+
+fn field_mut<'a>(item: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    let mut value = item;
+    for part in path.split('.') {
+        let map = value.as_object_mut()?;
+        value = map.entry(part).or_insert_with(|| Value::Object(Map::new()));
+    }
+    Some(value)
+}
+
+fn remove_field(item: &mut Value, path: &str) {
+    let (parent, last) = match path.rsplit_once('.') {
+        Some((parent, last)) => (field_mut(item, parent), last),
+        None => (Some(item), path),
+    };
+    if let Some(Value::Object(map)) = parent {
+        map.remove(last);
+    }
+}
+
+fn compare(a: &Value, b: &Value) -> Option<std::cmp::Ordering> {
+    match (a, b) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+fn contains(value: &Value, needle: &Value) -> bool {
+    match (value, needle) {
+        (Value::String(s), Value::String(n)) => s.contains(n.as_str()),
+        (Value::Array(items), needle) => items.contains(needle),
+        _ => false,
+    }
+}
+
+fn matches(item: &Value, condition: &str, expected: &Value) -> bool {
+    use std::cmp::Ordering::*;
+    let (path, op) = condition.rsplit_once('?').unwrap_or((condition, ""));
+    let actual = match field(item, path) {
+        Some(actual) => actual,
+        None => return op == "ne" || op == "not_contains",
+    };
+    let in_range = |range: &Value| match range.as_array().map(Vec::as_slice) {
+        Some([lo, hi]) => {
+            matches!(compare(actual, lo), Some(Greater | Equal))
+                && matches!(compare(actual, hi), Some(Less | Equal))
+        },
+        _ => false,
+    };
+    match op {
+        "" => actual == expected,
+        "ne" => actual != expected,
+        "gt" => compare(actual, expected) == Some(Greater),
+        "gte" => matches!(compare(actual, expected), Some(Greater | Equal)),
+        "lt" => compare(actual, expected) == Some(Less),
+        "lte" => matches!(compare(actual, expected), Some(Less | Equal)),
+        "pfx" => matches!(
+            (actual, expected), (Value::String(a), Value::String(p)) if a.starts_with(p.as_str())
+        ),
+        "r" | "range" => in_range(expected),
+        "contains" => contains(actual, expected),
+        "not_contains" => !contains(actual, expected),
+        _ => false,
+    }
+}
+
+fn apply_update(item: &mut Value, update: &Value) {
+    let ops = |name: &str| update[name].as_object().cloned().unwrap_or_default();
+    for (path, value) in ops("set") {
+        if let Some(slot) = field_mut(item, &path) {
+            *slot = value;
+        }
+    }
+    for (path, value) in ops("increment") {
+        if let Some(slot) = field_mut(item, &path) {
+            // a missing field was just created as an empty object
+            let current = if slot.is_object() { Value::from(0) } else { slot.take() };
+            *slot = match (current.as_i64(), value.as_i64()) {
+                (Some(a), Some(b)) => Value::from(a + b),
+                _ => Value::from(current.as_f64().unwrap_or_default() + value.as_f64().unwrap_or_default()),
+            };
+        }
+    }
+    for (name, front) in [("append", false), ("prepend", true)] {
+        for (path, value) in ops(name) {
+            if let Some(slot) = field_mut(item, &path) {
+                let mut items = match slot.take() {
+                    Value::Array(items) => items,
+                    _ => vec![],
+                };
+                let values = match value {
+                    Value::Array(values) => values,
+                    value => vec![value],
+                };
+                if front {
+                    items.splice(0..0, values);
+                } else {
+                    items.extend(values);
+                }
+                *slot = Value::Array(items);
+            }
+        }
+    }
+    for path in update["delete"].as_array().into_iter().flatten() {
+        if let Some(path) = path.as_str() {
+            remove_field(item, path);
+        }
+    }
+}
+
+impl State {
+
+    fn next_id(&mut self) -> String {
+        self.counter += 1;
+        format!("{:012x}", self.counter)
+    }
+
+    fn store(&mut self, name: &str, mut item: Value) -> Result<Value, Reply> {
+        if !item.is_object() {
+            return Err(failure(400, "item must be an object"));
+        }
+        let key = match &item["key"] {
+            Value::String(key) => key.clone(),
+            Value::Null => self.next_id(),
+            _ => return Err(failure(400, "key must be a string")),
+        };
+        item["key"] = Value::from(key.clone());
+        self.bases.entry(name.to_string()).or_default().insert(key, item.clone());
+        Ok(item)
+    }
+
+    fn base(&mut self, name: &str, method: &str, path: &[&str], body: Option<&[u8]>) -> Reply {
+        let body = json_body(body);
+        let items = self.bases.entry(name.to_string()).or_default();
+        items.retain(|_, item| !expired(item));
+        let key = path.get(1).map(|key| urlencoding::decode(key).map(|k| k.into_owned()).unwrap_or_default());
+        match (method, path.first().copied(), key) {
+            ("GET", Some("items"), Some(key)) => match items.get(&key) {
+                Some(item) => reply(200, item.clone()),
+                None => reply(404, json!({ "key": key })),
+            },
+            ("DELETE", Some("items"), Some(key)) => {
+                items.remove(&key);
+                reply(200, json!({ "key": key }))
+            },
+            ("PATCH", Some("items"), Some(key)) => match items.get_mut(&key) {
+                Some(item) => {
+                    apply_update(item, &body);
+                    let mut resp = body.clone();
+                    resp["key"] = Value::from(key);
+                    reply(200, resp)
+                },
+                None => reply(404, json!({ "key": key })),
+            },
+            ("PUT", Some("items"), None) => {
+                let mut processed = vec![];
+                for item in body["items"].as_array().cloned().unwrap_or_default() {
+                    match self.store(name, item) {
+                        Ok(item) => processed.push(item),
+                        Err(reply) => return reply,
+                    }
+                }
+                reply(200, json!({ "processed": { "items": processed } }))
+            },
+            ("POST", Some("items"), None) => {
+                if body["item"]["key"].as_str().is_some_and(|key| items.contains_key(key)) {
+                    return failure(409, "Key already exists");
+                }
+                match self.store(name, body["item"].clone()) {
+                    Ok(item) => reply(201, item),
+                    Err(reply) => reply,
+                }
+            },
+            ("POST", Some("query"), None) => query(items, &body),
+            _ => failure(400, "unsupported mock request"),
+        }
+    }
+
+    fn drive(
+        &mut self,
+        name: &str,
+        method: &str,
+        path: &[&str],
+        params: &HashMap<&str, String>,
+        body: Option<&[u8]>
+    ) -> Reply {
+        let file = params.get("name").cloned().unwrap_or_default();
+        let meta = |upload_id: &str| json!({
+            "name": file, "upload_id": upload_id, "project_id": "mock", "drive_name": name
+        });
+        match (method, path) {
+            ("GET", ["files"]) => {
+                let files = self.drives.entry(name.to_string()).or_default();
+                let limit = params.get("limit").and_then(|l| l.parse().ok()).unwrap_or(1000);
+                let prefix = params.get("prefix").map(String::as_str).unwrap_or_default();
+                let mut names = files.keys()
+                    .filter(|f| f.starts_with(prefix) && params.get("last").is_none_or(|last| *f > last))
+                    .cloned()
+                    .collect::<Vec<String>>();
+                let more = names.len() > limit;
+                names.truncate(limit);
+                let last = if more { names.last().cloned().unwrap_or_default() } else { String::new() };
+                reply(200, json!({ "paging": { "size": names.len(), "last": last }, "names": names }))
+            },
+            ("GET", ["files", "download"]) => {
+                match self.drives.get(name).and_then(|files| files.get(&file)) {
+                    Some(content) => (200, content.clone()),
+                    None => failure(404, "Not found"),
+                }
+            },
+            ("POST", ["files"]) => {
+                let content = body.unwrap_or_default().to_vec();
+                self.drives.entry(name.to_string()).or_default().insert(file.clone(), content);
+                reply(201, json!({ "name": file, "project_id": "mock", "drive_name": name }))
+            },
+            ("DELETE", ["files"]) => {
+                let body = json_body(body);
+                let names = body["names"].as_array().cloned().unwrap_or_default();
+                let files = self.drives.entry(name.to_string()).or_default();
+                for name in names.iter().filter_map(Value::as_str) {
+                    files.remove(name);
+                }
+                reply(200, json!({ "deleted": names }))
+            },
+            ("POST", ["uploads"]) => {
+                let upload_id = self.next_id();
+                self.uploads.insert(upload_id.clone(), Upload {
+                    drive: name.to_string(), name: file.clone(), parts: BTreeMap::new()
+                });
+                reply(202, meta(&upload_id))
+            },
+            ("POST", ["uploads", upload_id, "parts"]) => match self.uploads.get_mut(*upload_id) {
+                Some(upload) => {
+                    let part = params.get("part").and_then(|p| p.parse().ok()).unwrap_or_default();
+                    upload.parts.insert(part, body.unwrap_or_default().to_vec());
+                    reply(200, meta(upload_id))
+                },
+                None => failure(404, "Upload not found"),
+            },
+            ("PATCH", ["uploads", upload_id]) => match self.uploads.remove(*upload_id) {
+                Some(upload) => {
+                    let content = upload.parts.into_values().flatten().collect();
+                    self.drives.entry(upload.drive).or_default().insert(upload.name, content);
+                    reply(200, meta(upload_id))
+                },
+                None => failure(404, "Upload not found"),
+            },
+            ("DELETE", ["uploads", upload_id]) => match self.uploads.remove(*upload_id) {
+                Some(_) => reply(200, meta(upload_id)),
+                None => failure(404, "Upload not found"),
+            },
+            _ => failure(400, "unsupported mock request"),
+        }
+    }
+}
+
+fn query(items: &BTreeMap<String, Value>, body: &Value) -> Reply {
+    let groups = body["query"].as_array().cloned().unwrap_or_default();
+    let hit = |item: &Value| groups.is_empty() || groups.iter().any(|group| {
+        group.as_object().is_some_and(|group| group.iter().all(|(c, v)| matches(item, c, v)))
+    });
+    let desc = body["sort"] == "desc";
+    let last = body["last"].as_str();
+    let limit = body["limit"].as_u64().unwrap_or(1000) as usize;
+    let ordered: Box<dyn Iterator<Item = (&String, &Value)>> = match desc {
+        true => Box::new(items.iter().rev()),
+        false => Box::new(items.iter()),
+    };
+    let mut found = ordered
+        .filter(|(key, _)| last.is_none_or(|last| (key.as_str() < last) == desc && key.as_str() != last))
+        .filter(|(_, item)| hit(item))
+        .map(|(_, item)| item.clone())
+        .take(limit + 1)
+        .collect::<Vec<Value>>();
+    let more = found.len() > limit;
+    found.truncate(limit);
+    let last = match (more, found.last()) {
+        (true, Some(item)) => item["key"].clone(),
+        _ => Value::Null,
+    };
+    reply(200, json!({ "paging": { "size": found.len(), "last": last }, "items": found }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use serde_json::json;
+
+    use super::MockDeta;
+    use crate::errors::DetaError;
+
+    #[test]
+    fn base_roundtrip() {
+        let base = MockDeta::new().base("users");
+        base.put(vec![json!({ "key": "a", "age": 20 }), json!({ "key": "b", "age": 30 })]).unwrap();
+        assert!(matches!(base.insert(json!({ "key": "a" })), Err(DetaError::Conflict { .. })));
+        let updated = base.update("a")
+            .increment("age", json!(1))
+            .append("tags", json!("new"))
+            .commit()
+            .unwrap();
+        assert_eq!((updated.key.as_str(), &updated.increment["age"]), ("a", &json!(1)));
+        assert_eq!(base.insert(json!({ "key": "c", "x": 1 })).unwrap().fields["x"], 1);
+        assert_eq!(base.delete("c").unwrap().key, "c");
+        assert_eq!(base.get("a").unwrap(), json!({ "key": "a", "age": 21, "tags": ["new"] }));
+        let older = base.query().greater_than("age", json!(25)).walk().unwrap();
+        assert_eq!(older, vec![json!({ "key": "b", "age": 30 })]);
+        assert_eq!(base.query().limit(1).walk().unwrap().len(), 2);
+        assert_eq!(base.query().limit(1).count().unwrap(), 2);
+        let page = base.query().limit(1).run().unwrap();
+        assert_eq!((page.items.len(), page.size, page.last.as_deref()), (1, 1, Some("a")));
+        assert_eq!(base.query().limit(1).last("a").run().unwrap().last, None);
+        assert_eq!(base.query().sort(true).first().unwrap().unwrap()["key"], "b");
+        assert!(base.query().equals("age", json!(99)).first().unwrap().is_none());
+        base.delete("a").unwrap();
+        assert!(matches!(base.get("a"), Err(DetaError::NotFound { .. })));
+        assert_eq!(base.get_opt("a").unwrap(), None);
+        assert_eq!(base.get_opt_as::<serde_json::Value>("b").unwrap().unwrap()["age"], 30);
+        let found = base.get_many(&["a", "b"]);
+        assert!(matches!(found["a"], Err(DetaError::NotFound { .. })));
+        assert_eq!(found["b"].as_ref().unwrap()["age"], 30);
+    }
+
+    #[test]
+    fn expired_records_disappear() {
+        let base = MockDeta::new().base("sessions");
+        base.put(vec![json!({ "key": "old", "__expires": 1 }), json!({ "key": "new" })]).unwrap();
+        assert!(base.get("old").is_err());
+        assert_eq!(base.query().walk().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn drive_roundtrip() {
+        let drive = MockDeta::new().drive("files");
+        drive.put("a/b.bin", &[0, 159, 146, 150], None).unwrap();
+        assert_eq!(drive.walk(Some("a/")).unwrap(), vec!["a/b.bin"]);
+        let mut content = vec![];
+        drive.get("a/b.bin").unwrap().into_reader().read_to_end(&mut content).unwrap();
+        assert_eq!(content, vec![0, 159, 146, 150]);
+        drive.delete(vec!["a/b.bin"]).unwrap();
+        assert!(drive.walk(None).unwrap().is_empty());
+    }
+}
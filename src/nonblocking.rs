@@ -0,0 +1,529 @@
+//! Async counterparts of `Base` and `Drive`, enabled with the `tokio` or `wasm` feature.
+//! 
+//! Queries and updaters are built exactly like their blocking versions and
+//! executed with `Query::run_async`, `Query::walk_async` and `Updater::commit_async`.
+//!
+//! The `wasm` feature makes these available on `wasm32` targets such as browsers and
+//! Cloudflare Workers, where requests are sent with `fetch`. The blocking API needs
+//! threads and sockets, so build with `--no-default-features --features wasm` there.
+
+use std::{ collections::HashMap, time::Duration };
+
+use reqwest::Method;
+use serde::{ Serialize, de::DeserializeOwned };
+use serde_json::{ json, Map, Value };
+
+use crate::{
+    Deta,
+    base::{ typed, Base, DeleteResponse, InsertResponse, PutResponse, Upsert },
+    drive::{
+        Drive, FileList, Metadata, PendingUpload, UploadOptions, MAX_CHUNK_SIZE, PENDING_UPLOADS_PREFIX
+    },
+    errors::DetaError,
+    query::Query,
+    queue,
+    response,
+    telemetry::RequestSpan,
+    transport::Request,
+    updater::Updater,
+};
+
+/// Waits for `duration` on the tokio timer, or on `setTimeout` without the `tokio` feature.
+pub (crate) async fn sleep(duration: Duration) {
+    #[cfg(feature = "tokio")]
+    tokio::time::sleep(duration).await;
+    #[cfg(not(feature = "tokio"))]
+    {
+        let promise = js_sys::Promise::new(&mut |resolve, _| {
+            let global = js_sys::global();
+            let millis = duration.as_millis() as f64;
+            match js_sys::Reflect::get(&global, &"setTimeout".into()) {
+                Ok(set_timeout) if set_timeout.is_function() => {
+                    let _ = js_sys::Function::from(set_timeout).call2(&global, &resolve, &millis.into());
+                },
+                _ => { let _ = resolve.call0(&global); },
+            }
+        });
+        let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+    }
+}
+
+async fn de<T: DeserializeOwned>(resp: reqwest::Response) -> Result<T, DetaError> {
+    let status = resp.status().as_u16();
+    response::parse_bytes(status, &resp.bytes().await?)
+}
+
+impl Deta {
+
+    /// Sends a request asynchronously, holding a write slot for writes when a write queue is configured.
+    pub (crate) async fn send_async(
+        &self, method: Method, url: &str, body: Option<&[u8]>, content_type: Option<&str>
+    ) -> Result<reqwest::Response, DetaError> {
+        let writes = self.inner.writes.as_ref().filter(|_| queue::is_write(method.as_str(), url));
+        let _permit = match writes {
+            Some(writes) => loop {
+                let permit = writes.try_acquire(|| queue::JournalEntry {
+                    method: method.to_string(),
+                    url: url.to_string(),
+                    body: body.map(<[u8]>::to_vec),
+                    content_type: content_type.map(String::from),
+                })?;
+                match permit {
+                    Some(permit) => break Some(permit),
+                    None => sleep(Duration::from_millis(5)).await,
+                }
+            },
+            None => None,
+        };
+        let resp = self.send_now_async(method.clone(), url, body, content_type).await?;
+        #[cfg(feature = "blocking")]
+        if let Some(webhook) = &self.inner.webhook {
+            webhook.emit(method.as_str(), url, body);
+        }
+        Ok(resp)
+    }
+
+    /// Sends a request asynchronously, retrying transient failures according to the retry policy.
+    async fn send_now_async(
+        &self, method: Method, url: &str, body: Option<&[u8]>, content_type: Option<&str>
+    ) -> Result<reqwest::Response, DetaError> {
+        #[cfg(feature = "mock")]
+        if let Some(store) = &self.inner.mock {
+            self.charge(body.map_or(0, <[u8]>::len))?;
+            return store.respond_async(method.as_str(), url, body);
+        }
+        let span = RequestSpan::start(method.as_str(), url, &self.inner.project_key);
+        let mut attempt = 0;
+        loop {
+            if let Some(limiter) = &self.inner.limiter {
+                sleep(limiter.reserve()).await;
+            }
+            let headers = self.headers(body.map_or(0, <[u8]>::len), content_type)?;
+            let request = Request { method: method.as_str(), url, headers, body };
+            let (status, err) = match self.inner.http.send(request).await {
+                Ok(resp) if !resp.status().is_client_error() && !resp.status().is_server_error() => {
+                    if let Some(limiter) = &self.inner.limiter {
+                        limiter.succeeded();
+                    }
+                    span.finish(Some(resp.status().as_u16()), attempt);
+                    return Ok(resp);
+                },
+                Ok(resp) => {
+                    let status = resp.status();
+                    let reason = status.canonical_reason().unwrap_or_default();
+                    let body = resp.bytes().await.unwrap_or_default();
+                    (Some(status.as_u16()), DetaError::from_status(status.as_u16(), reason, &body))
+                },
+                Err(e) => (None, DetaError::from(e)),
+            };
+            if let (Some(limiter), Some(429)) = (&self.inner.limiter, status) {
+                limiter.throttled();
+            }
+            match &self.inner.retry {
+                Some(policy) if policy.should_retry(attempt, status) => {
+                    sleep(policy.delay(attempt)).await;
+                    attempt += 1;
+                },
+                _ => {
+                    span.finish(status, attempt);
+                    return Err(err);
+                },
+            }
+        }
+    }
+}
+
+impl Base {
+
+    pub (crate) async fn request_async(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Value>
+    ) -> Result<Value, DetaError> {
+        let body = body.map(|body| serde_json::to_vec(&body)).transpose()?;
+        let content_type = body.as_ref().map(|_| "application/json");
+        de(self.service.send_async(method, &self.url(path), body.as_deref(), content_type).await?).await
+    }
+}
+
+/// Represents a Deta Base accessed asynchronously.
+#[derive(Clone)]
+pub struct AsyncBase {
+    base: Base,
+}
+
+impl AsyncBase {
+
+    pub (crate) fn new(base: Base) -> AsyncBase {
+        AsyncBase { base }
+    }
+
+    /// The name of the base.
+    pub fn name(&self) -> &str {
+        self.base.name()
+    }
+
+    /// fetch a record by key from the base.
+    pub async fn get(&self, key: &str) -> Result<Value, DetaError> {
+        self.base.request_async(Method::GET, &format!("/items/{}", key), None).await
+    }
+
+    /// Fetch a record by key from the base and deserialize it to a struct.
+    pub async fn get_as<T: DeserializeOwned>(&self, key: &str) -> Result<T, DetaError> {
+        self.get(key).await.and_then(|v| serde_json::from_value::<T>(v).map_err(DetaError::from))
+    }
+
+    /// Fetch a record by key, returning `None` if there is no record with that key.
+    pub async fn get_opt(&self, key: &str) -> Result<Option<Value>, DetaError> {
+        match self.get(key).await {
+            Ok(record) => Ok(Some(record)),
+            Err(DetaError::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Same as `get_opt`, deserializing the record to a struct.
+    pub async fn get_opt_as<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, DetaError> {
+        self.get_opt(key).await?.map(typed).transpose()
+    }
+
+    /// Put a multiple serializable records into the base.
+    /// 
+    /// Maximum 25 records can be put at a time.
+    /// Records rejected by Deta are reported in `PutResponse::failed` instead of failing the call.
+    pub async fn put<T: Serialize>(&self, records: Vec<T>) -> Result<PutResponse, DetaError> {
+        self.put_raw(records).await.and_then(typed)
+    }
+
+    /// Same as `put`, returning the raw response body.
+    pub async fn put_raw<T: Serialize>(&self, records: Vec<T>) -> Result<Value, DetaError> {
+        if records.len() > 25 {
+            return Err(
+                DetaError::PayloadError {
+                    msg: "maximum 25 records can be put at a time".to_string()
+                }
+            );
+        }
+        let records = records.into_iter()
+            .map(|record| self.base.record(record))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut payload = Map::new();
+        payload.insert(String::from("items"), Value::Array(records));
+        match self.base.request_async(Method::PUT, "/items", Some(json!(payload))).await {
+            Err(DetaError::PartialFailure { body }) => Ok(body),
+            result => result,
+        }
+    }
+
+    /// Insert a serializable record into the base.
+    pub async fn insert<T: Serialize>(&self, record: T) -> Result<InsertResponse, DetaError> {
+        self.insert_raw(record).await.and_then(typed)
+    }
+
+    /// Same as `insert`, returning the raw response body.
+    pub async fn insert_raw<T: Serialize>(&self, record: T) -> Result<Value, DetaError> {
+        let mut payload = Map::new();
+        payload.insert(String::from("item"), self.base.record(record)?);
+        self.base.request_async(Method::POST, "/items", Some(json!(payload))).await
+    }
+
+    /// Insert a record, replacing the existing one if its key is already taken.
+    pub async fn upsert<T: Serialize>(&self, record: T) -> Result<Upsert, DetaError> {
+        let record = serde_json::to_value(record)?;
+        match self.insert(&record).await {
+            Ok(inserted) => Ok(Upsert::Inserted(inserted)),
+            Err(DetaError::Conflict { .. }) => self.put(vec![record]).await.map(Upsert::Replaced),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Delete a record by key from the base.
+    pub async fn delete(&self, key: &str) -> Result<DeleteResponse, DetaError> {
+        self.delete_raw(key).await.and_then(typed)
+    }
+
+    /// Same as `delete`, returning the raw response body.
+    pub async fn delete_raw(&self, key: &str) -> Result<Value, DetaError> {
+        self.base.request_async(Method::DELETE, &format!("/items/{}", key), None).await
+    }
+
+    /// Fetch multiple records by key, running at most `concurrency` requests at a time.
+    /// 
+    /// Returns the result for every key, missing records are `Err(DetaError::NotFound)`
+    /// and requests whose task panicked or was cancelled `Err(DetaError::TransportError)`.
+    pub async fn get_many(
+        &self, keys: &[&str], concurrency: usize
+    ) -> HashMap<String, Result<Value, DetaError>> {
+        self.fan_out(keys, concurrency, Method::GET).await
+    }
+
+    /// Delete multiple records by key, running at most `concurrency` requests at a time.
+    /// 
+    /// Returns the result for every key.
+    pub async fn delete_many(
+        &self, keys: &[&str], concurrency: usize
+    ) -> HashMap<String, Result<DeleteResponse, DetaError>> {
+        self.fan_out(keys, concurrency, Method::DELETE).await
+            .into_iter()
+            .map(|(key, result)| (key, result.and_then(typed)))
+            .collect()
+    }
+
+    #[cfg(feature = "tokio")]
+    async fn fan_out(
+        &self, keys: &[&str], concurrency: usize, method: Method
+    ) -> HashMap<String, Result<Value, DetaError>> {
+        let mut tasks = tokio::task::JoinSet::new();
+        // keys by task, so a task that panicked or was cancelled still gets a result
+        let mut pending = HashMap::new();
+        let mut results = HashMap::new();
+        let mut collect = |joined: Result<_, tokio::task::JoinError>, pending: &mut HashMap<_, String>| {
+            let (id, result) = match joined {
+                Ok((id, result)) => (id, result),
+                Err(e) => (e.id(), Err(DetaError::TransportError)),
+            };
+            if let Some(key) = pending.remove(&id) {
+                results.insert(key, result);
+            }
+        };
+        for key in keys {
+            if tasks.len() >= concurrency.max(1) {
+                if let Some(joined) = tasks.join_next_with_id().await {
+                    collect(joined, &mut pending);
+                }
+            }
+            let (base, method, path) = (self.base.clone(), method.clone(), format!("/items/{}", key));
+            let task = tasks.spawn(async move { base.request_async(method, &path, None).await });
+            pending.insert(task.id(), key.to_string());
+        }
+        while let Some(joined) = tasks.join_next_with_id().await {
+            collect(joined, &mut pending);
+        }
+        results
+    }
+
+    /// Without a tokio runtime to spawn on, the requests are sent one after another.
+    #[cfg(not(feature = "tokio"))]
+    async fn fan_out(
+        &self, keys: &[&str], _concurrency: usize, method: Method
+    ) -> HashMap<String, Result<Value, DetaError>> {
+        let mut results = HashMap::new();
+        for key in keys {
+            let result = self.base.request_async(method.clone(), &format!("/items/{}", key), None).await;
+            results.insert(key.to_string(), result);
+        }
+        results
+    }
+
+    /// Update a record by key in the base. Commit with `Updater::commit_async`.
+    pub fn update(&self, key: &str) -> Updater {
+        self.base.update(key)
+    }
+
+    /// Create a new query for this base. Run with `Query::run_async` or `Query::walk_async`.
+    pub fn query(&self) -> Query {
+        self.base.query()
+    }
+}
+
+/// Represents a Deta Drive accessed asynchronously.
+#[derive(Clone)]
+pub struct AsyncDrive {
+    drive: Drive,
+}
+
+impl AsyncDrive {
+
+    pub (crate) fn new(drive: Drive) -> AsyncDrive {
+        AsyncDrive { drive }
+    }
+
+    /// The name of the drive.
+    pub fn name(&self) -> &str {
+        self.drive.name()
+    }
+
+    /// Sets how files over 10 MB are uploaded in parts, see `UploadOptions`.
+    pub fn with_upload_options(self, options: UploadOptions) -> AsyncDrive {
+        AsyncDrive { drive: self.drive.with_upload_options(options) }
+    }
+
+    async fn send(
+        &self, method: Method, path: &str, body: Option<&[u8]>, content_type: Option<&str>
+    ) -> Result<reqwest::Response, DetaError> {
+        self.drive.service.send_async(method, &self.drive.url(path), body, content_type).await
+    }
+
+    /// List files in drive.
+    /// 
+    /// Upload session markers under `.detalib/uploads/` are left out unless `prefix` points inside them.
+    pub async fn list(
+        &self,
+        prefix: Option<&str>,
+        limit: Option<i32>,
+        last: Option<&str>,
+    ) -> Result<FileList, DetaError> {
+        let mut path = format!("/files?limit={}", limit.unwrap_or(1000));
+        if let Some(prefix) = prefix {
+            path.push_str(&format!("&prefix={}", prefix));
+        }
+        if let Some(last) = last {
+            path.push_str(&format!("&last={}", last));
+        }
+        Ok(de::<FileList>(self.send(Method::GET, &path, None, None).await?).await?.without_markers(prefix))
+    }
+
+    /// Walk through all files in drive and returns a list of file names.
+    ///
+    /// Fails if any page cannot be listed.
+    pub async fn walk(&self, prefix: Option<&str>) -> Result<Vec<String>, DetaError> {
+        let mut files: Vec<String> = vec![];
+        let mut last: Option<String> = None;
+        loop {
+            let list = self.list(prefix, None, last.as_deref()).await?;
+            files.extend(list.names);
+            match list.paging {
+                Some(paging) if !paging.last.is_empty() => last = Some(paging.last),
+                _ => return Ok(files),
+            }
+        }
+    }
+
+    /// Get the content of a file from drive.
+    pub async fn get(&self, name: &str) -> Result<Vec<u8>, DetaError> {
+        let path = format!("/files/download?name={}", urlencoding::encode(name));
+        let resp = self.send(Method::GET, &path, None, None).await?;
+        Ok(resp.bytes().await?.to_vec())
+    }
+
+    /// Put a new file to drive.
+    pub async fn put(
+        &self, save_as: &str, content: &[u8], content_type: Option<&str>
+    ) -> Result<Value, DetaError> {
+        let encoded = urlencoding::encode(save_as).into_owned();
+        if content.len() <= MAX_CHUNK_SIZE {
+            let path = format!("/files?name={}", encoded);
+            return de(self.send(Method::POST, &path, Some(content), content_type).await?).await;
+        }
+        let meta = de::<Metadata>(
+            self.send(Method::POST, &format!("/uploads?name={}", encoded), None, None).await?).await?;
+        let marker = PendingUpload {
+            upload_id: meta.upload_id.clone(),
+            name: save_as.to_string(),
+            started_at: chrono::Utc::now().timestamp(),
+        };
+        let marker_name = format!("{}{}", PENDING_UPLOADS_PREFIX, meta.upload_id);
+        let marker = serde_json::to_vec(&marker)?;
+        if let Err(e) = Box::pin(self.put(&marker_name, &marker, Some("application/json"))).await {
+            _ = self.abort_upload(&meta.upload_id, save_as).await;
+            return Err(e);
+        }
+        let parts_path = format!("/uploads/{}/parts?name={}", meta.upload_id, encoded);
+        if let Err(e) = self.send_parts(&parts_path, content, content_type).await {
+            _ = self.abort_upload(&meta.upload_id, save_as).await;
+            return Err(e);
+        }
+        let path = format!("/uploads/{}?name={}", meta.upload_id, encoded);
+        let resp = de(self.send(Method::PATCH, &path, None, None).await?).await?;
+        _ = self.delete(vec![&marker_name]).await;
+        Ok(resp)
+    }
+
+    /// Sends a part of an upload, retrying it as set by `UploadOptions`.
+    async fn send_part(
+        &self, path: &str, chunk: &[u8], content_type: Option<&str>
+    ) -> Result<(), DetaError> {
+        let mut attempt = 0;
+        loop {
+            match self.send(Method::POST, path, Some(chunk), content_type).await {
+                Ok(_) => return Ok(()),
+                Err(e) if self.drive.upload.should_retry(attempt, &e) => {
+                    sleep(UploadOptions::retry_delay(attempt)).await;
+                    attempt += 1;
+                },
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Sends the chunks of `content` as parts, up to `UploadOptions::parallelism` at once.
+    #[cfg(feature = "tokio")]
+    async fn send_parts(
+        &self, path: &str, content: &[u8], content_type: Option<&str>
+    ) -> Result<(), DetaError> {
+        let joined = |joined: Result<Result<(), DetaError>, tokio::task::JoinError>| {
+            joined.unwrap_or(Err(DetaError::TransportError))
+        };
+        let mut tasks = tokio::task::JoinSet::new();
+        for (i, chunk) in content.chunks(MAX_CHUNK_SIZE).enumerate() {
+            if tasks.len() >= self.drive.upload.parallelism {
+                if let Some(result) = tasks.join_next().await {
+                    joined(result)?;
+                }
+            }
+            let (drive, path, chunk) = (self.clone(), format!("{}&part={}", path, i + 1), chunk.to_vec());
+            let content_type = content_type.map(String::from);
+            tasks.spawn(async move { drive.send_part(&path, &chunk, content_type.as_deref()).await });
+        }
+        while let Some(result) = tasks.join_next().await {
+            joined(result)?;
+        }
+        Ok(())
+    }
+
+    /// Without a tokio runtime to spawn on, the parts are sent one after another.
+    #[cfg(not(feature = "tokio"))]
+    async fn send_parts(
+        &self, path: &str, content: &[u8], content_type: Option<&str>
+    ) -> Result<(), DetaError> {
+        for (i, chunk) in content.chunks(MAX_CHUNK_SIZE).enumerate() {
+            self.send_part(&format!("{}&part={}", path, i + 1), chunk, content_type).await?;
+        }
+        Ok(())
+    }
+
+    /// Abort a chunked upload session, discarding its uploaded parts.
+    pub async fn abort_upload(&self, upload_id: &str, name: &str) -> Result<(), DetaError> {
+        let encoded = urlencoding::encode(name).into_owned();
+        let path = format!("/uploads/{}?name={}", upload_id, encoded);
+        match self.send(Method::DELETE, &path, None, None).await {
+            Ok(_) | Err(DetaError::NotFound { .. }) => {},
+            Err(e) => return Err(e),
+        }
+        self.delete(vec![&format!("{}{}", PENDING_UPLOADS_PREFIX, upload_id)]).await?;
+        Ok(())
+    }
+
+    /// Delete multiple files from drive.
+    pub async fn delete(&self, names: Vec<&str>) -> Result<Value, DetaError> {
+        let body = serde_json::to_vec(&json!({ "names": names }))?;
+        de(self.send(Method::DELETE, "/files", Some(&body), Some("application/json")).await?).await
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use serde_json::json;
+
+    use crate::{ drive::UploadOptions, mock::MockDeta };
+
+    #[tokio::test]
+    async fn async_fan_out() {
+        let base = MockDeta::new().base_async("users");
+        base.put(vec![json!({ "key": "a" }), json!({ "key": "b" })]).await.unwrap();
+        let deleted = base.delete_many(&["a", "b", "c"], 2).await;
+        assert_eq!(deleted.len(), 3);
+        assert!(base.get_many(&["a", "b"], 2).await.values().all(Result::is_err));
+    }
+
+    #[tokio::test]
+    async fn async_parallel_upload() {
+        let drive = MockDeta::new().drive_async("files")
+            .with_upload_options(UploadOptions::new().parallelism(2));
+        let content = (0..25 * 1024 * 1024).map(|i| (i % 253) as u8).collect::<Vec<u8>>();
+        drive.put("big.bin", &content, None).await.unwrap();
+        assert!(drive.get("big.bin").await.unwrap() == content);
+        assert!(drive.walk(Some(".detalib/")).await.unwrap().is_empty());
+    }
+}
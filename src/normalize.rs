@@ -0,0 +1,142 @@
+use serde_json::Value;
+
+use crate::{ base::Base, errors::DetaError, query::Query };
+
+/// Progress and outcome of a `Normalizer` run.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NormalizeReport {
+    /// Records read so far.
+    pub scanned: usize,
+    /// Keys of the records the fix-up changed.
+    pub changed: Vec<String>,
+    /// Records written back. Always zero in a dry run.
+    pub written: usize,
+    /// Keys of the records Deta rejected.
+    pub failed: Vec<String>,
+}
+
+type Fix = Box<dyn FnMut(&Value) -> Option<Value>>;
+type Progress = Box<dyn FnMut(&NormalizeReport)>;
+
+/// A batch fix-up of records, created with `Base::normalize`.
+/// ```rust,no_run
+/// use detalib::Deta;
+/// use serde_json::json;
+/// 
+/// let base = Deta::new().base("users");
+/// let report = base
+///     .normalize(base.query(), |user| {
+///         let email = user["email"].as_str()?;
+///         let mut user = user.clone();
+///         user["email"] = json!(email.trim().to_lowercase());
+///         Some(user)
+///     })
+///     .dry_run(true)
+///     .on_progress(|report| println!("{} scanned", report.scanned))
+///     .run()
+///     .unwrap();
+/// println!("would change {:?}", report.changed);
+/// ```
+pub struct Normalizer {
+    base: Base,
+    query: Query,
+    fix: Fix,
+    dry_run: bool,
+    batch_size: usize,
+    progress: Option<Progress>,
+}
+
+impl Normalizer {
+
+    pub (crate) fn new<F>(base: Base, query: Query, fix: F) -> Normalizer
+        where F: FnMut(&Value) -> Option<Value> + 'static
+    {
+        Normalizer { base, query, fix: Box::new(fix), dry_run: false, batch_size: 25, progress: None }
+    }
+
+    /// Only report the records that would change, without writing them.
+    pub fn dry_run(mut self, dry_run: bool) -> Self {
+        self.dry_run = dry_run;
+        self
+    }
+
+    /// Sets how many changed records are written per request, at most 25.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.clamp(1, 25);
+        self
+    }
+
+    /// Calls `f` with the report so far after every written batch and at the end.
+    pub fn on_progress<F: FnMut(&NormalizeReport) + 'static>(mut self, f: F) -> Self {
+        self.progress = Some(Box::new(f));
+        self
+    }
+
+    fn flush(&mut self, batch: &mut Vec<Value>, report: &mut NormalizeReport) -> Result<(), DetaError> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        if !self.dry_run {
+            let result = self.base.put_many(batch)?;
+            report.written += result.processed.len();
+            report.failed.extend(result.failed);
+        }
+        batch.clear();
+        if let Some(progress) = &mut self.progress {
+            progress(report);
+        }
+        Ok(())
+    }
+
+    /// Walks the matching records, applying the fix-up and writing back the changed ones.
+    /// 
+    /// The fix-up returns `None` to leave a record untouched. The key of a record can not be changed.
+    pub fn run(mut self) -> Result<NormalizeReport, DetaError> {
+        let mut report = NormalizeReport::default();
+        let mut batch = Vec::new();
+        for item in self.query.iter() {
+            let item = item?;
+            report.scanned += 1;
+            let Some(mut fixed) = (self.fix)(&item) else { continue };
+            fixed["key"] = item["key"].clone();
+            if fixed == item {
+                continue;
+            }
+            report.changed.push(item["key"].as_str().unwrap_or_default().to_string());
+            batch.push(fixed);
+            if batch.len() >= self.batch_size {
+                self.flush(&mut batch, &mut report)?;
+            }
+        }
+        self.flush(&mut batch, &mut report)?;
+        if let Some(progress) = &mut self.progress {
+            progress(&report);
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use serde_json::json;
+
+    use crate::mock::MockDeta;
+
+    #[test]
+    fn normalize_writes_changed_records() {
+        let base = MockDeta::new().base("users");
+        let users = vec![json!({ "key": "a", "email": "A@X.IO " }), json!({ "key": "b", "email": "b@x.io" })];
+        base.put(users).unwrap();
+        let lower = |user: &serde_json::Value| {
+            let mut user = user.clone();
+            user["email"] = json!(user["email"].as_str()?.trim().to_lowercase());
+            Some(user)
+        };
+        let report = base.normalize(base.query(), lower).dry_run(true).run().unwrap();
+        assert_eq!((report.scanned, report.changed.clone(), report.written), (2, vec!["a".to_string()], 0));
+        assert_eq!(base.get("a").unwrap()["email"], "A@X.IO ");
+        let report = base.normalize(base.query(), lower).batch_size(1).run().unwrap();
+        assert_eq!(report.written, 1);
+        assert_eq!(base.get("a").unwrap()["email"], "a@x.io");
+    }
+}
@@ -0,0 +1,77 @@
+//! Pure parsers for Deta API responses.
+//! 
+//! These never panic on malformed input, which makes them suitable fuzzing targets.
+
+use serde_json::Value;
+
+use crate::errors::DetaError;
+
+fn malformed(msg: &str) -> DetaError {
+    DetaError::PayloadError { msg: format!("malformed response: {}", msg) }
+}
+
+fn items(value: &Value, msg: &str) -> Result<Vec<Value>, DetaError> {
+    match value {
+        Value::Array(items) => Ok(items.clone()),
+        Value::Null => Ok(Vec::new()),
+        _ => Err(malformed(msg)),
+    }
+}
+
+pub (crate) fn query_result(value: &Value) -> Result<(Vec<Value>, Option<String>), DetaError> {
+    if !value.is_object() {
+        return Err(malformed("query result is not an object"));
+    }
+    let items = items(&value["items"], "`items` is not an array")?;
+    let last = match &value["paging"]["last"] {
+        Value::String(last) if !last.is_empty() => Some(last.clone()),
+        Value::String(_) | Value::Null => None,
+        _ => return Err(malformed("`paging.last` is not a string")),
+    };
+    Ok((items, last))
+}
+
+pub (crate) fn put_response(value: &Value) -> Result<(Vec<Value>, Vec<Value>), DetaError> {
+    if !value.is_object() {
+        return Err(malformed("put response is not an object"));
+    }
+    let processed = items(&value["processed"]["items"], "`processed.items` is not an array")?;
+    let failed = items(&value["failed"]["items"], "`failed.items` is not an array")?;
+    Ok((processed, failed))
+}
+
+/// Parses the body of a query response into its items and the cursor of the next page.
+pub fn parse_query_result(body: &[u8]) -> Result<(Vec<Value>, Option<String>), DetaError> {
+    query_result(&serde_json::from_slice::<Value>(body)?)
+}
+
+/// Parses the body of a put response into its processed and failed items.
+pub fn parse_put_response(body: &[u8]) -> Result<(Vec<Value>, Vec<Value>), DetaError> {
+    put_response(&serde_json::from_slice::<Value>(body)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn query_result_cursor() {
+        let body = br#"{"paging": {"size": 1, "last": "k1"}, "items": [{"key": "k1"}]}"#;
+        let (items, last) = parse_query_result(body).unwrap();
+        assert_eq!(items, vec![json!({ "key": "k1" })]);
+        assert_eq!(last.as_deref(), Some("k1"));
+        let (_, last) = parse_query_result(br#"{"paging": {"size": 0}, "items": []}"#).unwrap();
+        assert!(last.is_none());
+    }
+
+    #[test]
+    fn malformed_bodies_are_errors() {
+        for body in [&b""[..], b"[]", b"{\"items\": 1}", b"{\"paging\": {\"last\": 3}}", b"\xff"] {
+            assert!(parse_query_result(body).is_err());
+        }
+        assert!(parse_put_response(b"{\"processed\": {\"items\": {}}}").is_err());
+        assert_eq!(parse_put_response(b"{}").unwrap(), (vec![], vec![]));
+    }
+}
@@ -1,21 +1,109 @@
+use std::sync::Arc;
+#[cfg(feature = "blocking")]
+use std::collections::{ hash_map::Entry, HashMap };
+
+use chrono::{ DateTime, TimeDelta, TimeZone, Utc };
 use serde_json::{ Value, Map };
 use serde::{ Deserialize, Serialize };
-use crate::{ base::Base, errors::DetaError };
+#[cfg(feature = "blocking")]
+use serde::de::DeserializeOwned;
+use crate::{
+    base::Base,
+    errors::DetaError,
+    parse,
+    sort::{ self, Order },
+    sql,
+};
+#[cfg(feature = "blocking")]
+use crate::{
+    cache::CachedQuery,
+    canonical::canonicalize,
+    histogram::{ Buckets, Histogram },
+    iter::QueryIter,
+    sort::{ SortedIter, DEFAULT_MEMORY_BUDGET },
+};
 
 
-#[derive(Deserialize, Serialize)]
-pub (crate) struct Paging {
-    pub(crate) size: u16,
+/// The `paging` object of a Deta query or file list response.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct Paging {
+    /// Number of results in the page.
+    pub size: u16,
+    /// Cursor of the next page, empty on the last page.
     #[serde(default)]
-    pub(crate) last: String
+    pub last: String
+}
+
+/// One page of query results, returned by `Query::run`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct QueryPage {
+    /// The results, passed through the `map`/`filter`/`flat_map` stages of the query.
+    pub items: Vec<Value>,
+    /// Cursor of the next page for `Query::last`, `None` on the last page.
+    pub last: Option<String>,
+    /// Number of results Deta returned in this page, before any stages.
+    pub size: usize,
+}
+
+#[cfg(feature = "blocking")]
+pub (crate) fn deserialize_items<T: DeserializeOwned>(items: Vec<Value>) -> Result<Vec<T>, DetaError> {
+    items.into_iter()
+        .enumerate()
+        .map(|(index, item)| {
+            let key = item["key"].as_str().map(String::from);
+            serde_json::from_value::<T>(item)
+                .map_err(|source| DetaError::ItemDeserialize { index, key, source })
+        })
+        .collect()
+}
+
+/// A query in human-readable form along with the exact payload sent to Deta.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryDescription {
+    /// The conditions, e.g. `age > 18 AND name contains 'Jo'`.
+    pub text: String,
+    /// The JSON body of the query request.
+    pub payload: Value,
+}
+
+impl std::fmt::Display for QueryDescription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.text)
+    }
+}
+
+fn describe_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("'{}'", s),
+        value => value.to_string(),
+    }
 }
 
-#[derive(Deserialize, Serialize)]
-struct QueryResult {
-    paging: Paging,
-    items: Vec<Value>
+fn describe_condition(condition: &str, value: &Value) -> String {
+    let (field, op) = condition.rsplit_once('?').unwrap_or((condition, ""));
+    let op = match op {
+        "" => "=",
+        "ne" => "!=",
+        "gt" => ">",
+        "gte" => ">=",
+        "lt" => "<",
+        "lte" => "<=",
+        "pfx" => "starts with",
+        "contains" => "contains",
+        "not_contains" => "does not contain",
+        "r" | "range" => match value.as_array().map(Vec::as_slice) {
+            Some([start, end]) => {
+                return format!("{} between {} and {}", field, describe_value(start), describe_value(end));
+            },
+            _ => "in range",
+        },
+        op => op,
+    };
+    format!("{} {} {}", field, op, describe_value(value))
 }
 
+type Stage = Arc<dyn Fn(Value) -> Vec<Value> + Send + Sync>;
+
 /// Represents a query.
 #[derive(Clone)]
 pub struct Query {
@@ -24,7 +112,9 @@ pub struct Query {
     last: Option<String>,
     sort: Option<bool>,
     container: Vec<Value>,
-    map: Map<String, Value>
+    map: Map<String, Value>,
+    pipeline: Vec<Stage>,
+    order: Vec<(String, Order)>,
 }
 
 impl Query {
@@ -36,41 +126,392 @@ impl Query {
             last: None,
             sort: Some(false),
             container: Vec::new(),
-            map: Map::new()
+            map: Map::new(),
+            pipeline: Vec::new(),
+            order: Vec::new(),
+        }
+    }
+
+    /// Builds a query for the base from a small SQL subset.
+    /// 
+    /// Supports `WHERE` with `AND`/`OR`, the comparison operators, `LIKE 'x%'`,
+    /// `LIKE '%x%'`, `NOT LIKE '%x%'`, `BETWEEN`, `ORDER BY key [DESC]` and `LIMIT`.
+    /// ```rust
+    /// use detalib::{ Deta, query::Query };
+    /// 
+    /// let base = Deta::new().base("users");
+    /// let query = Query::from_sql(&base, "WHERE age > 18 AND name LIKE 'Jo%' LIMIT 50").unwrap();
+    /// ```
+    pub fn from_sql(base: &Base, sql: &str) -> Result<Query, DetaError> {
+        let parsed = sql::parse(sql)?;
+        let mut query = Query::new(base.clone()).sort(parsed.desc);
+        if let Some(limit) = parsed.limit {
+            query = query.limit(limit);
+        }
+        let mut groups = parsed.groups.into_iter();
+        if let Some(first) = groups.next() {
+            query.map = first;
+        }
+        query.container.extend(groups.map(Value::Object));
+        Ok(query)
+    }
+
+    /// Builds a query for the base from a query request body, the inverse of serializing a query.
+    pub fn from_payload(base: &Base, payload: &Value) -> Result<Query, DetaError> {
+        let invalid = |msg: &str| DetaError::PayloadError { msg: format!("invalid query payload: {}", msg) };
+        let mut query = Query::new(base.clone()).sort(payload["sort"] == "desc");
+        match &payload["limit"] {
+            Value::Null => {},
+            limit => query = query.limit(
+                limit.as_u64().and_then(|l| u16::try_from(l).ok()).ok_or_else(|| invalid("bad `limit`"))?
+            ),
+        }
+        if let Some(last) = payload["last"].as_str() {
+            query = query.last(last);
         }
+        match &payload["query"] {
+            Value::Array(groups) if groups.iter().all(Value::is_object) => {
+                query.container.extend(groups.iter().cloned());
+            },
+            Value::Null => {},
+            _ => return Err(invalid("`query` must be an array of objects")),
+        }
+        Ok(query)
+    }
+
+    fn page(&self, resp: &Value) -> Result<QueryPage, DetaError> {
+        let (items, last) = parse::query_result(resp)?;
+        let size = items.len();
+        Ok(QueryPage { items: self.process(items), last, size })
     }
 
-    /// Executes the query on the base.
-    pub fn run(&self) -> Result<Value, DetaError> {
-        self.base.request("POST", "/query", Some(serde_json::to_value(self).unwrap()))
+    /// Executes the query on the base, returning the first page of results.
+    /// 
+    /// Pass `last` to `Query::last` to fetch the next page.
+    #[cfg(feature = "blocking")]
+    pub fn run(&self) -> Result<QueryPage, DetaError> {
+        self.page(&self.run_raw()?)
+    }
+
+    /// Same as `run`, returning the raw response body.
+    #[cfg(feature = "blocking")]
+    pub fn run_raw(&self) -> Result<Value, DetaError> {
+        let payload = serde_json::to_value(self)?;
+        let resp = self.base.request("POST", "/query", Some(payload.clone()))?;
+        self.record(&payload, &resp);
+        Ok(resp)
+    }
+
+    fn record(&self, payload: &Value, resp: &Value) {
+        if let Some(recorder) = &self.base.service.inner.recorder {
+            recorder.record(self.base.name(), payload, resp["items"].as_array().map_or(0, Vec::len));
+        }
     }
 
     /// Executes the query until there are no more results.
+    ///
+    /// Fails with the error of the first page that cannot be fetched, rather than returning
+    /// the results fetched before it.
+    #[cfg(feature = "blocking")]
     pub fn walk(&self) -> Result<Vec<Value>, DetaError> {
-        let mut items: Vec<Value> = Vec::new();
-        let mut resp = self.run();
-        if resp.is_err() {
-            return Err(resp.err().unwrap());
-        }
-        let result = serde_json::from_value::<QueryResult>
-            (resp.unwrap()).map_err(DetaError::from).unwrap();
-        items.extend(result.items);
-        let mut last = result.paging.last;
-        while !last.is_empty() {
-            let mut query = self.clone();
-            query = query.last(&last);
-            resp = query.run();
-            if resp.is_err() {
-                break;
-            }
-            let result = serde_json::from_value::<QueryResult>
-                (resp.unwrap()).map_err(DetaError::from).unwrap();
-            last = result.paging.last;
-            items.extend(result.items);
+        let QueryPage { mut items, mut last, .. } = self.run()?;
+        while let Some(cursor) = last {
+            let page = self.page(&self.clone().last(&cursor).run_raw()?)?;
+            items.extend(page.items);
+            last = page.last;
+        }
+        self.sort_items(&mut items);
+        Ok(items)
+    }
+
+    /// Executes the query on the base asynchronously, returning the first page of results.
+    #[cfg(any(feature = "tokio", feature = "wasm"))]
+    pub async fn run_async(&self) -> Result<QueryPage, DetaError> {
+        self.page(&self.run_raw_async().await?)
+    }
+
+    /// Same as `run_async`, returning the raw response body.
+    #[cfg(any(feature = "tokio", feature = "wasm"))]
+    pub async fn run_raw_async(&self) -> Result<Value, DetaError> {
+        let payload = serde_json::to_value(self)?;
+        let resp = self.base.request_async(reqwest::Method::POST, "/query", Some(payload.clone())).await?;
+        self.record(&payload, &resp);
+        Ok(resp)
+    }
+
+    /// Executes the query asynchronously until there are no more results.
+    ///
+    /// Like `walk`, fails with the error of the first page that cannot be fetched.
+    #[cfg(any(feature = "tokio", feature = "wasm"))]
+    pub async fn walk_async(&self) -> Result<Vec<Value>, DetaError> {
+        let QueryPage { mut items, mut last, .. } = self.run_async().await?;
+        while let Some(cursor) = last {
+            let page = self.page(&self.clone().last(&cursor).run_raw_async().await?)?;
+            items.extend(page.items);
+            last = page.last;
         }
+        self.sort_items(&mut items);
         Ok(items)
     }
 
+    /// Counts all results, paging through them without keeping them in memory.
+    #[cfg(feature = "blocking")]
+    pub fn count(&self) -> Result<usize, DetaError> {
+        let mut count = 0;
+        let mut query = self.clone();
+        loop {
+            let page = query.run()?;
+            count += page.items.len();
+            match page.last {
+                Some(cursor) => query = self.clone().last(&cursor),
+                None => return Ok(count),
+            }
+        }
+    }
+
+    /// Returns the first result, if any.
+    #[cfg(feature = "blocking")]
+    pub fn first(&self) -> Result<Option<Value>, DetaError> {
+        self.clone().limit(1).iter().next().transpose()
+    }
+
+    /// Returns the first result deserialized to a struct, if any.
+    #[cfg(feature = "blocking")]
+    pub fn first_as<T: DeserializeOwned>(&self) -> Result<Option<T>, DetaError> {
+        match self.first()? {
+            Some(item) => deserialize_items(vec![item]).map(|mut items| items.pop()),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns a lazy iterator over all results, fetching pages as they are consumed.
+    /// 
+    /// Unlike `walk`, only one page is held in memory at a time.
+    #[cfg(feature = "blocking")]
+    pub fn iter(&self) -> QueryIter {
+        QueryIter::new(self.clone())
+    }
+
+    /// Walks all results sorted by the given fields, since Deta itself only sorts by key.
+    /// 
+    /// Up to `DEFAULT_MEMORY_BUDGET` records are sorted in memory, larger result sets are
+    /// merged from sorted runs spilled to temporary files. Missing fields sort like `null`.
+    /// ```rust,no_run
+    /// use detalib::{ Deta, sort::Order };
+    /// 
+    /// let base = Deta::new().base("users");
+    /// for user in base.query().walk_sorted(&[("country", Order::Asc), ("age", Order::Desc)]).unwrap() {
+    ///     println!("{}", user.unwrap());
+    /// }
+    /// ```
+    #[cfg(feature = "blocking")]
+    pub fn walk_sorted(&self, keys: &[(&str, Order)]) -> Result<SortedIter, DetaError> {
+        self.walk_sorted_within(keys, DEFAULT_MEMORY_BUDGET)
+    }
+
+    /// Like `walk_sorted`, keeping at most `budget` records in memory.
+    #[cfg(feature = "blocking")]
+    pub fn walk_sorted_within(&self, keys: &[(&str, Order)], budget: usize) -> Result<SortedIter, DetaError> {
+        let keys = keys.iter().map(|(field, order)| (field.to_string(), *order)).collect();
+        SortedIter::new(keys, self.iter(), budget)
+    }
+
+    /// Sorts the results of `walk` by `field`, since Deta itself only sorts by key.
+    /// 
+    /// Calling it again adds a tie-breaker. All results are collected before sorting, so they
+    /// must fit in memory; use `walk_sorted` for larger result sets. Values of different types
+    /// sort by type first, and missing fields sort like `null`. `run` and `iter` are not sorted.
+    /// ```rust,no_run
+    /// use detalib::{ Deta, sort::Order };
+    /// 
+    /// let oldest = Deta::new().base("users").query().order_by("age", Order::Desc).walk().unwrap();
+    /// ```
+    pub fn order_by(mut self, field: &str, direction: Order) -> Self {
+        self.order.push((field.to_string(), direction));
+        self
+    }
+
+    fn sort_items(&self, items: &mut [Value]) {
+        if !self.order.is_empty() {
+            items.sort_by(|a, b| sort::compare(&self.order, a, b));
+        }
+    }
+
+    /// Returns the `n` results with the largest value of `field`, largest first.
+    /// 
+    /// Pages through all results while keeping only `n` of them in memory.
+    #[cfg(feature = "blocking")]
+    pub fn top_n_by(&self, field: &str, n: usize) -> Result<Vec<Value>, DetaError> {
+        sort::top_n(&[(field.to_string(), Order::Desc)], self.iter(), n)
+    }
+
+    /// Returns the `n` results with the smallest value of `field`, smallest first.
+    #[cfg(feature = "blocking")]
+    pub fn bottom_n_by(&self, field: &str, n: usize) -> Result<Vec<Value>, DetaError> {
+        sort::top_n(&[(field.to_string(), Order::Asc)], self.iter(), n)
+    }
+
+    /// Returns the unique values of `field` across all results, in the order they are first seen.
+    /// 
+    /// Records without the field are skipped. Nested fields are addressed with dots.
+    #[cfg(feature = "blocking")]
+    pub fn distinct(&self, field: &str) -> Result<Vec<Value>, DetaError> {
+        Ok(self.distinct_counts(field)?.into_iter().map(|(value, _)| value).collect())
+    }
+
+    /// Like `distinct`, along with how many results hold each value.
+    #[cfg(feature = "blocking")]
+    pub fn distinct_counts(&self, field: &str) -> Result<Vec<(Value, usize)>, DetaError> {
+        let pointer = format!("/{}", field.replace('.', "/"));
+        let mut seen: HashMap<String, usize> = HashMap::new();
+        let mut values: Vec<(Value, usize)> = Vec::new();
+        for item in self.iter() {
+            let Some(value) = item?.pointer_mut(&pointer).map(Value::take) else { continue };
+            match seen.entry(canonicalize(&value)) {
+                Entry::Occupied(index) => values[*index.get()].1 += 1,
+                Entry::Vacant(slot) => {
+                    slot.insert(values.len());
+                    values.push((value, 1));
+                },
+            }
+        }
+        Ok(values)
+    }
+
+    /// Counts the values of a numeric field in buckets of `bucket_width`, paging through all results.
+    /// 
+    /// Bucket `n` holds values in `[n * bucket_width, (n + 1) * bucket_width)`.
+    /// ```rust,no_run
+    /// use detalib::Deta;
+    /// 
+    /// let histogram = Deta::new().base("users").query().histogram("age", 10.0).unwrap();
+    /// for bucket in histogram.buckets {
+    ///     println!("{}-{}: {}", bucket.start, bucket.end, bucket.count);
+    /// }
+    /// ```
+    #[cfg(feature = "blocking")]
+    pub fn histogram(&self, field: &str, bucket_width: f64) -> Result<Histogram, DetaError> {
+        if !(bucket_width.is_finite() && bucket_width > 0.0) {
+            return Err(DetaError::PayloadError { msg: String::from("bucket width must be positive") });
+        }
+        let pointer = format!("/{}", field.replace('.', "/"));
+        let mut buckets = Buckets::new(bucket_width);
+        for item in self.iter() {
+            buckets.add(item?.pointer(&pointer));
+        }
+        Ok(buckets.finish())
+    }
+
+    /// Returns a lazy async iterator over all results, fetching pages as they are consumed.
+    #[cfg(any(feature = "tokio", feature = "wasm"))]
+    pub fn iter_async(&self) -> crate::iter::AsyncQueryIter {
+        crate::iter::AsyncQueryIter::new(self.clone())
+    }
+
+    /// Executes the query and deserializes the items of the first page.
+    #[cfg(feature = "blocking")]
+    pub fn run_as<T: DeserializeOwned>(&self) -> Result<Vec<T>, DetaError> {
+        deserialize_items(self.run()?.items)
+    }
+
+    /// Executes the query until there are no more results and deserializes every item.
+    /// 
+    /// Fails with `DetaError::ItemDeserialize` naming the first item that does not fit `T`.
+    #[cfg(feature = "blocking")]
+    pub fn walk_as<T: DeserializeOwned>(&self) -> Result<Vec<T>, DetaError> {
+        self.walk().and_then(deserialize_items)
+    }
+
+    /// Wraps the query in a cache that refreshes incrementally on each run.
+    #[cfg(feature = "blocking")]
+    pub fn cached(self) -> CachedQuery {
+        CachedQuery::new(self)
+    }
+
+    /// Describe the query for logging and debugging, without running it.
+    /// ```rust
+    /// use detalib::Deta;
+    /// use serde_json::json;
+    /// 
+    /// let query = Deta::new().base("users").query()
+    ///     .greater_than("age", json!(18))
+    ///     .contains("name", json!("Jo"));
+    /// assert_eq!(query.describe().text, "age > 18 AND name contains 'Jo' LIMIT 1000");
+    /// ```
+    pub fn describe(&self) -> QueryDescription {
+        let payload = serde_json::to_value(self).unwrap_or_default();
+        let groups = payload["query"].as_array().cloned().unwrap_or_default();
+        let parenthesize = groups.len() > 1;
+        let mut text = groups.iter()
+            .map(|group| {
+                let conditions = group.as_object()
+                    .map(|map| map.iter().map(|(c, v)| describe_condition(c, v)).collect::<Vec<_>>())
+                    .unwrap_or_default();
+                match conditions.len() {
+                    0 => String::from("*"),
+                    n if n > 1 && parenthesize => format!("({})", conditions.join(" AND ")),
+                    _ => conditions.join(" AND "),
+                }
+            })
+            .collect::<Vec<String>>()
+            .join(" OR ");
+        if self.sort == Some(true) {
+            text.push_str(" ORDER BY key DESC");
+        }
+        if let Some(limit) = self.limit {
+            text.push_str(&format!(" LIMIT {}", limit));
+        }
+        if let Some(last) = &self.last {
+            text.push_str(&format!(" AFTER '{}'", last));
+        }
+        QueryDescription { text, payload }
+    }
+
+    /// Transforms every result with `f`. Applied page by page by `run`, `walk`, `iter` and `run_as`,
+    /// but not to the raw response of `run_raw`.
+    pub fn map<F>(self, f: F) -> Self
+        where F: Fn(Value) -> Value + Send + Sync + 'static
+    {
+        self.flat_map(move |item| vec![f(item)])
+    }
+
+    /// Keeps only the results for which `f` returns `true`, see `map`.
+    pub fn filter<F>(self, f: F) -> Self
+        where F: Fn(&Value) -> bool + Send + Sync + 'static
+    {
+        self.flat_map(move |item| if f(&item) { vec![item] } else { vec![] })
+    }
+
+    /// Replaces every result with the results returned by `f`, see `map`.
+    pub fn flat_map<F>(mut self, f: F) -> Self
+        where F: Fn(Value) -> Vec<Value> + Send + Sync + 'static
+    {
+        self.pipeline.push(Arc::new(f));
+        self
+    }
+
+    /// Keeps only `key` and the given fields of every result, see `map`.
+    ///
+    /// Deta has no server-side projection, so full records are still transferred, but only the
+    /// selected fields are held once a page is processed. Dotted paths select nested fields,
+    /// keeping their parent objects. Stages added after `select` only see the selected fields.
+    /// ```rust,no_run
+    /// use detalib::Deta;
+    ///
+    /// let users = Deta::new().base("users").query().select(&["name", "address.city"]).walk().unwrap();
+    /// ```
+    pub fn select(self, fields: &[&str]) -> Self {
+        let fields = fields.iter().map(|field| field.to_string()).collect::<Vec<_>>();
+        self.map(move |item| select(&item, &fields))
+    }
+
+    /// Runs a page of results through the `map`/`filter`/`flat_map` stages.
+    pub (crate) fn process(&self, items: Vec<Value>) -> Vec<Value> {
+        self.pipeline.iter().fold(items, |items, stage| {
+            items.into_iter().flat_map(|item| stage(item)).collect()
+        })
+    }
+
     /// Sets the limit of the query.
     pub fn limit(mut self, limit: u16) -> Self {
         self.limit = Some(limit);
@@ -95,12 +536,67 @@ impl Query {
         self
     }
 
+    /// Adds an alternative group of conditions built by `f`. Records match if they
+    /// satisfy the conditions of this query OR those of the group.
+    /// ```rust
+    /// use detalib::Deta;
+    /// use serde_json::json;
+    /// 
+    /// let query = Deta::new().base("users").query()
+    ///     .greater_than("age", json!(65))
+    ///     .or(|q| q.less_than("age", json!(18)).equals("guardian", json!(true)));
+    /// ```
+    pub fn or<F: FnOnce(Query) -> Query>(self, f: F) -> Self {
+        let other = f(Query::new(self.base.clone()));
+        self.union(other)
+    }
+
+    /// Adds every given query as an alternative, see `or`.
+    pub fn any_of(self, queries: Vec<Query>) -> Self {
+        queries.into_iter().fold(self, Query::union)
+    }
+
     /// Merges the given query into this query.
     pub fn union(mut self, other: Query) -> Self {
         for item in other.container {
             self.container.push(item);
         }
-        self.container.push(Value::Object(other.map));
+        if !other.map.is_empty() {
+            self.container.push(Value::Object(other.map));
+        }
+        self
+    }
+
+    /// The groups of alternative conditions, empty if the query matches every record.
+    pub (crate) fn groups(&self) -> Vec<Map<String, Value>> {
+        let mut groups = self.container.iter()
+            .filter_map(|group| group.as_object().cloned())
+            .collect::<Vec<_>>();
+        if !self.map.is_empty() {
+            groups.push(self.map.clone());
+        }
+        groups
+    }
+
+    /// Replaces the conditions with the given groups of alternatives.
+    pub (crate) fn with_groups(mut self, groups: Vec<Map<String, Value>>) -> Self {
+        self.container = groups.into_iter().map(Value::Object).collect();
+        self.map = Map::new();
+        self
+    }
+
+    /// Adds a condition, e.g. `updated_at?gte`, to every group of alternatives,
+    /// so it holds whichever group a record matches.
+    #[cfg(feature = "blocking")]
+    pub (crate) fn in_every_group(mut self, condition: &str, value: Value) -> Self {
+        for group in self.container.iter_mut() {
+            if let Value::Object(group) = group {
+                group.insert(condition.to_string(), value.clone());
+            }
+        }
+        if !self.map.is_empty() || self.container.is_empty() {
+            self.map.insert(condition.to_string(), value);
+        }
         self
     }
 
@@ -140,9 +636,43 @@ impl Query {
         self
     }
 
-    /// Checks if the given field is in the given range.
+    /// Checks if the given field is in the given `[start, end]` range.
     pub fn in_range(mut self, field: &str, value: Value) -> Self {
-        self.map.insert(format!("{}?range", field), value);
+        self.map.insert(format!("{}?r", field), value);
+        self
+    }
+
+    /// Checks if the given field is between `start` and `end` (both inclusive).
+    pub fn range(self, field: &str, start: Value, end: Value) -> Self {
+        self.in_range(field, Value::Array(vec![start, end]))
+    }
+
+    /// Checks if the given date field is between `from` and `to` (both inclusive).
+    /// 
+    /// The dates are converted to the base's `DateFormat`.
+    /// ```rust
+    /// use chrono::{ TimeZone, Utc };
+    /// use detalib::{ Deta, dates::DateFormat };
+    /// 
+    /// let base = Deta::from("id_secret").base("events").with_date_format(DateFormat::EpochMillis);
+    /// let from = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    /// let to = Utc.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap();
+    /// let query = base.query().between_dates("created", from, to);
+    /// ```
+    pub fn between_dates<Tz: TimeZone>(self, field: &str, from: DateTime<Tz>, to: DateTime<Tz>) -> Self {
+        let format = self.base.date_format();
+        self.range(field, format.encode(&from), format.encode(&to))
+    }
+
+    /// Checks if the given date field is no older than `duration` from now.
+    pub fn since(self, field: &str, duration: TimeDelta) -> Self {
+        let date = self.base.date_format().encode(&(Utc::now() - duration));
+        self.greater_than_or_equals(field, date)
+    }
+
+    /// Checks if the given string field starts with the given prefix.
+    pub fn prefix(mut self, field: &str, prefix: &str) -> Self {
+        self.map.insert(format!("{}?pfx", field), Value::from(prefix));
         self
     }
 
@@ -152,21 +682,298 @@ impl Query {
         self
     }
 
+    /// Checks if the given field does not contain the given value.
+    pub fn not_contains(mut self, field: &str, value: Value) -> Self {
+        self.map.insert(format!("{}?not_contains", field), value);
+        self
+    }
+
+    /// Checks if the record key equals the given key.
+    /// 
+    /// Keys are always strings, so the value is sent as a JSON string.
+    pub fn key_equals(mut self, key: &str) -> Self {
+        self.map.insert(String::from("key"), Value::from(key));
+        self
+    }
+
+    /// Checks if the record key starts with the given prefix.
+    pub fn key_prefix(mut self, prefix: &str) -> Self {
+        self.map.insert(String::from("key?pfx"), Value::from(prefix));
+        self
+    }
+
+    /// Checks if the record key is between `start` and `end` (both inclusive).
+    pub fn key_range(mut self, start: &str, end: &str) -> Self {
+        self.map.insert(String::from("key?r"), Value::from(vec![start, end]));
+        self
+    }
+
 }
 
 impl Serialize for Query {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error> where S: serde::Serializer {
         let mut map = Map::new();
-        map.insert("limit".to_string(), Value::from(self.limit.unwrap()));
+        if let Some(limit) = self.limit {
+            map.insert("limit".to_string(), Value::from(limit));
+        }
         if self.last.is_some() {
             map.insert("last".to_string(), Value::from(self.last.clone()));
         }
-        if self.sort.is_some() && self.sort.unwrap() {
+        if self.sort == Some(true) {
             map.insert("sort".to_string(), serde_json::json!("desc"));
         }
         let mut outer = self.container.clone();
-        outer.push(Value::Object(self.map.clone()));
+        // an empty group matches every record, so it only stands alone
+        if !self.map.is_empty() || outer.is_empty() {
+            outer.push(Value::Object(self.map.clone()));
+        }
         map.insert(String::from("query"), Value::Array(outer));
         Value::Object(map).serialize(serializer)
     }
-}
\ No newline at end of file
+}
+
+fn select(item: &Value, fields: &[String]) -> Value {
+    let mut selected = Map::new();
+    if let Some(key) = item.get("key") {
+        selected.insert(String::from("key"), key.clone());
+    }
+    for field in fields {
+        let Some(value) = item.pointer(&format!("/{}", field.replace('.', "/"))) else {
+            continue;
+        };
+        let mut parts = field.split('.').peekable();
+        let mut slot = &mut selected;
+        while let Some(part) = parts.next() {
+            if parts.peek().is_none() {
+                slot.insert(part.to_string(), value.clone());
+                break;
+            }
+            let next = slot.entry(part).or_insert_with(|| Value::Object(Map::new()));
+            match next {
+                Value::Object(map) => slot = map,
+                _ => break,
+            }
+        }
+    }
+    Value::Object(selected)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::{ Deta, errors::DetaError, transport::Request };
+    #[cfg(feature = "mock")]
+    use crate::{ mock::MockDeta, sort::Order };
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn conditions_in_every_group() {
+        let query = Deta::from("id_secret").base("b").query()
+            .equals("a", json!(1))
+            .or(|q| q.equals("b", json!(2)))
+            .in_every_group("t?gte", json!(5));
+        let groups = json!([{ "b": 2, "t?gte": 5 }, { "a": 1, "t?gte": 5 }]);
+        assert_eq!(serde_json::to_value(&query).unwrap()["query"], groups);
+        let query = Deta::from("id_secret").base("b").query().in_every_group("t?gte", json!(5));
+        assert_eq!(serde_json::to_value(&query).unwrap()["query"], json!([{ "t?gte": 5 }]));
+    }
+
+    #[test]
+    fn date_filters_use_base_format() {
+        use chrono::{ TimeDelta, TimeZone, Utc };
+
+        use crate::dates::DateFormat;
+
+        let base = Deta::from("id_secret").base("events").with_date_format(DateFormat::Rfc3339);
+        let from = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let query = base.query().between_dates("at", from, from + TimeDelta::days(1));
+        assert_eq!(
+            serde_json::to_value(&query).unwrap()["query"],
+            json!([{ "at?r": ["2024-01-01T00:00:00.000Z", "2024-01-02T00:00:00.000Z"] }])
+        );
+        let query = base.with_date_format(DateFormat::EpochSeconds).query().since("at", TimeDelta::hours(1));
+        let since = serde_json::to_value(&query).unwrap()["query"][0]["at?gte"].as_i64().unwrap();
+        assert!((since - (Utc::now().timestamp() - 3600)).abs() <= 1);
+    }
+
+    #[test]
+    fn key_operators() {
+        let base = Deta::from("id_secret").base("hello");
+        let query = base.query().key_prefix("user_").key_range("user_a", "user_m");
+        assert_eq!(
+            serde_json::to_value(&query).unwrap()["query"],
+            json!([{ "key?pfx": "user_", "key?r": ["user_a", "user_m"] }])
+        );
+        let query = base.query().key_equals("42");
+        assert_eq!(serde_json::to_value(&query).unwrap()["query"], json!([{ "key": "42" }]));
+    }
+
+    #[test]
+    fn field_operators() {
+        let base = Deta::from("id_secret").base("hello");
+        let query = base.query()
+            .prefix("name", "Jo")
+            .not_contains("tags", json!("spam"))
+            .range("age", json!(18), json!(65));
+        assert_eq!(
+            serde_json::to_value(&query).unwrap()["query"],
+            json!([{ "name?pfx": "Jo", "tags?not_contains": "spam", "age?r": [18, 65] }])
+        );
+    }
+
+    #[test]
+    fn pipeline_stages_in_order() {
+        let query = Deta::from("id_secret").base("hello").query()
+            .filter(|item| item["n"].as_i64().is_some_and(|n| n % 2 == 0))
+            .map(|item| json!(item["n"].as_i64().unwrap_or_default() * 10))
+            .flat_map(|item| vec![item.clone(), item]);
+        let page = (1..=4).map(|n| json!({ "n": n })).collect();
+        assert_eq!(query.process(page), vec![json!(20), json!(20), json!(40), json!(40)]);
+    }
+
+    #[test]
+    fn select_keeps_listed_fields() {
+        let query = Deta::from("id_secret").base("hello").query()
+            .select(&["name", "address.city", "missing"]);
+        let user = json!({ "key": "u", "name": "John", "age": 30, "address": { "city": "Pune", "zip": 1 } });
+        assert_eq!(
+            query.process(vec![user]),
+            vec![json!({ "key": "u", "name": "John", "address": { "city": "Pune" } })]
+        );
+    }
+
+    #[test]
+    fn order_by_sorts_by_fields() {
+        use crate::sort::Order;
+
+        let query = Deta::from("id_secret").base("hello").query()
+            .order_by("age", Order::Desc)
+            .order_by("name", Order::Asc);
+        let mut items = vec![
+            json!({ "name": "b", "age": 30 }),
+            json!({ "name": "c", "age": 40 }),
+            json!({ "name": "a", "age": 30 }),
+        ];
+        query.sort_items(&mut items);
+        let names = items.iter().map(|item| item["name"].as_str().unwrap()).collect::<Vec<_>>();
+        assert_eq!(names, ["c", "a", "b"]);
+    }
+
+    #[test]
+    fn or_groups() {
+        let base = Deta::from("id_secret").base("hello");
+        let query = base.query().equals("a", json!(1)).or(|q| q.equals("b", json!(2)));
+        assert_eq!(serde_json::to_value(&query).unwrap()["query"], json!([{ "b": 2 }, { "a": 1 }]));
+        let query = base.query().any_of(vec![base.query().equals("a", json!(1)), base.query()]);
+        assert_eq!(serde_json::to_value(&query).unwrap()["query"], json!([{ "a": 1 }]));
+        assert_eq!(serde_json::to_value(base.query()).unwrap()["query"], json!([{}]));
+    }
+
+    #[test]
+    fn describes_groups() {
+        let base = Deta::from("id_secret").base("hello");
+        let query = base.query().equals("a", json!(1)).equals("b", json!("x"))
+            .union(base.query().in_range("c", json!([1, 5])))
+            .sort(true);
+        let description = query.describe();
+        assert_eq!(description.text, "c between 1 and 5 OR (a = 1 AND b = 'x') ORDER BY key DESC LIMIT 1000");
+        assert_eq!(description.payload, serde_json::to_value(&query).unwrap());
+    }
+
+    #[cfg(feature = "blocking")]
+    #[derive(serde::Deserialize)]
+    struct Aged {
+        age: u8,
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn item_errors_name_the_record() {
+        let items = vec![json!({ "key": "a", "age": 1 }), json!({ "key": "b", "age": "old" })];
+        assert_eq!(super::deserialize_items::<Aged>(items[..1].to_vec()).unwrap()[0].age, 1);
+        match super::deserialize_items::<Aged>(items) {
+            Err(DetaError::ItemDeserialize { index, key, .. }) => {
+                assert_eq!((index, key.as_deref()), (1, Some("b")));
+            },
+            _ => panic!("expected an item error"),
+        }
+    }
+
+    /// Answers the first page of a query and fails the next one with a 500.
+    struct FailingSecondPage;
+
+    impl FailingSecondPage {
+        fn respond(request: &Request) -> (u16, &'static str) {
+            match serde_json::from_slice::<serde_json::Value>(request.body.unwrap_or_default()) {
+                Ok(payload) if payload["last"].is_null() => {
+                    (200, r#"{"items": [{"key": "a"}], "paging": {"last": "a"}}"#)
+                },
+                _ => (500, ""),
+            }
+        }
+    }
+
+    #[cfg(feature = "blocking")]
+    impl crate::transport::Transport for FailingSecondPage {
+        fn send(&self, request: &Request) -> Result<ureq::Response, ureq::Error> {
+            match FailingSecondPage::respond(request) {
+                (200, body) => ureq::Response::new(200, "OK", body),
+                (status, body) => {
+                    Err(ureq::Error::Status(status, ureq::Response::new(status, "Server Error", body)?))
+                },
+            }
+        }
+    }
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn walk_fails_on_page_errors() {
+        let deta = Deta::builder().project_key("id_secret").transport(FailingSecondPage).build();
+        let query = deta.base("hello").query();
+        assert_eq!(query.run().unwrap().items.len(), 1);
+        assert!(matches!(query.walk(), Err(DetaError::HTTPError { status: 500, .. })));
+    }
+
+    #[cfg(any(feature = "tokio", feature = "wasm"))]
+    impl crate::transport::AsyncTransport for FailingSecondPage {
+        fn send<'a>(
+            &'a self, request: Request<'a>
+        ) -> crate::transport::BoxFuture<'a, Result<reqwest::Response, reqwest::Error>> {
+            let (status, body) = FailingSecondPage::respond(&request);
+            let resp = http::Response::builder().status(status).body(body).expect("valid response");
+            Box::pin(async move { Ok(reqwest::Response::from(resp)) })
+        }
+    }
+
+    #[cfg(any(feature = "tokio", feature = "wasm"))]
+    #[tokio::test]
+    async fn walk_async_fails_on_page_errors() {
+        let deta = Deta::builder().project_key("id_secret").async_transport(FailingSecondPage).build();
+        let query = deta.base("hello").query();
+        assert_eq!(query.run_async().await.unwrap().items.len(), 1);
+        assert!(matches!(query.walk_async().await, Err(DetaError::HTTPError { status: 500, .. })));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn walk_sorted_orders_by_fields() {
+        let base = MockDeta::new().base("users");
+        let users = (0..10).map(|i| json!({ "key": format!("{}", i), "age": i % 3 })).collect::<Vec<_>>();
+        base.put(users).unwrap();
+        let keys = [("age", Order::Desc), ("key", Order::Asc)];
+        let sorted = base.query().limit(4).walk_sorted_within(&keys, 3).unwrap()
+            .map(|user| user.unwrap()["key"].as_str().unwrap().to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(sorted, vec!["2", "5", "8", "1", "4", "7", "0", "3", "6", "9"]);
+        let oldest = base.query().limit(3).top_n_by("age", 2).unwrap();
+        assert_eq!((&oldest[0]["key"], &oldest[1]["key"]), (&json!("2"), &json!("5")));
+        assert_eq!(base.query().bottom_n_by("age", 1).unwrap()[0]["key"], "0");
+        assert_eq!(base.query().limit(4).distinct("age").unwrap(), vec![json!(0), json!(1), json!(2)]);
+        assert_eq!(base.query().distinct_counts("age").unwrap()[0], (json!(0), 4));
+        assert!(base.query().distinct("missing").unwrap().is_empty());
+        let histogram = base.query().histogram("age", 2.0).unwrap();
+        assert_eq!(histogram.buckets.iter().map(|b| b.count).collect::<Vec<_>>(), vec![7, 3]);
+        assert!(base.query().histogram("age", 0.0).is_err());
+    }
+}
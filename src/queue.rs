@@ -0,0 +1,286 @@
+use std::{
+    fs::{ File, OpenOptions },
+    io::{ BufRead, BufReader, Write },
+    path::{ Path, PathBuf },
+    sync::{ Condvar, Mutex },
+};
+
+use serde::{ Deserialize, Serialize };
+
+#[cfg(feature = "blocking")]
+use crate::Deta;
+use crate::errors::DetaError;
+
+/// What happens to a write that arrives while all write slots are busy.
+#[derive(Debug, Clone)]
+pub enum Overflow {
+    /// Wait for a free slot. Once the queue is full, further writes are rejected.
+    Block,
+    /// Fail immediately with `DetaError::Overloaded`.
+    Reject,
+    /// Append the write to the journal at the given path, to be sent later with `Journal::replay`.
+    /// The write fails with `DetaError::Spilled`.
+    Spill(PathBuf),
+}
+
+/// Limits the number of concurrent writes of a Deta client.
+///
+/// Reads, including queries, are never limited, so latency-sensitive lookups are not stuck behind bursts of writes.
+/// ```rust
+/// use detalib::{ Deta, queue::{ Overflow, WriteQueue } };
+///
+/// let deta = Deta::builder()
+///     .write_queue(WriteQueue::new(4).queue_size(64).overflow(Overflow::Block))
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct WriteQueue {
+    max_in_flight: usize,
+    queue_size: usize,
+    overflow: Overflow,
+}
+
+impl WriteQueue {
+
+    /// Allows at most `max_in_flight` writes at a time, blocking the rest.
+    pub fn new(max_in_flight: usize) -> WriteQueue {
+        WriteQueue { max_in_flight: max_in_flight.max(1), queue_size: usize::MAX, overflow: Overflow::Block }
+    }
+
+    /// Sets how many writes may wait for a slot with `Overflow::Block`. Unbounded by default.
+    pub fn queue_size(mut self, queue_size: usize) -> Self {
+        self.queue_size = queue_size;
+        self
+    }
+
+    /// Sets what happens to writes while all slots are busy.
+    pub fn overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
+}
+
+/// A write recorded in a journal instead of being sent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub method: String,
+    pub url: String,
+    pub body: Option<Vec<u8>>,
+    pub content_type: Option<String>,
+}
+
+/// An append-only file of writes that were spilled by `Overflow::Spill`.
+pub struct Journal {
+    path: PathBuf,
+}
+
+impl Journal {
+
+    /// Open the journal at the given path.
+    pub fn open<P: AsRef<Path>>(path: P) -> Journal {
+        Journal { path: path.as_ref().to_path_buf() }
+    }
+
+    pub (crate) fn append(&self, entry: &JournalEntry) -> Result<(), DetaError> {
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        file.write_all(&line)?;
+        Ok(())
+    }
+
+    /// The writes currently recorded in the journal, oldest first.
+    pub fn entries(&self) -> Result<Vec<JournalEntry>, DetaError> {
+        let file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        BufReader::new(file).lines()
+            .map(|line| Ok(serde_json::from_str(&line?)?))
+            .collect()
+    }
+
+    /// Send every journaled write with the given client and truncate the journal.
+    ///
+    /// Stops at the first failure, keeping that write and the ones after it.
+    /// Returns the number of writes sent.
+    #[cfg(feature = "blocking")]
+    pub fn replay(&self, deta: &Deta) -> Result<usize, DetaError> {
+        let entries = self.entries()?;
+        for (i, entry) in entries.iter().enumerate() {
+            let sent = deta.send_now(
+                &entry.method, &entry.url, entry.body.as_deref(), entry.content_type.as_deref());
+            if let Err(e) = sent {
+                self.rewrite(&entries[i..])?;
+                return Err(e);
+            }
+        }
+        self.rewrite(&[])?;
+        Ok(entries.len())
+    }
+
+    #[cfg(feature = "blocking")]
+    fn rewrite(&self, entries: &[JournalEntry]) -> Result<(), DetaError> {
+        let mut content = Vec::new();
+        for entry in entries {
+            serde_json::to_writer(&mut content, entry)?;
+            content.push(b'\n');
+        }
+        std::fs::write(&self.path, content)?;
+        Ok(())
+    }
+}
+
+struct Slots {
+    in_flight: usize,
+    #[cfg(feature = "blocking")]
+    waiting: usize,
+}
+
+pub (crate) struct WriteLimiter {
+    queue: WriteQueue,
+    slots: Mutex<Slots>,
+    freed: Condvar,
+}
+
+/// A write slot, released when dropped.
+pub (crate) struct Permit<'a> {
+    limiter: &'a WriteLimiter,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        let mut slots = self.limiter.slots.lock().unwrap_or_else(|e| e.into_inner());
+        slots.in_flight -= 1;
+        self.limiter.freed.notify_one();
+    }
+}
+
+/// Whether a request changes data. Queries are sent with `POST` but only read.
+pub (crate) fn is_write(method: &str, url: &str) -> bool {
+    let path = url.split('?').next().unwrap_or(url);
+    !method.eq_ignore_ascii_case("GET") && !method.eq_ignore_ascii_case("HEAD") && !path.ends_with("/query")
+}
+
+impl WriteLimiter {
+
+    pub (crate) fn new(queue: WriteQueue) -> WriteLimiter {
+        let slots = Slots {
+            in_flight: 0,
+            #[cfg(feature = "blocking")]
+            waiting: 0,
+        };
+        WriteLimiter { queue, slots: Mutex::new(slots), freed: Condvar::new() }
+    }
+
+    fn overflow(&self, entry: impl FnOnce() -> JournalEntry) -> DetaError {
+        match &self.queue.overflow {
+            Overflow::Spill(path) => match Journal::open(path).append(&entry()) {
+                Ok(()) => DetaError::Spilled { path: path.clone() },
+                Err(e) => e,
+            },
+            _ => DetaError::Overloaded { msg: format!("{} writes in flight", self.queue.max_in_flight) },
+        }
+    }
+
+    /// Takes a free slot without waiting, or applies the overflow policy if there is none.
+    /// With `Overflow::Block` this returns `Ok(None)` when the caller should wait and try again.
+    #[cfg(any(test, feature = "tokio", feature = "wasm"))]
+    pub (crate) fn try_acquire(
+        &self, entry: impl FnOnce() -> JournalEntry
+    ) -> Result<Option<Permit<'_>>, DetaError> {
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        if slots.in_flight < self.queue.max_in_flight {
+            slots.in_flight += 1;
+            return Ok(Some(Permit { limiter: self }));
+        }
+        match self.queue.overflow {
+            Overflow::Block => Ok(None),
+            _ => Err(self.overflow(entry)),
+        }
+    }
+
+    /// Takes a free slot, waiting for one with `Overflow::Block`.
+    #[cfg(feature = "blocking")]
+    pub (crate) fn acquire(
+        &self, entry: impl FnOnce() -> JournalEntry
+    ) -> Result<Permit<'_>, DetaError> {
+        let mut slots = self.slots.lock().unwrap_or_else(|e| e.into_inner());
+        if slots.in_flight >= self.queue.max_in_flight {
+            if !matches!(self.queue.overflow, Overflow::Block) || slots.waiting >= self.queue.queue_size {
+                return Err(self.overflow(entry));
+            }
+            slots.waiting += 1;
+            while slots.in_flight >= self.queue.max_in_flight {
+                slots = self.freed.wait(slots).unwrap_or_else(|e| e.into_inner());
+            }
+            slots.waiting -= 1;
+        }
+        slots.in_flight += 1;
+        Ok(Permit { limiter: self })
+    }
+}
+
+#[cfg(all(test, feature = "blocking"))]
+mod tests {
+    use super::*;
+
+    fn entry() -> JournalEntry {
+        JournalEntry { method: "PUT".into(), url: "u".into(), body: Some(b"{}".to_vec()), content_type: None }
+    }
+
+    #[test]
+    fn rejects_beyond_limit() {
+        let limiter = WriteLimiter::new(WriteQueue::new(1).overflow(Overflow::Reject));
+        let permit = limiter.acquire(entry).unwrap();
+        assert!(matches!(limiter.acquire(entry), Err(DetaError::Overloaded { .. })));
+        drop(permit);
+        assert!(limiter.acquire(entry).is_ok());
+    }
+
+    #[test]
+    fn spills_to_journal() {
+        let path = std::env::temp_dir().join(format!("detalib-journal-{}", std::process::id()));
+        let limiter = WriteLimiter::new(WriteQueue::new(1).overflow(Overflow::Spill(path.clone())));
+        let _permit = limiter.acquire(entry).unwrap();
+        assert!(matches!(limiter.acquire(entry), Err(DetaError::Spilled { .. })));
+        let entries = Journal::open(&path).entries().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].body.as_deref(), Some(&b"{}"[..]));
+    }
+
+    #[test]
+    fn blocked_writers_wait_for_a_slot() {
+        let limiter = WriteLimiter::new(WriteQueue::new(1).queue_size(0));
+        let permit = limiter.acquire(entry).unwrap();
+        assert!(limiter.try_acquire(entry).unwrap().is_none());
+        assert!(matches!(limiter.acquire(entry), Err(DetaError::Overloaded { .. })));
+        drop(permit);
+        assert!(limiter.try_acquire(entry).unwrap().is_some());
+    }
+
+    #[test]
+    fn queries_are_reads() {
+        assert!(is_write("POST", "https://database.deta.sh/v1/p/users/items"));
+        assert!(!is_write("POST", "https://database.deta.sh/v1/p/users/query"));
+        assert!(!is_write("GET", "https://drive.deta.sh/v1/p/files/files/download?name=a"));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn queries_run_while_queue_is_full() {
+        let deta = Deta::builder()
+            .project_key("mock_key")
+            .mock(std::sync::Arc::default())
+            .write_queue(WriteQueue::new(1).overflow(Overflow::Reject))
+            .build();
+        let base = deta.base("users");
+        base.put(vec![serde_json::json!({ "key": "a" })]).unwrap();
+        let limiter = deta.inner.writes.as_ref().unwrap();
+        let _permit = limiter.acquire(entry).unwrap();
+        assert!(matches!(base.put(vec![serde_json::json!({ "key": "b" })]), Err(DetaError::Overloaded { .. })));
+        assert_eq!(base.query().walk().unwrap().len(), 1);
+    }
+}
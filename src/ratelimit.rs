@@ -0,0 +1,223 @@
+use std::{ sync::Mutex, time::Duration };
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+#[cfg(feature = "blocking")]
+use crate::{ base::Base, errors::DetaError };
+
+/// The window of `RateLimit::check`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    /// Counts requests per calendar window, e.g. per clock minute. Up to twice the limit
+    /// may pass around the boundary of two windows.
+    Fixed(Duration),
+    /// Weighs the count of the previous window by how much of it still overlaps the last
+    /// `window`, smoothing the boundary at the cost of one more read.
+    Sliding(Duration),
+}
+
+/// The outcome of `RateLimit::check`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decision {
+    pub allowed: bool,
+    /// Requests counted in the window, including this one.
+    pub count: u64,
+    pub limit: u64,
+    /// Time until the current window ends.
+    pub reset_after: Duration,
+}
+
+impl Decision {
+
+    /// Requests left in the window.
+    pub fn remaining(&self) -> u64 {
+        self.limit.saturating_sub(self.count)
+    }
+}
+
+/// A client-side token bucket limiting the request rate of a Deta client.
+///
+/// Every request takes a token; requests beyond the rate wait for the next token instead of
+/// tripping Deta's rate limits. With `adaptive`, a `429 Too Many Requests` halves the rate,
+/// which then recovers gradually with successful requests.
+/// ```rust
+/// use detalib::{ Deta, ratelimit::RateLimit };
+///
+/// let deta = Deta::builder()
+///     .rate_limit(RateLimit::per_second(10).burst(20))
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    per_second: f64,
+    burst: f64,
+    adaptive: bool,
+}
+
+impl RateLimit {
+
+    /// Allows `requests` requests per second, with bursts of the same size.
+    pub fn per_second(requests: u32) -> RateLimit {
+        let requests = f64::from(requests.max(1));
+        RateLimit { per_second: requests, burst: requests, adaptive: true }
+    }
+
+    /// Sets how many requests may be sent at once after a quiet period.
+    pub fn burst(mut self, burst: u32) -> Self {
+        self.burst = f64::from(burst.max(1));
+        self
+    }
+
+    /// Sets whether the rate backs off on `429` responses. Enabled by default.
+    pub fn adaptive(mut self, adaptive: bool) -> Self {
+        self.adaptive = adaptive;
+        self
+    }
+
+    /// Counts a request against `bucket_key`, e.g. a user id, and decides whether it is allowed,
+    /// for rate limiting the users of an app.
+    ///
+    /// Counters are records of `base` keyed `{bucket_key}_{window number}`, incremented atomically
+    /// and expired by Deta after two windows. Rejected requests count too, so a client that keeps
+    /// retrying stays limited.
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    /// use detalib::{ Deta, ratelimit::{ RateLimit, Window } };
+    ///
+    /// let counters = Deta::new().base("rate_limits");
+    /// let window = Window::Sliding(Duration::from_secs(60));
+    /// let decision = RateLimit::check(&counters, "user_42", 100, window).unwrap();
+    /// if !decision.allowed {
+    ///     println!("retry in {:?}", decision.reset_after);
+    /// }
+    /// ```
+    #[cfg(feature = "blocking")]
+    pub fn check(base: &Base, bucket_key: &str, limit: u64, window: Window) -> Result<Decision, DetaError> {
+        let (Window::Fixed(length) | Window::Sliding(length)) = window;
+        let length = length.as_millis().max(1) as i64;
+        let now = chrono::Utc::now().timestamp_millis();
+        let (number, elapsed) = (now.div_euclid(length), now.rem_euclid(length));
+        let counters = base.kv();
+        let key = |number: i64| format!("{}_{}", bucket_key, number);
+        let current = counters.incr(&key(number), 1)?;
+        if current == 1 {
+            let ttl = (2 * length as u64).div_ceil(1000);
+            counters.expire(&key(number), ttl)?;
+        }
+        let count = match window {
+            Window::Fixed(_) => current.max(0) as u64,
+            Window::Sliding(_) => {
+                let previous = counters.get_as::<i64>(&key(number - 1))?.unwrap_or_default();
+                let overlap = 1.0 - elapsed as f64 / length as f64;
+                current.max(0) as u64 + (previous.max(0) as f64 * overlap).floor() as u64
+            },
+        };
+        Ok(Decision {
+            allowed: count <= limit,
+            count,
+            limit,
+            reset_after: Duration::from_millis((length - elapsed) as u64),
+        })
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    rate: f64,
+    refilled: Instant,
+}
+
+pub (crate) struct RateLimiter {
+    limit: RateLimit,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+
+    pub (crate) fn new(limit: RateLimit) -> RateLimiter {
+        let bucket = Bucket { tokens: limit.burst, rate: limit.per_second, refilled: Instant::now() };
+        RateLimiter { limit, bucket: Mutex::new(bucket) }
+    }
+
+    fn bucket(&self) -> std::sync::MutexGuard<'_, Bucket> {
+        self.bucket.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Takes a token, returning how long the caller must wait before sending.
+    ///
+    /// Tokens may go into debt, so waiting callers are served in the order they arrived.
+    pub (crate) fn reserve(&self) -> Duration {
+        let mut bucket = self.bucket();
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * bucket.rate).min(self.limit.burst) - 1.0;
+        bucket.refilled = now;
+        match bucket.tokens < 0.0 {
+            true => Duration::from_secs_f64(-bucket.tokens / bucket.rate),
+            false => Duration::ZERO,
+        }
+    }
+
+    /// Halves the rate after Deta answered `429`, down to a sixteenth of the configured rate.
+    pub (crate) fn throttled(&self) {
+        if self.limit.adaptive {
+            let mut bucket = self.bucket();
+            bucket.rate = (bucket.rate / 2.0).max(self.limit.per_second / 16.0);
+            bucket.tokens = bucket.tokens.min(0.0);
+        }
+    }
+
+    /// Recovers a twentieth of the configured rate after a successful request.
+    pub (crate) fn succeeded(&self) {
+        if self.limit.adaptive {
+            let mut bucket = self.bucket();
+            bucket.rate = (bucket.rate + self.limit.per_second / 20.0).min(self.limit.per_second);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "mock")]
+    use crate::mock::MockDeta;
+
+    #[test]
+    fn waits_once_burst_is_spent() {
+        let limiter = RateLimiter::new(RateLimit::per_second(10).burst(2));
+        assert_eq!(limiter.reserve(), Duration::ZERO);
+        assert_eq!(limiter.reserve(), Duration::ZERO);
+        let wait = limiter.reserve();
+        assert!(wait > Duration::from_millis(90) && wait <= Duration::from_millis(100));
+        assert!(limiter.reserve() > wait);
+    }
+
+    #[test]
+    fn backs_off_on_throttling() {
+        let limiter = RateLimiter::new(RateLimit::per_second(10).burst(1));
+        limiter.reserve();
+        limiter.throttled();
+        let wait = limiter.reserve();
+        assert!(wait > Duration::from_millis(190) && wait <= Duration::from_millis(200));
+        for _ in 0..20 {
+            limiter.succeeded();
+        }
+        assert_eq!(limiter.bucket().rate, 10.0);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn rate_limit_counts_per_bucket() {
+        let counters = MockDeta::new().base("rate_limits");
+        let window = Window::Fixed(Duration::from_secs(3600));
+        let check = |bucket, window| RateLimit::check(&counters, bucket, 3, window).unwrap();
+        let decisions = (0..4).map(|_| check("u1", window)).collect::<Vec<_>>();
+        assert_eq!(decisions.iter().map(|d| d.allowed).collect::<Vec<_>>(), [true, true, true, false]);
+        assert_eq!((decisions[1].count, decisions[1].remaining()), (2, 1));
+        assert!(decisions[3].reset_after <= Duration::from_secs(3600));
+        assert!(check("u2", Window::Sliding(Duration::from_secs(3600))).allowed);
+    }
+}
@@ -0,0 +1,86 @@
+use serde::{ Serialize, de::DeserializeOwned };
+
+/// A struct stored as a Deta Base record.
+/// 
+/// Usually derived with `#[derive(DetaRecord)]` (requires the `derive` feature), which also
+/// generates `Serialize` and `Deserialize` implementations that map the key field to `key`,
+/// apply field renames and set `__expires` on writes. It also generates a `SessionFields`
+/// struct with a `filter::Field` for every field, used by `Collection::find_where`.
+/// ```rust,ignore
+/// use detalib::DetaRecord;
+/// 
+/// #[derive(DetaRecord)]
+/// #[deta(expires_in = 3600)]
+/// struct Session {
+///     #[deta(key)]
+///     id: String,
+///     #[deta(rename = "uid")]
+///     user_id: String,
+/// }
+/// ```
+pub trait DetaRecord: Serialize + DeserializeOwned {
+    /// The stored names of all fields.
+    const FIELDS: &'static [&'static str];
+    /// Seconds after each write at which the record expires.
+    const EXPIRES_IN: Option<u64> = None;
+    /// The fields of the record, to build typed filters with.
+    type Fields;
+
+    /// The fields of the record, see `Collection::find_where`.
+    fn fields() -> Self::Fields;
+
+    /// The key of the record.
+    fn key(&self) -> String;
+}
+
+#[cfg(all(test, feature = "derive"))]
+mod tests {
+    use serde_json::json;
+
+    use crate::DetaRecord;
+
+    #[derive(DetaRecord, Debug, PartialEq)]
+    #[deta(expires_in = 60)]
+    struct Session {
+        #[deta(key)]
+        id: String,
+        #[deta(rename = "uid")]
+        user_id: u32,
+        note: Option<String>,
+    }
+
+    #[test]
+    fn derived_record_roundtrip() {
+        let session = Session { id: String::from("s1"), user_id: 7, note: None };
+        let value = serde_json::to_value(&session).unwrap();
+        assert_eq!(value["key"], json!("s1"));
+        assert_eq!(value["uid"], json!(7));
+        assert!(value["__expires"].as_i64().unwrap() > chrono::Utc::now().timestamp());
+        assert_eq!(serde_json::from_value::<Session>(value).unwrap(), session);
+        assert_eq!(session.key(), "s1");
+        assert_eq!(Session::FIELDS, &["key", "uid", "note"]);
+        let fields = Session::fields();
+        assert_eq!((fields.id.name(), fields.user_id.name(), fields.note.name()), ("key", "uid", "note"));
+    }
+
+    #[cfg(feature = "mock")]
+    #[derive(DetaRecord, Debug, PartialEq)]
+    struct User {
+        key: String,
+        #[deta(rename = "years")]
+        age: u8,
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn finds_with_typed_filters() {
+        let users = crate::mock::MockDeta::new().collection::<User>("users");
+        let user = |key: &str, age| User { key: key.to_string(), age };
+        users.put(&[user("a", 12), user("b", 30), user("c", 45)]).unwrap();
+        let adults = users.find_where(|f| f.age.gt(18).and(f.key.ne("c"))).unwrap();
+        assert_eq!(adults, vec![user("b", 30)]);
+        let either = users.find_where(|f| f.age.lt(18).or(f.age.gte(45))).unwrap();
+        assert_eq!(either.len(), 2);
+        assert_eq!(users.find_one_where(|f| f.age.range(40, 50)).unwrap(), Some(user("c", 45)));
+    }
+}
@@ -0,0 +1,100 @@
+use std::{ collections::HashMap, path::Path };
+
+use serde_json::Value;
+
+use crate::{ base::Base, errors::DetaError, query::Query };
+
+/// Named, reusable queries for a base.
+/// 
+/// Queries can be registered in code or loaded from a JSON file mapping names to either
+/// SQL strings (see `Query::from_sql`) or query request bodies:
+/// ```json
+/// {
+///     "active_adults": "WHERE age >= 18 AND active = true",
+///     "newest": { "query": [{ "key?pfx": "user_" }], "sort": "desc", "limit": 10 }
+/// }
+/// ```
+/// ```rust
+/// use detalib::{ Deta, registry::QueryRegistry };
+/// use serde_json::json;
+/// 
+/// let base = Deta::new().base("users");
+/// let registry = QueryRegistry::new(&base)
+///     .register("adults", base.query().greater_than_or_equals("age", json!(18)));
+/// let adults = registry.get("adults").unwrap();
+/// ```
+#[derive(Clone)]
+pub struct QueryRegistry {
+    base: Base,
+    queries: HashMap<String, Query>,
+}
+
+impl QueryRegistry {
+
+    /// Create an empty registry for the base.
+    pub fn new(base: &Base) -> QueryRegistry {
+        QueryRegistry { base: base.clone(), queries: HashMap::new() }
+    }
+
+    /// Register a query under the given name, replacing any query with the same name.
+    pub fn register(mut self, name: &str, query: Query) -> Self {
+        self.queries.insert(name.to_string(), query);
+        self
+    }
+
+    /// Register every query defined in a JSON value, see the type level docs for the format.
+    pub fn load_json(mut self, definitions: &Value) -> Result<Self, DetaError> {
+        let definitions = definitions.as_object().ok_or_else(|| DetaError::PayloadError {
+            msg: "query definitions must be an object".to_string()
+        })?;
+        for (name, definition) in definitions {
+            let query = match definition {
+                Value::String(sql) => Query::from_sql(&self.base, sql)?,
+                payload => Query::from_payload(&self.base, payload)?,
+            };
+            self.queries.insert(name.clone(), query);
+        }
+        Ok(self)
+    }
+
+    /// Register every query defined in a JSON file.
+    pub fn load_file<P: AsRef<Path>>(self, path: P) -> Result<Self, DetaError> {
+        let content = std::fs::read(path)?;
+        self.load_json(&serde_json::from_slice(&content)?)
+    }
+
+    /// Get a copy of a registered query, to run or refine further.
+    pub fn get(&self, name: &str) -> Result<Query, DetaError> {
+        self.queries.get(name).cloned().ok_or_else(|| DetaError::PayloadError {
+            msg: format!("no query named `{}`", name)
+        })
+    }
+
+    /// The names of all registered queries.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.queries.keys().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::Deta;
+
+    #[test]
+    fn loads_sql_and_payloads() {
+        let base = Deta::from("id_secret").base("users");
+        let registry = QueryRegistry::new(&base).load_json(&json!({
+            "adults": "WHERE age >= 18 LIMIT 5",
+            "newest": { "query": [{ "key?pfx": "u" }], "sort": "desc" },
+        })).unwrap();
+        let adults = serde_json::to_value(registry.get("adults").unwrap()).unwrap();
+        assert_eq!(adults, json!({ "limit": 5, "query": [{ "age?gte": 18 }] }));
+        let newest = serde_json::to_value(registry.get("newest").unwrap()).unwrap();
+        assert_eq!(newest, json!({ "limit": 1000, "sort": "desc", "query": [{ "key?pfx": "u" }] }));
+        assert!(registry.get("missing").is_err());
+        assert!(QueryRegistry::new(&base).load_json(&json!({ "bad": { "query": 1 } })).is_err());
+    }
+}
@@ -0,0 +1,317 @@
+//! Continuous replication of a base into another base, e.g. in another project for disaster recovery.
+
+use std::{
+    cmp::Ordering,
+    collections::HashSet,
+    sync::{ Arc, Condvar, Mutex },
+    thread::{ self, JoinHandle },
+    time::Duration,
+};
+
+use chrono::{ DateTime, Utc };
+use serde_json::Value;
+
+use crate::{ base::Base, errors::DetaError, sort::compare_values };
+
+/// What happens when a changed record already exists in the target base.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// The source record replaces the target record.
+    #[default]
+    SourceWins,
+    /// The target record is kept if its timestamp is newer than the source record's.
+    NewerWins,
+    /// Records that already exist in the target are never replaced.
+    TargetWins,
+}
+
+/// Metrics of a `Replicator`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReplicationStats {
+    pub polls: usize,
+    /// Records written to the target base.
+    pub replicated: usize,
+    /// Records kept in the target base by the conflict policy.
+    pub skipped: usize,
+    /// Records the target base rejected.
+    pub failed: usize,
+    /// Polls that failed, e.g. because a base could not be reached.
+    pub errors: usize,
+    pub last_error: Option<String>,
+    pub last_poll: Option<DateTime<Utc>>,
+    /// The newest timestamp replicated so far, to resume with `Replicator::resume_from`.
+    pub checkpoint: Option<Value>,
+    /// How long the oldest change replicated by the last poll waited, zero if it found no changes.
+    ///
+    /// `None` if the timestamps could not be read in the date format of the source base.
+    pub lag: Option<Duration>,
+}
+
+/// Copies changed records from a source base to a target base, usually in another project.
+///
+/// Changes are found by polling for records whose timestamp field (`updated_at` by default)
+/// is at or after the newest timestamp replicated so far, so writers must keep that field current.
+/// The first poll copies the whole base. Deletions are not replicated.
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use detalib::{ Deta, replicate::{ ConflictPolicy, Replicator } };
+///
+/// let (primary, standby) = (Deta::new(), Deta::from("standby_project_key"));
+/// let replicator = Replicator::new(primary.base("orders"), standby.base("orders"))
+///     .interval(Duration::from_secs(10))
+///     .conflict_policy(ConflictPolicy::NewerWins)
+///     .start();
+/// println!("lag: {:?}", replicator.stats().lag);
+/// let checkpoint = replicator.stop().stats().checkpoint.clone();
+/// ```
+pub struct Replicator {
+    source: Base,
+    target: Base,
+    field: String,
+    interval: Duration,
+    policy: ConflictPolicy,
+    replicated_at_checkpoint: HashSet<String>,
+    stats: ReplicationStats,
+}
+
+impl Replicator {
+
+    pub fn new(source: Base, target: Base) -> Replicator {
+        Replicator {
+            source,
+            target,
+            field: String::from("updated_at"),
+            interval: Duration::from_secs(30),
+            policy: ConflictPolicy::default(),
+            replicated_at_checkpoint: HashSet::new(),
+            stats: ReplicationStats::default(),
+        }
+    }
+
+    /// Sets the timestamp field used to detect changed records.
+    pub fn updated_field(mut self, field: &str) -> Self {
+        self.field = field.to_string();
+        self
+    }
+
+    /// Sets how long `start` waits between polls. Defaults to 30 seconds.
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    pub fn conflict_policy(mut self, policy: ConflictPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Skips the initial full copy and replicates records changed at or after `checkpoint`.
+    pub fn resume_from(mut self, checkpoint: Value) -> Self {
+        self.stats.checkpoint = Some(checkpoint);
+        self.replicated_at_checkpoint.clear();
+        self
+    }
+
+    pub fn stats(&self) -> &ReplicationStats {
+        &self.stats
+    }
+
+    /// Replicates the changes since the last poll. Returns the number of records written.
+    pub fn poll(&mut self) -> Result<usize, DetaError> {
+        let result = self.replicate();
+        self.stats.polls += 1;
+        self.stats.last_poll = Some(Utc::now());
+        if let Err(e) = &result {
+            self.stats.errors += 1;
+            self.stats.last_error = Some(e.to_string());
+        }
+        result
+    }
+
+    /// Polls in a background thread every `interval` until the handle is stopped or dropped.
+    pub fn start(self) -> ReplicatorHandle {
+        let shared = Arc::new(Shared {
+            stats: Mutex::new(self.stats.clone()),
+            stopped: Mutex::new(false),
+            stop: Condvar::new(),
+        });
+        let poller = shared.clone();
+        let mut replicator = self;
+        let thread = thread::spawn(move || {
+            loop {
+                let _ = replicator.poll();
+                *poller.stats.lock().unwrap_or_else(|e| e.into_inner()) = replicator.stats.clone();
+                let stopped = poller.stopped.lock().unwrap_or_else(|e| e.into_inner());
+                let stopped = poller.stop
+                    .wait_timeout_while(stopped, replicator.interval, |stopped| !*stopped)
+                    .unwrap_or_else(|e| e.into_inner()).0;
+                if *stopped {
+                    return replicator;
+                }
+            }
+        });
+        ReplicatorHandle { shared, thread: Some(thread) }
+    }
+
+    fn stamp<'a>(&self, item: &'a Value) -> Option<&'a Value> {
+        item.get(&self.field).filter(|stamp| !stamp.is_null())
+    }
+
+    fn is_replicated(&self, item: &Value) -> bool {
+        match (self.stamp(item), &self.stats.checkpoint) {
+            (Some(stamp), Some(checkpoint)) => compare_values(stamp, checkpoint) == Ordering::Equal
+                && item["key"].as_str().is_some_and(|key| self.replicated_at_checkpoint.contains(key)),
+            _ => false,
+        }
+    }
+
+    fn keeps_target(&self, item: &Value) -> Result<bool, DetaError> {
+        if self.policy == ConflictPolicy::SourceWins {
+            return Ok(false);
+        }
+        let existing = match item["key"].as_str() {
+            Some(key) => self.target.get_opt(key)?,
+            None => return Ok(false),
+        };
+        Ok(match (existing, self.policy) {
+            (None, _) => false,
+            (Some(_), ConflictPolicy::TargetWins) => true,
+            (Some(existing), _) => match (self.stamp(&existing), self.stamp(item)) {
+                (Some(theirs), Some(ours)) => compare_values(theirs, ours) == Ordering::Greater,
+                (Some(_), None) => true,
+                (None, _) => false,
+            },
+        })
+    }
+
+    fn advance(&mut self, item: &Value) {
+        let (Some(stamp), Some(key)) = (self.stamp(item), item["key"].as_str()) else {
+            return;
+        };
+        let newer = match &self.stats.checkpoint {
+            Some(checkpoint) => match compare_values(stamp, checkpoint) {
+                Ordering::Greater => true,
+                Ordering::Equal => false,
+                Ordering::Less => return,
+            },
+            None => true,
+        };
+        if newer {
+            self.stats.checkpoint = Some(stamp.clone());
+            self.replicated_at_checkpoint.clear();
+        }
+        self.replicated_at_checkpoint.insert(key.to_string());
+    }
+
+    fn replicate(&mut self) -> Result<usize, DetaError> {
+        let query = match &self.stats.checkpoint {
+            Some(checkpoint) => self.source.query().greater_than_or_equals(&self.field, checkpoint.clone()),
+            None => self.source.query(),
+        };
+        let mut changes = query.walk()?;
+        changes.retain(|item| !self.is_replicated(item));
+        changes.sort_by(|a, b| match (self.stamp(a), self.stamp(b)) {
+            (Some(a), Some(b)) => compare_values(a, b),
+            (a, b) => a.is_some().cmp(&b.is_some()),
+        });
+        let format = self.source.date_format();
+        self.stats.lag = match changes.iter().find_map(|item| self.stamp(item)) {
+            Some(oldest) => format.decode(oldest).map(|at| (Utc::now() - at).to_std().unwrap_or_default()),
+            None => Some(Duration::ZERO),
+        };
+        let mut written = 0;
+        for chunk in changes.chunks(25) {
+            let mut batch = Vec::with_capacity(chunk.len());
+            for item in chunk {
+                match self.keeps_target(item)? {
+                    true => self.stats.skipped += 1,
+                    false => batch.push(item),
+                }
+            }
+            if !batch.is_empty() {
+                let result = self.target.put_many(&batch)?;
+                written += result.processed.len();
+                self.stats.replicated += result.processed.len();
+                self.stats.failed += result.failed.len();
+            }
+            chunk.iter().for_each(|item| self.advance(item));
+        }
+        Ok(written)
+    }
+}
+
+struct Shared {
+    stats: Mutex<ReplicationStats>,
+    stopped: Mutex<bool>,
+    stop: Condvar,
+}
+
+/// A running `Replicator`, stopped when dropped.
+pub struct ReplicatorHandle {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<Replicator>>,
+}
+
+impl ReplicatorHandle {
+
+    /// The metrics as of the last poll.
+    pub fn stats(&self) -> ReplicationStats {
+        self.shared.stats.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Stops polling and returns the replicator, which can be started again where it left off.
+    pub fn stop(mut self) -> Replicator {
+        self.signal();
+        let thread = self.thread.take().expect("replicator thread is joined once");
+        thread.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic))
+    }
+
+    fn signal(&self) {
+        *self.shared.stopped.lock().unwrap_or_else(|e| e.into_inner()) = true;
+        self.shared.stop.notify_all();
+    }
+}
+
+impl Drop for ReplicatorHandle {
+    fn drop(&mut self) {
+        self.signal();
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::time::Duration;
+
+    use serde_json::json;
+
+    use super::{ ConflictPolicy, Replicator };
+    use crate::mock::MockDeta;
+
+    #[test]
+    fn replicator_copies_changes() {
+        let (primary, standby) = (MockDeta::new().base("orders"), MockDeta::new().base("orders"));
+        let orders = (0..30).map(|i| json!({ "key": format!("o{:02}", i), "updated_at": i }));
+        let orders = orders.collect::<Vec<_>>();
+        primary.put_many(&orders).unwrap();
+        let mut replicator = Replicator::new(primary.clone(), standby.clone())
+            .conflict_policy(ConflictPolicy::NewerWins);
+        assert_eq!(replicator.poll().unwrap(), 30);
+        assert_eq!(replicator.poll().unwrap(), 0);
+        assert_eq!(replicator.stats().checkpoint, Some(json!(29)));
+        primary.put(vec![json!({ "key": "o05", "updated_at": 40, "paid": true })]).unwrap();
+        standby.put(vec![json!({ "key": "o06", "updated_at": 50 })]).unwrap();
+        primary.put(vec![json!({ "key": "o06", "updated_at": 41 })]).unwrap();
+        assert_eq!(replicator.poll().unwrap(), 1);
+        assert_eq!(standby.get("o05").unwrap()["paid"], true);
+        assert_eq!(standby.get("o06").unwrap()["updated_at"], 50);
+        let stats = replicator.stats();
+        assert_eq!((stats.polls, stats.replicated, stats.skipped, stats.errors), (3, 31, 1, 0));
+
+        let handle = replicator.interval(Duration::from_millis(5)).start();
+        primary.put(vec![json!({ "key": "o07", "updated_at": 42 })]).unwrap();
+        while handle.stats().replicated < 32 {
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(handle.stop().stats().checkpoint, Some(json!(42)));
+    }
+}
@@ -0,0 +1,56 @@
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::errors::DetaError;
+
+/// Reads a JSON response body, treating an empty body as `null`.
+/// 
+/// A `207 Multi-Status` response is surfaced as `DetaError::PartialFailure`
+/// carrying the parsed body, so partially applied writes are never mistaken for success.
+/// `Base::put` turns it back into a `PutResponse` listing the failed records.
+#[cfg(feature = "blocking")]
+pub (crate) fn parse<T: DeserializeOwned>(resp: ureq::Response) -> Result<T, DetaError> {
+    let status = resp.status();
+    let mut body = Vec::new();
+    std::io::Read::read_to_end(&mut resp.into_reader(), &mut body)?;
+    parse_bytes(status, &body)
+}
+
+/// Same as `parse` for an already buffered body.
+pub (crate) fn parse_bytes<T: DeserializeOwned>(status: u16, body: &[u8]) -> Result<T, DetaError> {
+    let value = if body.iter().all(u8::is_ascii_whitespace) {
+        Value::Null
+    } else {
+        serde_json::from_slice::<Value>(body)?
+    };
+    if status == 207 {
+        return Err(DetaError::PartialFailure { body: value });
+    }
+    serde_json::from_value::<T>(value).map_err(DetaError::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{ json, Value };
+
+    use super::*;
+
+    #[test]
+    fn empty_bodies_are_null() {
+        assert_eq!(parse_bytes::<Value>(200, b"").unwrap(), Value::Null);
+        assert_eq!(parse_bytes::<Value>(204, b" \r\n\t").unwrap(), Value::Null);
+        assert_eq!(parse_bytes::<Option<u8>>(200, b"\n").unwrap(), None);
+        assert!(parse_bytes::<u8>(200, b"").is_err());
+    }
+
+    #[test]
+    fn multi_status_is_a_partial_failure() {
+        let body = br#"{"processed": {"items": []}, "failed": {"items": [{"key": "a"}]}}"#;
+        let Err(DetaError::PartialFailure { body }) = parse_bytes::<Value>(207, body) else {
+            panic!("expected a partial failure");
+        };
+        assert_eq!(body["failed"]["items"], json!([{ "key": "a" }]));
+        let empty = parse_bytes::<Value>(207, b"");
+        assert!(matches!(empty, Err(DetaError::PartialFailure { body: Value::Null })));
+    }
+}
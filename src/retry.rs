@@ -0,0 +1,121 @@
+use std::{ collections::hash_map::RandomState, hash::{ BuildHasher, Hasher }, time::Duration };
+
+/// When and how often failed requests are retried.
+/// 
+/// Delays grow exponentially from `base_delay` up to `max_delay`, optionally with random jitter.
+/// ```rust
+/// use std::time::Duration;
+/// use detalib::{ Deta, retry::RetryPolicy };
+/// 
+/// let deta = Deta::builder()
+///     .retry(RetryPolicy::default().max_attempts(5).base_delay(Duration::from_millis(100)))
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: bool,
+    retry_on: Vec<u16>,
+    retry_transport: bool,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(5),
+            jitter: true,
+            retry_on: vec![429, 500, 502, 503, 504],
+            retry_transport: true,
+        }
+    }
+}
+
+impl RetryPolicy {
+
+    /// Sets the total number of attempts, including the first one.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Sets the delay before the first retry.
+    pub fn base_delay(mut self, base_delay: Duration) -> Self {
+        self.base_delay = base_delay;
+        self
+    }
+
+    /// Sets the upper bound for the delay between attempts.
+    pub fn max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    /// Sets whether delays are randomized to avoid retry storms.
+    pub fn jitter(mut self, jitter: bool) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Sets the HTTP status codes that are retried.
+    pub fn retry_on(mut self, statuses: &[u16]) -> Self {
+        self.retry_on = statuses.to_vec();
+        self
+    }
+
+    /// Sets whether transport errors (connection failures, timeouts) are retried.
+    pub fn retry_transport(mut self, retry_transport: bool) -> Self {
+        self.retry_transport = retry_transport;
+        self
+    }
+
+    /// Whether a request that failed on the given attempt (starting at 0) should be retried.
+    /// `status` is `None` for transport errors.
+    pub (crate) fn should_retry(&self, attempt: u32, status: Option<u16>) -> bool {
+        attempt + 1 < self.max_attempts && match status {
+            Some(status) => self.retry_on.contains(&status),
+            None => self.retry_transport,
+        }
+    }
+
+    /// The delay before retrying after the given attempt (starting at 0).
+    pub (crate) fn delay(&self, attempt: u32) -> Duration {
+        let delay = self.base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        if !self.jitter {
+            return delay;
+        }
+        let random = RandomState::new().build_hasher().finish();
+        delay.mul_f64(0.5 + (random % 1000) as f64 / 2000.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exponential_capped_delays() {
+        let policy = RetryPolicy::default()
+            .jitter(false)
+            .base_delay(Duration::from_millis(100))
+            .max_delay(Duration::from_millis(350));
+        assert_eq!(policy.delay(0), Duration::from_millis(100));
+        assert_eq!(policy.delay(1), Duration::from_millis(200));
+        assert_eq!(policy.delay(2), Duration::from_millis(350));
+        assert!(RetryPolicy::default().delay(1) <= Duration::from_millis(400));
+    }
+
+    #[test]
+    fn retries_configured_failures_only() {
+        let policy = RetryPolicy::default().max_attempts(2);
+        assert!(policy.should_retry(0, Some(503)));
+        assert!(policy.should_retry(0, None));
+        assert!(!policy.should_retry(0, Some(404)));
+        assert!(!policy.should_retry(1, Some(503)));
+    }
+}
@@ -0,0 +1,222 @@
+use serde::{ Serialize, de::DeserializeOwned };
+use serde_json::Value;
+use ureq::Response;
+
+use crate::{
+    Deta,
+    base::{ Base, DeleteResponse, InsertResponse, PutResponse },
+    drive::Drive,
+    errors::DetaError,
+    query::Query,
+    updater::Updater,
+};
+
+/// The operations and names a `ScopedClient` is allowed to use.
+/// 
+/// Name patterns may contain `*` wildcards, e.g. `public_*`.
+#[derive(Debug, Clone, Default)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub bases: Vec<String>,
+    pub drives: Vec<String>,
+}
+
+fn matches(pattern: &str, name: &str) -> bool {
+    let parts = pattern.split('*').collect::<Vec<&str>>();
+    if parts.len() == 1 {
+        return pattern == name;
+    }
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !name.starts_with(first) || !name.ends_with(last) || name.len() < first.len() + last.len() {
+        return false;
+    }
+    let mut rest = &name[first.len()..name.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+fn forbidden(msg: String) -> DetaError {
+    DetaError::Forbidden { msg }
+}
+
+impl Permissions {
+
+    fn check(&self, write: bool, target: &str) -> Result<(), DetaError> {
+        match (write, self.read, self.write) {
+            (false, false, _) => Err(forbidden(format!("read access to `{}` is not allowed", target))),
+            (true, _, false) => Err(forbidden(format!("write access to `{}` is not allowed", target))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A restricted Deta client that enforces `Permissions` client-side.
+/// 
+/// Useful for handing plugins or untrusted modules a handle that can only
+/// touch some bases and drives, and only with the allowed kinds of operations.
+#[derive(Clone)]
+pub struct ScopedClient {
+    deta: Deta,
+    permissions: Permissions,
+}
+
+impl ScopedClient {
+
+    pub fn new(deta: Deta, permissions: Permissions) -> ScopedClient {
+        ScopedClient { deta, permissions }
+    }
+
+    /// The permissions of this client.
+    pub fn permissions(&self) -> &Permissions {
+        &self.permissions
+    }
+
+    /// Get a scoped handle to a base, if its name is allowed.
+    pub fn base(&self, name: &str) -> Result<ScopedBase, DetaError> {
+        if !self.permissions.bases.iter().any(|p| matches(p, name)) {
+            return Err(forbidden(format!("base `{}` is not allowed", name)));
+        }
+        Ok(ScopedBase { base: self.deta.base(name), permissions: self.permissions.clone() })
+    }
+
+    /// Get a scoped handle to a drive, if its name is allowed.
+    pub fn drive(&self, name: &str) -> Result<ScopedDrive, DetaError> {
+        if !self.permissions.drives.iter().any(|p| matches(p, name)) {
+            return Err(forbidden(format!("drive `{}` is not allowed", name)));
+        }
+        Ok(ScopedDrive { drive: self.deta.drive(name), permissions: self.permissions.clone() })
+    }
+}
+
+/// A base handle restricted by `Permissions`.
+#[derive(Clone)]
+pub struct ScopedBase {
+    base: Base,
+    permissions: Permissions,
+}
+
+impl ScopedBase {
+
+    fn read(&self) -> Result<&Base, DetaError> {
+        self.permissions.check(false, self.base.name()).map(|_| &self.base)
+    }
+
+    fn write(&self) -> Result<&Base, DetaError> {
+        self.permissions.check(true, self.base.name()).map(|_| &self.base)
+    }
+
+    /// See `Base::get`.
+    pub fn get(&self, key: &str) -> Result<Value, DetaError> {
+        self.read()?.get(key)
+    }
+
+    /// See `Base::get_as`.
+    pub fn get_as<T: DeserializeOwned>(&self, key: &str) -> Result<T, DetaError> {
+        self.read()?.get_as(key)
+    }
+
+    /// See `Base::query`.
+    pub fn query(&self) -> Result<Query, DetaError> {
+        self.read().map(Base::query)
+    }
+
+    /// See `Base::put`.
+    pub fn put<T: Serialize>(&self, records: Vec<T>) -> Result<PutResponse, DetaError> {
+        self.write()?.put(records)
+    }
+
+    /// See `Base::insert`.
+    pub fn insert<T: Serialize>(&self, record: T) -> Result<InsertResponse, DetaError> {
+        self.write()?.insert(record)
+    }
+
+    /// See `Base::delete`.
+    pub fn delete(&self, key: &str) -> Result<DeleteResponse, DetaError> {
+        self.write()?.delete(key)
+    }
+
+    /// See `Base::update`.
+    pub fn update(&self, key: &str) -> Result<Updater, DetaError> {
+        self.write().map(|base| base.update(key))
+    }
+
+    /// See `Base::patch`.
+    pub fn patch<T: Serialize>(&self, key: &str, partial: T) -> Result<Updater, DetaError> {
+        self.write()?.patch(key, partial)
+    }
+}
+
+/// A drive handle restricted by `Permissions`.
+#[derive(Clone)]
+pub struct ScopedDrive {
+    drive: Drive,
+    permissions: Permissions,
+}
+
+impl ScopedDrive {
+
+    fn read(&self) -> Result<&Drive, DetaError> {
+        self.permissions.check(false, self.drive.name()).map(|_| &self.drive)
+    }
+
+    fn write(&self) -> Result<&Drive, DetaError> {
+        self.permissions.check(true, self.drive.name()).map(|_| &self.drive)
+    }
+
+    /// See `Drive::walk`.
+    pub fn walk(&self, prefix: Option<&str>) -> Result<Vec<String>, DetaError> {
+        self.read()?.walk(prefix)
+    }
+
+    /// See `Drive::get`.
+    pub fn get(&self, name: &str) -> Result<Response, DetaError> {
+        self.read()?.get(name)
+    }
+
+    /// See `Drive::put`.
+    pub fn put(
+        &self, save_as: &str, content: &[u8], content_type: Option<&str>
+    ) -> Result<Response, DetaError> {
+        self.write()?.put(save_as, content, content_type)
+    }
+
+    /// See `Drive::delete`.
+    pub fn delete(&self, names: Vec<&str>) -> Result<Response, DetaError> {
+        self.write()?.delete(names)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcard_patterns() {
+        assert!(matches("public_*", "public_posts"));
+        assert!(matches("*", "anything"));
+        assert!(matches("a*b*c", "a_x_b_y_c"));
+        assert!(!matches("public_*", "private_posts"));
+        assert!(!matches("a*a", "a"));
+        assert!(matches("exact", "exact"));
+    }
+
+    #[test]
+    fn enforces_permissions() {
+        let client = ScopedClient::new(Deta::from("id_secret"), Permissions {
+            read: true,
+            bases: vec![String::from("public_*")],
+            ..Default::default()
+        });
+        assert!(client.base("private").is_err());
+        assert!(client.drive("public_files").is_err());
+        let base = client.base("public_posts").unwrap();
+        assert!(base.query().is_ok());
+        assert!(matches!(base.delete("k"), Err(DetaError::Forbidden { .. })));
+    }
+}
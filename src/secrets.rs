@@ -0,0 +1,245 @@
+use aes_gcm::{ Aes256Gcm, Key, KeyInit, Nonce, aead::{ Aead, AeadCore, OsRng, Payload } };
+use serde::{ Deserialize, Serialize };
+
+use crate::{ base::Base, checksum, errors::DetaError, hex };
+
+const SEPARATOR: char = '@';
+
+#[derive(Deserialize, Serialize)]
+struct Entry {
+    key: String,
+    name: String,
+    version: u32,
+    /// The id of the encryption key the entry was written with.
+    key_id: String,
+    nonce: String,
+    ciphertext: String,
+    created_at: i64,
+}
+
+fn entry_key(name: &str, version: u32) -> String {
+    format!("{}{}{:010}", name, SEPARATOR, version)
+}
+
+/// Identifies an encryption key without revealing it: the start of its SHA-256 digest.
+fn key_id(key: &[u8; 32]) -> String {
+    checksum::digest(key)[..16].to_string()
+}
+
+fn unhex(s: &str) -> Result<Vec<u8>, DetaError> {
+    hex::decode(s).ok_or_else(|| DetaError::PayloadError { msg: "invalid hex string".to_string() })
+}
+
+fn crypto_err(_: aes_gcm::Error) -> DetaError {
+    DetaError::PayloadError { msg: "secret encryption or decryption failed".to_string() }
+}
+
+/// A lightweight vault storing versioned secrets encrypted with AES-256-GCM in a base.
+/// 
+/// Values are encrypted client-side, so the base only ever sees ciphertext.
+/// Every `set_secret` stores a new version under the key `<name>@<version>`.
+/// The name and version are authenticated along with the ciphertext,
+/// so a version moved to another name or version no longer decrypts.
+pub struct Secrets {
+    base: Base,
+    cipher: Aes256Gcm,
+    key_id: String,
+}
+
+impl Secrets {
+
+    /// Create a secrets store over the given base using a 256-bit encryption key.
+    pub fn new(base: &Base, key: &[u8; 32]) -> Secrets {
+        Secrets {
+            base: base.clone(),
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+            key_id: key_id(key),
+        }
+    }
+
+    fn encrypt(&self, name: &str, version: u32, plaintext: &[u8]) -> Result<(String, String), DetaError> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let aad = entry_key(name, version);
+        let ciphertext = self.cipher
+            .encrypt(&nonce, Payload { msg: plaintext, aad: aad.as_bytes() })
+            .map_err(crypto_err)?;
+        Ok((hex::encode(&nonce), hex::encode(&ciphertext)))
+    }
+
+    fn decrypt(&self, entry: &Entry) -> Result<String, DetaError> {
+        if entry.key_id != self.key_id {
+            let (key, found, expected) = (&entry.key, &entry.key_id, &self.key_id);
+            let msg = format!("secret {} is encrypted with key {}, not {}", key, found, expected);
+            return Err(DetaError::PayloadError { msg });
+        }
+        let nonce = unhex(&entry.nonce)?;
+        if nonce.len() != 12 {
+            return Err(DetaError::PayloadError { msg: "invalid nonce length".to_string() });
+        }
+        let aad = entry_key(&entry.name, entry.version);
+        if entry.key != aad {
+            let msg = format!("secret {} is stored under another key", aad);
+            return Err(DetaError::PayloadError { msg });
+        }
+        let ciphertext = unhex(&entry.ciphertext)?;
+        let plaintext = self.cipher
+            .decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: aad.as_bytes() })
+            .map_err(crypto_err)?;
+        String::from_utf8(plaintext).map_err(|e| DetaError::PayloadError { msg: e.to_string() })
+    }
+
+    fn entries(&self, name: &str) -> Result<Vec<Entry>, DetaError> {
+        let items = self.base.query().key_prefix(&format!("{}{}", name, SEPARATOR)).walk()?;
+        let mut entries = items.into_iter()
+            .map(|item| serde_json::from_value::<Entry>(item).map_err(DetaError::from))
+            .collect::<Result<Vec<Entry>, DetaError>>()?;
+        entries.sort_by_key(|entry| entry.version);
+        Ok(entries)
+    }
+
+    fn store(&self, name: &str, version: u32, value: &str) -> Result<(), DetaError> {
+        let (nonce, ciphertext) = self.encrypt(name, version, value.as_bytes())?;
+        let entry = Entry {
+            key: entry_key(name, version),
+            name: name.to_string(),
+            version,
+            key_id: self.key_id.clone(),
+            nonce,
+            ciphertext,
+            created_at: chrono::Utc::now().timestamp(),
+        };
+        self.base.insert(entry).map(|_| ())
+    }
+
+    /// Store a new version of a secret and return its version number.
+    pub fn set_secret(&self, name: &str, value: &str) -> Result<u32, DetaError> {
+        if name.is_empty() || name.contains(SEPARATOR) {
+            return Err(DetaError::PayloadError {
+                msg: format!("secret names must be non-empty and not contain `{}`", SEPARATOR)
+            });
+        }
+        let version = self.entries(name)?.last().map_or(1, |entry| entry.version + 1);
+        self.store(name, version, value)?;
+        Ok(version)
+    }
+
+    /// Get the latest version of a secret.
+    pub fn get_secret(&self, name: &str) -> Result<Option<String>, DetaError> {
+        match self.entries(name)?.last() {
+            Some(entry) => self.decrypt(entry).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Get a specific version of a secret.
+    pub fn get_secret_version(&self, name: &str, version: u32) -> Result<Option<String>, DetaError> {
+        match self.base.get(&entry_key(name, version)) {
+            Ok(item) => self.decrypt(&serde_json::from_value::<Entry>(item)?).map(Some),
+            Err(DetaError::NotFound { .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// List the stored versions of a secret in ascending order.
+    pub fn versions(&self, name: &str) -> Result<Vec<u32>, DetaError> {
+        Ok(self.entries(name)?.iter().map(|entry| entry.version).collect())
+    }
+
+    /// Delete all but the `keep` most recent versions of a secret.
+    pub fn prune(&self, name: &str, keep: usize) -> Result<usize, DetaError> {
+        let entries = self.entries(name)?;
+        let stale = entries.len().saturating_sub(keep);
+        for entry in entries.iter().take(stale) {
+            self.base.delete(&entry.key)?;
+        }
+        Ok(stale)
+    }
+
+    /// Re-encrypt every stored secret version with a new key and return a store using it.
+    /// 
+    /// Every version records the id of its key, so a rotation that fails part way leaves each
+    /// version readable with exactly one of the keys. Rotating again to the same key resumes it,
+    /// skipping the versions that already use the new key.
+    pub fn rotate_key(&self, new_key: &[u8; 32]) -> Result<Secrets, DetaError> {
+        let rotated = Secrets::new(&self.base, new_key);
+        let items = self.base.query().walk()?;
+        let mut updated = Vec::new();
+        for item in items {
+            let mut entry = serde_json::from_value::<Entry>(item)?;
+            if entry.key_id == rotated.key_id {
+                continue;
+            }
+            let plaintext = self.decrypt(&entry)?;
+            let (nonce, ciphertext) = rotated.encrypt(&entry.name, entry.version, plaintext.as_bytes())?;
+            entry.nonce = nonce;
+            entry.ciphertext = ciphertext;
+            entry.key_id = rotated.key_id.clone();
+            updated.push(entry);
+        }
+        for chunk in updated.chunks(25) {
+            self.base.put(chunk.iter().collect())?;
+        }
+        Ok(rotated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::Deta;
+
+    use super::*;
+
+    fn entry(secrets: &Secrets, name: &str, version: u32, value: &str) -> Entry {
+        let (nonce, ciphertext) = secrets.encrypt(name, version, value.as_bytes()).unwrap();
+        Entry {
+            key: entry_key(name, version),
+            name: name.to_string(),
+            version,
+            key_id: secrets.key_id.clone(),
+            nonce,
+            ciphertext,
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn roundtrip_and_wrong_key() {
+        let base = Deta::from("id_secret").base("secrets");
+        let secrets = Secrets::new(&base, &[7; 32]);
+        let entry = entry(&secrets, "db", 1, "hunter2");
+        assert_eq!(secrets.decrypt(&entry).unwrap(), "hunter2");
+        let other = Secrets::new(&base, &[8; 32]);
+        assert!(other.decrypt(&entry).unwrap_err().to_string().contains(&secrets.key_id));
+        assert!(other.decrypt(&Entry { key_id: other.key_id.clone(), ..entry }).is_err());
+    }
+
+    #[test]
+    fn name_and_version_are_authenticated() {
+        let secrets = Secrets::new(&Deta::from("id_secret").base("secrets"), &[7; 32]);
+        let moved = Entry { key: entry_key("db", 2), version: 2, ..entry(&secrets, "db", 1, "hunter2") };
+        assert!(secrets.decrypt(&moved).is_err());
+        let renamed = Entry { name: String::from("api"), ..entry(&secrets, "db", 1, "hunter2") };
+        assert!(secrets.decrypt(&Entry { key: entry_key("api", 1), ..renamed }).is_err());
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn interrupted_rotation_resumes() {
+        let base = crate::mock::MockDeta::new().base("secrets");
+        let old = Secrets::new(&base, &[7; 32]);
+        old.set_secret("db", "a").unwrap();
+        old.set_secret("db", "b").unwrap();
+        old.set_secret("api", "c").unwrap();
+        let unrotated = base.get(&entry_key("db", 1)).unwrap();
+
+        let new = old.rotate_key(&[8; 32]).unwrap();
+        base.put(vec![unrotated]).unwrap();
+        assert!(new.get_secret_version("db", 1).is_err());
+        assert_eq!(new.get_secret("db").unwrap().as_deref(), Some("b"));
+
+        let new = old.rotate_key(&[8; 32]).unwrap();
+        assert_eq!(new.get_secret_version("db", 1).unwrap().as_deref(), Some("a"));
+        assert_eq!(new.get_secret("api").unwrap().as_deref(), Some("c"));
+        assert!(old.get_secret("api").is_err());
+    }
+}
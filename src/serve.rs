@@ -0,0 +1,242 @@
+//! A small HTTP server streaming drive files to holders of links made with `Drive::proxy_url`.
+
+use std::{
+    io::{ self, BufRead, BufReader, Read, Write },
+    net::{ TcpListener, TcpStream },
+    thread,
+};
+
+use chrono::Utc;
+
+use crate::{ drive::{ self, Drive }, errors::DetaError };
+
+/// Request heads longer than this are rejected.
+const MAX_HEAD: u64 = 16 * 1024;
+
+/// The part of a file requested by a `Range` header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Range {
+    Full,
+    /// The first and last byte, inclusive.
+    Partial(u64, u64),
+    Unsatisfiable,
+}
+
+/// Reads a single `bytes=` range. Malformed headers and multiple ranges get the whole file.
+fn byte_range(header: Option<&str>, size: u64) -> Range {
+    let Some(spec) = header.and_then(|header| header.trim().strip_prefix("bytes=")) else {
+        return Range::Full;
+    };
+    let Some((first, last)) = spec.split_once('-').filter(|_| !spec.contains(',')) else {
+        return Range::Full;
+    };
+    let (first, last) = (first.trim(), last.trim());
+    if first.is_empty() {
+        return match last.parse::<u64>() {
+            Ok(0) => Range::Unsatisfiable,
+            Ok(_) if size == 0 => Range::Unsatisfiable,
+            Ok(suffix) => Range::Partial(size.saturating_sub(suffix), size - 1),
+            Err(_) => Range::Full,
+        };
+    }
+    let Ok(first) = first.parse::<u64>() else {
+        return Range::Full;
+    };
+    let last = match last {
+        "" => u64::MAX,
+        last => match last.parse::<u64>() {
+            Ok(last) if last >= first => last,
+            _ => return Range::Full,
+        },
+    };
+    match first < size {
+        true => Range::Partial(first, last.min(size - 1)),
+        false => Range::Unsatisfiable,
+    }
+}
+
+/// The name of the file a proxy link points to, if it was signed for this drive and has not expired.
+fn verify(drive: &Drive, target: &str) -> Option<String> {
+    let (path, query) = target.split_once('?')?;
+    let (rest, name) = path.rsplit_once('/')?;
+    let name = urlencoding::decode(name).ok()?;
+    let (mut expires, mut signature) = (None, None);
+    for (key, value) in query.split('&').filter_map(|pair| pair.split_once('=')) {
+        match key {
+            "expires" => expires = value.parse::<i64>().ok(),
+            "signature" => signature = Some(value),
+            _ => {},
+        }
+    }
+    let (expires, signature) = (expires?, signature?);
+    let expected = drive::proxy_signature(drive, &name, expires);
+    let differences = expected.bytes().zip(signature.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b));
+    let valid = differences == 0 && expected.len() == signature.len();
+    let current = rest.rsplit('/').next() == Some(drive.name()) && expires >= Utc::now().timestamp();
+    (valid && current).then(|| name.into_owned())
+}
+
+fn write_head(
+    stream: &mut TcpStream, status: &str, headers: &[(&str, String)], length: Option<u64>
+) -> io::Result<()> {
+    write!(stream, "HTTP/1.1 {}\r\nConnection: close\r\n", status)?;
+    for (name, value) in headers {
+        write!(stream, "{}: {}\r\n", name, value)?;
+    }
+    if let Some(length) = length {
+        write!(stream, "Content-Length: {}\r\n", length)?;
+    }
+    stream.write_all(b"\r\n")
+}
+
+fn respond(stream: &mut TcpStream, status: &str, headers: &[(&str, String)], body: &str) -> io::Result<()> {
+    write_head(stream, status, headers, Some(body.len() as u64))?;
+    stream.write_all(body.as_bytes())
+}
+
+fn handle(drive: &Drive, mut stream: TcpStream) -> io::Result<()> {
+    let mut reader = BufReader::new((&stream).take(MAX_HEAD));
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let target = parts.next().unwrap_or_default().to_string();
+    let mut range = None;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return respond(&mut stream, "400 Bad Request", &[], "request head too long\n");
+        }
+        if line.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("range") {
+                range = Some(value.trim().to_string());
+            }
+        }
+    }
+    if method != "GET" && method != "HEAD" {
+        let allow = [("Allow", String::from("GET, HEAD"))];
+        return respond(&mut stream, "405 Method Not Allowed", &allow, "method not allowed\n");
+    }
+    let Some(name) = verify(drive, &target) else {
+        return respond(&mut stream, "403 Forbidden", &[], "link is invalid or expired\n");
+    };
+    let resp = match drive.get(&name) {
+        Ok(resp) => resp,
+        Err(DetaError::NotFound { .. }) => return respond(&mut stream, "404 Not Found", &[], "not found\n"),
+        Err(_) => return respond(&mut stream, "502 Bad Gateway", &[], "drive request failed\n"),
+    };
+    let size = resp.header("Content-Length").and_then(|size| size.parse::<u64>().ok());
+    let mut headers = vec![
+        ("Accept-Ranges", String::from("bytes")),
+        ("Cache-Control", String::from("private")),
+        ("Content-Type", resp.header("Content-Type").unwrap_or("application/octet-stream").to_string()),
+    ];
+    let (status, skip, length) = match size.map(|size| (size, byte_range(range.as_deref(), size))) {
+        None => ("200 OK", 0, None),
+        Some((size, Range::Full)) => ("200 OK", 0, Some(size)),
+        Some((size, Range::Partial(first, last))) => {
+            headers.push(("Content-Range", format!("bytes {}-{}/{}", first, last, size)));
+            ("206 Partial Content", first, Some(last - first + 1))
+        },
+        Some((size, Range::Unsatisfiable)) => {
+            headers.push(("Content-Range", format!("bytes */{}", size)));
+            return respond(&mut stream, "416 Range Not Satisfiable", &headers, "");
+        },
+    };
+    write_head(&mut stream, status, &headers, length)?;
+    if method == "GET" {
+        let mut content = resp.into_reader();
+        io::copy(&mut content.by_ref().take(skip), &mut io::sink())?;
+        io::copy(&mut content.take(length.unwrap_or(u64::MAX)), &mut stream)?;
+    }
+    stream.flush()
+}
+
+/// Serves the files of a drive over HTTP to anyone holding a link made with `Drive::proxy_url`,
+/// until accepting a connection fails.
+///
+/// Every connection is handled on its own thread and answers a single `GET` or `HEAD` request.
+/// Files are streamed from the drive without being buffered, and a single `Range` of bytes
+/// can be requested, so browsers can seek in audio and video. Links signed for another drive
+/// or with another project key, tampered with or expired are answered with 403.
+/// ```rust,no_run
+/// use std::net::TcpListener;
+/// use detalib::{ Deta, drive };
+///
+/// let uploads = Deta::new().drive("uploads");
+/// drive::serve(&uploads, TcpListener::bind("0.0.0.0:8080").unwrap()).unwrap();
+/// ```
+pub fn serve(drive: &Drive, listener: TcpListener) -> Result<(), DetaError> {
+    loop {
+        let (stream, _) = listener.accept()?;
+        let drive = drive.clone();
+        thread::spawn(move || handle(&drive, stream));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "mock")]
+    use crate::mock::MockDeta;
+
+    #[test]
+    fn byte_ranges() {
+        let range = |header: &str| byte_range(Some(header), 100);
+        assert_eq!(byte_range(None, 100), Range::Full);
+        assert_eq!(range("bytes=0-9"), Range::Partial(0, 9));
+        assert_eq!(range("bytes=90-"), Range::Partial(90, 99));
+        assert_eq!(range("bytes=90-500"), Range::Partial(90, 99));
+        assert_eq!(range("bytes=-10"), Range::Partial(90, 99));
+        assert_eq!(range("bytes=-500"), Range::Partial(0, 99));
+        assert_eq!(range("bytes=100-"), Range::Unsatisfiable);
+        assert_eq!(range("bytes=-0"), Range::Unsatisfiable);
+        assert_eq!(range("bytes=5-1"), Range::Full);
+        assert_eq!(range("bytes=0-1,5-6"), Range::Full);
+        assert_eq!(range("items=0-1"), Range::Full);
+        assert_eq!(byte_range(Some("bytes=-1"), 0), Range::Unsatisfiable);
+    }
+
+    #[test]
+    fn verifies_links() {
+        let drive = crate::Deta::from("a_b").drive("uploads");
+        let link = drive.proxy_url("photos/cat 1.png").base_url("https://example.com/files/").to_string();
+        let target = link.strip_prefix("https://example.com").unwrap();
+        assert_eq!(verify(&drive, target).as_deref(), Some("photos/cat 1.png"));
+        assert_eq!(verify(&crate::Deta::from("a_c").drive("uploads"), target), None);
+        assert_eq!(verify(&crate::Deta::from("a_b").drive("other"), target), None);
+        assert_eq!(verify(&drive, &target.replace("cat", "dog")), None);
+        let expires = Utc::now().timestamp() - 1;
+        let signature = drive::proxy_signature(&drive, "a.png", expires);
+        let expired = format!("/uploads/a.png?expires={}&signature={}", expires, signature);
+        assert_eq!(verify(&drive, &expired), None);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn proxy_links_serve_ranges() {
+        let drive = MockDeta::new().drive("uploads");
+        drive.put("a/b.txt", b"hello world", Some("text/plain")).unwrap();
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let server = drive.clone();
+        std::thread::spawn(move || crate::drive::serve(&server, listener));
+        let link = drive.proxy_url("a/b.txt").base_url(&address).to_string();
+        let resp = ureq::get(&link).call().unwrap();
+        assert_eq!((resp.status(), resp.header("Accept-Ranges")), (200, Some("bytes")));
+        assert_eq!(resp.into_string().unwrap(), "hello world");
+        let resp = ureq::get(&link).set("Range", "bytes=6-").call().unwrap();
+        assert_eq!((resp.status(), resp.header("Content-Range")), (206, Some("bytes 6-10/11")));
+        assert_eq!(resp.into_string().unwrap(), "world");
+        let status = |url: &str| match ureq::get(url).set("Range", "bytes=20-").call() {
+            Err(ureq::Error::Status(status, _)) => status,
+            other => panic!("unexpected {:?}", other.map(|resp| resp.status())),
+        };
+        assert_eq!(status(&link), 416);
+        assert_eq!(status(&link.replace("expires=", "expires=1")), 403);
+        assert_eq!(status(&drive.proxy_url("missing").base_url(&address).to_string()), 404);
+    }
+}
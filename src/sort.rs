@@ -0,0 +1,293 @@
+//! Client-side sorting of query results by arbitrary fields.
+//!
+//! Deta only sorts by key, so `Query::walk_sorted` sorts on the client.
+//! Results beyond the memory budget are sorted in runs, spilled to temporary files and merged.
+
+use std::cmp::Ordering;
+#[cfg(feature = "blocking")]
+use std::{
+    collections::BinaryHeap,
+    fs::File,
+    io::{ BufRead, BufReader, BufWriter, Lines, Write },
+    path::PathBuf,
+    sync::atomic::{ AtomicU64, Ordering as AtomicOrdering },
+    vec::IntoIter,
+};
+
+use serde_json::Value;
+
+use crate::canonical::canonicalize;
+#[cfg(feature = "blocking")]
+use crate::errors::DetaError;
+
+/// The number of records `Query::walk_sorted` keeps in memory before spilling to disk.
+pub const DEFAULT_MEMORY_BUDGET: usize = 100_000;
+
+/// Direction of a sort key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
+/// Sort keys: dotted field paths with their direction.
+#[cfg(feature = "blocking")]
+pub (crate) type SortKeys = Vec<(String, Order)>;
+
+fn rank(value: &Value) -> u8 {
+    match value {
+        Value::Null => 0,
+        Value::Bool(_) => 1,
+        Value::Number(_) => 2,
+        Value::String(_) => 3,
+        Value::Array(_) => 4,
+        Value::Object(_) => 5,
+    }
+}
+
+/// Orders values by type first (null, bool, number, string, array, object), then by value.
+/// A missing field sorts like `null`.
+pub (crate) fn compare_values(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+        (Value::Number(a), Value::Number(b)) => match (a.as_i64(), b.as_i64()) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => a.as_f64().unwrap_or_default().total_cmp(&b.as_f64().unwrap_or_default()),
+        },
+        (Value::String(a), Value::String(b)) => a.cmp(b),
+        (Value::Array(_), Value::Array(_)) | (Value::Object(_), Value::Object(_)) => {
+            canonicalize(a).cmp(&canonicalize(b))
+        },
+        _ => rank(a).cmp(&rank(b)),
+    }
+}
+
+pub (crate) fn compare(keys: &[(String, Order)], a: &Value, b: &Value) -> Ordering {
+    for (field, order) in keys {
+        let lookup = |item| field.split('.').try_fold(item, |value: &Value, part| value.get(part));
+        let ordering = compare_values(lookup(a).unwrap_or(&Value::Null), lookup(b).unwrap_or(&Value::Null));
+        let ordering = match order {
+            Order::Asc => ordering,
+            Order::Desc => ordering.reverse(),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// A heap entry ordered by the sort keys, then by arrival so earlier items win ties.
+#[cfg(feature = "blocking")]
+struct Ranked<'a> {
+    keys: &'a [(String, Order)],
+    index: usize,
+    value: Value,
+}
+
+#[cfg(feature = "blocking")]
+impl Ord for Ranked<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare(self.keys, &self.value, &other.value).then(self.index.cmp(&other.index))
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl PartialOrd for Ranked<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl PartialEq for Ranked<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl Eq for Ranked<'_> {}
+
+/// The first `n` items in sort order, holding no more than `n + 1` items at a time.
+#[cfg(feature = "blocking")]
+pub (crate) fn top_n<I>(keys: &[(String, Order)], items: I, n: usize) -> Result<Vec<Value>, DetaError>
+    where I: Iterator<Item = Result<Value, DetaError>>
+{
+    let mut heap = BinaryHeap::with_capacity(n + 1);
+    for (index, value) in items.enumerate() {
+        heap.push(Ranked { keys, index, value: value? });
+        if heap.len() > n {
+            heap.pop();
+        }
+    }
+    Ok(heap.into_sorted_vec().into_iter().map(|ranked| ranked.value).collect())
+}
+
+#[cfg(feature = "blocking")]
+static RUN_ID: AtomicU64 = AtomicU64::new(0);
+
+/// A sorted run spilled to a temporary file, removed when dropped.
+#[cfg(feature = "blocking")]
+struct Run {
+    path: PathBuf,
+    lines: Lines<BufReader<File>>,
+}
+
+#[cfg(feature = "blocking")]
+impl Run {
+
+    fn spill(items: &[Value]) -> Result<Run, DetaError> {
+        let id = RUN_ID.fetch_add(1, AtomicOrdering::Relaxed);
+        let path = std::env::temp_dir().join(format!("detalib-sort-{}-{}.jsonl", std::process::id(), id));
+        let mut writer = BufWriter::new(File::create(&path)?);
+        for item in items {
+            serde_json::to_writer(&mut writer, item)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        let lines = BufReader::new(File::open(&path)?).lines();
+        Ok(Run { path, lines })
+    }
+
+    fn next(&mut self) -> Option<Result<Value, DetaError>> {
+        self.lines.next().map(|line| Ok(serde_json::from_str(&line?)?))
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl Drop for Run {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(feature = "blocking")]
+enum Source {
+    Memory(IntoIter<Value>),
+    Merge { runs: Vec<Run>, heads: Vec<Option<Value>> },
+}
+
+/// Iterator over sorted query results, returned by `Query::walk_sorted`.
+#[cfg(feature = "blocking")]
+pub struct SortedIter {
+    keys: SortKeys,
+    source: Source,
+}
+
+#[cfg(feature = "blocking")]
+impl SortedIter {
+
+    /// Sorts the items, spilling sorted runs of `budget` items to disk when there are more.
+    pub (crate) fn new<I>(keys: SortKeys, items: I, budget: usize) -> Result<SortedIter, DetaError>
+        where I: Iterator<Item = Result<Value, DetaError>>
+    {
+        let budget = budget.max(1);
+        let mut buffer = Vec::new();
+        let mut runs = Vec::new();
+        for item in items {
+            buffer.push(item?);
+            if buffer.len() >= budget {
+                buffer.sort_by(|a, b| compare(&keys, a, b));
+                runs.push(Run::spill(&buffer)?);
+                buffer.clear();
+            }
+        }
+        buffer.sort_by(|a, b| compare(&keys, a, b));
+        if runs.is_empty() {
+            return Ok(SortedIter { keys, source: Source::Memory(buffer.into_iter()) });
+        }
+        if !buffer.is_empty() {
+            runs.push(Run::spill(&buffer)?);
+        }
+        let heads = runs.iter_mut().map(Run::next).map(Option::transpose).collect::<Result<_, _>>()?;
+        Ok(SortedIter { keys, source: Source::Merge { runs, heads } })
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl Iterator for SortedIter {
+    type Item = Result<Value, DetaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (runs, heads) = match &mut self.source {
+            Source::Memory(items) => return items.next().map(Ok),
+            Source::Merge { runs, heads } => (runs, heads),
+        };
+        // Earlier runs win ties, which keeps the sort stable.
+        let mut smallest: Option<usize> = None;
+        for (i, head) in heads.iter().enumerate() {
+            let Some(head) = head else { continue };
+            let smaller = smallest.and_then(|s| heads[s].as_ref())
+                .is_none_or(|current| compare(&self.keys, head, current) == Ordering::Less);
+            if smaller {
+                smallest = Some(i);
+            }
+        }
+        let i = smallest?;
+        let next = runs[i].next().transpose();
+        match next {
+            Ok(next) => std::mem::replace(&mut heads[i], next).map(Ok),
+            Err(e) => {
+                heads[i] = None;
+                Some(Err(e))
+            },
+        }
+    }
+}
+
+#[cfg(all(test, feature = "blocking"))]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn keys() -> SortKeys {
+        vec![("country".into(), Order::Asc), ("age".into(), Order::Desc)]
+    }
+
+    fn people() -> Vec<Value> {
+        vec![
+            json!({ "key": "a", "country": "us", "age": 20 }),
+            json!({ "key": "b", "country": "de", "age": 30 }),
+            json!({ "key": "c", "country": "us", "age": 40 }),
+            json!({ "key": "d", "age": 50 }),
+            json!({ "key": "e", "country": "de", "age": 30.5 }),
+        ]
+    }
+
+    fn sorted(budget: usize) -> Vec<Value> {
+        let items = people().into_iter().map(Ok);
+        SortedIter::new(keys(), items, budget).unwrap()
+            .map(|item| item.unwrap()["key"].clone())
+            .collect()
+    }
+
+    #[test]
+    fn sorts_by_several_fields() {
+        assert_eq!(sorted(100), vec!["d", "e", "b", "c", "a"]);
+    }
+
+    #[test]
+    fn merges_spilled_runs() {
+        assert_eq!(sorted(2), sorted(100));
+        assert_eq!(sorted(1), sorted(100));
+    }
+
+    #[test]
+    fn top_n_keeps_the_best() {
+        let top = top_n(&keys(), people().into_iter().map(Ok), 3).unwrap();
+        assert_eq!(top.iter().map(|item| item["key"].clone()).collect::<Vec<_>>(), vec!["d", "e", "b"]);
+        assert!(top_n(&keys(), people().into_iter().map(Ok), 0).unwrap().is_empty());
+    }
+
+    #[test]
+    fn ties_keep_input_order() {
+        let items = (0..5).map(|i| Ok(json!({ "key": i, "group": 1 })));
+        let keys = vec![("group".to_string(), Order::Asc)];
+        let order = SortedIter::new(keys, items, 2).unwrap()
+            .map(|item| item.unwrap()["key"].clone())
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec![0, 1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,252 @@
+use serde_json::{ Map, Number, Value };
+
+use crate::errors::DetaError;
+
+/// The parts of a Deta query extracted from a SQL-like string.
+#[derive(Debug, Default, PartialEq)]
+pub (crate) struct Parsed {
+    pub(crate) groups: Vec<Map<String, Value>>,
+    pub(crate) limit: Option<u16>,
+    pub(crate) desc: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Literal(Value),
+    Symbol(String),
+}
+
+fn invalid(msg: impl Into<String>) -> DetaError {
+    DetaError::PayloadError { msg: format!("invalid SQL: {}", msg.into()) }
+}
+
+fn tokenize(sql: &str) -> Result<Vec<Token>, DetaError> {
+    let mut tokens = Vec::new();
+    let chars = sql.chars().collect::<Vec<char>>();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() || c == ',' {
+            i += 1;
+        } else if c == '\'' {
+            let mut s = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(invalid("unterminated string literal")),
+                    Some('\'') if chars.get(i + 1) == Some(&'\'') => { s.push('\''); i += 2; },
+                    Some('\'') => { i += 1; break; },
+                    Some(c) => { s.push(*c); i += 1; },
+                }
+            }
+            tokens.push(Token::Literal(Value::String(s)));
+        } else if c.is_ascii_digit() || (c == '-' && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit())) {
+            let start = i;
+            i += 1;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text = chars[start..i].iter().collect::<String>();
+            let number = match text.parse::<i64>() {
+                Ok(n) => Number::from(n),
+                Err(_) => text.parse::<f64>().ok()
+                    .and_then(Number::from_f64)
+                    .ok_or_else(|| invalid(format!("bad number `{}`", text)))?,
+            };
+            tokens.push(Token::Literal(Value::Number(number)));
+        } else if c.is_alphanumeric() || c == '_' || c == '"' {
+            let quoted = c == '"';
+            let start = if quoted { i + 1 } else { i };
+            i = start;
+            while i < chars.len() && (if quoted { chars[i] != '"' } else {
+                chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.'
+            }) {
+                i += 1;
+            }
+            let word = chars[start..i].iter().collect::<String>();
+            if quoted {
+                i += 1;
+            }
+            tokens.push(match word.to_lowercase().as_str() {
+                "true" if !quoted => Token::Literal(Value::Bool(true)),
+                "false" if !quoted => Token::Literal(Value::Bool(false)),
+                "null" if !quoted => Token::Literal(Value::Null),
+                _ => Token::Word(word),
+            });
+        } else {
+            let two = chars[i..chars.len().min(i + 2)].iter().collect::<String>();
+            let symbol = match two.as_str() {
+                ">=" | "<=" | "!=" | "<>" => two,
+                _ if "=<>".contains(c) => c.to_string(),
+                _ => return Err(invalid(format!("unexpected character `{}`", c))),
+            };
+            i += symbol.len();
+            tokens.push(Token::Symbol(symbol));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.tokens.get(self.pos), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek_keyword(keyword);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), DetaError> {
+        if self.keyword(keyword) { Ok(()) } else { Err(invalid(format!("expected `{}`", keyword))) }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn literal(&mut self) -> Result<Value, DetaError> {
+        match self.next() {
+            Some(Token::Literal(v)) => Ok(v),
+            _ => Err(invalid("expected a literal value")),
+        }
+    }
+
+    fn condition(&mut self, group: &mut Map<String, Value>) -> Result<(), DetaError> {
+        let field = match self.next() {
+            Some(Token::Word(w)) => w,
+            _ => return Err(invalid("expected a field name")),
+        };
+        let negated = self.keyword("NOT");
+        if self.keyword("LIKE") {
+            let pattern = match self.literal()? {
+                Value::String(s) => s,
+                _ => return Err(invalid("LIKE expects a string pattern")),
+            };
+            let inner = pattern.trim_matches('%');
+            if inner.contains('%') || inner.contains('_') {
+                return Err(invalid("only 'x%', '%x%' and exact LIKE patterns are supported"));
+            }
+            let (op, value) = match (pattern.starts_with('%'), pattern.ends_with('%') && pattern.len() > 1) {
+                (false, true) if !negated => ("?pfx", inner),
+                (true, true) => (if negated { "?not_contains" } else { "?contains" }, inner),
+                (false, false) => (if negated { "?ne" } else { "" }, inner),
+                _ => return Err(invalid("unsupported LIKE pattern")),
+            };
+            group.insert(format!("{}{}", field, op), Value::from(value));
+            return Ok(());
+        }
+        if negated {
+            return Err(invalid("NOT is only supported before LIKE"));
+        }
+        if self.keyword("BETWEEN") {
+            let start = self.literal()?;
+            self.expect_keyword("AND")?;
+            let end = self.literal()?;
+            group.insert(format!("{}?r", field), Value::Array(vec![start, end]));
+            return Ok(());
+        }
+        let op = match self.next() {
+            Some(Token::Symbol(s)) => match s.as_str() {
+                "=" => "",
+                "!=" | "<>" => "?ne",
+                ">" => "?gt",
+                ">=" => "?gte",
+                "<" => "?lt",
+                "<=" => "?lte",
+                _ => return Err(invalid(format!("unknown operator `{}`", s))),
+            },
+            _ => return Err(invalid("expected an operator")),
+        };
+        group.insert(format!("{}{}", field, op), self.literal()?);
+        Ok(())
+    }
+}
+
+/// Parses a small SQL subset: `[WHERE cond [AND|OR cond]...] [ORDER BY key [ASC|DESC]] [LIMIT n]`.
+pub (crate) fn parse(sql: &str) -> Result<Parsed, DetaError> {
+    let mut parser = Parser { tokens: tokenize(sql)?, pos: 0 };
+    let mut parsed = Parsed::default();
+    if parser.keyword("WHERE") {
+        let mut group = Map::new();
+        loop {
+            parser.condition(&mut group)?;
+            if parser.keyword("AND") {
+                continue;
+            }
+            if parser.keyword("OR") {
+                parsed.groups.push(std::mem::take(&mut group));
+                continue;
+            }
+            break;
+        }
+        parsed.groups.push(group);
+    }
+    if parser.keyword("ORDER") {
+        parser.expect_keyword("BY")?;
+        parser.expect_keyword("key")?;
+        parsed.desc = parser.keyword("DESC");
+        if !parsed.desc {
+            parser.keyword("ASC");
+        }
+    }
+    if parser.keyword("LIMIT") {
+        parsed.limit = match parser.literal()? {
+            Value::Number(n) => n.as_u64().and_then(|n| u16::try_from(n).ok()),
+            _ => None,
+        };
+        if parsed.limit.is_none() {
+            return Err(invalid("LIMIT expects a positive integer"));
+        }
+    }
+    if parser.pos < parser.tokens.len() {
+        return Err(invalid(format!("unexpected token {:?}", parser.tokens[parser.pos])));
+    }
+    Ok(parsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::parse;
+
+    #[test]
+    fn parses_where_and_limit() {
+        let parsed = parse("WHERE age > 18 AND name LIKE 'Jo%' LIMIT 50").unwrap();
+        assert_eq!(parsed.limit, Some(50));
+        assert_eq!(json!(parsed.groups), json!([{ "age?gt": 18, "name?pfx": "Jo" }]));
+    }
+
+    #[test]
+    fn parses_or_groups() {
+        let parsed = parse(
+            "where tags LIKE '%rust%' or score BETWEEN 1 and 5 and active = true order by key desc"
+        ).unwrap();
+        assert!(parsed.desc);
+        assert_eq!(
+            json!(parsed.groups),
+            json!([{ "tags?contains": "rust" }, { "score?r": [1, 5], "active": true }])
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_syntax() {
+        assert!(parse("WHERE (age > 1)").is_err());
+        assert!(parse("WHERE name LIKE 'J_n'").is_err());
+        assert!(parse("LIMIT -1").is_err());
+        assert!(parse("WHERE name = 'unterminated").is_err());
+    }
+}
@@ -0,0 +1,196 @@
+use std::{ collections::BTreeMap, path::Path };
+
+use rusqlite::{ Connection, types::Value as SqlValue };
+use serde_json::{ Map, Value };
+
+use crate::{ base::Base, errors::DetaError };
+
+const SQLITE_BATCH: usize = 1000;
+
+fn sqlite_err(e: rusqlite::Error) -> DetaError {
+    DetaError::PayloadError { msg: e.to_string() }
+}
+
+fn quote(ident: &str) -> String {
+    format!("\"{}\"", ident.replace('"', "\"\""))
+}
+
+/// Flattens nested objects into `parent.child` columns, keeping arrays as JSON text.
+fn flatten(prefix: &str, map: Map<String, Value>, out: &mut BTreeMap<String, Value>) {
+    for (field, value) in map {
+        let column = if prefix.is_empty() { field } else { format!("{}.{}", prefix, field) };
+        match value {
+            Value::Object(inner) if !inner.is_empty() => flatten(&column, inner, out),
+            value => { out.insert(column, value); },
+        }
+    }
+}
+
+fn column_type(values: &[Option<&Value>]) -> &'static str {
+    let present = values.iter().flatten().filter(|v| !v.is_null());
+    let mut kind = "INTEGER";
+    for value in present {
+        kind = match (kind, value) {
+            ("INTEGER", Value::Bool(_)) => "INTEGER",
+            ("INTEGER", Value::Number(n)) if n.is_i64() || n.is_u64() => "INTEGER",
+            ("INTEGER" | "REAL", Value::Number(_)) => "REAL",
+            _ => return "TEXT",
+        };
+    }
+    kind
+}
+
+fn to_sql(value: Option<&Value>) -> SqlValue {
+    match value {
+        None | Some(Value::Null) => SqlValue::Null,
+        Some(Value::Bool(b)) => SqlValue::Integer(*b as i64),
+        Some(Value::Number(n)) => match n.as_i64() {
+            Some(i) => SqlValue::Integer(i),
+            None => SqlValue::Real(n.as_f64().unwrap_or_default()),
+        },
+        Some(Value::String(s)) => SqlValue::Text(s.clone()),
+        Some(other) => SqlValue::Text(other.to_string()),
+    }
+}
+
+fn row(item: Value) -> BTreeMap<String, Value> {
+    let mut row = BTreeMap::new();
+    if let Value::Object(map) = item {
+        flatten("", map, &mut row);
+    }
+    row
+}
+
+impl Base {
+
+    /// Export every record in the base into a table of a local SQLite database.
+    /// 
+    /// Records are streamed in batches of 1000, so only one batch is held in memory at a time.
+    /// Column types are inferred from the first batch a column appears in, nested objects
+    /// are flattened into `parent.child` columns and arrays are stored as JSON text.
+    /// An existing table with the same name is replaced.
+    pub fn export_sqlite<P: AsRef<Path>>(&self, path: P, table: &str) -> Result<usize, DetaError> {
+        let mut conn = Connection::open(path).map_err(sqlite_err)?;
+        self.write_sqlite(&mut conn, table)
+    }
+
+    fn write_sqlite(&self, conn: &mut Connection, table: &str) -> Result<usize, DetaError> {
+        let tx = conn.transaction().map_err(sqlite_err)?;
+        tx.execute(&format!("DROP TABLE IF EXISTS {}", quote(table)), []).map_err(sqlite_err)?;
+        tx.execute(
+            &format!("CREATE TABLE {} (\"key\" TEXT PRIMARY KEY)", quote(table)), []
+        ).map_err(sqlite_err)?;
+        let mut columns = vec![String::from("key")];
+        let mut items = self.query().iter();
+        let mut count = 0;
+        loop {
+            let rows = items.by_ref()
+                .take(SQLITE_BATCH)
+                .map(|item| item.map(row))
+                .collect::<Result<Vec<_>, DetaError>>()?;
+            if rows.is_empty() {
+                break;
+            }
+            for row in rows.iter() {
+                for column in row.keys() {
+                    if columns.contains(column) {
+                        continue;
+                    }
+                    let values = rows.iter().map(|row| row.get(column)).collect::<Vec<_>>();
+                    let sql = format!(
+                        "ALTER TABLE {} ADD COLUMN {} {}", quote(table), quote(column), column_type(&values)
+                    );
+                    tx.execute(&sql, []).map_err(sqlite_err)?;
+                    columns.push(column.clone());
+                }
+            }
+            let sql = format!(
+                "INSERT INTO {} ({}) VALUES ({})",
+                quote(table),
+                columns.iter().map(|c| quote(c)).collect::<Vec<_>>().join(", "),
+                vec!["?"; columns.len()].join(", ")
+            );
+            let mut stmt = tx.prepare(&sql).map_err(sqlite_err)?;
+            for row in rows.iter() {
+                let params = columns.iter().map(|c| to_sql(row.get(c))).collect::<Vec<_>>();
+                stmt.execute(rusqlite::params_from_iter(params)).map_err(sqlite_err)?;
+            }
+            count += rows.len();
+        }
+        tx.commit().map_err(sqlite_err)?;
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn flattens_nested_objects() {
+        let Value::Object(map) = json!({
+            "key": "a",
+            "profile": { "city": "Oslo", "geo": { "lat": 59.9 }, "extra": {} },
+            "tags": ["x", "y"],
+        }) else { unreachable!() };
+        let mut row = BTreeMap::new();
+        flatten("", map, &mut row);
+        assert_eq!(row, BTreeMap::from([
+            (String::from("key"), json!("a")),
+            (String::from("profile.city"), json!("Oslo")),
+            (String::from("profile.extra"), json!({})),
+            (String::from("profile.geo.lat"), json!(59.9)),
+            (String::from("tags"), json!(["x", "y"])),
+        ]));
+    }
+
+    #[test]
+    fn infers_column_types() {
+        let kind = |values: &[Value]| column_type(&values.iter().map(Some).collect::<Vec<_>>());
+        assert_eq!(kind(&[json!(1), json!(true), Value::Null]), "INTEGER");
+        assert_eq!(kind(&[json!(1), json!(2.5)]), "REAL");
+        assert_eq!(kind(&[json!(2.5), json!(1)]), "REAL");
+        assert_eq!(kind(&[json!(1), json!("high")]), "TEXT");
+        assert_eq!(kind(&[json!(1.5), json!([1])]), "TEXT");
+        assert_eq!(column_type(&[None, Some(&json!(3))]), "INTEGER");
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn exports_into_a_table() {
+        let base = crate::mock::MockDeta::new().base("users");
+        base.put(vec![
+            json!({ "key": "a", "score": 1, "profile": { "city": "Oslo" }, "tags": ["x"] }),
+            json!({ "key": "b", "score": 2.5, "active": true }),
+        ]).unwrap();
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute("CREATE TABLE \"users\" (\"stale\" TEXT)", []).unwrap();
+        assert_eq!(base.write_sqlite(&mut conn, "users").unwrap(), 2);
+
+        let columns = conn.prepare("SELECT name, type FROM pragma_table_info('users')").unwrap()
+            .query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(columns, [
+            ("key", "TEXT"), ("profile.city", "TEXT"), ("score", "REAL"),
+            ("tags", "TEXT"), ("active", "INTEGER"),
+        ].map(|(name, kind)| (String::from(name), String::from(kind))));
+        let rows = conn.prepare("SELECT key, score, \"profile.city\", tags, active FROM users ORDER BY key")
+            .unwrap()
+            .query_map([], |row| Ok(format!(
+                "{}|{:?}|{:?}|{:?}|{:?}",
+                row.get::<_, String>(0)?, row.get::<_, f64>(1)?, row.get::<_, Option<String>>(2)?,
+                row.get::<_, Option<String>>(3)?, row.get::<_, Option<i64>>(4)?
+            )))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(rows, [
+            r#"a|1.0|Some("Oslo")|Some("[\"x\"]")|None"#,
+            r#"b|2.5|None|None|Some(1)"#,
+        ]);
+    }
+}
@@ -0,0 +1,74 @@
+use std::{ collections::VecDeque, thread, time::Duration };
+
+use serde_json::{ json, Value };
+
+use crate::{ base::Base, errors::DetaError };
+
+/// An endless iterator over records inserted into a base.
+/// 
+/// Records are detected by polling for keys greater than the last seen key,
+/// so the base must use lexicographically increasing (time-ordered) keys.
+pub struct Tail {
+    base: Base,
+    interval: Duration,
+    last: Option<String>,
+    buffer: VecDeque<Value>,
+}
+
+impl Tail {
+
+    pub (crate) fn new(base: Base, interval: Duration) -> Tail {
+        Tail {
+            base,
+            interval,
+            last: None,
+            buffer: VecDeque::new(),
+        }
+    }
+
+    /// Starts tailing after the given key instead of after the newest record.
+    pub fn after(mut self, key: &str) -> Self {
+        self.last = Some(key.to_string());
+        self
+    }
+
+    fn latest_key(&self) -> Result<String, DetaError> {
+        let page = self.base.query().sort(true).limit(1).run()?;
+        Ok(page.items.first().and_then(|item| item["key"].as_str()).unwrap_or_default().to_string())
+    }
+
+    fn poll(&mut self) -> Result<(), DetaError> {
+        let last = match &self.last {
+            Some(last) => last.clone(),
+            None => {
+                let last = self.latest_key()?;
+                self.last = Some(last.clone());
+                last
+            }
+        };
+        let items = self.base.query().greater_than("key", json!(last)).walk()?;
+        if let Some(key) = items.last().and_then(|item| item["key"].as_str()) {
+            self.last = Some(key.to_string());
+        }
+        self.buffer.extend(items);
+        Ok(())
+    }
+}
+
+impl Iterator for Tail {
+    type Item = Result<Value, DetaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.buffer.pop_front() {
+                return Some(Ok(item));
+            }
+            if let Err(e) = self.poll() {
+                return Some(Err(e));
+            }
+            if self.buffer.is_empty() {
+                thread::sleep(self.interval);
+            }
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Request instrumentation with the `tracing` crate, enabled with the `tracing` feature.
+//!
+//! Every request gets a `deta.request` span with the method, path and redacted API key.
+//! When the request finishes, an event records the status, latency and retry count.
+//! Without the feature, `RequestSpan` compiles to nothing.
+
+#[cfg(all(feature = "tracing", not(target_arch = "wasm32")))]
+use std::time::Instant;
+#[cfg(all(feature = "tracing", target_arch = "wasm32"))]
+use web_time::Instant;
+
+/// Keeps the project id and hides the secret of a project key.
+#[cfg(feature = "tracing")]
+fn redact(project_key: &str) -> String {
+    match project_key.split_once('_') {
+        Some((id, _)) => format!("{}_***", id),
+        None => String::from("***"),
+    }
+}
+
+/// The path of a request URL without scheme and host, e.g. `/v1/{project}/users/items/k`.
+#[cfg(feature = "tracing")]
+fn path(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    rest.find('/').map_or("/", |start| &rest[start..])
+}
+
+#[cfg(feature = "tracing")]
+pub (crate) struct RequestSpan {
+    span: tracing::Span,
+    started: Instant,
+}
+
+#[cfg(not(feature = "tracing"))]
+pub (crate) struct RequestSpan;
+
+impl RequestSpan {
+
+    #[cfg(feature = "tracing")]
+    pub (crate) fn start(method: &str, url: &str, project_key: &str) -> RequestSpan {
+        let span = tracing::info_span!(
+            "deta.request",
+            method = %method.to_ascii_uppercase(),
+            path = %path(url),
+            api_key = %redact(project_key),
+        );
+        RequestSpan { span, started: Instant::now() }
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub (crate) fn start(_method: &str, _url: &str, _project_key: &str) -> RequestSpan {
+        RequestSpan
+    }
+
+    /// Records the outcome of the request. `status` is `None` for transport errors.
+    #[cfg(feature = "tracing")]
+    pub (crate) fn finish(&self, status: Option<u16>, retries: u32) {
+        let latency_ms = self.started.elapsed().as_millis() as u64;
+        match status {
+            Some(status) if status < 400 => tracing::debug!(
+                parent: &self.span, status, latency_ms, retries, "deta request finished"
+            ),
+            Some(status) => tracing::warn!(
+                parent: &self.span, status, latency_ms, retries, "deta request failed"
+            ),
+            None => tracing::warn!(
+                parent: &self.span, latency_ms, retries, "deta request failed without a response"
+            ),
+        }
+    }
+
+    #[cfg(not(feature = "tracing"))]
+    pub (crate) fn finish(&self, _status: Option<u16>, _retries: u32) {}
+}
+
+#[cfg(all(test, feature = "tracing"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hides_the_secret() {
+        assert_eq!(redact("a0abcyxz_aSecretValue"), "a0abcyxz_***");
+        assert_eq!(redact("nokey"), "***");
+        assert_eq!(path("https://database.deta.sh/v1/p/users/items/k"), "/v1/p/users/items/k");
+    }
+}
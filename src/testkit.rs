@@ -0,0 +1,43 @@
+//! Helpers for setting up and tearing down bases in integration tests.
+
+use std::path::Path;
+
+use serde_json::Value;
+
+use crate::{ base::{ Base, ClearConfirmation }, errors::DetaError };
+
+const WORKERS: usize = 8;
+
+/// Put every record of a JSON fixture into the base and return how many were stored.
+/// 
+/// The fixture is either an array of records or an object mapping keys to records.
+pub fn seed(base: &Base, fixtures_json: &str) -> Result<usize, DetaError> {
+    let records = match serde_json::from_str::<Value>(fixtures_json)? {
+        Value::Array(records) => records,
+        Value::Object(map) => map.into_iter()
+            .map(|(key, mut record)| {
+                if let Value::Object(fields) = &mut record {
+                    fields.entry("key").or_insert(Value::from(key));
+                }
+                record
+            })
+            .collect(),
+        _ => return Err(
+            DetaError::PayloadError { msg: "fixtures must be a JSON array or object".to_string() }
+        ),
+    };
+    for chunk in records.chunks(25) {
+        base.put(chunk.iter().collect())?;
+    }
+    Ok(records.len())
+}
+
+/// Same as `seed`, reading the fixture from a file.
+pub fn seed_file<P: AsRef<Path>>(base: &Base, path: P) -> Result<usize, DetaError> {
+    seed(base, &std::fs::read_to_string(path)?)
+}
+
+/// Delete every record in the base using parallel requests and return how many were deleted.
+pub fn truncate(base: &Base) -> Result<usize, DetaError> {
+    base.clear(ClearConfirmation::new(base.name()), WORKERS)
+}
@@ -0,0 +1,215 @@
+//! Product analytics: events batched in memory and flushed to a base or a drive.
+
+use std::{
+    mem,
+    sync::{ Arc, Condvar, Mutex, MutexGuard },
+    thread,
+    time::Duration,
+};
+
+use serde::Serialize;
+use serde_json::{ json, Value };
+
+use crate::{ base::Base, drive::Drive, errors::DetaError, keys::Key };
+
+struct Config {
+    base: Option<Base>,
+    drive: Option<(Drive, String)>,
+    max_batch: usize,
+    interval: Duration,
+}
+
+struct Shared {
+    config: Mutex<Config>,
+    buffer: Mutex<Vec<Value>>,
+    stopped: Mutex<bool>,
+    stop: Condvar,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Shared {
+
+    fn flush(&self) -> Result<usize, DetaError> {
+        let events = mem::take(&mut *lock(&self.buffer));
+        if events.is_empty() {
+            return Ok(0);
+        }
+        let written = self.write(&events);
+        if written.is_err() {
+            // keys are stable, so events written to one sink before the failure are not duplicated
+            let mut buffer = lock(&self.buffer);
+            let newer = mem::replace(&mut *buffer, events);
+            buffer.extend(newer);
+        }
+        written
+    }
+
+    fn write(&self, events: &[Value]) -> Result<usize, DetaError> {
+        let config = lock(&self.config);
+        if let Some(base) = &config.base {
+            base.put_many(events)?;
+        }
+        if let Some((drive, prefix)) = &config.drive {
+            let mut lines = Vec::new();
+            for event in events {
+                serde_json::to_writer(&mut lines, event)?;
+                lines.push(b'\n');
+            }
+            let first = events[0]["key"].as_str().unwrap_or_default();
+            drive.put(&format!("{}{}.jsonl", prefix, first), &lines, Some("application/x-ndjson"))?;
+        }
+        Ok(events.len())
+    }
+}
+
+/// Records analytics events and writes them in batches.
+///
+/// Events are stored as `{ "key": <ULID>, "name": ..., "props": ..., "at": <unix millis> }`,
+/// in a base, as JSON Lines files on a drive, or both. A batch is written once it reaches
+/// `max_batch` events, every `flush_interval` by a background thread, and when the tracker
+/// is dropped. A failed write keeps the events for the next flush.
+/// ```rust,no_run
+/// use std::time::Duration;
+/// use detalib::{ Deta, tracker::Tracker };
+/// use serde_json::json;
+///
+/// let deta = Deta::new();
+/// let tracker = Tracker::new(deta.base("events"))
+///     .drive(deta.drive("analytics"), "events/")
+///     .max_batch(50)
+///     .flush_interval(Duration::from_secs(5));
+/// tracker.event("signup", json!({ "plan": "pro" }));
+/// ```
+pub struct Tracker {
+    shared: Arc<Shared>,
+}
+
+impl Tracker {
+
+    /// Writes events to `base`.
+    pub fn new(base: Base) -> Tracker {
+        Tracker::start(Some(base), None)
+    }
+
+    /// Writes events only as JSON Lines files on `drive`, named `{prefix}{first event key}.jsonl`.
+    pub fn for_drive(drive: Drive, prefix: &str) -> Tracker {
+        Tracker::start(None, Some((drive, prefix.to_string())))
+    }
+
+    fn start(base: Option<Base>, drive: Option<(Drive, String)>) -> Tracker {
+        let config = Config { base, drive, max_batch: 100, interval: Duration::from_secs(10) };
+        let shared = Arc::new(Shared {
+            config: Mutex::new(config),
+            buffer: Mutex::new(Vec::new()),
+            stopped: Mutex::new(false),
+            stop: Condvar::new(),
+        });
+        let flusher = shared.clone();
+        thread::spawn(move || {
+            let mut stopped = lock(&flusher.stopped);
+            loop {
+                let interval = lock(&flusher.config).interval;
+                stopped = flusher.stop.wait_timeout(stopped, interval).unwrap_or_else(|e| e.into_inner()).0;
+                if *stopped {
+                    return;
+                }
+                drop(stopped);
+                let _ = flusher.flush();
+                stopped = lock(&flusher.stopped);
+            }
+        });
+        Tracker { shared }
+    }
+
+    /// Also writes every batch as a JSON Lines file on `drive`, see `for_drive`.
+    pub fn drive(self, drive: Drive, prefix: &str) -> Self {
+        lock(&self.shared.config).drive = Some((drive, prefix.to_string()));
+        self
+    }
+
+    /// Sets how many events are buffered before they are written. Defaults to 100.
+    pub fn max_batch(self, max_batch: usize) -> Self {
+        lock(&self.shared.config).max_batch = max_batch.max(1);
+        self
+    }
+
+    /// Sets how often buffered events are written. Defaults to 10 seconds.
+    ///
+    /// Takes effect after the current interval.
+    pub fn flush_interval(self, interval: Duration) -> Self {
+        lock(&self.shared.config).interval = interval;
+        self
+    }
+
+    /// Records an event, writing the batch if it is full.
+    ///
+    /// Write errors are not reported here, the events are retried with the next flush.
+    pub fn event<T: Serialize>(&self, name: &str, props: T) {
+        let event = json!({
+            "key": Key::ulid(),
+            "name": name,
+            "props": serde_json::to_value(props).unwrap_or(Value::Null),
+            "at": chrono::Utc::now().timestamp_millis(),
+        });
+        let full = {
+            let mut buffer = lock(&self.shared.buffer);
+            buffer.push(event);
+            buffer.len() >= lock(&self.shared.config).max_batch
+        };
+        if full {
+            let _ = self.shared.flush();
+        }
+    }
+
+    /// Number of events waiting to be written.
+    pub fn pending(&self) -> usize {
+        lock(&self.shared.buffer).len()
+    }
+
+    /// Writes the buffered events now. Returns how many were written.
+    pub fn flush(&self) -> Result<usize, DetaError> {
+        self.shared.flush()
+    }
+}
+
+impl Drop for Tracker {
+    fn drop(&mut self) {
+        *lock(&self.shared.stopped) = true;
+        self.shared.stop.notify_all();
+        let _ = self.shared.flush();
+    }
+}
+
+#[cfg(all(test, feature = "mock"))]
+mod tests {
+    use std::io::Read;
+
+    use serde_json::json;
+
+    use super::Tracker;
+    use crate::mock::MockDeta;
+
+    #[test]
+    fn tracker_flushes_batches() {
+        let deta = MockDeta::new();
+        let (events, analytics) = (deta.base("events"), deta.drive("analytics"));
+        let tracker = Tracker::new(events.clone()).drive(analytics.clone(), "events/").max_batch(3);
+        (0..4).for_each(|n| tracker.event("click", json!({ "n": n })));
+        assert_eq!(tracker.pending(), 1);
+        assert_eq!(events.query().count().unwrap(), 3);
+        assert_eq!(analytics.walk(Some("events/")).unwrap().len(), 1);
+        drop(tracker);
+        let clicks = events.query().equals("name", json!("click")).walk().unwrap();
+        let numbers = clicks.iter().map(|event| event["props"]["n"].as_i64().unwrap()).collect::<Vec<_>>();
+        assert_eq!(numbers, [0, 1, 2, 3]);
+        let mut lines = String::new();
+        let files = analytics.walk(Some("events/")).unwrap();
+        for name in &files {
+            analytics.get(name).unwrap().into_reader().read_to_string(&mut lines).unwrap();
+        }
+        assert_eq!((files.len(), lines.lines().count()), (2, 4));
+    }
+}
@@ -0,0 +1,172 @@
+//! The HTTP layer under `Deta`, replaceable to route requests through proxies or test doubles.
+//!
+//! With the default `blocking` feature, blocking requests go through a `Transport`, backed by
+//! `ureq`. With the `tokio` or `wasm` feature, async requests go through an `AsyncTransport`,
+//! backed by `reqwest` by default. Either can be enabled without the other.
+//! Retries, rate limiting, budgets and write queues are applied by the client on top of
+//! the transport, so a custom transport only needs to perform single requests.
+
+#[cfg(any(feature = "tokio", feature = "wasm"))]
+use std::{ future::Future, pin::Pin };
+
+/// A request ready to be sent, with the authentication and content headers set.
+#[derive(Debug, Clone)]
+pub struct Request<'a> {
+    pub method: &'a str,
+    pub url: &'a str,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: Option<&'a [u8]>,
+}
+
+impl Request<'_> {
+
+    /// The value of a header, matched case-insensitively.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)).map(|(_, value)| value.as_str())
+    }
+}
+
+/// Performs blocking HTTP requests for a `Deta` client, enabled with the default `blocking` feature.
+/// ```rust
+/// use detalib::{ Deta, transport::{ Request, Transport } };
+///
+/// struct Offline;
+///
+/// impl Transport for Offline {
+///     fn send(&self, request: &Request) -> Result<ureq::Response, ureq::Error> {
+///         Err(ureq::Error::Status(503, ureq::Response::new(503, "Service Unavailable", "")?))
+///     }
+/// }
+///
+/// let deta = Deta::builder().project_key("id_secret").transport(Offline).build();
+/// assert!(deta.base("users").get("john").is_err());
+/// ```
+#[cfg(feature = "blocking")]
+pub trait Transport: Send + Sync {
+    /// Sends a request. Responses with a status of 400 or more are returned as `ureq::Error::Status`.
+    #[allow(clippy::result_large_err)]
+    fn send(&self, request: &Request) -> Result<ureq::Response, ureq::Error>;
+}
+
+/// The default blocking transport, a `ureq` agent sharing one connection pool.
+#[cfg(feature = "blocking")]
+#[derive(Clone)]
+pub struct UreqTransport {
+    agent: ureq::Agent,
+}
+
+#[cfg(feature = "blocking")]
+impl UreqTransport {
+
+    pub fn new(agent: ureq::Agent) -> UreqTransport {
+        UreqTransport { agent }
+    }
+
+    /// Returns the underlying agent.
+    pub fn agent(&self) -> &ureq::Agent {
+        &self.agent
+    }
+}
+
+#[cfg(feature = "blocking")]
+impl Transport for UreqTransport {
+    fn send(&self, request: &Request) -> Result<ureq::Response, ureq::Error> {
+        let req = request.headers.iter()
+            .fold(self.agent.request(request.method, request.url), |req, (name, value)| req.set(name, value));
+        match request.body {
+            Some(body) => req.send_bytes(body),
+            None => req.call(),
+        }
+    }
+}
+
+/// A boxed future returned by `AsyncTransport::send`.
+#[cfg(all(any(feature = "tokio", feature = "wasm"), not(target_arch = "wasm32")))]
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// A boxed future returned by `AsyncTransport::send`.
+///
+/// Not `Send` on wasm32, as `fetch` futures are bound to their thread.
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + 'a>>;
+
+/// Performs async HTTP requests for a `Deta` client, enabled with the `tokio` or `wasm` feature.
+///
+/// Unlike `Transport`, error statuses are returned as successful responses.
+#[cfg(any(feature = "tokio", feature = "wasm"))]
+pub trait AsyncTransport: Send + Sync {
+    fn send<'a>(&'a self, request: Request<'a>) -> BoxFuture<'a, Result<reqwest::Response, reqwest::Error>>;
+}
+
+/// The default async transport, a `reqwest` client sharing one connection pool.
+#[cfg(any(feature = "tokio", feature = "wasm"))]
+#[derive(Clone)]
+pub struct ReqwestTransport {
+    client: reqwest::Client,
+}
+
+#[cfg(any(feature = "tokio", feature = "wasm"))]
+impl ReqwestTransport {
+
+    pub fn new(client: reqwest::Client) -> ReqwestTransport {
+        ReqwestTransport { client }
+    }
+
+    /// Returns the underlying client.
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+}
+
+#[cfg(any(feature = "tokio", feature = "wasm"))]
+impl AsyncTransport for ReqwestTransport {
+    fn send<'a>(&'a self, request: Request<'a>) -> BoxFuture<'a, Result<reqwest::Response, reqwest::Error>> {
+        let method = reqwest::Method::from_bytes(request.method.as_bytes()).unwrap_or(reqwest::Method::GET);
+        let mut req = request.headers.iter()
+            .fold(self.client.request(method, request.url), |req, (name, value)| req.header(*name, value));
+        if let Some(body) = request.body {
+            req = req.body(body.to_vec());
+        }
+        Box::pin(req.send())
+    }
+}
+
+#[cfg(all(test, feature = "blocking"))]
+mod tests {
+    use std::sync::{ Arc, Mutex };
+
+    use super::*;
+    use crate::{ Deta, retry::RetryPolicy };
+
+    #[derive(Default)]
+    struct Recorder {
+        requests: Mutex<Vec<(String, String, Option<String>)>>,
+    }
+
+    impl Transport for Arc<Recorder> {
+        fn send(&self, request: &Request) -> Result<ureq::Response, ureq::Error> {
+            let mut requests = self.requests.lock().unwrap();
+            let key = request.header("x-api-key").map(String::from);
+            requests.push((request.method.to_string(), request.url.to_string(), key));
+            match requests.len() {
+                1 => Err(ureq::Error::Status(503, ureq::Response::new(503, "Service Unavailable", "")?)),
+                _ => ureq::Response::new(200, "OK", r#"{"key": "john"}"#),
+            }
+        }
+    }
+
+    #[test]
+    fn custom_transport_is_retried() {
+        let recorder = Arc::new(Recorder::default());
+        let deta = Deta::builder()
+            .project_key("id_secret")
+            .retry(RetryPolicy::default())
+            .transport(recorder.clone())
+            .build();
+        assert_eq!(deta.base("users").get("john").unwrap()["key"], "john");
+        let requests = recorder.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        let url = "https://database.deta.sh/v1/id/users/items/john";
+        assert_eq!(requests[1], (String::from("GET"), String::from(url), Some(String::from("id_secret"))));
+    }
+}
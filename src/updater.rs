@@ -1,10 +1,12 @@
+use std::time::Duration;
+
 use serde_json::{ Map, Value };
 use serde::{ Serialize, Serializer };
 
-use crate::{ base::Base, errors::DetaError };
+use crate::{ base::{ typed, Base, UpdateResponse }, errors::DetaError, guard::Guards };
 
 /// Represents the operation to be performed on a field.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub (crate) enum Operation {
     /// Set the field to the given value.
     Set,
@@ -24,6 +26,48 @@ impl Operation {
     }
 }
 
+/// Whether one field path is the other or nested in it.
+#[cfg(feature = "blocking")]
+fn overlaps(a: &str, b: &str) -> bool {
+    let nested = |outer: &str, inner: &str| {
+        inner.strip_prefix(outer).is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+    };
+    nested(a, b) || nested(b, a)
+}
+
+#[cfg(feature = "blocking")]
+fn items(value: &Value) -> Vec<Value> {
+    match value {
+        Value::Array(items) => items.clone(),
+        value => vec![value.clone()],
+    }
+}
+
+#[cfg(feature = "blocking")]
+fn sum(a: &Value, b: &Value) -> Option<Value> {
+    match (a.as_i64(), b.as_i64()) {
+        (Some(a), Some(b)) => a.checked_add(b).map(Value::from),
+        _ => Some(Value::from(a.as_f64()? + b.as_f64()?)),
+    }
+}
+
+/// A single operation with the effect of `first` followed by `then` on the same field, if there is one.
+#[cfg(feature = "blocking")]
+fn combine(first: (&Value, &Operation), then: (&Value, &Operation)) -> Option<(Value, Operation)> {
+    use Operation::*;
+    match (first, then) {
+        (_, (value, Set)) => Some((value.clone(), Set)),
+        (_, (_, Delete)) => Some((Value::Null, Delete)),
+        ((a, Set), (b, Increment)) => Some((sum(a, b)?, Set)),
+        ((a, Increment), (b, Increment)) => Some((sum(a, b)?, Increment)),
+        ((Value::Array(a), Set), (b, Append)) => Some((Value::Array([a.clone(), items(b)].concat()), Set)),
+        ((Value::Array(a), Set), (b, Prepend)) => Some((Value::Array([items(b), a.clone()].concat()), Set)),
+        ((a, Append), (b, Append)) => Some((Value::Array([items(a), items(b)].concat()), Append)),
+        ((a, Prepend), (b, Prepend)) => Some((Value::Array([items(b), items(a)].concat()), Prepend)),
+        _ => None,
+    }
+}
+
 /// Represents an updater to update a field in a record.
 /// 
 /// For delete operations, the value is ignored, so it can be anything.
@@ -35,7 +79,8 @@ impl Operation {
 pub struct Updater {
     key: String,
     base: Base,
-    data: Vec<(String, Value, Operation)>
+    data: Vec<(String, Value, Operation)>,
+    guards: Guards,
 }
 
 impl Updater {
@@ -44,7 +89,8 @@ impl Updater {
         Updater {
             base,
             key: key.to_string(),
-            data: Vec::new()
+            data: Vec::new(),
+            guards: Guards::default(),
         }
     }
 
@@ -83,12 +129,104 @@ impl Updater {
         self
     }
 
+    /// Only commits the updates if the field currently holds the given value.
+    /// 
+    /// Nested fields can be addressed with dots, e.g. `profile.state`.
+    /// Multiple guards must all hold for the commit to happen.
+    pub fn only_if(mut self, field: &str, value: Value) -> Self {
+        self.guards.fields.push((field.to_string(), value));
+        self
+    }
+
+    /// Sets how many times a guarded commit reads the record again when a guard does not hold.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.guards.retries = retries;
+        self
+    }
+
+    /// Sets the delay before each retry of a guarded commit, 50 milliseconds by default.
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.guards.backoff = backoff;
+        self
+    }
+
+    /// The URL of the record this updater changes, identifying it across bases and projects.
+    #[cfg(feature = "blocking")]
+    pub (crate) fn target(&self) -> String {
+        self.base.url(&format!("/items/{}", self.key))
+    }
+
+    /// Folds the updates of `later` into this updater, as if both were committed one after the other.
+    ///
+    /// Gives `later` back and leaves this updater unchanged if that cannot be done in one commit:
+    /// either has guards, they change other records, or a field is changed in ways that do not
+    /// combine, e.g. appended to after being incremented, or changed along with a nested field.
+    #[cfg(feature = "blocking")]
+    pub (crate) fn absorb(&mut self, later: Updater) -> Option<Updater> {
+        if !self.guards.is_empty() || !later.guards.is_empty() || self.target() != later.target() {
+            return Some(later);
+        }
+        let mut data = self.data.clone();
+        for (field, value, operation) in later.data.iter() {
+            let mut earlier = data.iter().enumerate().filter(|(_, (other, _, _))| overlaps(other, field));
+            let combined = match (earlier.next(), earlier.next()) {
+                (None, _) => {
+                    data.push((field.clone(), value.clone(), operation.clone()));
+                    continue;
+                },
+                (Some((i, (other, first, first_op))), None) if other == field => {
+                    combine((first, first_op), (value, operation)).map(|combined| (i, combined))
+                },
+                _ => None,
+            };
+            let Some((i, (value, operation))) = combined else {
+                return Some(later);
+            };
+            data[i] = (field.clone(), value, operation);
+        }
+        self.data = data;
+        None
+    }
+
     /// Commits the updates to the record.
-    pub fn commit(&self) -> Result<Value, DetaError> {
-        self.base.request(
-            "PATCH", &format!("/items/{}", self.key),
-            Some(serde_json::to_value(self).unwrap())
-        )
+    /// 
+    /// If guards were added with `only_if`, the record is fetched and checked first.
+    /// This is best-effort: a change made between that read and the commit is not detected.
+    #[cfg(feature = "blocking")]
+    pub fn commit(&self) -> Result<UpdateResponse, DetaError> {
+        self.commit_raw().and_then(typed)
+    }
+
+    /// Same as `commit`, returning the raw response body.
+    #[cfg(feature = "blocking")]
+    pub fn commit_raw(&self) -> Result<Value, DetaError> {
+        let path = format!("/items/{}", self.key);
+        let body = serde_json::to_value(self)?;
+        if self.guards.is_empty() {
+            return self.base.request("PATCH", &path, Some(body));
+        }
+        self.guards.commit(&self.base, &self.key, |_| self.base.request("PATCH", &path, Some(body)))
+    }
+
+    /// Commits the updates to the record asynchronously.
+    /// 
+    /// If guards were added with `only_if`, the record is fetched and checked first.
+    /// This is best-effort: a change made between that read and the commit is not detected.
+    #[cfg(any(feature = "tokio", feature = "wasm"))]
+    pub async fn commit_async(&self) -> Result<UpdateResponse, DetaError> {
+        self.commit_raw_async().await.and_then(typed)
+    }
+
+    /// Same as `commit_async`, returning the raw response body.
+    #[cfg(any(feature = "tokio", feature = "wasm"))]
+    pub async fn commit_raw_async(&self) -> Result<Value, DetaError> {
+        let path = format!("/items/{}", self.key);
+        let body = serde_json::to_value(self)?;
+        if self.guards.is_empty() {
+            return self.base.request_async(reqwest::Method::PATCH, &path, Some(body)).await;
+        }
+        let patch = |_| self.base.request_async(reqwest::Method::PATCH, &path, Some(body));
+        self.guards.commit_async(&self.base, &self.key, patch).await
     }
 
 }
@@ -101,11 +239,13 @@ impl Serialize for Updater {
         let mut del_vec = vec![];
         for (field, value, operation) in self.data.iter() {
             if operation == &Operation::Delete {
-                del_vec.push(value.clone())
+                del_vec.push(Value::from(field.clone()))
             } else {
                 let tmp = main_map.entry(operation.as_string())
                     .or_insert(Value::Object(Map::new()));
-                tmp.as_object_mut().unwrap().insert(field.clone().to_string(), value.clone());
+                if let Value::Object(fields) = tmp {
+                    fields.insert(field.clone(), value.clone());
+                }
             }
         }
         if !del_vec.is_empty() {
@@ -113,4 +253,74 @@ impl Serialize for Updater {
         }
         Value::Object(main_map).serialize(serializer)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::Deta;
+    #[cfg(feature = "mock")]
+    use std::time::Duration;
+    #[cfg(feature = "mock")]
+    use crate::{ errors::DetaError, mock::MockDeta };
+
+    #[cfg(feature = "blocking")]
+    #[test]
+    fn absorbs_later_updates() {
+        let base = Deta::from("id_secret").base("hello");
+        let mut merged = base.update("k")
+            .set("title", json!("a"))
+            .increment("visits", json!(1))
+            .set("tags", json!(["x"]));
+        for later in [
+            base.update("k").set("title", json!("b")).increment("visits", json!(2)),
+            base.update("k").append("tags", json!("y")).prepend("log", json!([1])),
+            base.update("k").prepend("log", json!(0)).delete("draft"),
+        ] {
+            assert!(merged.absorb(later).is_none());
+        }
+        assert_eq!(serde_json::to_value(&merged).unwrap(), json!({
+            "set": { "title": "b", "tags": ["x", "y"] },
+            "increment": { "visits": 3 },
+            "prepend": { "log": [0, 1] },
+            "delete": ["draft"]
+        }));
+        let before = serde_json::to_value(&merged).unwrap();
+        assert!(merged.absorb(base.update("k").append("visits", json!(1))).is_some());
+        assert!(merged.absorb(base.update("k").set("title.sub", json!(1))).is_some());
+        assert!(merged.absorb(base.update("other").set("title", json!(1))).is_some());
+        assert!(merged.absorb(base.update("k").set("x", json!(1)).only_if("x", json!(0))).is_some());
+        assert_eq!(serde_json::to_value(&merged).unwrap(), before);
+    }
+
+    #[test]
+    fn serializes_grouped_operations() {
+        let updater = Deta::from("id_secret").base("hello").update("k")
+            .set("name", json!("John"))
+            .set("profile.age", json!(20))
+            .increment("visits", json!(1))
+            .delete("legacy");
+        assert_eq!(serde_json::to_value(&updater).unwrap(), json!({
+            "set": { "name": "John", "profile.age": 20 },
+            "increment": { "visits": 1 },
+            "delete": ["legacy"]
+        }));
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn guarded_update_checks_field() {
+        let base = MockDeta::new().base("docs");
+        base.put(vec![json!({ "key": "d", "rev": 1 })]).unwrap();
+        let update = |rev| base.update("d")
+            .only_if("rev", json!(rev))
+            .increment("rev", json!(1))
+            .retries(2)
+            .backoff(Duration::ZERO)
+            .commit();
+        update(1).unwrap();
+        assert!(matches!(update(1), Err(DetaError::PreconditionFailed { .. })));
+        assert_eq!(base.get("d").unwrap()["rev"], 2);
+    }
+}
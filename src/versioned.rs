@@ -0,0 +1,92 @@
+use chrono::{ DateTime, Utc };
+use serde::{ Deserialize, Serialize };
+use serde_json::Value;
+
+use crate::{ base::{ Base, DeleteResponse, PutResponse }, errors::DetaError };
+
+/// A single entry in the history of a record.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    /// The key of the versioned record.
+    pub record_key: String,
+    /// Unix timestamp in milliseconds of the write.
+    pub at: i64,
+    /// Whether the write deleted the record.
+    pub deleted: bool,
+    /// The full record after the write, `null` for deletions.
+    pub value: Value,
+}
+
+/// A base that keeps the full history of every record in a second base.
+/// 
+/// Every write through a `VersionedBase` also stores a snapshot under the key
+/// `<key>@<millis>` in the history base, so past states can be read back with `as_of`.
+#[derive(Clone)]
+pub struct VersionedBase {
+    data: Base,
+    history: Base,
+}
+
+fn history_key(key: &str, millis: i64) -> String {
+    format!("{}@{:020}", key, millis.max(0))
+}
+
+impl VersionedBase {
+
+    /// Create a versioned base storing records in `data` and snapshots in `history`.
+    pub fn new(data: &Base, history: &Base) -> VersionedBase {
+        VersionedBase { data: data.clone(), history: history.clone() }
+    }
+
+    fn record(&self, key: &str, deleted: bool, value: Value) -> Result<(), DetaError> {
+        let at = Utc::now().timestamp_millis();
+        let entry = HistoryEntry { record_key: key.to_string(), at, deleted, value };
+        let mut item = serde_json::to_value(entry)?;
+        item["key"] = Value::from(history_key(key, at));
+        self.history.put(vec![item]).map(|_| ())
+    }
+
+    /// Fetch the current state of a record.
+    pub fn get(&self, key: &str) -> Result<Value, DetaError> {
+        self.data.get(key)
+    }
+
+    /// Put a record and append its new state to the history.
+    pub fn put<T: Serialize>(&self, record: T) -> Result<PutResponse, DetaError> {
+        let value = serde_json::to_value(record)?;
+        let key = match value["key"].as_str() {
+            Some(key) => key.to_string(),
+            None => return Err(
+                DetaError::PayloadError { msg: "versioned records require a string key".to_string() }
+            ),
+        };
+        let resp = self.data.put(vec![&value])?;
+        self.record(&key, false, value)?;
+        Ok(resp)
+    }
+
+    /// Delete a record and append a deletion marker to the history.
+    pub fn delete(&self, key: &str) -> Result<DeleteResponse, DetaError> {
+        let resp = self.data.delete(key)?;
+        self.record(key, true, Value::Null)?;
+        Ok(resp)
+    }
+
+    /// The full history of a record, oldest first.
+    pub fn history(&self, key: &str) -> Result<Vec<HistoryEntry>, DetaError> {
+        self.history.query().key_prefix(&format!("{}@", key)).walk_as::<HistoryEntry>()
+    }
+
+    /// Reconstruct the state of a record at the given point in time.
+    /// 
+    /// Returns `None` if the record did not exist or was deleted at that time.
+    pub fn as_of(&self, key: &str, timestamp: DateTime<Utc>) -> Result<Option<Value>, DetaError> {
+        let entries = self.history.query()
+            .key_range(&format!("{}@", key), &history_key(key, timestamp.timestamp_millis()))
+            .walk_as::<HistoryEntry>()?;
+        Ok(entries.into_iter()
+            .max_by_key(|entry| entry.at)
+            .filter(|entry| !entry.deleted)
+            .map(|entry| entry.value))
+    }
+}
@@ -0,0 +1,288 @@
+//! Change notifications: successful writes are posted as signed JSON events to a webhook.
+
+#[cfg(feature = "blocking")]
+use std::{
+    sync::mpsc::{ self, Receiver, SyncSender },
+    thread,
+};
+use std::time::Duration;
+
+use serde::{ Deserialize, Serialize };
+use serde_json::Value;
+use sha2::{ Digest, Sha256 };
+
+use crate::{ drive::PENDING_UPLOADS_PREFIX, hex };
+
+/// The header carrying the HMAC-SHA256 signature of the event body, as `sha256=<hex>`.
+pub const SIGNATURE_HEADER: &str = "X-Signature-256";
+
+/// A URL that receives an event after every successful write, set with `DetaBuilder::webhook`.
+///
+/// Events are queued in memory and posted by a background thread, so writes never wait for
+/// the webhook. Failed deliveries are retried with exponential backoff. Events are dropped
+/// when the queue is full or every attempt failed, so receivers should treat them as hints
+/// and re-read the data they care about. Delivery needs the default `blocking` feature.
+/// ```rust
+/// use detalib::{ Deta, webhook::Webhook };
+///
+/// let deta = Deta::builder()
+///     .webhook(Webhook::new("https://example.com/hooks/deta").secret("s3cret"))
+///     .build();
+/// ```
+#[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "blocking"), allow(dead_code))]
+pub struct Webhook {
+    url: String,
+    secret: Option<String>,
+    retries: u32,
+    backoff: Duration,
+    buffer: usize,
+}
+
+impl Webhook {
+
+    pub fn new(url: &str) -> Webhook {
+        Webhook {
+            url: url.to_string(),
+            secret: None,
+            retries: 3,
+            backoff: Duration::from_millis(500),
+            buffer: 1024,
+        }
+    }
+
+    /// Signs every event with this secret, see `SIGNATURE_HEADER`.
+    pub fn secret(mut self, secret: &str) -> Self {
+        self.secret = Some(secret.to_string());
+        self
+    }
+
+    /// Sets how often a failed delivery is retried. Defaults to 3.
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Sets the delay before the first retry, doubled for every further retry.
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Sets how many events may wait for delivery. Defaults to 1024.
+    pub fn buffer(mut self, events: usize) -> Self {
+        self.buffer = events.max(1);
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Service {
+    Base,
+    Drive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Put,
+    Insert,
+    Update,
+    Delete,
+}
+
+/// The JSON body posted to a webhook.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MutationEvent {
+    pub service: Service,
+    /// Name of the base or drive.
+    pub name: String,
+    pub action: Action,
+    /// Keys of the written records, or names of the written files.
+    ///
+    /// Records put without a key are missing, as their key is generated by Deta.
+    pub keys: Vec<String>,
+    /// Unix timestamp (seconds) of the write.
+    pub timestamp: i64,
+}
+
+#[cfg_attr(not(feature = "blocking"), allow(dead_code))]
+impl MutationEvent {
+
+    /// Describes a successful request, or returns `None` if it did not change any data.
+    pub (crate) fn from_request(method: &str, url: &str, body: Option<&[u8]>) -> Option<MutationEvent> {
+        let (host, rest) = url.split_once("://")?.1.split_once('/')?;
+        let mut segments = rest.splitn(4, '/');
+        let (_version, _project) = (segments.next()?, segments.next()?);
+        let name = segments.next()?.to_string();
+        let path = segments.next().unwrap_or_default();
+        let (path, query) = path.split_once('?').unwrap_or((path, ""));
+        let body = || body.and_then(|body| serde_json::from_slice::<Value>(body).ok()).unwrap_or_default();
+        let keys = |items: &Value| items.as_array().map_or_else(Vec::new, |items| {
+            items.iter().filter_map(|item| item["key"].as_str().map(String::from)).collect()
+        });
+        let (service, action, keys) = match (host.split('.').next()?, method.to_ascii_uppercase().as_str()) {
+            ("database", "PUT") if path == "items" => (Service::Base, Action::Put, keys(&body()["items"])),
+            ("database", "POST") if path == "items" => {
+                let key = body()["item"]["key"].as_str().map(String::from);
+                (Service::Base, Action::Insert, key.into_iter().collect())
+            },
+            ("database", method @ ("PATCH" | "DELETE")) => {
+                let key = urlencoding::decode(path.strip_prefix("items/")?).ok()?.into_owned();
+                let action = if method == "PATCH" { Action::Update } else { Action::Delete };
+                (Service::Base, action, vec![key])
+            },
+            ("drive", "POST") if path == "files" => (Service::Drive, Action::Put, vec![file_name(query)?]),
+            ("drive", "PATCH") if path.starts_with("uploads/") => {
+                (Service::Drive, Action::Put, vec![file_name(query)?])
+            },
+            ("drive", "DELETE") if path == "files" => {
+                (Service::Drive, Action::Delete, keys_of(&body()["names"]))
+            },
+            _ => return None,
+        };
+        let keys = keys.into_iter()
+            .filter(|key| !key.starts_with(PENDING_UPLOADS_PREFIX))
+            .collect::<Vec<_>>();
+        if service == Service::Drive && keys.is_empty() {
+            return None;
+        }
+        Some(MutationEvent { service, name, action, keys, timestamp: chrono::Utc::now().timestamp() })
+    }
+}
+
+fn file_name(query: &str) -> Option<String> {
+    let name = query.split('&').find_map(|pair| pair.strip_prefix("name="))?;
+    urlencoding::decode(name).ok().map(|name| name.into_owned())
+}
+
+fn keys_of(names: &Value) -> Vec<String> {
+    names.as_array().map_or_else(Vec::new, |names| {
+        names.iter().filter_map(|name| name.as_str().map(String::from)).collect()
+    })
+}
+
+/// HMAC-SHA256 of `payload` keyed with `secret`, hex encoded.
+pub (crate) fn sign(secret: &[u8], payload: &[u8]) -> String {
+    let mut key = [0u8; 64];
+    match secret.len() > key.len() {
+        true => key[..32].copy_from_slice(&Sha256::digest(secret)),
+        false => key[..secret.len()].copy_from_slice(secret),
+    }
+    let pad = |byte: u8| key.iter().map(|k| k ^ byte).collect::<Vec<_>>();
+    let inner = Sha256::new().chain_update(pad(0x36)).chain_update(payload).finalize();
+    let outer = Sha256::new().chain_update(pad(0x5c)).chain_update(inner).finalize();
+    hex::encode(&outer)
+}
+
+/// Queues events for the delivery thread, which exits once the sender is dropped.
+#[cfg(feature = "blocking")]
+pub (crate) struct WebhookSender {
+    events: SyncSender<MutationEvent>,
+}
+
+#[cfg(feature = "blocking")]
+impl WebhookSender {
+
+    pub (crate) fn new(webhook: Webhook, agent: ureq::Agent) -> WebhookSender {
+        let (events, queue) = mpsc::sync_channel(webhook.buffer);
+        thread::spawn(move || deliver(webhook, agent, queue));
+        WebhookSender { events }
+    }
+
+    /// Queues the event of a successful write, dropping it if the queue is full.
+    pub (crate) fn emit(&self, method: &str, url: &str, body: Option<&[u8]>) {
+        if let Some(event) = MutationEvent::from_request(method, url, body) {
+            let _ = self.events.try_send(event);
+        }
+    }
+}
+
+#[cfg(feature = "blocking")]
+fn deliver(webhook: Webhook, agent: ureq::Agent, queue: Receiver<MutationEvent>) {
+    for event in queue {
+        let Ok(body) = serde_json::to_vec(&event) else {
+            continue;
+        };
+        for attempt in 0..=webhook.retries {
+            if attempt > 0 {
+                thread::sleep(webhook.backoff.saturating_mul(1 << (attempt - 1).min(16)));
+            }
+            let mut req = agent.post(&webhook.url).set("Content-Type", "application/json");
+            if let Some(secret) = &webhook.secret {
+                req = req.set(SIGNATURE_HEADER, &format!("sha256={}", sign(secret.as_bytes(), &body)));
+            }
+            if req.send_bytes(&body).is_ok() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    #[cfg(feature = "mock")]
+    use std::sync::Arc;
+    #[cfg(feature = "mock")]
+    use crate::Deta;
+
+    #[test]
+    fn signs_with_hmac_sha256() {
+        let signature = sign(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(signature, "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+    }
+
+    #[test]
+    fn describes_writes() {
+        let base = "https://database.deta.sh/v1/p/users";
+        let body = serde_json::to_vec(&json!({ "items": [{ "key": "a" }, { "name": "no key" }] })).unwrap();
+        let event = MutationEvent::from_request("PUT", &format!("{}/items", base), Some(&body)).unwrap();
+        assert_eq!((event.service, event.action, event.keys), (Service::Base, Action::Put, vec!["a".into()]));
+        let event = MutationEvent::from_request("DELETE", &format!("{}/items/a%20b", base), None).unwrap();
+        assert_eq!((event.action, event.keys), (Action::Delete, vec!["a b".into()]));
+        let drive = "https://drive.deta.sh/v1/p/files";
+        let event = MutationEvent::from_request("POST", &format!("{}/files?name=a%2Fb.txt", drive), None);
+        assert_eq!(event.unwrap().keys, vec!["a/b.txt"]);
+        let marker = format!("{}/files?name=.detalib%2Fuploads%2Fid", drive);
+        assert_eq!(MutationEvent::from_request("POST", &marker, None), None);
+        assert_eq!(MutationEvent::from_request("POST", &format!("{}/uploads?name=a", drive), None), None);
+        assert_eq!(MutationEvent::from_request("GET", &format!("{}/items/a", base), None), None);
+    }
+
+    #[cfg(feature = "mock")]
+    #[test]
+    fn webhook_receives_signed_events() {
+        use std::{ io::{ BufRead, BufReader, Read, Write }, net::TcpListener };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let receiver = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let (mut headers, mut line) = (Vec::new(), String::new());
+            while reader.read_line(&mut line).unwrap() > 2 {
+                headers.push(std::mem::take(&mut line).trim().to_lowercase());
+            }
+            let length = headers.iter().find_map(|h| h.strip_prefix("content-length: ")).unwrap();
+            let mut body = vec![0; length.parse().unwrap()];
+            reader.read_exact(&mut body).unwrap();
+            (&stream).write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n").unwrap();
+            (headers, body)
+        });
+        let deta = Deta::builder()
+            .project_key("mock_key")
+            .mock(Arc::default())
+            .webhook(Webhook::new(&url).secret("s3cret"))
+            .build();
+        deta.base("users").get_opt("u").unwrap();
+        deta.base("users").put(vec![json!({ "key": "u" })]).unwrap();
+        let (headers, body) = receiver.join().unwrap();
+        let event = serde_json::from_slice::<MutationEvent>(&body).unwrap();
+        assert_eq!((event.name.as_str(), event.action, event.keys), ("users", Action::Put, vec!["u".into()]));
+        assert!(headers.iter().any(|h| h.starts_with("x-signature-256: sha256=")));
+    }
+}